@@ -0,0 +1,799 @@
+use std::fmt;
+use std::collections::BTreeMap;
+use crate::bits::BitReader;
+use crate::codec::Codec;
+use crate::demux::DemuxHandler;
+use crate::error::ParseError;
+use crate::packet::Packet;
+use crate::pes::{Pes, PES_CLOCK_FREQUENCY};
+use crate::psi::pmt::Pmt;
+
+// Constants
+pub const AC3_SYNC_WORD: u16 = 0x0B77;
+/// More than any frame header (with a StreamMuxConfig) needs
+const MAX_HEADER_SIZE: usize = 64;
+/// sampling_frequency_index of the AAC headers
+const AAC_SAMPLE_RATES: [u32; 13] = [96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350];
+/// AC-3 bitrates in kbit/s by frmsizecod / 2
+const AC3_BITRATES: [u32; 19] = [32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384, 448, 512, 576, 640];
+/// MPEG audio bitrates in kbit/s by bitrate_index: MPEG-1 layers I, II and III, then
+/// MPEG-2/2.5 layer I and layers II/III
+const MPEG_AUDIO_BITRATES: [[u32; 15]; 5] = [
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// Name of an MPEG-4 audioObjectType
+pub fn audio_object_type_name(audio_object_type: u8) -> &'static str {
+    match audio_object_type {
+        1 => "AAC Main",
+        2 => "AAC LC",
+        3 => "AAC SSR",
+        4 => "AAC LTP",
+        5 => "HE-AAC",
+        6 => "AAC Scalable",
+        29 => "HE-AACv2",
+        42 => "USAC",
+        _ => "AAC",
+    }
+}
+
+/// Name of an AAC channel_configuration
+fn aac_channel_layout(channel_configuration: u8) -> (&'static str, u8) {
+    match channel_configuration {
+        1 => ("Mono", 1),
+        2 => ("Stereo", 2),
+        3 => ("3.0", 3),
+        4 => ("4.0", 4),
+        5 => ("5.0", 5),
+        6 => ("5.1", 6),
+        7 => ("7.1", 8),
+        _ => ("Defined in PCE", 0),
+    }
+}
+
+/// Name of an AC-3/E-AC-3 audio coding mode (front/rear channels), with the number of
+/// full bandwidth channels
+fn ac3_channel_layout(acmod: u8) -> (&'static str, u8) {
+    match acmod {
+        0 => ("1+1", 2),
+        1 => ("1/0", 1),
+        2 => ("2/0", 2),
+        3 => ("3/0", 3),
+        4 => ("2/1", 3),
+        5 => ("3/1", 4),
+        6 => ("2/2", 4),
+        _ => ("3/2", 5),
+    }
+}
+
+/// What the header of an audio frame says
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FrameHeader {
+    /// The format and its profile or layer, e.g. "AAC LC" or "MPEG-1 Layer II"
+    pub format: &'static str,
+    /// Output sample rate in Hz
+    pub sample_rate: u32,
+    pub channel_layout: String,
+    /// Number of channels (including the LFE channel)
+    pub channels: u8,
+    /// In bit/s
+    pub bitrate: u32,
+    /// Number of samples per channel the frame decodes to (at its core sample rate)
+    pub samples: u32,
+    /// Sample rate the samples are counted at (differs from sample_rate with SBR)
+    pub core_sample_rate: u32,
+    /// Length of the frame in bytes
+    pub length: usize,
+    /// Dialogue normalization in dB below full scale (AC-3 and E-AC-3)
+    pub dialnorm: Option<u8>,
+    /// Whether the frame is a dependent E-AC-3 substream (extra channels of the frame before)
+    pub dependent: bool,
+}
+
+impl FrameHeader {
+    /// Duration of the frame in seconds
+    pub fn duration(&self) -> f64 {
+        f64::from(self.samples) / f64::from(self.core_sample_rate)
+    }
+
+    /// Whether two frames have the same format, sample rate and channels (bitrates can vary)
+    pub fn same_format(&self, other: &FrameHeader) -> bool {
+        self.format == other.format && self.sample_rate == other.sample_rate &&
+            self.channel_layout == other.channel_layout
+    }
+
+    /// Parse the header of an ADTS frame, None if `buf` doesn't start with its syncword
+    pub fn adts(buf: &[u8]) -> Result<Option<FrameHeader>, ParseError> {
+        let mut r = BitReader::new(buf);
+        if r.bits(12, "syncword")? != 0xFFF {
+            return Ok(None);
+        }
+        r.skip(1, "ID")?;
+        if r.bits(2, "layer")? != 0 {
+            return Ok(None);
+        }
+        r.skip(1, "protection_absent")?;
+        let audio_object_type = r.bits(2, "profile_ObjectType")? as u8 + 1;
+        let sampling_frequency_index = r.bits(4, "sampling_frequency_index")?;
+        let sample_rate = *AAC_SAMPLE_RATES.get(sampling_frequency_index as usize)
+            .ok_or(ParseError::InvalidValue { pid: None, offset: 2, field: "sampling_frequency_index", value: sampling_frequency_index })?;
+        r.skip(1, "private_bit")?;
+        let (channel_layout, channels) = aac_channel_layout(r.bits(3, "channel_configuration")? as u8);
+        r.skip(4, "copyright_identification")?;
+        let length = r.bits(13, "aac_frame_length")? as usize;
+        r.skip(11, "adts_buffer_fullness")?;
+        let samples = 1024 * (r.bits(2, "number_of_raw_data_blocks_in_frame")? + 1);
+        if length < 7 {
+            return Err(ParseError::InvalidValue { pid: None, offset: 3, field: "aac_frame_length", value: length as u32 });
+        }
+        Ok(Some(FrameHeader {
+            format: audio_object_type_name(audio_object_type),
+            sample_rate,
+            channel_layout: channel_layout.to_string(),
+            channels,
+            bitrate: (length as u64 * 8 * u64::from(sample_rate) / u64::from(samples)) as u32,
+            samples,
+            core_sample_rate: sample_rate,
+            length,
+            dialnorm: None,
+            dependent: false,
+        }))
+    }
+
+    /// Parse the header of an MPEG-1/MPEG-2 audio frame, None if `buf` doesn't start with
+    /// its syncword (free format frames aren't supported)
+    pub fn mpeg_audio(buf: &[u8]) -> Result<Option<FrameHeader>, ParseError> {
+        let mut r = BitReader::new(buf);
+        if r.bits(11, "syncword")? != 0x7FF {
+            return Ok(None);
+        }
+        let version = r.bits(2, "version")?;
+        let layer = 4 - r.bits(2, "layer")?;
+        r.skip(1, "protection_bit")?;
+        let bitrate_index = r.bits(4, "bitrate_index")? as usize;
+        let sampling_frequency = r.bits(2, "sampling_frequency")? as usize;
+        let padding = r.bits(1, "padding_bit")?;
+        r.skip(1, "private_bit")?;
+        let mode = r.bits(2, "mode")?;
+        if version == 1 || layer == 4 || bitrate_index == 0 || bitrate_index == 15 || sampling_frequency == 3 {
+            return Ok(None);
+        }
+
+        let mpeg1 = version == 3;
+        let table = match (mpeg1, layer) {
+            (true, _) => layer as usize - 1,
+            (false, 1) => 3,
+            (false, _) => 4,
+        };
+        let bitrate = MPEG_AUDIO_BITRATES[table][bitrate_index] * 1000;
+        let sample_rate = [44100, 48000, 32000][sampling_frequency] >> (3 - version).min(2);
+        let samples = match (layer, mpeg1) {
+            (1, _) => 384,
+            (3, false) => 576,
+            _ => 1152,
+        };
+        let length = match layer {
+            1 => (12 * bitrate / sample_rate + padding) * 4,
+            _ => samples / 8 * bitrate / sample_rate + padding,
+        } as usize;
+        let format = match (version, layer) {
+            (3, 1) => "MPEG-1 Layer I",
+            (3, 2) => "MPEG-1 Layer II",
+            (3, _) => "MPEG-1 Layer III",
+            (2, 1) => "MPEG-2 Layer I",
+            (2, 2) => "MPEG-2 Layer II",
+            (2, _) => "MPEG-2 Layer III",
+            (_, 1) => "MPEG-2.5 Layer I",
+            (_, 2) => "MPEG-2.5 Layer II",
+            _ => "MPEG-2.5 Layer III",
+        };
+        let (channel_layout, channels) = match mode {
+            0 => ("Stereo", 2),
+            1 => ("Joint stereo", 2),
+            2 => ("Dual channel", 2),
+            _ => ("Mono", 1),
+        };
+        Ok(Some(FrameHeader {
+            format,
+            sample_rate,
+            channel_layout: channel_layout.to_string(),
+            channels,
+            bitrate,
+            samples,
+            core_sample_rate: sample_rate,
+            length,
+            dialnorm: None,
+            dependent: false,
+        }))
+    }
+
+    /// Parse the header of an AC-3 or E-AC-3 syncframe (told apart by bsid), None if `buf`
+    /// doesn't start with its syncword
+    pub fn ac3(buf: &[u8]) -> Result<Option<FrameHeader>, ParseError> {
+        let mut r = BitReader::new(buf);
+        if r.bits(16, "syncword")? != u32::from(AC3_SYNC_WORD) {
+            return Ok(None);
+        }
+        // bsid is at the same place in both
+        let mut bsi = BitReader::new(buf);
+        bsi.skip(40, "syncinfo")?;
+        let bsid = bsi.bits(5, "bsid")?;
+        if bsid > 10 && bsid <= 16 {
+            return FrameHeader::eac3(r);
+        }
+        if bsid > 8 {
+            return Ok(None);
+        }
+
+        r.skip(16, "crc1")?;
+        let fscod = r.bits(2, "fscod")?;
+        let frmsizecod = r.bits(6, "frmsizecod")?;
+        r.skip(8, "bsid")?;
+        let acmod = r.bits(3, "acmod")? as u8;
+        if fscod == 3 || frmsizecod > 37 {
+            return Ok(None);
+        }
+        if acmod & 0x1 != 0 && acmod != 1 {
+            r.skip(2, "cmixlev")?;
+        }
+        if acmod & 0x4 != 0 {
+            r.skip(2, "surmixlev")?;
+        }
+        if acmod == 2 {
+            r.skip(2, "dsurmod")?;
+        }
+        let lfeon = r.flag("lfeon")?;
+        let dialnorm = r.bits(5, "dialnorm")? as u8;
+
+        let kbps = AC3_BITRATES[frmsizecod as usize / 2];
+        let sample_rate = [48000, 44100, 32000][fscod as usize];
+        let words = match fscod {
+            0 => 2 * kbps,
+            1 => kbps * 1000 * 1536 / 44100 / 16 + (frmsizecod & 1),
+            _ => 3 * kbps,
+        };
+        let (layout, channels) = ac3_channel_layout(acmod);
+        Ok(Some(FrameHeader {
+            format: "AC-3",
+            sample_rate,
+            channel_layout: if lfeon { format!("{}+LFE", layout) } else { layout.to_string() },
+            channels: channels + lfeon as u8,
+            bitrate: kbps * 1000,
+            samples: 1536,
+            core_sample_rate: sample_rate,
+            length: words as usize * 2,
+            dialnorm: Some(if dialnorm == 0 { 31 } else { dialnorm }),
+            dependent: false,
+        }))
+    }
+
+    /// Parse the rest of an E-AC-3 bsi() following the syncword
+    fn eac3(mut r: BitReader) -> Result<Option<FrameHeader>, ParseError> {
+        let strmtyp = r.bits(2, "strmtyp")?;
+        r.skip(3, "substreamid")?;
+        let words = r.bits(11, "frmsiz")? + 1;
+        let fscod = r.bits(2, "fscod")?;
+        let (sample_rate, blocks) = if fscod == 3 {
+            match r.bits(2, "fscod2")? {
+                3 => return Ok(None),
+                fscod2 => ([24000, 22050, 16000][fscod2 as usize], 6),
+            }
+        } else {
+            ([48000, 44100, 32000][fscod as usize], [1, 2, 3, 6][r.bits(2, "numblkscod")? as usize])
+        };
+        let acmod = r.bits(3, "acmod")? as u8;
+        let lfeon = r.flag("lfeon")?;
+        r.skip(5, "bsid")?;
+        let dialnorm = r.bits(5, "dialnorm")? as u8;
+
+        let samples = 256 * blocks;
+        let (layout, channels) = ac3_channel_layout(acmod);
+        Ok(Some(FrameHeader {
+            format: "E-AC-3",
+            sample_rate,
+            channel_layout: if lfeon { format!("{}+LFE", layout) } else { layout.to_string() },
+            channels: channels + lfeon as u8,
+            bitrate: words * 16 * sample_rate / samples,
+            samples,
+            core_sample_rate: sample_rate,
+            length: words as usize * 2,
+            dialnorm: Some(if dialnorm == 0 { 31 } else { dialnorm }),
+            dependent: strmtyp == 1,
+        }))
+    }
+}
+
+impl fmt::Display for FrameHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {} Hz, {} ({} channels)", self.format, self.sample_rate, self.channel_layout, self.channels)
+    }
+}
+
+/// The AudioSpecificConfig of the StreamMuxConfig of a LATM stream (its first layer)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LatmConfig {
+    pub audio_object_type: u8,
+    pub sample_rate: u32,
+    /// The sample rate with SBR (HE-AAC)
+    pub extension_sample_rate: Option<u32>,
+    pub channel_configuration: u8,
+    /// Number of AAC frames per AudioMuxElement
+    pub num_sub_frames: u32,
+}
+
+/// LatmGetValue()
+fn latm_value(r: &mut BitReader) -> Result<u32, ParseError> {
+    let bytes = r.bits(2, "bytesForValue")? + 1;
+    r.bits(8 * bytes, "valueTmp")
+}
+
+/// GetAudioObjectType()
+fn get_audio_object_type(r: &mut BitReader) -> Result<u8, ParseError> {
+    match r.bits(5, "audioObjectType")? {
+        31 => Ok(32 + r.bits(6, "audioObjectTypeExt")? as u8),
+        audio_object_type => Ok(audio_object_type as u8),
+    }
+}
+
+/// A samplingFrequencyIndex (or the explicit samplingFrequency)
+fn sampling_frequency(r: &mut BitReader) -> Result<u32, ParseError> {
+    match r.bits(4, "samplingFrequencyIndex")? {
+        0xF => r.bits(24, "samplingFrequency"),
+        index => AAC_SAMPLE_RATES.get(index as usize).copied()
+            .ok_or(ParseError::InvalidValue { pid: None, offset: r.offset(), field: "samplingFrequencyIndex", value: index }),
+    }
+}
+
+impl LatmConfig {
+    /// Parse a StreamMuxConfig()
+    fn new(r: &mut BitReader) -> Result<LatmConfig, ParseError> {
+        let audio_mux_version = r.flag("audioMuxVersion")?;
+        if audio_mux_version && r.flag("audioMuxVersionA")? {
+            return Err(ParseError::InvalidValue { pid: None, offset: r.offset(), field: "audioMuxVersionA", value: 1 });
+        }
+        if audio_mux_version {
+            latm_value(r)?;
+        }
+        r.skip(1, "allStreamsSameTimeFraming")?;
+        let num_sub_frames = r.bits(6, "numSubFrames")? + 1;
+        r.skip(7, "numProgram")?;
+        if audio_mux_version {
+            latm_value(r)?;
+        }
+        let audio_object_type = get_audio_object_type(r)?;
+        let sample_rate = sampling_frequency(r)?;
+        let channel_configuration = r.bits(4, "channelConfiguration")? as u8;
+        let mut extension_sample_rate = None;
+        if audio_object_type == 5 || audio_object_type == 29 {
+            extension_sample_rate = Some(sampling_frequency(r)?);
+            if get_audio_object_type(r)? == 22 {
+                r.skip(4, "extensionChannelConfiguration")?;
+            }
+        }
+        Ok(LatmConfig { audio_object_type, sample_rate, extension_sample_rate, channel_configuration, num_sub_frames })
+    }
+}
+
+/// An audio format change in the middle of a stream
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FormatChange {
+    /// Offset of the packet completing the PES packet of the first frame in the new format
+    pub offset: u64,
+    pub pts: Option<u64>,
+    pub from: FrameHeader,
+    pub to: FrameHeader,
+}
+
+impl fmt::Display for FormatChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Format change at offset {}", self.offset)?;
+        if let Some(pts) = self.pts {
+            write!(f, " (PTS {:.3}s)", pts as f64 / PES_CLOCK_FREQUENCY as f64)?;
+        }
+        write!(f, ": {} -> {}", self.from, self.to)
+    }
+}
+
+/// What the frame headers of an audio stream say about it
+#[derive(Clone, Debug)]
+pub struct Stream {
+    pub pid: u16,
+    pub program_number: u16,
+    pub codec: Codec,
+    /// The header of the last frame (of the independent substream for E-AC-3)
+    pub format: Option<FrameHeader>,
+    pub latm_config: Option<LatmConfig>,
+    pub frames: u64,
+    /// Number of times a frame didn't start where the one before ended
+    pub sync_errors: u64,
+    pub min_bitrate: u32,
+    pub max_bitrate: u32,
+    /// The dialnorm values seen (AC-3 and E-AC-3)
+    pub dialnorms: BTreeMap<u8, u64>,
+    /// Seconds of audio in the frames
+    pub duration: f64,
+    pub format_changes: Vec<FormatChange>,
+    /// Bytes of the frame that continues in the next PES packet
+    buffer: Vec<u8>,
+    in_sync: bool,
+}
+
+impl Stream {
+    fn new(pid: u16, program_number: u16, codec: Codec) -> Stream {
+        Stream {
+            pid,
+            program_number,
+            codec,
+            format: None,
+            latm_config: None,
+            frames: 0,
+            sync_errors: 0,
+            min_bitrate: u32::MAX,
+            max_bitrate: 0,
+            dialnorms: BTreeMap::new(),
+            duration: 0.0,
+            format_changes: vec![],
+            buffer: vec![],
+            in_sync: false,
+        }
+    }
+
+    /// Whether the audio in `codec` can be analyzed
+    pub fn is_supported(codec: Codec) -> bool {
+        matches!(codec, Codec::Mpeg1Audio | Codec::Mpeg2Audio | Codec::AacAdts | Codec::AacLatm | Codec::Aac |
+            Codec::Ac3 | Codec::EAc3)
+    }
+
+    /// Parse the frame at the start of `buf`: its length and header (None for LATM frames
+    /// before the first StreamMuxConfig), or None if it doesn't start with a frame
+    fn parse_frame(&mut self, buf: &[u8]) -> Result<Option<(usize, Option<FrameHeader>)>, ParseError> {
+        let header = match self.codec {
+            Codec::Mpeg1Audio | Codec::Mpeg2Audio => FrameHeader::mpeg_audio(buf)?,
+            Codec::Ac3 | Codec::EAc3 => FrameHeader::ac3(buf)?,
+            Codec::AacAdts => FrameHeader::adts(buf)?,
+            Codec::AacLatm => return self.parse_loas(buf),
+            // The AAC_descriptor doesn't say which transport the AAC is in
+            _ => match FrameHeader::adts(buf)? {
+                Some(header) => Some(header),
+                None => return self.parse_loas(buf),
+            },
+        };
+        Ok(header.map(|h| (h.length, Some(h))))
+    }
+
+    /// Parse an AudioSyncStream() frame (LOAS) and the StreamMuxConfig it may carry
+    fn parse_loas(&mut self, buf: &[u8]) -> Result<Option<(usize, Option<FrameHeader>)>, ParseError> {
+        let mut r = BitReader::new(buf);
+        if r.bits(11, "syncword")? != 0x2B7 {
+            return Ok(None);
+        }
+        let length = 3 + r.bits(13, "audioMuxLengthBytes")? as usize;
+        if !r.flag("useSameStreamMux")? {
+            self.latm_config = Some(LatmConfig::new(&mut r)?);
+        }
+        let config = match &self.latm_config {
+            Some(config) => config,
+            None => return Ok(Some((length, None))),
+        };
+        let samples = 1024 * config.num_sub_frames;
+        let (channel_layout, channels) = aac_channel_layout(config.channel_configuration);
+        Ok(Some((length, Some(FrameHeader {
+            format: audio_object_type_name(config.audio_object_type),
+            sample_rate: config.extension_sample_rate.unwrap_or(config.sample_rate),
+            channel_layout: channel_layout.to_string(),
+            channels,
+            bitrate: (length as u64 * 8 * u64::from(config.sample_rate) / u64::from(samples)) as u32,
+            samples,
+            core_sample_rate: config.sample_rate,
+            length,
+            dialnorm: None,
+            dependent: false,
+        }))))
+    }
+
+    /// Handle the frames of a PES packet
+    fn push(&mut self, payload: &[u8], offset: u64, pts: Option<u64>) -> Vec<ParseError> {
+        let mut errors = vec![];
+        self.buffer.extend_from_slice(payload);
+        let buffer = std::mem::take(&mut self.buffer);
+        let mut pos = 0;
+        while pos < buffer.len() {
+            let (length, header) = match self.parse_frame(&buffer[pos..]) {
+                Ok(Some((length, header))) if length > 0 => (length, header),
+                // Wait for the rest of the header
+                Err(ParseError::Truncated { .. }) if buffer.len() - pos < MAX_HEADER_SIZE => break,
+                result => {
+                    // Only a frame where one was expected is worth reporting, not every
+                    // false syncword while searching for one
+                    if let (Err(e), true) = (result, self.in_sync) {
+                        errors.push(e.shift(pos).with_pid(self.pid));
+                    }
+                    if self.in_sync {
+                        self.sync_errors += 1;
+                        self.in_sync = false;
+                    }
+                    pos += 1;
+                    continue;
+                },
+            };
+            if pos + length > buffer.len() {
+                break;
+            }
+            // While searching, only take a syncword followed by another frame
+            if !self.in_sync && !matches!(self.parse_frame(&buffer[pos + length..]), Ok(Some(_)) | Err(ParseError::Truncated { .. })) {
+                pos += 1;
+                continue;
+            }
+            pos += length;
+            self.in_sync = true;
+            if let Some(header) = header {
+                self.push_frame(header, offset, pts);
+            }
+        }
+        self.buffer = buffer[pos..].to_vec();
+        errors
+    }
+
+    fn push_frame(&mut self, header: FrameHeader, offset: u64, pts: Option<u64>) {
+        if header.dependent {
+            return;
+        }
+        self.frames += 1;
+        self.duration += header.duration();
+        self.min_bitrate = self.min_bitrate.min(header.bitrate);
+        self.max_bitrate = self.max_bitrate.max(header.bitrate);
+        if let Some(dialnorm) = header.dialnorm {
+            *self.dialnorms.entry(dialnorm).or_insert(0) += 1;
+        }
+        if let Some(format) = &self.format {
+            if !format.same_format(&header) {
+                self.format_changes.push(FormatChange { offset, pts, from: format.clone(), to: header.clone() });
+            }
+        }
+        self.format = Some(header);
+    }
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[Audio] PID {:#X} (Program {}): {}", self.pid, self.program_number, self.codec)?;
+        let format = match &self.format {
+            Some(format) => format,
+            None => return write!(f, ", no frames found"),
+        };
+        write!(f, "\n\t=> {}", format)?;
+        if self.min_bitrate == self.max_bitrate {
+            write!(f, ", {} kbit/s", self.max_bitrate / 1000)?;
+        } else {
+            write!(f, ", {}-{} kbit/s", self.min_bitrate / 1000, self.max_bitrate / 1000)?;
+        }
+        write!(f, ", {:.3} ms frames", format.duration() * 1000.0)?;
+        if !self.dialnorms.is_empty() {
+            let dialnorms: Vec<String> = self.dialnorms.keys().map(|d| format!("-{} dB", d)).collect();
+            write!(f, ", Dialnorm: {}", dialnorms.join(", "))?;
+        }
+        write!(f, "\n\t=> Frames: {} ({:.3}s), Sync errors: {}", self.frames, self.duration, self.sync_errors)?;
+        for change in &self.format_changes {
+            write!(f, "\n\t=> {}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Follows the frames of the audio streams as a DemuxHandler, checking their sync and
+/// catching format changes (such as stereo to 5.1) the PMT doesn't announce
+#[derive(Clone, Debug, Default)]
+pub struct AudioAnalyzer {
+    streams: BTreeMap<u16, Stream>,
+    offset: u64,
+    errors: Vec<(u64, ParseError)>,
+}
+
+impl AudioAnalyzer {
+    pub fn new() -> AudioAnalyzer {
+        Default::default()
+    }
+
+    /// The audio streams found so far by PID
+    pub fn streams(&self) -> &BTreeMap<u16, Stream> {
+        &self.streams
+    }
+
+    /// The offset of every frame header that failed to parse (or the packet that completed
+    /// its PES packet), with why
+    pub fn errors(&self) -> &[(u64, ParseError)] {
+        &self.errors
+    }
+}
+
+impl DemuxHandler for AudioAnalyzer {
+    fn on_packet(&mut self, packet: &Packet) {
+        self.offset = packet.offset;
+    }
+
+    fn on_pmt(&mut self, _pid: u16, pmt: &Pmt) {
+        for es in pmt.elementary_streams.iter().filter(|es| Stream::is_supported(es.get_codec())) {
+            let pid = es.get_elementary_pid();
+            self.streams.entry(pid).or_insert_with(|| Stream::new(pid, pmt.get_program_number(), es.get_codec()));
+        }
+    }
+
+    fn on_pes(&mut self, pid: u16, pes: &Pes) {
+        if let Some(stream) = self.streams.get_mut(&pid) {
+            let offset = self.offset;
+            let errors = stream.push(&pes.payload, offset, pes.pts());
+            self.errors.extend(errors.into_iter().map(|e| (offset, e)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::BitWriter;
+
+    fn adts(channel_configuration: u64, length: u64) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        // MPEG-4 AAC LC at 48 kHz, no CRC, one raw_data_block
+        w.bits(12, 0xFFF).flag(false).bits(2, 0).flag(true).bits(2, 1).bits(4, 3).flag(false);
+        w.bits(3, channel_configuration).bits(4, 0).bits(13, length).bits(11, 0x7FF).bits(2, 0);
+        w.finish()
+    }
+
+    fn ac3(acmod: u64, lfeon: bool) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        // 48 kHz at 448 kbit/s, bsid 8
+        w.bits(16, 0x0B77).bits(16, 0).bits(2, 0).bits(6, 30).bits(5, 8).bits(3, 0).bits(3, acmod);
+        if acmod & 0x1 != 0 && acmod != 1 {
+            w.bits(2, 0);
+        }
+        if acmod & 0x4 != 0 {
+            w.bits(2, 0);
+        }
+        if acmod == 2 {
+            w.bits(2, 0);
+        }
+        w.flag(lfeon).bits(5, 27);
+        w.finish()
+    }
+
+    fn eac3(strmtyp: u64, numblkscod: u64, words: u64, acmod: u64, lfeon: bool) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.bits(16, 0x0B77).bits(2, strmtyp).bits(3, 0).bits(11, words - 1).bits(2, 0).bits(2, numblkscod);
+        w.bits(3, acmod).flag(lfeon).bits(5, 16).bits(5, 24);
+        w.finish()
+    }
+
+    /// An AudioSyncStream() frame with a StreamMuxConfig of one AAC frame
+    fn loas(audio_object_type: u64, length: u64) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.bits(11, 0x2B7).bits(13, length - 3).flag(false);
+        w.flag(false).flag(true).bits(6, 0).bits(4, 0).bits(3, 0).bits(5, audio_object_type);
+        if audio_object_type == 5 {
+            // 24 kHz core with SBR to 48 kHz
+            w.bits(4, 6).bits(4, 2).bits(4, 3).bits(5, 2);
+        } else {
+            w.bits(4, 3).bits(4, 2);
+        }
+        w.finish()
+    }
+
+    fn vectors() -> Vec<(Codec, Vec<u8>, &'static str, u32, usize)> {
+        vec![
+            (Codec::AacAdts, adts(2, 768), "AAC LC, 48000 Hz, Stereo (2 channels)", 288_000, 768),
+            (Codec::Mpeg1Audio, vec![0xFF, 0xFD, 0xA4, 0x00], "MPEG-1 Layer II, 48000 Hz, Stereo (2 channels)", 192_000, 576),
+            (Codec::Mpeg2Audio, vec![0xFF, 0xF3, 0x84, 0xC0], "MPEG-2 Layer III, 24000 Hz, Mono (1 channels)", 64_000, 192),
+            (Codec::Ac3, ac3(7, true), "AC-3, 48000 Hz, 3/2+LFE (6 channels)", 448_000, 1792),
+            (Codec::EAc3, eac3(0, 3, 384, 2, false), "E-AC-3, 48000 Hz, 2/0 (2 channels)", 192_000, 768),
+            (Codec::AacLatm, loas(2, 300), "AAC LC, 48000 Hz, Stereo (2 channels)", 112_500, 300),
+            (Codec::AacLatm, loas(5, 200), "HE-AAC, 48000 Hz, Stereo (2 channels)", 37_500, 200),
+        ]
+    }
+
+    #[test]
+    fn frame_headers() {
+        for (codec, buf, expected, bitrate, length) in vectors() {
+            let mut stream = Stream::new(0x100, 1, codec);
+            let (frame_length, header) = stream.parse_frame(&buf).unwrap().unwrap();
+            let header = header.unwrap();
+            assert_eq!(header.to_string(), expected);
+            assert_eq!((header.bitrate, header.length, frame_length), (bitrate, length, length), "{}", expected);
+        }
+
+        let header = FrameHeader::ac3(&ac3(7, true)).unwrap().unwrap();
+        assert_eq!((header.dialnorm, header.samples, header.duration()), (Some(27), 1536, 0.032));
+        // Neither an AC-3 syncword nor an MPEG audio layer
+        assert_eq!(FrameHeader::ac3(&[0x0B, 0x78, 0, 0, 0, 0]), Ok(None));
+        assert_eq!(FrameHeader::mpeg_audio(&[0xFF, 0xF9, 0xA4, 0x00]), Ok(None));
+        assert!(FrameHeader::adts(&adts(2, 6)).is_err());
+    }
+
+    #[test]
+    fn invalid_frame_headers() {
+        for (codec, buf, expected, _, _) in vectors() {
+            // Each header needs all its bytes
+            for len in 0..buf.len() {
+                let mut stream = Stream::new(0x100, 1, codec);
+                assert!(stream.parse_frame(&buf[..len]).is_err(), "{} bytes of {}", len, expected);
+            }
+        }
+        assert_eq!(FrameHeader::adts(&adts(2, 6)),
+            Err(ParseError::InvalidValue { pid: None, offset: 3, field: "aac_frame_length", value: 6 }));
+        let mut buf = adts(2, 768);
+        buf[2] = (buf[2] & 0xC3) | (13 << 2);
+        assert_eq!(FrameHeader::adts(&buf),
+            Err(ParseError::InvalidValue { pid: None, offset: 2, field: "sampling_frequency_index", value: 13 }));
+        // audioMuxVersionA is reserved
+        let mut w = BitWriter::default();
+        w.bits(11, 0x2B7).bits(13, 100).flag(false).flag(true).flag(true);
+        assert_eq!(Stream::new(0x100, 1, Codec::AacLatm).parse_frame(&w.finish()),
+            Err(ParseError::InvalidValue { pid: None, offset: 3, field: "audioMuxVersionA", value: 1 }));
+    }
+
+    /// `header` padded to a whole frame of `length` bytes
+    fn frame(header: Vec<u8>, length: usize) -> Vec<u8> {
+        let mut buf = header;
+        buf.resize(length, 0x00);
+        buf
+    }
+
+    #[test]
+    fn sync_and_format_change() {
+        let stereo = frame(ac3(2, false), 1792);
+        let surround = frame(ac3(7, true), 1792);
+        let mut stream = Stream::new(0x100, 1, Codec::Ac3);
+        let first = [stereo.clone(), stereo.clone(), stereo].concat();
+        assert_eq!(stream.push(&first, 0, Some(0)), vec![]);
+        assert_eq!((stream.frames, stream.sync_errors), (3, 0));
+
+        // Garbage before the 5.1 frames, the last of which continues in the next PES packet
+        let second = [vec![0x12; 5], surround.clone(), surround.clone(), surround[..1000].to_vec()].concat();
+        assert_eq!(stream.push(&second, 188, Some(90000)), vec![]);
+        assert_eq!((stream.frames, stream.sync_errors), (5, 1));
+        assert_eq!(stream.push(&surround[1000..], 376, Some(95760)), vec![]);
+
+        assert_eq!(stream.format_changes.len(), 1);
+        assert_eq!(stream.format_changes[0].to_string(), "Format change at offset 188 (PTS 1.000s): \
+            AC-3, 48000 Hz, 2/0 (2 channels) -> AC-3, 48000 Hz, 3/2+LFE (6 channels)");
+        assert_eq!(stream.to_string(), "[Audio] PID 0x100 (Program 1): AC-3\
+            \n\t=> AC-3, 48000 Hz, 3/2+LFE (6 channels), 448 kbit/s, 32.000 ms frames, Dialnorm: -27 dB\
+            \n\t=> Frames: 6 (0.192s), Sync errors: 1\
+            \n\t=> Format change at offset 188 (PTS 1.000s): AC-3, 48000 Hz, 2/0 (2 channels) -> \
+            AC-3, 48000 Hz, 3/2+LFE (6 channels)");
+    }
+
+    #[test]
+    fn eac3_bitrate() {
+        // The same bitrate from 6 and 2 audio blocks per frame
+        for (numblkscod, words, samples) in [(3, 768, 1536), (1, 256, 512)] {
+            let header = FrameHeader::ac3(&eac3(0, numblkscod, words, 7, true)).unwrap().unwrap();
+            assert_eq!((header.bitrate, header.samples, header.length), (384_000, samples, words as usize * 2));
+            assert_eq!(header.to_string(), "E-AC-3, 48000 Hz, 3/2+LFE (6 channels)");
+        }
+
+        // The dependent substreams (here the extra channels of 7.1) aren't frames of their own
+        let independent = frame(eac3(0, 3, 768, 7, true), 1536);
+        let dependent = frame(eac3(1, 3, 128, 2, false), 256);
+        let mut stream = Stream::new(0x100, 1, Codec::EAc3);
+        let buf = [independent.clone(), dependent.clone(), independent, dependent].concat();
+        assert_eq!(stream.push(&buf, 0, None), vec![]);
+        assert_eq!((stream.frames, stream.sync_errors, stream.min_bitrate, stream.max_bitrate), (2, 0, 384_000, 384_000));
+        assert_eq!(stream.format.as_ref().unwrap().channels, 6);
+    }
+
+    #[test]
+    fn adts_sync() {
+        // A frame claiming to be longer than it is loses the sync
+        let good = frame(adts(2, 400), 400);
+        let short = frame(adts(2, 400), 300);
+        let mut stream = Stream::new(0x100, 1, Codec::AacAdts);
+        let buf = [good.clone(), good.clone(), short, good.clone(), good.clone(), good].concat();
+        assert_eq!(stream.push(&buf, 0, None), vec![]);
+        assert_eq!((stream.frames, stream.sync_errors), (5, 1));
+        assert!((stream.duration - 5.0 * 1024.0 / 48000.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::error::ParseError;
+
+/// Reads the fields of a bitstream (e.g. the RBSP of a NAL unit) MSB first
+#[derive(Clone, Debug)]
+pub struct BitReader<'a> {
+    buf: &'a [u8],
+    /// Position in bits
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(buf: &'a [u8]) -> BitReader<'a> {
+        BitReader { buf, pos: 0 }
+    }
+
+    /// Byte offset of the next field
+    pub fn offset(&self) -> usize {
+        self.pos / 8
+    }
+
+    /// Number of bits left
+    pub fn remaining(&self) -> usize {
+        (self.buf.len() * 8).saturating_sub(self.pos)
+    }
+
+    pub fn is_byte_aligned(&self) -> bool {
+        self.pos.is_multiple_of(8)
+    }
+
+    /// Read `n` (at most 32) bits holding `field`
+    pub fn bits(&mut self, n: u32, field: &'static str) -> Result<u32, ParseError> {
+        if self.remaining() < n as usize {
+            return Err(ParseError::Truncated { pid: None, offset: self.offset(), field });
+        }
+        let mut value = 0u32;
+        for _ in 0..n {
+            let bit = (self.buf[self.pos / 8] >> (7 - self.pos % 8)) & 1;
+            value = (value << 1) | u32::from(bit);
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    pub fn flag(&mut self, field: &'static str) -> Result<bool, ParseError> {
+        Ok(self.bits(1, field)? == 1)
+    }
+
+    pub fn byte(&mut self, field: &'static str) -> Result<u8, ParseError> {
+        Ok(self.bits(8, field)? as u8)
+    }
+
+    pub fn skip(&mut self, n: usize, field: &'static str) -> Result<(), ParseError> {
+        if self.remaining() < n {
+            return Err(ParseError::Truncated { pid: None, offset: self.offset(), field });
+        }
+        self.pos += n;
+        Ok(())
+    }
+
+    /// Read an unsigned Exp-Golomb code ue(v) (at most 2^32 - 2)
+    pub fn ue(&mut self, field: &'static str) -> Result<u32, ParseError> {
+        let mut leading_zeros = 0;
+        while !self.flag(field)? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return Err(ParseError::InvalidValue { pid: None, offset: self.offset(), field, value: leading_zeros });
+            }
+        }
+        Ok(((1u64 << leading_zeros) - 1 + u64::from(self.bits(leading_zeros, field)?)) as u32)
+    }
+
+    /// Read a ue(v) the spec limits to at most `max`
+    pub fn ue_max(&mut self, max: u32, field: &'static str) -> Result<u32, ParseError> {
+        let offset = self.offset();
+        let value = self.ue(field)?;
+        if value > max {
+            return Err(ParseError::InvalidValue { pid: None, offset, field, value });
+        }
+        Ok(value)
+    }
+
+    /// Read a signed Exp-Golomb code se(v)
+    pub fn se(&mut self, field: &'static str) -> Result<i32, ParseError> {
+        let k = self.ue(field)?;
+        Ok(if k % 2 == 1 { (k / 2 + 1) as i32 } else { -((k / 2) as i32) })
+    }
+}
+
+/// Split a buffer into the units following each 00 00 01 start code (each starting with
+/// the byte after the start code, and running up to the next one)
+pub fn start_code_units(buf: &[u8]) -> Vec<&[u8]> {
+    let mut starts = vec![];
+    let mut i = 0;
+    while i + 3 <= buf.len() {
+        if buf[i] == 0 && buf[i + 1] == 0 && buf[i + 2] == 1 {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    starts.iter().enumerate().map(|(k, &start)| {
+        &buf[start..starts.get(k + 1).map_or(buf.len(), |next| next - 3)]
+    }).collect()
+}
+
+/// Split an Annex B byte stream (NAL units preceded by 00 00 01 start codes) into its NAL units
+pub fn annex_b_units(buf: &[u8]) -> Vec<&[u8]> {
+    start_code_units(buf).into_iter().filter_map(|unit| {
+        // The zero_byte of a 4 byte start code (and any trailing_zero_8bits) isn't part of the unit
+        let len = unit.iter().rposition(|b| *b != 0).map_or(0, |p| p + 1);
+        if len == 0 { None } else { Some(&unit[..len]) }
+    }).collect()
+}
+
+/// Remove the emulation_prevention_three_bytes of a NAL unit to get its RBSP
+pub fn rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &b in nal {
+        if zeros >= 2 && b == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        out.push(b);
+    }
+    out
+}
@@ -0,0 +1,932 @@
+use std::fmt;
+use std::io;
+use std::collections::BTreeMap;
+use crate::bits;
+use crate::codec::Codec;
+use crate::demux::DemuxHandler;
+use crate::{h264, hevc};
+use crate::mpeg2video::USER_DATA_START_CODE;
+use crate::pes::{Pes, PES_CLOCK_FREQUENCY};
+use crate::psi::pmt::Pmt;
+
+// Constants
+/// payloadType of the user_data_registered_itu_t_t35 SEI
+pub const SEI_USER_DATA_REGISTERED_ITU_T_T35: u32 = 4;
+/// itu_t_t35_country_code and itu_t_t35_provider_code of ATSC
+pub const ATSC_COUNTRY_CODE: u8 = 0xB5;
+pub const ATSC_PROVIDER_CODE: u16 = 0x0031;
+/// user_identifier and user_data_type_code of A/53 caption data
+pub const GA94_IDENTIFIER: &[u8; 4] = b"GA94";
+pub const CC_DATA_TYPE_CODE: u8 = 0x03;
+/// cc_type of the cc_data() constructs
+pub const CC_TYPE_NTSC_FIELD_1: u8 = 0;
+pub const CC_TYPE_NTSC_FIELD_2: u8 = 1;
+pub const CC_TYPE_DTVCC_DATA: u8 = 2;
+pub const CC_TYPE_DTVCC_START: u8 = 3;
+/// Number of pictures held back to put the caption data of B pictures in presentation order
+const REORDER_DEPTH: usize = 8;
+const CEA608_ROWS: usize = 15;
+const CEA608_COLUMNS: usize = 32;
+
+/// A valid cc_data construct: (cc_type, cc_data_1, cc_data_2)
+pub type CcTriplet = (u8, u8, u8);
+
+/// Parse the A/53 user data following its user_identifier ("GA94") into its valid cc_data
+/// constructs, None if it isn't caption data
+pub fn a53_cc_data(user_data: &[u8]) -> Option<Vec<CcTriplet>> {
+    if user_data.get(0..4)? != GA94_IDENTIFIER || *user_data.get(4)? != CC_DATA_TYPE_CODE {
+        return None;
+    }
+    let flags = *user_data.get(5)?;
+    // process_cc_data_flag
+    if flags & 0x40 == 0 {
+        return Some(vec![]);
+    }
+    let cc_count = usize::from(flags & 0x1F);
+    let constructs = user_data.get(7..7 + 3 * cc_count)?;
+    Some(constructs.chunks(3)
+        .filter(|c| c[0] & 0x04 != 0)
+        .map(|c| (c[0] & 0x03, c[1], c[2]))
+        .collect())
+}
+
+/// The caption data of a user_data_registered_itu_t_t35 SEI payload (H.264 and HEVC)
+pub fn t35_cc_data(payload: &[u8]) -> Option<Vec<CcTriplet>> {
+    let (country_code, rest) = payload.split_first()?;
+    if *country_code != ATSC_COUNTRY_CODE || rest.get(0..2)? != ATSC_PROVIDER_CODE.to_be_bytes() {
+        return None;
+    }
+    a53_cc_data(&rest[2..])
+}
+
+/// A caption channel of the stream
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Channel {
+    /// CEA-608 data channel CC1 to CC4
+    Cea608(u8),
+    /// CEA-708 caption service 1 to 63
+    Cea708(u8),
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Channel::Cea608(n) => write!(f, "CC{}", n),
+            Channel::Cea708(n) => write!(f, "Service {}", n),
+        }
+    }
+}
+
+/// A caption shown from `start` until `end` (PTS in 90kHz units)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cue {
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+}
+
+/// Format a PTS difference as hh:mm:ss with the milliseconds after `separator`
+fn timestamp(pts: u64, separator: char) -> String {
+    let ms = pts * 1000 / PES_CLOCK_FREQUENCY;
+    format!("{:02}:{:02}:{:02}{}{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, separator, ms % 1000)
+}
+
+/// Write cues as SubRip, timed from `base` (the first PTS of the video)
+pub fn write_srt<W: io::Write>(cues: &[Cue], base: u64, w: &mut W) -> io::Result<()> {
+    for (i, cue) in cues.iter().enumerate() {
+        writeln!(w, "{}", i + 1)?;
+        writeln!(w, "{} --> {}", timestamp(cue.start.saturating_sub(base), ','), timestamp(cue.end.saturating_sub(base), ','))?;
+        writeln!(w, "{}", cue.text)?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Write cues as WebVTT, timed from `base` (which the X-TIMESTAMP-MAP header maps back to the PTS)
+pub fn write_webvtt<W: io::Write>(cues: &[Cue], base: u64, w: &mut W) -> io::Result<()> {
+    writeln!(w, "WEBVTT")?;
+    writeln!(w, "X-TIMESTAMP-MAP=MPEGTS:{},LOCAL:00:00:00.000", base)?;
+    writeln!(w)?;
+    for cue in cues {
+        writeln!(w, "{} --> {}", timestamp(cue.start.saturating_sub(base), '.'), timestamp(cue.end.saturating_sub(base), '.'))?;
+        writeln!(w, "{}", cue.text.replace('&', "&amp;").replace('<', "&lt;"))?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Turns the text on screen into cues as it changes
+#[derive(Clone, Debug, Default)]
+struct CueBuilder {
+    /// The text shown since a PTS
+    shown: Option<(u64, String)>,
+    cues: Vec<Cue>,
+}
+
+impl CueBuilder {
+    fn show(&mut self, pts: u64, text: String) {
+        if self.shown.as_ref().map(|(_, shown)| shown) == Some(&text) {
+            return;
+        }
+        // Text replaced within the same picture never got on screen
+        if let Some((start, shown)) = self.shown.take().filter(|(start, _)| *start != pts) {
+            self.cues.push(Cue { start, end: pts, text: shown });
+        }
+        if !text.is_empty() {
+            self.shown = Some((pts, text));
+        }
+    }
+}
+
+/// The characters of the CEA-608 standard character set that differ from ASCII
+fn cea608_char(b: u8) -> char {
+    match b {
+        0x2A => 'á',
+        0x5C => 'é',
+        0x5E => 'í',
+        0x5F => 'ó',
+        0x60 => 'ú',
+        0x7B => 'ç',
+        0x7C => '÷',
+        0x7D => 'Ñ',
+        0x7E => 'ñ',
+        0x7F => '█',
+        _ => char::from(b),
+    }
+}
+
+/// The CEA-608 special (0x11 0x30 to 0x3F) and extended (0x12/0x13 0x20 to 0x3F) characters
+const CEA608_SPECIAL: [char; 16] = ['®', '°', '½', '¿', '™', '¢', '£', '♪', 'à', ' ', 'è', 'â', 'ê', 'î', 'ô', 'û'];
+const CEA608_EXTENDED: [[char; 32]; 2] = [
+    ['Á', 'É', 'Ó', 'Ú', 'Ü', 'ü', '‘', '¡', '*', '’', '—', '©', '℠', '•', '“', '”',
+     'À', 'Â', 'Ç', 'È', 'Ê', 'Ë', 'ë', 'Î', 'Ï', 'ï', 'Ô', 'Ù', 'ù', 'Û', '«', '»'],
+    ['Ã', 'ã', 'Í', 'Ì', 'ì', 'Ò', 'ò', 'Õ', 'õ', '{', '}', '\\', '^', '_', '|', '~',
+     'Ä', 'ä', 'Ö', 'ö', 'ß', '¥', '¤', '│', 'Å', 'å', 'Ø', 'ø', '┌', '┐', '└', '┘'],
+];
+
+/// How a CEA-608 channel puts captions on screen
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Cea608Mode {
+    PopOn,
+    /// With the number of rows
+    RollUp(usize),
+    PaintOn,
+}
+
+type Grid = Vec<Vec<char>>;
+
+fn empty_grid() -> Grid {
+    vec![vec![' '; CEA608_COLUMNS]; CEA608_ROWS]
+}
+
+fn grid_text(grid: &Grid) -> String {
+    grid.iter().map(|row| row.iter().collect::<String>().trim().to_string())
+        .filter(|row| !row.is_empty()).collect::<Vec<String>>().join("\n")
+}
+
+/// The screen of a CEA-608 data channel
+#[derive(Clone, Debug)]
+struct Cea608Channel {
+    mode: Cea608Mode,
+    displayed: Grid,
+    non_displayed: Grid,
+    row: usize,
+    column: usize,
+    cues: CueBuilder,
+}
+
+impl Cea608Channel {
+    fn new() -> Cea608Channel {
+        Cea608Channel {
+            mode: Cea608Mode::PopOn,
+            displayed: empty_grid(),
+            non_displayed: empty_grid(),
+            row: CEA608_ROWS - 1,
+            column: 0,
+            cues: Default::default(),
+        }
+    }
+
+    /// The memory characters go to
+    fn grid(&mut self) -> &mut Grid {
+        if self.mode == Cea608Mode::PopOn { &mut self.non_displayed } else { &mut self.displayed }
+    }
+
+    fn write(&mut self, c: char) {
+        let (row, column) = (self.row, self.column);
+        self.grid()[row][column] = c;
+        self.column = (column + 1).min(CEA608_COLUMNS - 1);
+    }
+
+    fn backspace(&mut self) {
+        self.column = self.column.saturating_sub(1);
+        let (row, column) = (self.row, self.column);
+        self.grid()[row][column] = ' ';
+    }
+
+    fn commit(&mut self, pts: u64) {
+        let text = grid_text(&self.displayed);
+        self.cues.show(pts, text);
+    }
+
+    /// Handle a miscellaneous control code (the second byte of 0x14/0x1C 0x20 to 0x2F)
+    fn control(&mut self, code: u8, pts: u64) {
+        match code {
+            0x20 => self.mode = Cea608Mode::PopOn,
+            0x21 => self.backspace(),
+            0x24 => {
+                let (row, column) = (self.row, self.column);
+                self.grid()[row][column..].iter_mut().for_each(|c| *c = ' ');
+            },
+            0x25..=0x27 => {
+                if !matches!(self.mode, Cea608Mode::RollUp(_)) {
+                    self.displayed = empty_grid();
+                    self.non_displayed = empty_grid();
+                    self.row = CEA608_ROWS - 1;
+                    self.commit(pts);
+                }
+                self.mode = Cea608Mode::RollUp(usize::from(code - 0x23));
+                self.column = 0;
+            },
+            0x29 => self.mode = Cea608Mode::PaintOn,
+            0x2C => {
+                self.displayed = empty_grid();
+                self.commit(pts);
+            },
+            0x2D => {
+                if let Cea608Mode::RollUp(rows) = self.mode {
+                    let top = (self.row + 1).saturating_sub(rows);
+                    for row in top..self.row {
+                        self.displayed[row] = self.displayed[row + 1].clone();
+                    }
+                    if top > 0 {
+                        self.displayed[top - 1] = vec![' '; CEA608_COLUMNS];
+                    }
+                    self.displayed[self.row] = vec![' '; CEA608_COLUMNS];
+                    self.commit(pts);
+                }
+                self.column = 0;
+            },
+            0x2E => self.non_displayed = empty_grid(),
+            0x2F => {
+                std::mem::swap(&mut self.displayed, &mut self.non_displayed);
+                self.mode = Cea608Mode::PopOn;
+                self.commit(pts);
+            },
+            _ => (),
+        }
+    }
+
+    /// Handle a preamble address code: move to its row (and indent)
+    fn preamble(&mut self, b1: u8, b2: u8) {
+        let row = match (b1 & 0x07, b2 & 0x20 != 0) {
+            (1, false) => 1,
+            (1, true) => 2,
+            (2, false) => 3,
+            (2, true) => 4,
+            (5, false) => 5,
+            (5, true) => 6,
+            (6, false) => 7,
+            (6, true) => 8,
+            (7, false) => 9,
+            (7, true) => 10,
+            (0, _) => 11,
+            (3, false) => 12,
+            (3, true) => 13,
+            (4, false) => 14,
+            _ => 15,
+        };
+        if let Cea608Mode::RollUp(rows) = self.mode {
+            // The roll-up window moves to the new base row
+            let row = (row - 1).max(rows - 1);
+            if row != self.row {
+                let old = std::mem::replace(&mut self.displayed, empty_grid());
+                for i in 0..rows {
+                    if let (Some(from), Some(to)) = (self.row.checked_sub(i), row.checked_sub(i)) {
+                        self.displayed[to] = old[from].clone();
+                    }
+                }
+            }
+            self.row = row;
+        } else {
+            self.row = row - 1;
+        }
+        self.column = if b2 & 0x10 != 0 { usize::from((b2 & 0x0E) >> 1) * 4 } else { 0 };
+    }
+}
+
+/// The state of a CEA-608 field: which data channel it's on, and whether it's in XDS data
+#[derive(Copy, Clone, Debug, Default)]
+struct Cea608Field {
+    /// 0 for CC1/CC3, 1 for CC2/CC4
+    data_channel: u8,
+    last_control: Option<(u8, u8)>,
+    xds: bool,
+}
+
+/// A CEA-708 window (only its text and visibility)
+#[derive(Clone, Debug, Default)]
+struct Window {
+    visible: bool,
+    row_count: usize,
+    rows: Vec<String>,
+}
+
+impl Window {
+    fn write(&mut self, c: char) {
+        if self.rows.is_empty() {
+            self.rows.push(String::new());
+        }
+        if let Some(row) = self.rows.last_mut() {
+            row.push(c);
+        }
+    }
+
+    fn carriage_return(&mut self) {
+        self.rows.push(String::new());
+        while self.rows.len() > self.row_count.max(1) {
+            self.rows.remove(0);
+        }
+    }
+}
+
+/// Number of parameter bytes of each C1 command (0x80 to 0x9F)
+const CEA708_C1_PARAMETERS: [usize; 32] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 0, 0,
+    2, 3, 2, 0, 0, 0, 0, 4, 6, 6, 6, 6, 6, 6, 6, 6];
+
+/// The characters of the CEA-708 G2 set (following EXT1) that have a counterpart
+fn cea708_g2_char(b: u8) -> Option<char> {
+    Some(match b {
+        0x20 | 0x21 => ' ',
+        0x25 => '…',
+        0x2A => 'Š',
+        0x2C => 'Œ',
+        0x30 => '█',
+        0x31 => '‘',
+        0x32 => '’',
+        0x33 => '“',
+        0x34 => '”',
+        0x35 => '•',
+        0x39 => '™',
+        0x3A => 'š',
+        0x3C => 'œ',
+        0x3D => '℠',
+        0x3F => 'Ÿ',
+        0x76 => '⅛',
+        0x77 => '⅜',
+        0x78 => '⅝',
+        0x79 => '⅞',
+        0x7A => '│',
+        0x7B => '┐',
+        0x7C => '└',
+        0x7D => '─',
+        0x7E => '┘',
+        0x7F => '┌',
+        _ => return None,
+    })
+}
+
+/// The windows of a CEA-708 caption service
+#[derive(Clone, Debug, Default)]
+struct Cea708Service {
+    windows: BTreeMap<u8, Window>,
+    current: u8,
+    cues: CueBuilder,
+}
+
+impl Cea708Service {
+    fn window(&mut self) -> &mut Window {
+        self.windows.entry(self.current).or_default()
+    }
+
+    fn commit(&mut self, pts: u64) {
+        let text = self.windows.values().filter(|w| w.visible)
+            .flat_map(|w| w.rows.iter().map(|row| row.trim().to_string()))
+            .filter(|row| !row.is_empty()).collect::<Vec<String>>().join("\n");
+        self.cues.show(pts, text);
+    }
+
+    /// Apply the windows in a bitmap parameter to `f`
+    fn each_window<F: FnMut(&mut Window)>(&mut self, bitmap: u8, mut f: F) {
+        for (id, window) in self.windows.iter_mut() {
+            if bitmap & (1 << id) != 0 {
+                f(window);
+            }
+        }
+    }
+
+    /// Decode a service block
+    fn push(&mut self, block: &[u8], pts: u64) {
+        let mut i = 0;
+        while i < block.len() {
+            let code = block[i];
+            i += 1;
+            match code {
+                0x03 => self.commit(pts),
+                0x08 => {
+                    if let Some(row) = self.window().rows.last_mut() {
+                        row.pop();
+                    }
+                },
+                0x0C => {
+                    self.window().rows.clear();
+                    self.commit(pts);
+                },
+                0x0D => {
+                    self.window().carriage_return();
+                    self.commit(pts);
+                },
+                0x0E => {
+                    if let Some(row) = self.window().rows.last_mut() {
+                        row.clear();
+                    }
+                },
+                0x10 => {
+                    // EXT1: the G2/G3 characters and the C2/C3 codes (skipped with their parameters)
+                    let ext = match block.get(i) {
+                        Some(ext) => *ext,
+                        None => break,
+                    };
+                    i += 1;
+                    match ext {
+                        0x00..=0x07 => (),
+                        0x08..=0x0F => i += 1,
+                        0x10..=0x17 => i += 2,
+                        0x18..=0x1F => i += 3,
+                        0x20..=0x7F => {
+                            if let Some(c) = cea708_g2_char(ext) {
+                                self.window().write(c);
+                            }
+                        },
+                        0x80..=0x87 => i += 4,
+                        0x88..=0x8F => i += 5,
+                        0x90..=0x9F => i += 1 + block.get(i).map_or(0, |b| usize::from(b & 0x3F)),
+                        _ => (),
+                    }
+                },
+                0x11..=0x17 => i += 1,
+                0x18..=0x1F => i += 2,
+                0x7F => self.window().write('♪'),
+                0x20..=0x7E => self.window().write(char::from(code)),
+                0x80..=0x9F => {
+                    let count = CEA708_C1_PARAMETERS[usize::from(code - 0x80)];
+                    let parameters = match block.get(i..i + count) {
+                        Some(parameters) => parameters,
+                        None => break,
+                    };
+                    i += count;
+                    self.command(code, parameters, pts);
+                },
+                0xA0..=0xFF => self.window().write(char::from(code)),
+                _ => (),
+            }
+        }
+    }
+
+    /// Handle a C1 window command
+    fn command(&mut self, code: u8, parameters: &[u8], pts: u64) {
+        let bitmap = parameters.first().copied().unwrap_or(0);
+        match code {
+            0x80..=0x87 => self.current = code - 0x80,
+            0x88 => self.each_window(bitmap, |w| w.rows.clear()),
+            0x89 => self.each_window(bitmap, |w| w.visible = true),
+            0x8A => self.each_window(bitmap, |w| w.visible = false),
+            0x8B => self.each_window(bitmap, |w| w.visible = !w.visible),
+            0x8C => {
+                for id in 0..8 {
+                    if bitmap & (1 << id) != 0 {
+                        self.windows.remove(&id);
+                    }
+                }
+            },
+            0x8F => self.windows.clear(),
+            0x98..=0x9F => {
+                // DefineWindow: visible is bit 5 of the first parameter, row_count-1 the low
+                // nibble of the fourth
+                self.current = code - 0x98;
+                let window = self.window();
+                window.visible = bitmap & 0x20 != 0;
+                window.row_count = usize::from(parameters[3] & 0x0F) + 1;
+            },
+            _ => return,
+        }
+        self.commit(pts);
+    }
+}
+
+/// The captions of a video stream
+#[derive(Clone, Debug)]
+pub struct Stream {
+    pub pid: u16,
+    pub program_number: u16,
+    pub codec: Codec,
+    /// The PTS of the first PES packet (the time the exported cues start from)
+    pub first_pts: Option<u64>,
+    /// Number of pictures with caption data
+    pub pictures_with_captions: u64,
+    last_pts: u64,
+    /// Caption data waiting for the pictures before it in presentation order
+    reorder: BTreeMap<u64, Vec<CcTriplet>>,
+    fields: [Cea608Field; 2],
+    cea608: BTreeMap<u8, Cea608Channel>,
+    cea708: BTreeMap<u8, Cea708Service>,
+    /// The DTVCC packet being assembled, with its size
+    dtvcc: Vec<u8>,
+    dtvcc_size: usize,
+}
+
+impl Stream {
+    fn new(pid: u16, program_number: u16, codec: Codec) -> Stream {
+        Stream {
+            pid,
+            program_number,
+            codec,
+            first_pts: None,
+            pictures_with_captions: 0,
+            last_pts: 0,
+            reorder: BTreeMap::new(),
+            fields: Default::default(),
+            cea608: BTreeMap::new(),
+            cea708: BTreeMap::new(),
+            dtvcc: vec![],
+            dtvcc_size: 0,
+        }
+    }
+
+    /// Whether captions can be taken from the video in `codec`
+    pub fn is_supported(codec: Codec) -> bool {
+        matches!(codec, Codec::Mpeg2Video | Codec::H264 | Codec::Hevc)
+    }
+
+    /// The caption data in the SEI or user data of a PES packet
+    fn cc_data(&self, payload: &[u8]) -> Vec<CcTriplet> {
+        let mut triplets = vec![];
+        for unit in bits::start_code_units(payload) {
+            let user_data = match (self.codec, unit.first()) {
+                (Codec::Mpeg2Video, Some(&USER_DATA_START_CODE)) => {
+                    triplets.extend(a53_cc_data(&unit[1..]).unwrap_or_default());
+                    continue;
+                },
+                (Codec::H264, Some(b)) if b & 0x1F == h264::NAL_SEI => bits::rbsp(&unit[1..]),
+                (Codec::Hevc, Some(b)) if (b >> 1) & 0x3F == hevc::NAL_PREFIX_SEI && unit.len() > 2 => bits::rbsp(&unit[2..]),
+                _ => continue,
+            };
+            for (payload_type, payload) in h264::sei_messages(&user_data) {
+                if payload_type == SEI_USER_DATA_REGISTERED_ITU_T_T35 {
+                    triplets.extend(t35_cc_data(payload).unwrap_or_default());
+                }
+            }
+        }
+        triplets
+    }
+
+    fn push(&mut self, pes: &Pes) {
+        let pts = pes.pts().unwrap_or(self.last_pts);
+        self.first_pts.get_or_insert(pts);
+        self.last_pts = self.last_pts.max(pts);
+        let triplets = self.cc_data(&pes.payload);
+        if triplets.is_empty() {
+            return;
+        }
+        self.pictures_with_captions += 1;
+        self.reorder.entry(pts).or_default().extend(triplets);
+        while self.reorder.len() > REORDER_DEPTH {
+            self.decode_first();
+        }
+    }
+
+    /// Decode the caption data of the earliest picture held back
+    fn decode_first(&mut self) {
+        let pts = match self.reorder.keys().next() {
+            Some(pts) => *pts,
+            None => return,
+        };
+        for (cc_type, b1, b2) in self.reorder.remove(&pts).unwrap_or_default() {
+            match cc_type {
+                CC_TYPE_NTSC_FIELD_1 | CC_TYPE_NTSC_FIELD_2 => self.push_cea608(cc_type, b1, b2, pts),
+                CC_TYPE_DTVCC_START => {
+                    self.push_dtvcc_packet(pts);
+                    self.dtvcc_size = match b1 & 0x3F { 0 => 128, size => usize::from(size) * 2 };
+                    self.dtvcc = vec![b1, b2];
+                },
+                _ => {
+                    if !self.dtvcc.is_empty() {
+                        self.dtvcc.extend_from_slice(&[b1, b2]);
+                    }
+                },
+            }
+            if !self.dtvcc.is_empty() && self.dtvcc.len() >= self.dtvcc_size {
+                self.push_dtvcc_packet(pts);
+            }
+        }
+    }
+
+    /// Handle a CEA-608 byte pair of a field
+    fn push_cea608(&mut self, field: u8, b1: u8, b2: u8, pts: u64) {
+        // Both bytes have odd parity
+        if b1.count_ones().is_multiple_of(2) || b2.count_ones().is_multiple_of(2) {
+            return;
+        }
+        let (b1, b2) = (b1 & 0x7F, b2 & 0x7F);
+        let state = &mut self.fields[usize::from(field)];
+        match b1 {
+            0x00 if b2 == 0x00 => return,
+            0x01..=0x0F => {
+                // XDS packets (field 2) run until their 0x0F end code
+                state.xds = b1 != 0x0F;
+                return;
+            },
+            0x10..=0x1F => {
+                state.xds = false;
+                // Control codes are sent twice, only the first one counts
+                if state.last_control.replace((b1, b2)) == Some((b1, b2)) {
+                    state.last_control = None;
+                    return;
+                }
+                state.data_channel = (b1 >> 3) & 0x01;
+            },
+            _ => {
+                state.last_control = None;
+                if state.xds {
+                    return;
+                }
+            },
+        }
+        let number = 1 + 2 * field + state.data_channel;
+        let channel = self.cea608.entry(number).or_insert_with(Cea608Channel::new);
+        match (b1 & 0x77, b2) {
+            // Field 2 sends them with 0x15/0x1D
+            (0x14, 0x20..=0x2F) | (0x15, 0x20..=0x2F) => channel.control(b2, pts),
+            (0x17, 0x21..=0x23) => channel.column = (channel.column + usize::from(b2 - 0x20)).min(CEA608_COLUMNS - 1),
+            // Mid-row style codes show as a space
+            (0x11, 0x20..=0x2F) => channel.write(' '),
+            (0x11, 0x30..=0x3F) => channel.write(CEA608_SPECIAL[usize::from(b2 - 0x30)]),
+            (0x12, 0x20..=0x3F) | (0x13, 0x20..=0x3F) => {
+                // Extended characters replace the standard one sent before them
+                channel.backspace();
+                channel.write(CEA608_EXTENDED[usize::from(b1 & 0x01)][usize::from(b2 - 0x20)]);
+            },
+            (0x10..=0x17, 0x40..=0x7F) => channel.preamble(b1, b2),
+            (0x10..=0x17, _) => (),
+            _ => {
+                channel.write(cea608_char(b1));
+                if b2 >= 0x20 {
+                    channel.write(cea608_char(b2));
+                }
+            },
+        }
+        if channel.mode == Cea608Mode::PaintOn && (0x10..=0x1F).contains(&b1) {
+            channel.commit(pts);
+        }
+    }
+
+    /// Decode the service blocks of the DTVCC packet assembled so far
+    fn push_dtvcc_packet(&mut self, pts: u64) {
+        let packet = std::mem::take(&mut self.dtvcc);
+        let mut i = 1;
+        while let Some(&header) = packet.get(i) {
+            let (mut service, size) = (header >> 5, usize::from(header & 0x1F));
+            i += 1;
+            if service == 0 || size == 0 {
+                break;
+            }
+            if service == 7 {
+                service = packet.get(i).map_or(0, |b| b & 0x3F);
+                i += 1;
+            }
+            let block = match packet.get(i..i + size) {
+                Some(block) => block,
+                None => break,
+            };
+            i += size;
+            self.cea708.entry(service).or_default().push(block, pts);
+        }
+    }
+
+    /// Decode the caption data held back and end the cues still shown
+    fn finish(&mut self) {
+        while !self.reorder.is_empty() {
+            self.decode_first();
+        }
+        self.push_dtvcc_packet(self.last_pts);
+        for channel in self.cea608.values_mut() {
+            channel.cues.show(self.last_pts, String::new());
+        }
+        for service in self.cea708.values_mut() {
+            service.cues.show(self.last_pts, String::new());
+        }
+    }
+
+    /// The cues of every channel with any
+    pub fn tracks(&self) -> BTreeMap<Channel, &[Cue]> {
+        let cea608 = self.cea608.iter().map(|(n, c)| (Channel::Cea608(*n), c.cues.cues.as_slice()));
+        let cea708 = self.cea708.iter().map(|(n, s)| (Channel::Cea708(*n), s.cues.cues.as_slice()));
+        cea608.chain(cea708).filter(|(_, cues)| !cues.is_empty()).collect()
+    }
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[CC] PID {:#X} (Program {}): {}, {} pictures with caption data", self.pid, self.program_number,
+            self.codec, self.pictures_with_captions)?;
+        let base = self.first_pts.unwrap_or(0);
+        for (channel, cues) in self.tracks() {
+            write!(f, "\n\t=> {}: {} cues", channel, cues.len())?;
+            for cue in cues.iter().take(3) {
+                write!(f, "\n\t\t{} --> {} {}", timestamp(cue.start.saturating_sub(base), '.'),
+                    timestamp(cue.end.saturating_sub(base), '.'), cue.text.replace('\n', " / "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Extracts the ATSC A/53 captions of the video streams as a DemuxHandler, decoding their
+/// CEA-608 channels and CEA-708 services into timed cues
+#[derive(Clone, Debug, Default)]
+pub struct CaptionExtractor {
+    streams: BTreeMap<u16, Stream>,
+}
+
+impl CaptionExtractor {
+    pub fn new() -> CaptionExtractor {
+        Default::default()
+    }
+
+    /// The video streams found so far by PID
+    pub fn streams(&self) -> &BTreeMap<u16, Stream> {
+        &self.streams
+    }
+}
+
+impl DemuxHandler for CaptionExtractor {
+    fn on_pmt(&mut self, _pid: u16, pmt: &Pmt) {
+        for es in pmt.elementary_streams.iter().filter(|es| Stream::is_supported(es.get_codec())) {
+            let pid = es.get_elementary_pid();
+            self.streams.entry(pid).or_insert_with(|| Stream::new(pid, pmt.get_program_number(), es.get_codec()));
+        }
+    }
+
+    fn on_pes(&mut self, pid: u16, pes: &Pes) {
+        if let Some(stream) = self.streams.get_mut(&pid) {
+            stream.push(pes);
+        }
+    }
+
+    fn on_end(&mut self) {
+        for stream in self.streams.values_mut() {
+            stream.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// A video PES packet with a PTS around `payload`
+    fn pes(pts: u64, payload: &[u8]) -> Pes {
+        let mut buf = vec![0x00, 0x00, 0x01, 0xE0, 0x00, 0x00, 0x80, 0x80, 0x05];
+        buf.extend_from_slice(&[
+            0x21 | ((pts >> 29) & 0x0E) as u8, (pts >> 22) as u8, 0x01 | ((pts >> 14) & 0xFE) as u8,
+            (pts >> 7) as u8, 0x01 | ((pts << 1) & 0xFE) as u8,
+        ]);
+        buf.extend_from_slice(payload);
+        Pes::new(&buf).unwrap()
+    }
+
+    /// The A/53 cc_data() of `triplets` following the GA94 user_identifier
+    fn ga94(triplets: &[CcTriplet]) -> Vec<u8> {
+        let mut buf = GA94_IDENTIFIER.to_vec();
+        buf.extend_from_slice(&[CC_DATA_TYPE_CODE, 0x40 | triplets.len() as u8, 0xFF]);
+        for (cc_type, b1, b2) in triplets {
+            buf.extend_from_slice(&[0xFC | cc_type, *b1, *b2]);
+        }
+        buf.push(0xFF);
+        buf
+    }
+
+    /// A CEA-608 byte pair on field 1 with its odd parity bits
+    fn cea608(b1: u8, b2: u8) -> CcTriplet {
+        let parity = |b: u8| if b.count_ones().is_multiple_of(2) { b | 0x80 } else { b };
+        (CC_TYPE_NTSC_FIELD_1, parity(b1), parity(b2))
+    }
+
+    /// The cc_data constructs of a DTVCC packet (sequence 0) carrying `block` for `service`
+    fn dtvcc(service: u8, block: &[u8]) -> Vec<CcTriplet> {
+        let mut packet = vec![0x00, (service << 5) | block.len() as u8];
+        packet.extend_from_slice(block);
+        if !packet.len().is_multiple_of(2) {
+            packet.push(0x00);
+        }
+        packet[0] = (packet.len() / 2) as u8;
+        packet.chunks(2).enumerate().map(|(i, pair)| {
+            (if i == 0 { CC_TYPE_DTVCC_START } else { CC_TYPE_DTVCC_DATA }, pair[0], pair[1])
+        }).collect()
+    }
+
+    #[test]
+    fn cc_data() {
+        let triplets = vec![(CC_TYPE_NTSC_FIELD_1, 0x94, 0x20), (CC_TYPE_DTVCC_START, 0x02, 0x21)];
+        assert_eq!(a53_cc_data(&ga94(&triplets)), Some(triplets.clone()));
+        // cc_valid cleared
+        let mut buf = ga94(&triplets);
+        buf[7] &= !0x04;
+        assert_eq!(a53_cc_data(&buf), Some(triplets[1..].to_vec()));
+        // process_cc_data_flag cleared
+        buf[5] &= !0x40;
+        assert_eq!(a53_cc_data(&buf), Some(vec![]));
+        assert_eq!(a53_cc_data(b"DTG1\x03"), None);
+        assert_eq!(a53_cc_data(&ga94(&triplets)[..12]), None);
+
+        let mut t35 = vec![ATSC_COUNTRY_CODE, 0x00, 0x31];
+        t35.extend(ga94(&triplets));
+        assert_eq!(t35_cc_data(&t35), Some(triplets));
+        t35[0] = 0x26;
+        assert_eq!(t35_cc_data(&t35), None);
+    }
+
+    #[test]
+    fn cea608_pop_on() {
+        let user_data = |triplets: &[CcTriplet]| [&[0x00, 0x00, 0x01, USER_DATA_START_CODE][..], &ga94(triplets)].concat();
+        let mut stream = Stream::new(0x100, 1, Codec::Mpeg2Video);
+        // Resume caption loading, row 15, "Hi", then end of caption (sent twice) and erase
+        // displayed memory, with the third picture sent before the second as a B picture would
+        stream.push(&pes(3003, &user_data(&[cea608(0x14, 0x20), cea608(0x14, 0x60)])));
+        stream.push(&pes(9009, &user_data(&[cea608(0x14, 0x2F), cea608(0x14, 0x2F)])));
+        stream.push(&pes(6006, &user_data(&[cea608(b'H', b'i')])));
+        stream.push(&pes(12012, &[]));
+        stream.push(&pes(18018, &user_data(&[cea608(0x14, 0x2C)])));
+        stream.finish();
+
+        assert_eq!((stream.first_pts, stream.pictures_with_captions), (Some(3003), 4));
+        let tracks = stream.tracks();
+        assert_eq!(tracks.keys().copied().collect::<Vec<Channel>>(), vec![Channel::Cea608(1)]);
+        assert_eq!(tracks[&Channel::Cea608(1)], [Cue { start: 9009, end: 18018, text: "Hi".to_string() }]);
+
+        let mut srt = vec![];
+        write_srt(tracks[&Channel::Cea608(1)], 3003, &mut srt).unwrap();
+        assert_eq!(String::from_utf8(srt).unwrap(), "1\n00:00:00,066 --> 00:00:00,166\nHi\n\n");
+        assert_eq!(stream.to_string(), "[CC] PID 0x100 (Program 1): MPEG-2 video, 4 pictures with caption data\
+            \n\t=> CC1: 1 cues\n\t\t00:00:00.066 --> 00:00:00.166 Hi");
+    }
+
+    #[test]
+    fn cea708_service() {
+        // The captions of H.264 are in user_data_registered_itu_t_t35 SEI messages
+        let sei = |triplets: &[CcTriplet]| {
+            let mut payload = vec![ATSC_COUNTRY_CODE, 0x00, 0x31];
+            payload.extend(ga94(triplets));
+            let mut rbsp = vec![SEI_USER_DATA_REGISTERED_ITU_T_T35 as u8, payload.len() as u8];
+            rbsp.extend(payload);
+            rbsp.push(0x80);
+            test_util::nal_unit(&[h264::NAL_SEI], &rbsp)
+        };
+        // DefineWindow 0 (visible, 2 rows), "Hi", carriage return, "there", ETX: only the
+        // last of the texts shown within the same picture makes a cue
+        let define = [0x98, 0x20, 0x00, 0x00, 0x01, 0x1F, 0x00];
+        let text = [&define[..], b"Hi\x0Dthere\x03"].concat();
+        let mut stream = Stream::new(0x100, 1, Codec::H264);
+        stream.push(&pes(0, &sei(&dtvcc(1, &text))));
+        // Another carriage return scrolls "Hi" out of the window
+        stream.push(&pes(90000, &sei(&dtvcc(1, b"\x0Dagain\x03"))));
+        // DeleteWindows 0
+        stream.push(&pes(180000, &sei(&dtvcc(1, &[0x8C, 0x01]))));
+        stream.finish();
+
+        let tracks = stream.tracks();
+        assert_eq!(tracks.keys().copied().collect::<Vec<Channel>>(), vec![Channel::Cea708(1)]);
+        assert_eq!(tracks[&Channel::Cea708(1)], [
+            Cue { start: 0, end: 90000, text: "Hi\nthere".to_string() },
+            Cue { start: 90000, end: 180000, text: "there\nagain".to_string() },
+        ]);
+
+        let mut vtt = vec![];
+        write_webvtt(tracks[&Channel::Cea708(1)], 0, &mut vtt).unwrap();
+        assert_eq!(String::from_utf8(vtt).unwrap(), "WEBVTT\nX-TIMESTAMP-MAP=MPEGTS:0,LOCAL:00:00:00.000\n\n\
+            00:00:00.000 --> 00:00:01.000\nHi\nthere\n\n00:00:01.000 --> 00:00:02.000\nthere\nagain\n\n");
+    }
+
+    #[test]
+    fn malformed_caption_data() {
+        let user_data = |triplets: &[CcTriplet]| [&[0x00, 0x00, 0x01, USER_DATA_START_CODE][..], &ga94(triplets)].concat();
+        // "Hi" sent without its parity bits is dropped
+        let mut stream = Stream::new(0x100, 1, Codec::Mpeg2Video);
+        stream.push(&pes(0, &user_data(&[cea608(0x14, 0x20), (CC_TYPE_NTSC_FIELD_1, b'H', b'i'), cea608(0x14, 0x2F)])));
+        stream.push(&pes(3003, &user_data(&[cea608(0x14, 0x2C)])));
+        stream.finish();
+        assert_eq!(stream.pictures_with_captions, 2);
+        assert!(stream.tracks().is_empty());
+
+        // A DTVCC packet announcing a 5 byte service block it doesn't have, then a good one
+        let define = [0x98, 0x20, 0x00, 0x00, 0x01, 0x1F, 0x00];
+        let mut triplets = vec![(CC_TYPE_DTVCC_START, 0x01, 0x25)];
+        triplets.extend(dtvcc(1, &[&define[..], b"Hi\x03"].concat()));
+        let mut stream = Stream::new(0x100, 1, Codec::Mpeg2Video);
+        stream.push(&pes(0, &user_data(&triplets)));
+        stream.push(&pes(90000, &user_data(&dtvcc(1, &[0x8C, 0x01]))));
+        stream.finish();
+        assert_eq!(stream.tracks()[&Channel::Cea708(1)], [Cue { start: 0, end: 90000, text: "Hi".to_string() }]);
+    }
+}
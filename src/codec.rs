@@ -0,0 +1,217 @@
+use std::fmt;
+use crate::psi::Descriptor;
+
+/// What an elementary stream is coded with, as signalled by its stream_type and descriptors
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Codec {
+    Mpeg1Video,
+    Mpeg2Video,
+    Mpeg4Visual,
+    H264,
+    Hevc,
+    Vvc,
+    Av1,
+    Vc1,
+    Jpeg2000,
+    Mpeg1Audio,
+    Mpeg2Audio,
+    AacAdts,
+    AacLatm,
+    /// AAC signalled by a DVB AAC_descriptor on a stream of PES private data
+    Aac,
+    Ac3,
+    EAc3,
+    Dts,
+    MpegH3dAudio,
+    Opus,
+    /// SMPTE 302M (AES3) audio
+    Smpte302m,
+    Teletext,
+    DvbSubtitles,
+    Scte35,
+    Id3,
+    Klv,
+    PrivateSections,
+    DsmCc,
+    /// Anything else, with its stream_type
+    Unknown(u8),
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Codec::Mpeg1Video => "MPEG-1 video",
+            Codec::Mpeg2Video => "MPEG-2 video",
+            Codec::Mpeg4Visual => "MPEG-4 Visual",
+            Codec::H264 => "H.264/AVC",
+            Codec::Hevc => "H.265/HEVC",
+            Codec::Vvc => "H.266/VVC",
+            Codec::Av1 => "AV1",
+            Codec::Vc1 => "VC-1",
+            Codec::Jpeg2000 => "JPEG 2000",
+            Codec::Mpeg1Audio => "MPEG-1 audio",
+            Codec::Mpeg2Audio => "MPEG-2 audio",
+            Codec::AacAdts => "AAC (ADTS)",
+            Codec::AacLatm => "AAC (LATM)",
+            Codec::Aac => "AAC",
+            Codec::Ac3 => "AC-3",
+            Codec::EAc3 => "E-AC-3",
+            Codec::Dts => "DTS",
+            Codec::MpegH3dAudio => "MPEG-H 3D Audio",
+            Codec::Opus => "Opus",
+            Codec::Smpte302m => "SMPTE 302M",
+            Codec::Teletext => "Teletext",
+            Codec::DvbSubtitles => "DVB subtitles",
+            Codec::Scte35 => "SCTE-35",
+            Codec::Id3 => "ID3",
+            Codec::Klv => "KLV",
+            Codec::PrivateSections => "Private sections",
+            Codec::DsmCc => "DSM-CC",
+            Codec::Unknown(stream_type) => return write!(f, "Unknown (stream_type {:#X})", stream_type),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Codec {
+    /// Work out the codec of a stream from its stream_type, then (when that doesn't settle
+    /// it: private sections, PES private data, metadata and the unassigned user private types)
+    /// its DVB and metadata descriptors, then its registration descriptor
+    pub fn new(stream_type: u8, descriptors: &[Descriptor]) -> Codec {
+        let codec = Codec::from_stream_type(stream_type);
+        if !matches!(codec, Codec::Unknown(_) | Codec::PrivateSections) {
+            return codec;
+        }
+        descriptors.iter().find_map(Codec::from_descriptor)
+            .or_else(|| descriptors.iter().find_map(|d| match d {
+                Descriptor::Registration { format_identifier, .. } => Codec::from_format_identifier(format_identifier),
+                _ => None,
+            }))
+            .unwrap_or(codec)
+    }
+
+    /// The codec the stream_type alone says the stream has (Unknown for PES private data,
+    /// metadata and the user private types other than the ATSC/SCTE ones)
+    pub fn from_stream_type(stream_type: u8) -> Codec {
+        match stream_type {
+            0x01 => Codec::Mpeg1Video,
+            0x02 => Codec::Mpeg2Video,
+            0x03 => Codec::Mpeg1Audio,
+            0x04 => Codec::Mpeg2Audio,
+            0x05 => Codec::PrivateSections,
+            0x0A..=0x0D => Codec::DsmCc,
+            0x0F => Codec::AacAdts,
+            0x10 => Codec::Mpeg4Visual,
+            0x11 => Codec::AacLatm,
+            0x1B => Codec::H264,
+            0x21 => Codec::Jpeg2000,
+            0x24 => Codec::Hevc,
+            0x2D => Codec::MpegH3dAudio,
+            0x33 => Codec::Vvc,
+            0x81 => Codec::Ac3,
+            0x86 => Codec::Scte35,
+            0x87 => Codec::EAc3,
+            0xEA => Codec::Vc1,
+            _ => Codec::Unknown(stream_type),
+        }
+    }
+
+    /// The codec a DVB or metadata descriptor signals, if it signals one
+    fn from_descriptor(descriptor: &Descriptor) -> Option<Codec> {
+        match descriptor {
+            // How ID3 timed metadata (e.g. in HLS) is signalled on stream_type 0x15
+            Descriptor::Metadata { metadata_format_identifier, .. } if metadata_format_identifier == &Some(*b"ID3 ") =>
+                Some(Codec::Id3),
+            Descriptor::Ac3(_) => Some(Codec::Ac3),
+            Descriptor::EnhancedAc3(_) => Some(Codec::EAc3),
+            Descriptor::Dts(_) => Some(Codec::Dts),
+            Descriptor::Aac(_) => Some(Codec::Aac),
+            Descriptor::Teletext(_) => Some(Codec::Teletext),
+            Descriptor::Subtitling(_) => Some(Codec::DvbSubtitles),
+            _ => None,
+        }
+    }
+
+    /// The codec a format_identifier registered with SMPTE-RA stands for, if known
+    pub fn from_format_identifier(format_identifier: &[u8; 4]) -> Option<Codec> {
+        match format_identifier {
+            b"AC-3" => Some(Codec::Ac3),
+            b"EAC3" => Some(Codec::EAc3),
+            b"DTS1" | b"DTS2" | b"DTS3" => Some(Codec::Dts),
+            b"Opus" => Some(Codec::Opus),
+            b"BSSD" => Some(Codec::Smpte302m),
+            b"HEVC" => Some(Codec::Hevc),
+            b"AV01" => Some(Codec::Av1),
+            b"VC-1" => Some(Codec::Vc1),
+            b"CUEI" => Some(Codec::Scte35),
+            b"ID3 " => Some(Codec::Id3),
+            b"KLVA" => Some(Codec::Klv),
+            _ => None,
+        }
+    }
+
+    pub fn is_video(&self) -> bool {
+        matches!(self, Codec::Mpeg1Video | Codec::Mpeg2Video | Codec::Mpeg4Visual | Codec::H264 |
+            Codec::Hevc | Codec::Vvc | Codec::Av1 | Codec::Vc1 | Codec::Jpeg2000)
+    }
+
+    pub fn is_audio(&self) -> bool {
+        matches!(self, Codec::Mpeg1Audio | Codec::Mpeg2Audio | Codec::AacAdts | Codec::AacLatm |
+            Codec::Aac | Codec::Ac3 | Codec::EAc3 | Codec::Dts | Codec::MpegH3dAudio | Codec::Opus |
+            Codec::Smpte302m)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registration(format_identifier: &[u8; 4]) -> Descriptor {
+        Descriptor::Registration { format_identifier: *format_identifier, additional_identification_info: vec![] }
+    }
+
+    fn metadata(metadata_format_identifier: &[u8; 4]) -> Descriptor {
+        Descriptor::Metadata {
+            metadata_application_format: 0xFFFF,
+            metadata_format: 0xFF,
+            metadata_format_identifier: Some(*metadata_format_identifier),
+        }
+    }
+
+    fn ac3() -> Descriptor {
+        Descriptor::new(0x6A, &[0x00]).unwrap()
+    }
+
+    #[test]
+    fn stream_types() {
+        for (stream_type, codec) in [(0x02, Codec::Mpeg2Video), (0x0F, Codec::AacAdts), (0x1B, Codec::H264),
+            (0x24, Codec::Hevc), (0x81, Codec::Ac3), (0x86, Codec::Scte35), (0x06, Codec::Unknown(0x06))] {
+            assert_eq!(Codec::new(stream_type, &[]), codec, "stream_type {:#X}", stream_type);
+        }
+        assert!(Codec::H264.is_video() && !Codec::H264.is_audio());
+        assert!(Codec::Ac3.is_audio() && !Codec::Scte35.is_audio());
+    }
+
+    #[test]
+    fn precedence() {
+        // PES private data is settled by a DVB descriptor, before the registration descriptor
+        assert_eq!(Codec::new(0x06, &[ac3()]), Codec::Ac3);
+        assert_eq!(Codec::new(0x06, &[registration(b"EAC3"), ac3()]), Codec::Ac3);
+        assert_eq!(Codec::new(0x06, &[registration(b"Opus")]), Codec::Opus);
+        assert_eq!(Codec::new(0x06, &[registration(b"ABCD")]), Codec::Unknown(0x06));
+        // An assigned stream_type isn't overridden by the descriptors
+        assert_eq!(Codec::new(0x1B, &[registration(b"HEVC"), ac3()]), Codec::H264);
+        // Private sections are only refined by the descriptors
+        assert_eq!(Codec::new(0x05, &[registration(b"CUEI")]), Codec::Scte35);
+        assert_eq!(Codec::new(0x05, &[]), Codec::PrivateSections);
+    }
+
+    #[test]
+    fn id3_metadata() {
+        assert_eq!(Codec::new(0x15, &[metadata(b"ID3 ")]), Codec::Id3);
+        assert_eq!(Codec::new(0x15, &[metadata(b"KLVA")]), Codec::Unknown(0x15));
+        assert_eq!(Codec::new(0x15, &[registration(b"ID3 ")]), Codec::Id3);
+        assert_eq!(Codec::new(0x15, &[]), Codec::Unknown(0x15));
+    }
+}
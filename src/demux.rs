@@ -0,0 +1,591 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::{PidRole, PidState, error::ParseError};
+use crate::reader::{PacketFormat, PacketReader, ReadEvent, SyncStats};
+use crate::packet::{Packet, NULL_PACKET_PID};
+use crate::pes::Pes;
+use crate::psi::{Psi, section, psip::Psip, cat::Cat, eit::Eit, nit::Nit, pat::Pat, pmt::Pmt, sdt::Sdt, tdt::{Tdt, Tot}};
+
+/// PID of the NIT unless the PAT gives another one
+const DEFAULT_NETWORK_PID: u16 = 0x0010;
+
+/// Callbacks the Demuxer invokes as it works through the stream.
+/// Every method has an empty default so a handler only implements what it needs.
+pub trait DemuxHandler {
+    /// Every packet that parsed, before any of its payload is handled
+    fn on_packet(&mut self, _packet: &Packet) {}
+    /// A packet whose continuity_counter isn't the `expected` one
+    fn on_continuity_error(&mut self, _packet: &Packet, _expected: u8) {}
+    /// Every complete section on a PSI PID, whether or not the table is understood
+    fn on_section(&mut self, _pid: u16, _table_id: u8, _section: &[u8]) {}
+    /// A new or changed PAT section
+    fn on_pat(&mut self, _pat: &Pat) {}
+    /// A new or changed CAT section
+    fn on_cat(&mut self, _cat: &Cat) {}
+    /// A new or changed PMT section
+    fn on_pmt(&mut self, _pid: u16, _pmt: &Pmt) {}
+    /// A new or changed NIT section (of this or another network)
+    fn on_nit(&mut self, _pid: u16, _nit: &Nit) {}
+    /// A new or changed SDT section (of this or another transport stream)
+    fn on_sdt(&mut self, _pid: u16, _sdt: &Sdt) {}
+    /// A new or changed EIT section (present/following or schedule)
+    fn on_eit(&mut self, _pid: u16, _eit: &Eit) {}
+    /// Every TDT
+    fn on_tdt(&mut self, _pid: u16, _tdt: &Tdt) {}
+    /// Every TOT
+    fn on_tot(&mut self, _pid: u16, _tot: &Tot) {}
+    /// A new or changed ATSC PSIP table (every STT)
+    fn on_psip(&mut self, _pid: u16, _psip: &Psip) {}
+    /// A complete PES packet on an elementary stream PID
+    fn on_pes(&mut self, _pid: u16, _pes: &Pes) {}
+    /// A packet, section or PES packet starting at stream `offset` failed to parse
+    fn on_error(&mut self, _offset: u64, _error: &ParseError) {}
+    /// Sync was lost at stream `offset`
+    fn on_sync_lost(&mut self, _offset: u64) {}
+    /// Sync was (re)acquired at stream `offset` after skipping `skipped` bytes
+    fn on_sync_acquired(&mut self, _offset: u64, _skipped: u64) {}
+    /// The end of the stream, after the last PES packets were handed out
+    fn on_end(&mut self) {}
+}
+
+/// Registered handlers along with the PID they are interested in (None for all PIDs)
+#[derive(Default)]
+struct Handlers<'a>(Vec<(Option<u16>, &'a mut dyn DemuxHandler)>);
+
+impl<'a> Handlers<'a> {
+    /// Call `f` on every handler interested in `pid`.
+    /// Events without a PID only go to the handlers that listen to all PIDs
+    fn each<F: FnMut(&mut dyn DemuxHandler)>(&mut self, pid: Option<u16>, mut f: F) {
+        for (filter, handler) in self.0.iter_mut() {
+            if filter.is_none() || *filter == pid {
+                f(&mut **handler);
+            }
+        }
+    }
+}
+
+/// Demultiplexes a transport stream fed in arbitrary chunks.
+///
+/// The PAT, CAT and PMTs are tracked internally: PMT PIDs (and the network PID) are
+/// learnt from the current PAT, EMM PIDs from the CAT and elementary stream PIDs from
+/// the current PMTs.
+/// The names of the programs are taken from the SDT of the stream, or from the ATSC
+/// VCT along with their channel numbers, whose EIT/ETT PIDs come from the MGT. Sections
+/// (of the PSI PIDs, of the section streams of the PMTs such as SCTE-35 and of any PID
+/// added with `add_section_pid`) and PES packets are reassembled per PID and handed to
+/// the registered handlers.
+#[derive(Default)]
+pub struct Demuxer<'a> {
+    pid_states: HashMap<u16, PidState>,
+    /// The sections of the current PAT by section_number
+    pat_sections: BTreeMap<u8, Pat>,
+    /// The current PMT of each program_number
+    pmts: BTreeMap<u16, Pmt>,
+    pmt_pids: HashSet<u16>,
+    es_pids: HashSet<u16>,
+    emm_pids: HashSet<u16>,
+    /// Network PID given by the PAT
+    network_pid: Option<u16>,
+    /// PMT PID of each program_number
+    programs: BTreeMap<u16, u16>,
+    /// Name of each service_id (i.e. program_number) of this transport stream
+    service_names: HashMap<u16, String>,
+    /// ATSC virtual channel number of each program_number of this transport stream
+    channel_numbers: HashMap<u16, String>,
+    /// PIDs of the ATSC EITs and ETTs given by the MGT
+    psip_pids: HashSet<u16>,
+    /// Section streams of the PMTs (e.g. SCTE-35)
+    pmt_section_pids: HashSet<u16>,
+    /// Additional PIDs whose sections should be assembled
+    section_pids: HashSet<u16>,
+    handlers: Handlers<'a>,
+    reader: PacketReader,
+}
+
+impl<'a> Demuxer<'a> {
+    pub fn new() -> Demuxer<'a> {
+        Default::default()
+    }
+
+    /// Register a handler for the events of every PID
+    pub fn add_handler(&mut self, handler: &'a mut dyn DemuxHandler) {
+        self.handlers.0.push((None, handler));
+    }
+
+    /// Register a handler for the events of a single PID
+    pub fn add_pid_handler(&mut self, pid: u16, handler: &'a mut dyn DemuxHandler) {
+        self.handlers.0.push((Some(pid), handler));
+    }
+
+    /// Assemble the sections of `pid` (e.g. a private table) and pass them to `on_section`
+    pub fn add_section_pid(&mut self, pid: u16) {
+        self.section_pids.insert(pid);
+    }
+
+    pub fn pid_states(&self) -> &HashMap<u16, PidState> {
+        &self.pid_states
+    }
+
+    pub fn pmt_pids(&self) -> &HashSet<u16> {
+        &self.pmt_pids
+    }
+
+    pub fn es_pids(&self) -> &HashSet<u16> {
+        &self.es_pids
+    }
+
+    pub fn emm_pids(&self) -> &HashSet<u16> {
+        &self.emm_pids
+    }
+
+    /// The PMT PID of each program_number in the PAT
+    pub fn programs(&self) -> &BTreeMap<u16, u16> {
+        &self.programs
+    }
+
+    /// The name the SDT (or the ATSC VCT) gives the program, if any
+    pub fn program_name(&self, program_number: u16) -> Option<&str> {
+        self.service_names.get(&program_number).map(|s| s.as_str())
+    }
+
+    /// The virtual channel number (e.g. 7.1) the ATSC VCT gives the program, if any
+    pub fn program_channel(&self, program_number: u16) -> Option<&str> {
+        self.channel_numbers.get(&program_number).map(|s| s.as_str())
+    }
+
+    /// The PID of the NIT: the one given by the PAT or else the DVB default
+    pub fn network_pid(&self) -> u16 {
+        self.network_pid.unwrap_or(DEFAULT_NETWORK_PID)
+    }
+
+    /// What the PID carries, as far as the tables seen so far tell
+    pub fn pid_role(&self, pid: u16) -> Option<PidRole> {
+        if Psi::is_pat(&pid) {
+            Some(PidRole::Pat)
+        } else if Psi::is_cat(&pid) {
+            Some(PidRole::Cat)
+        } else if pid == NULL_PACKET_PID {
+            Some(PidRole::Null)
+        } else if self.pmt_pids.contains(&pid) {
+            Some(PidRole::Pmt)
+        } else if self.es_pids.contains(&pid) {
+            // A PMT can claim the fixed SI PIDs for one of its streams
+            Some(PidRole::Elementary)
+        } else if pid == self.network_pid() {
+            Some(PidRole::Nit)
+        } else if Psi::is_sdt(&pid) {
+            Some(PidRole::Sdt)
+        } else if Psi::is_eit(&pid) {
+            Some(PidRole::Eit)
+        } else if Psi::is_tdt(&pid) {
+            Some(PidRole::Tdt)
+        } else if Psip::is_base_pid(&pid) || self.psip_pids.contains(&pid) {
+            Some(PidRole::Psip)
+        } else if self.emm_pids.contains(&pid) {
+            Some(PidRole::Emm)
+        } else {
+            None
+        }
+    }
+
+    pub fn sync_stats(&self) -> &SyncStats {
+        self.reader.stats()
+    }
+
+    /// The detected packet format (None until sync is first acquired)
+    pub fn packet_format(&self) -> Option<PacketFormat> {
+        self.reader.format()
+    }
+
+    /// Feed the next chunk of the stream; chunks don't have to be packet aligned
+    pub fn push(&mut self, data: &[u8]) {
+        self.reader.push(data);
+        self.read_packets();
+    }
+
+    /// Signal the end of the stream: handles the last packets and hands out
+    /// the PES packets still being assembled
+    pub fn finish(&mut self) {
+        self.reader.finish();
+        self.read_packets();
+
+        let handlers = &mut self.handlers;
+        for (pid, state) in self.pid_states.iter_mut() {
+            let offset = state.prev_packet.offset;
+            match state.pes.flush() {
+                Some(Ok(pes)) => handlers.each(Some(*pid), |h| h.on_pes(*pid, &pes)),
+                Some(Err(e)) => {
+                    state.errors.parse_errors += 1;
+                    let e = e.with_pid(*pid);
+                    handlers.each(Some(*pid), |h| h.on_error(offset, &e));
+                },
+                None => (),
+            }
+        }
+        self.handlers.each(None, |h| h.on_end());
+    }
+
+    /// Handle every packet (and sync change) the reader can give out
+    fn read_packets(&mut self) {
+        while let Some(event) = self.reader.next_event() {
+            match event {
+                ReadEvent::Packet { offset, data, extra_header } => match Packet::new(&data) {
+                    Ok(mut packet) => {
+                        packet.offset = offset;
+                        packet.tp_extra_header = extra_header;
+                        self.handle_packet(&packet);
+                    },
+                    Err(e) => self.handlers.each(e.pid(), |h| h.on_error(offset, &e)),
+                },
+                ReadEvent::SyncLost { offset } =>
+                    self.handlers.each(None, |h| h.on_sync_lost(offset)),
+                ReadEvent::SyncAcquired { offset, skipped, .. } =>
+                    self.handlers.each(None, |h| h.on_sync_acquired(offset, skipped)),
+            }
+        }
+    }
+
+    /// Update the counts and errors of the packet's PidState and hand out
+    /// any section or PES packet it completes
+    fn handle_packet(&mut self, packet: &Packet) {
+        let pid = packet.pid;
+        self.handlers.each(Some(pid), |h| h.on_packet(packet));
+
+        // Get or create the state
+        let created = !self.pid_states.contains_key(&pid);
+        let role = self.pid_role(pid);
+        let network_pid = self.network_pid();
+        let s = self.pid_states.entry(pid).or_insert_with(|| PidState {
+            prev_packet: packet.clone(),
+            role,
+            ..Default::default()
+        });
+
+        // Update
+        let is_dup = !created && *packet == s.prev_packet;
+        let last_cc = s.prev_packet.continuity_counter;
+        s.count += 1;
+        s.duplicate_count = if is_dup { s.duplicate_count + 1 } else { 0 };
+
+        // Check for continuity errors
+        let cc_error = !created && pid != NULL_PACKET_PID &&
+            packet.has_continuity_error(last_cc, s.duplicate_count, is_dup);
+        if cc_error {
+            s.errors.cc_errors += 1;
+            let expected = (last_cc + 1) & 0x0F;
+            self.handlers.each(Some(pid), |h| h.on_continuity_error(packet, expected));
+        }
+        s.prev_packet = packet.clone();
+
+        // Duplicates carry no new data
+        if is_dup {
+            return;
+        }
+        let is_psi = matches!(role, Some(PidRole::Pat | PidRole::Cat | PidRole::Pmt | PidRole::Nit |
+            PidRole::Sdt | PidRole::Eit | PidRole::Tdt | PidRole::Psip));
+        let mut roles_changed = false;
+        let is_section_stream = self.pmt_section_pids.contains(&pid) || self.section_pids.contains(&pid);
+        if is_psi || is_section_stream {
+            if cc_error {
+                s.sections.reset();
+            }
+            for section in s.sections.push(packet) {
+                self.handlers.each(Some(pid), |h| h.on_section(pid, section[0], &section));
+                if !is_psi {
+                    continue;
+                }
+                match Psi::new(&section, &pid, &self.pmt_pids, network_pid, &self.psip_pids) {
+                    Ok(psi) => {
+                        // Check for crc errors (and don't trust the table if there is one)
+                        if psi.get_crc_error() {
+                            s.errors.crc_errors += 1;
+                            continue;
+                        }
+                        // Only hand out tables that are new according to their crc (the TDT
+                        // has none, and is new every time anyway)
+                        let prev_crc = s.psi_crcs.insert(section::section_key(&section), psi.get_crc());
+                        if prev_crc == Some(psi.get_crc()) && !matches!(psi, Psi::Tdt(_)) {
+                            continue;
+                        }
+                        match &psi {
+                            Psi::Pat(pat) => {
+                                // A new version replaces all the sections of the previous one
+                                if self.pat_sections.values().any(|p| p.get_version_number() != pat.get_version_number()) {
+                                    self.pat_sections.clear();
+                                }
+                                self.pat_sections.insert(pat.get_section_number(), pat.clone());
+                                self.handlers.each(Some(pid), |h| h.on_pat(pat));
+                            },
+                            Psi::Cat(cat) => {
+                                self.emm_pids.extend(&cat.get_emm_pids());
+                                self.handlers.each(Some(pid), |h| h.on_cat(cat));
+                            },
+                            Psi::Pmt(pmt) => {
+                                self.pmts.insert(pmt.get_program_number(), pmt.clone());
+                                self.handlers.each(Some(pid), |h| h.on_pmt(pid, pmt));
+                            },
+                            Psi::Nit(nit) => self.handlers.each(Some(pid), |h| h.on_nit(pid, nit)),
+                            Psi::Sdt(sdt) => {
+                                if sdt.is_actual() {
+                                    self.service_names.extend(sdt.services.iter().filter_map(|s| {
+                                        s.service_name.clone().map(|name| (s.service_id, name))
+                                    }));
+                                }
+                                self.handlers.each(Some(pid), |h| h.on_sdt(pid, sdt));
+                            },
+                            Psi::Eit(eit) => self.handlers.each(Some(pid), |h| h.on_eit(pid, eit)),
+                            // The time tables don't change what the PIDs are
+                            Psi::Tdt(tdt) => {
+                                self.handlers.each(Some(pid), |h| h.on_tdt(pid, tdt));
+                                continue;
+                            },
+                            Psi::Tot(tot) => {
+                                self.handlers.each(Some(pid), |h| h.on_tot(pid, tot));
+                                continue;
+                            },
+                            Psi::Psip(psip) => {
+                                match psip {
+                                    Psip::Mgt(mgt) => self.psip_pids.extend(mgt.get_event_pids()),
+                                    Psip::Vct(vct) => {
+                                        let tsid = vct.get_transport_stream_id();
+                                        for c in vct.channels.iter().filter(|c| c.channel_tsid == tsid) {
+                                            self.channel_numbers.insert(c.program_number, c.channel_number());
+                                            self.service_names.insert(c.program_number, c.short_name.clone());
+                                        }
+                                    },
+                                    _ => (),
+                                }
+                                self.handlers.each(Some(pid), |h| h.on_psip(pid, psip));
+                            },
+                        }
+                        roles_changed = true;
+                    },
+                    // Other tables on the SI PIDs are only handed out as sections
+                    Err(ParseError::UnknownTable { .. }) => (),
+                    Err(e) => {
+                        s.errors.parse_errors += 1;
+                        self.handlers.each(Some(pid), |h| h.on_error(packet.offset, &e));
+                    },
+                }
+            }
+        }
+
+        // Collect the payloads of elementary streams into PES packets
+        if self.es_pids.contains(&pid) && !is_section_stream {
+            if cc_error {
+                s.pes.reset();
+            }
+            for result in s.pes.push(packet) {
+                match result {
+                    Ok(pes) => self.handlers.each(Some(pid), |h| h.on_pes(pid, &pes)),
+                    Err(e) => {
+                        s.errors.parse_errors += 1;
+                        let e = e.with_pid(pid);
+                        self.handlers.each(Some(pid), |h| h.on_error(packet.offset, &e));
+                    },
+                }
+            }
+        }
+
+        if roles_changed {
+            self.update_pids();
+            self.update_roles();
+        }
+    }
+
+    /// Rebuild the PIDs learnt from the current PAT and PMTs, forgetting the ones a new
+    /// version of a table no longer lists
+    fn update_pids(&mut self) {
+        self.programs = self.pat_sections.values().flat_map(|pat| pat.get_programs()).collect();
+        self.network_pid = self.pat_sections.values().find_map(|pat| pat.get_network_pid());
+        self.pmt_pids = self.programs.values().copied().collect();
+        let programs = &self.programs;
+        self.pmts.retain(|program_number, _| programs.contains_key(program_number));
+        self.es_pids = self.pmts.values().flat_map(|pmt| pmt.get_es_pids()).collect();
+        // Streams of sections (e.g. SCTE-35 cues) are handed out as sections
+        self.pmt_section_pids = self.pmts.values()
+            .flat_map(|pmt| pmt.elementary_streams.iter())
+            .filter(|es| es.carries_sections())
+            .map(|es| es.get_elementary_pid())
+            .collect();
+    }
+
+    /// Set the role of every PID from the tables seen so far
+    fn update_roles(&mut self) {
+        let roles: Vec<_> = self.pid_states.keys().map(|pid| (*pid, self.pid_role(*pid))).collect();
+        for (pid, role) in roles {
+            if let Some(s) = self.pid_states.get_mut(&pid) {
+                s.role = role;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// Records the events it gets as strings
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl DemuxHandler for Recorder {
+        fn on_section(&mut self, pid: u16, table_id: u8, _section: &[u8]) {
+            self.0.push(format!("section {:#X} {:#X}", pid, table_id));
+        }
+        fn on_pat(&mut self, pat: &Pat) {
+            self.0.push(format!("pat {:?}", pat.get_programs()));
+        }
+        fn on_pmt(&mut self, pid: u16, pmt: &Pmt) {
+            self.0.push(format!("pmt {:#X} {:?}", pid, pmt.elementary_streams.iter().map(|es| es.get_elementary_pid()).collect::<Vec<u16>>()));
+        }
+        fn on_pes(&mut self, pid: u16, pes: &Pes) {
+            self.0.push(format!("pes {:#X} {:?}", pid, pes.payload));
+        }
+        fn on_error(&mut self, _offset: u64, error: &ParseError) {
+            self.0.push(format!("error {}", error));
+        }
+    }
+
+    /// Packetises sections and PES packets, keeping the continuity_counter of each PID
+    #[derive(Default)]
+    struct Muxer(HashMap<u16, u8>);
+
+    impl Muxer {
+        fn packet(&mut self, pid: u16, unit_start: bool, payload: &[u8]) -> Packet {
+            let cc = self.0.entry(pid).or_insert(0);
+            *cc = (*cc + 1) & 0x0F;
+            Packet::new(&test_util::packet(pid, unit_start, *cc, None, payload)).unwrap()
+        }
+
+        fn section(&mut self, pid: u16, section: &[u8]) -> Packet {
+            self.packet(pid, true, &[&[0x00], section].concat())
+        }
+
+        /// A bounded PES packet with no optional header fields
+        fn pes(&mut self, pid: u16, payload: &[u8]) -> Packet {
+            let mut buf = vec![0x00, 0x00, 0x01, 0xE0, 0x00, 3 + payload.len() as u8, 0x80, 0x00, 0x00];
+            buf.extend_from_slice(payload);
+            self.packet(pid, true, &buf)
+        }
+    }
+
+    /// A PAT of `version` listing (program_number, PMT PID)
+    fn pat(version: u8, programs: &[(u16, u16)]) -> Vec<u8> {
+        let body: Vec<u8> = programs.iter().flat_map(|(n, pid)| [(n >> 8) as u8, *n as u8, 0xE0 | (pid >> 8) as u8, *pid as u8]).collect();
+        with_version(test_util::section(0x00, 1, &body), version)
+    }
+
+    /// A PMT of `version` listing (stream_type, elementary_PID), with its PCR on the first
+    fn pmt(program_number: u16, version: u8, streams: &[(u8, u16)]) -> Vec<u8> {
+        let mut body = vec![0xE0 | (streams[0].1 >> 8) as u8, streams[0].1 as u8, 0xF0, 0x00];
+        for (stream_type, pid) in streams {
+            body.extend_from_slice(&[*stream_type, 0xE0 | (pid >> 8) as u8, *pid as u8, 0xF0, 0x00]);
+        }
+        with_version(test_util::section(0x02, program_number, &body), version)
+    }
+
+    fn with_version(mut section: Vec<u8>, version: u8) -> Vec<u8> {
+        section.truncate(section.len() - 4);
+        section[5] = 0xC1 | (version << 1);
+        test_util::with_crc(section)
+    }
+
+    #[test]
+    fn pat_pmt_pes() {
+        let mut recorder = Recorder::default();
+        let mut mux = Muxer::default();
+        let mut demux = Demuxer::new();
+        demux.add_handler(&mut recorder);
+        // PES packets before the PMT lists their PID aren't assembled
+        demux.handle_packet(&mux.pes(0x101, &[1]));
+        demux.handle_packet(&mux.section(0x00, &pat(0, &[(0, 0x10), (1, 0x100)])));
+        demux.handle_packet(&mux.section(0x100, &pmt(1, 0, &[(0x1B, 0x101), (0x86, 0x102)])));
+        demux.handle_packet(&mux.pes(0x101, &[2, 3]));
+        // The SCTE-35 stream is handed out as sections (this one doesn't parse as a PES packet)
+        demux.handle_packet(&mux.section(0x102, &test_util::section(0xFC, 0, &[])));
+        // The same PAT again is not handed out again
+        demux.handle_packet(&mux.section(0x00, &pat(0, &[(0, 0x10), (1, 0x100)])));
+
+        assert_eq!(demux.programs(), &[(1, 0x100)].iter().copied().collect());
+        assert_eq!(demux.network_pid(), 0x10);
+        let roles: Vec<_> = [0x00, 0x10, 0x100, 0x101, 0x102, 0x103].iter().map(|pid| demux.pid_role(*pid)).collect();
+        assert_eq!(roles, vec![Some(PidRole::Pat), Some(PidRole::Nit), Some(PidRole::Pmt), Some(PidRole::Elementary),
+            Some(PidRole::Elementary), None]);
+        assert_eq!(demux.pid_states()[&0x101].role, Some(PidRole::Elementary));
+        drop(demux);
+        assert_eq!(recorder.0, vec![
+            "section 0x0 0x0", "pat [(1, 256)]",
+            "section 0x100 0x2", "pmt 0x100 [257, 258]",
+            "pes 0x101 [2, 3]",
+            "section 0x102 0xFC",
+            "section 0x0 0x0",
+        ]);
+    }
+
+    #[test]
+    fn elementary_streams_on_si_pids() {
+        let mut recorder = Recorder::default();
+        let mut mux = Muxer::default();
+        let mut demux = Demuxer::new();
+        demux.add_pid_handler(0x11, &mut recorder);
+        // A PMT giving the SDT PID to a video stream: its PES packets aren't taken for sections
+        demux.handle_packet(&mux.section(0x00, &pat(0, &[(1, 0x100)])));
+        demux.handle_packet(&mux.section(0x100, &pmt(1, 0, &[(0x1B, 0x11)])));
+        demux.handle_packet(&mux.pes(0x11, &[0x42]));
+        assert_eq!(demux.pid_role(0x11), Some(PidRole::Elementary));
+        assert_eq!(demux.pid_states()[&0x11].errors.parse_errors, 0);
+        drop(demux);
+        assert_eq!(recorder.0, vec!["pes 0x11 [66]"]);
+    }
+
+    #[test]
+    fn version_change() {
+        let mut recorder = Recorder::default();
+        let mut mux = Muxer::default();
+        let mut demux = Demuxer::new();
+        demux.add_handler(&mut recorder);
+        demux.handle_packet(&mux.section(0x00, &pat(0, &[(1, 0x100), (2, 0x200)])));
+        demux.handle_packet(&mux.section(0x100, &pmt(1, 0, &[(0x1B, 0x101), (0x86, 0x102)])));
+        demux.handle_packet(&mux.section(0x200, &pmt(2, 0, &[(0x1B, 0x201)])));
+        assert_eq!(demux.es_pids(), &[0x101, 0x102, 0x201].iter().copied().collect());
+
+        // Program 2 goes, and program 1 swaps its SCTE-35 stream for an audio one on the same PID
+        demux.handle_packet(&mux.section(0x00, &pat(1, &[(1, 0x100)])));
+        demux.handle_packet(&mux.section(0x100, &pmt(1, 1, &[(0x1B, 0x101), (0x0F, 0x102)])));
+        assert_eq!(demux.pmt_pids(), &[0x100].iter().copied().collect());
+        assert_eq!(demux.es_pids(), &[0x101, 0x102].iter().copied().collect());
+        assert_eq!(demux.programs(), &[(1, 0x100)].iter().copied().collect());
+        assert_eq!((demux.pid_role(0x200), demux.pid_role(0x201)), (None, None));
+
+        // Neither the old PMT PID nor the old stream are parsed any more, and the former
+        // section stream is assembled into PES packets
+        demux.handle_packet(&mux.section(0x200, &pmt(2, 1, &[(0x1B, 0x202)])));
+        demux.handle_packet(&mux.pes(0x201, &[5]));
+        demux.handle_packet(&mux.pes(0x102, &[4]));
+        assert_eq!(demux.pid_role(0x202), None);
+        drop(demux);
+        assert_eq!(recorder.0[6..], [
+            "section 0x0 0x0", "pat [(1, 256)]",
+            "section 0x100 0x2", "pmt 0x100 [257, 258]",
+            "pes 0x102 [4]",
+        ]);
+    }
+
+    #[test]
+    fn program_names() {
+        let sdt = |table_id: u8, name: &[u8]| {
+            let mut body = vec![0x00, 0x02, 0xFF, 0x00, 0x01, 0xFF, 0x80, 5 + name.len() as u8, 0x48, 3 + name.len() as u8, 0x01, 0x00, name.len() as u8];
+            body.extend_from_slice(name);
+            test_util::section(table_id, 1, &body)
+        };
+        let mut mux = Muxer::default();
+        let mut demux = Demuxer::new();
+        demux.handle_packet(&mux.section(0x00, &pat(0, &[(1, 0x100)])));
+        demux.handle_packet(&mux.section(0x11, &sdt(0x42, b"One")));
+        // The SDT of another transport stream doesn't name this one's programs
+        demux.handle_packet(&mux.section(0x11, &sdt(0x46, b"Other")));
+        assert_eq!(demux.program_name(1), Some("One"));
+        assert_eq!(demux.program_name(2), None);
+    }
+}
@@ -0,0 +1,258 @@
+use std::fmt;
+use std::io;
+use std::collections::{BTreeMap, HashMap};
+use crate::demux::DemuxHandler;
+use crate::psi::{eit::{Eit, Event}, sdt::Sdt};
+
+/// The DVB triplet identifying a service
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct ChannelId {
+    pub original_network_id: u16,
+    pub transport_stream_id: u16,
+    pub service_id: u16,
+}
+
+impl fmt::Display for ChannelId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.original_network_id, self.transport_stream_id, self.service_id)
+    }
+}
+
+/// Collects the events of the EITs (present/following and schedule) into a
+/// programme guide as a DemuxHandler, naming the channels from the SDTs
+#[derive(Clone, Debug, Default)]
+pub struct Guide {
+    /// Name of each channel, once an SDT gives one
+    channels: BTreeMap<ChannelId, Option<String>>,
+    /// Latest version of each event
+    events: HashMap<(ChannelId, u16), Event>,
+}
+
+impl Guide {
+    pub fn new() -> Guide {
+        Default::default()
+    }
+
+    /// Every channel with its name (if known)
+    pub fn channels(&self) -> &BTreeMap<ChannelId, Option<String>> {
+        &self.channels
+    }
+
+    /// The events of `channel` in order of their start time
+    pub fn events(&self, channel: &ChannelId) -> Vec<&Event> {
+        let mut events: Vec<&Event> = self.events.iter()
+            .filter(|((id, _), _)| id == channel)
+            .map(|(_, e)| e)
+            .collect();
+        events.sort_by_key(|e| (e.start_time, e.event_id));
+        events
+    }
+
+    /// Write the guide as an XMLTV document
+    pub fn write_xmltv<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(w, "<!DOCTYPE tv SYSTEM \"xmltv.dtd\">")?;
+        writeln!(w, "<tv generator-info-name=\"mpeg_parser\">")?;
+        for (id, name) in &self.channels {
+            writeln!(w, "  <channel id=\"{}\">", id)?;
+            writeln!(w, "    <display-name>{}</display-name>", escape(name.as_deref().unwrap_or(&id.to_string())))?;
+            writeln!(w, "  </channel>")?;
+        }
+        for id in self.channels.keys() {
+            for event in self.events(id) {
+                let (start, end) = match (event.start_time, event.end_time()) {
+                    (Some(start), Some(end)) => (start, end),
+                    _ => continue,
+                };
+                let lang = event.language.as_deref().map_or(String::new(), |l| format!(" lang=\"{}\"", escape(l)));
+                writeln!(w, "  <programme start=\"{}\" stop=\"{}\" channel=\"{}\">", start.xmltv(), end.xmltv(), id)?;
+                writeln!(w, "    <title{}>{}</title>", lang, escape(event.title.as_deref().unwrap_or("")))?;
+                // The short text is usually a subtitle when there is a longer description
+                let desc = match (&event.short_text, &event.extended_text) {
+                    (Some(short), Some(extended)) if !short.is_empty() => {
+                        writeln!(w, "    <sub-title{}>{}</sub-title>", lang, escape(short))?;
+                        Some(extended)
+                    },
+                    (_, Some(extended)) => Some(extended),
+                    (Some(short), None) => Some(short),
+                    (None, None) => None,
+                };
+                if let Some(desc) = desc.filter(|d| !d.is_empty()) {
+                    writeln!(w, "    <desc{}>{}</desc>", lang, escape(desc))?;
+                }
+                for content in &event.content {
+                    writeln!(w, "    <category lang=\"en\">{}</category>", escape(content.genre()))?;
+                }
+                for rating in &event.parental_ratings {
+                    if let Some(age) = rating.minimum_age() {
+                        writeln!(w, "    <rating system=\"{}\">", escape(&rating.country_code))?;
+                        writeln!(w, "      <value>{}</value>", age)?;
+                        writeln!(w, "    </rating>")?;
+                    }
+                }
+                writeln!(w, "  </programme>")?;
+            }
+        }
+        writeln!(w, "</tv>")
+    }
+}
+
+/// Escape the characters XML gives a meaning to, and drop the ones XML 1.0 doesn't
+/// allow at all (the C0 controls other than tab, LF and CR, U+FFFE and U+FFFF) that
+/// the DVB character tables can still decode to
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1F}' | '\u{FFFE}' | '\u{FFFF}' => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl DemuxHandler for Guide {
+    fn on_sdt(&mut self, _pid: u16, sdt: &Sdt) {
+        for service in &sdt.services {
+            let id = ChannelId {
+                original_network_id: sdt.get_original_network_id(),
+                transport_stream_id: sdt.get_transport_stream_id(),
+                service_id: service.service_id,
+            };
+            let name = self.channels.entry(id).or_insert(None);
+            if service.service_name.is_some() {
+                *name = service.service_name.clone();
+            }
+        }
+    }
+
+    fn on_eit(&mut self, _pid: u16, eit: &Eit) {
+        let id = ChannelId {
+            original_network_id: eit.get_original_network_id(),
+            transport_stream_id: eit.get_transport_stream_id(),
+            service_id: eit.get_service_id(),
+        };
+        self.channels.entry(id).or_insert(None);
+        for event in &eit.events {
+            self.events.insert((id, event.event_id), event.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    const NEWS: ChannelId = ChannelId { original_network_id: 2, transport_stream_id: 1, service_id: 0x10 };
+    const UNNAMED: ChannelId = ChannelId { original_network_id: 2, transport_stream_id: 1, service_id: 0x12 };
+
+    /// An SDT of transport stream 1 and original network 2 naming service 0x10
+    fn sdt(name: &[u8]) -> Sdt {
+        let mut service_descriptor = vec![0x01, 0x00, name.len() as u8];
+        service_descriptor.extend_from_slice(name);
+        let descriptors = test_util::descriptor(0x48, &service_descriptor);
+        let mut body = vec![0x00, 0x02, 0xFF, 0x00, 0x10, 0xFF, 0x80, descriptors.len() as u8];
+        body.extend(descriptors);
+        Sdt::new(&test_util::section(0x42, 1, &body)).unwrap()
+    }
+
+    /// A schedule EIT of `service_id` with one event of an hour starting at `hour`
+    /// on 2024-01-15 (or with no start time)
+    fn eit(service_id: u16, event_id: u16, hour: Option<u8>, descriptors: &[u8]) -> Eit {
+        let mut body = vec![0x00, 0x01, 0x00, 0x02, 0x00, 0x50];
+        body.extend_from_slice(&event_id.to_be_bytes());
+        match hour {
+            Some(hour) => body.extend_from_slice(&[0xEB, 0xA4, hour, 0x00, 0x00]),
+            None => body.extend_from_slice(&[0xFF; 5]),
+        }
+        body.extend_from_slice(&[0x01, 0x00, 0x00, 0x80, descriptors.len() as u8]);
+        body.extend_from_slice(descriptors);
+        Eit::new(&test_util::section(0x50, service_id, &body)).unwrap()
+    }
+
+    fn short_event(title: &[u8], text: &[u8]) -> Vec<u8> {
+        let mut body = b"eng".to_vec();
+        body.push(title.len() as u8);
+        body.extend_from_slice(title);
+        body.push(text.len() as u8);
+        body.extend_from_slice(text);
+        test_util::descriptor(0x4D, &body)
+    }
+
+    fn xmltv(guide: &Guide) -> String {
+        let mut buf = vec![];
+        guide.write_xmltv(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn guide() {
+        let mut guide = Guide::new();
+        guide.on_eit(0x12, &eit(0x10, 2, Some(0x21), &short_event(b"Late", b"")));
+        let mut descriptors = short_event(b"Early", b"Part 1");
+        descriptors.extend(test_util::descriptor(0x4E, b"\x00eng\x00\x0BWhole story"));
+        descriptors.extend(test_util::descriptor(0x54, &[0x20, 0x00]));
+        descriptors.extend(test_util::descriptor(0x55, b"GBR\x09FRA\x20"));
+        guide.on_eit(0x12, &eit(0x10, 1, Some(0x20), &descriptors));
+        // Events without a start time can't be placed in the guide
+        guide.on_eit(0x12, &eit(0x10, 3, None, &short_event(b"Whenever", b"")));
+        guide.on_eit(0x12, &eit(0x12, 1, Some(0x20), &short_event(b"Film", b"A film")));
+        // Channels keep the last name they were given, and events their last version
+        guide.on_sdt(0x11, &sdt(b"Old"));
+        guide.on_sdt(0x11, &sdt(b"News"));
+        guide.on_eit(0x12, &eit(0x10, 2, Some(0x21), &short_event(b"Later", b"")));
+
+        assert_eq!(guide.channels().iter().map(|(id, name)| (*id, name.as_deref())).collect::<Vec<_>>(),
+            vec![(NEWS, Some("News")), (UNNAMED, None)]);
+        let titles: Vec<_> = guide.events(&NEWS).iter().map(|e| e.title.as_deref()).collect();
+        assert_eq!(titles, vec![Some("Whenever"), Some("Early"), Some("Later")]);
+        assert_eq!(xmltv(&guide), "\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE tv SYSTEM \"xmltv.dtd\">
+<tv generator-info-name=\"mpeg_parser\">
+  <channel id=\"2.1.16\">
+    <display-name>News</display-name>
+  </channel>
+  <channel id=\"2.1.18\">
+    <display-name>2.1.18</display-name>
+  </channel>
+  <programme start=\"20240115200000 +0000\" stop=\"20240115210000 +0000\" channel=\"2.1.16\">
+    <title lang=\"eng\">Early</title>
+    <sub-title lang=\"eng\">Part 1</sub-title>
+    <desc lang=\"eng\">Whole story</desc>
+    <category lang=\"en\">News/Current affairs</category>
+    <rating system=\"GBR\">
+      <value>12</value>
+    </rating>
+  </programme>
+  <programme start=\"20240115210000 +0000\" stop=\"20240115220000 +0000\" channel=\"2.1.16\">
+    <title lang=\"eng\">Later</title>
+  </programme>
+  <programme start=\"20240115200000 +0000\" stop=\"20240115210000 +0000\" channel=\"2.1.18\">
+    <title lang=\"eng\">Film</title>
+    <desc lang=\"eng\">A film</desc>
+  </programme>
+</tv>
+");
+    }
+
+    #[test]
+    fn escaping() {
+        let mut guide = Guide::new();
+        guide.on_sdt(0x11, &sdt(b"<Tom & Jerry's>"));
+        // Control characters that XML 1.0 doesn't allow, around a line break it does
+        guide.on_eit(0x12, &eit(0x10, 1, Some(0x20), &short_event(b"\"Quoted\"\x1B\x01", b"One\x8ATwo\x00")));
+        let xmltv = xmltv(&guide);
+        assert!(xmltv.contains("<display-name>&lt;Tom &amp; Jerry&apos;s&gt;</display-name>"), "{}", xmltv);
+        assert!(xmltv.contains("<title lang=\"eng\">&quot;Quoted&quot;</title>"), "{}", xmltv);
+        assert!(xmltv.contains("<desc lang=\"eng\">One\nTwo</desc>"), "{}", xmltv);
+        assert_eq!(escape("a\tb\rc\u{FFFE}d\u{FFFF}"), "a\tb\rcd");
+    }
+}
@@ -0,0 +1,123 @@
+use std::{error, fmt};
+
+/// Describes why a packet, section or PES packet could not be parsed.
+///
+/// `pid` is filled in by whoever knows which PID the buffer came from (parsers of
+/// sections and PES packets only see the bytes) and `offset` is the byte offset of
+/// the offending field from the start of the packet, section or PES packet.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// The packet buffer is not exactly one transport packet long
+    PacketSize { len: usize },
+    /// The packet does not start with the sync byte
+    SyncByte { found: u8 },
+    /// The buffer ends before `field` could be read
+    Truncated { pid: Option<u16>, offset: usize, field: &'static str },
+    /// `field` holds a value the spec does not allow
+    InvalidValue { pid: Option<u16>, offset: usize, field: &'static str, value: u32 },
+    /// A section was given to the parser of a different table
+    TableId { pid: Option<u16>, expected: u8, found: u8 },
+    /// The PID does not carry any table this crate knows how to parse
+    UnknownPid { pid: u16 },
+    /// The PID carries tables this crate can parse, but not ones with this table_id
+    UnknownTable { pid: u16, table_id: u8 },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(pid) = self.pid() {
+            write!(f, "PID {:#X}: ", pid)?;
+        }
+        match self {
+            ParseError::PacketSize { len } =>
+                write!(f, "packet is {} bytes, expected {}", len, crate::packet::PACKET_SIZE),
+            ParseError::SyncByte { found } =>
+                write!(f, "sync byte is {:#X}, expected {:#X}", found, crate::packet::SYNC_BYTE_VAL),
+            ParseError::Truncated { offset, field, .. } =>
+                write!(f, "buffer ends before {} at offset {}", field, offset),
+            ParseError::InvalidValue { offset, field, value, .. } =>
+                write!(f, "invalid {} {:#X} at offset {}", field, value, offset),
+            ParseError::TableId { expected, found, .. } =>
+                write!(f, "table_id is {:#X}, expected {:#X}", found, expected),
+            ParseError::UnknownPid { .. } => write!(f, "no table parser for this PID"),
+            ParseError::UnknownTable { table_id, .. } =>
+                write!(f, "no parser for table_id {:#X} on this PID", table_id),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl ParseError {
+    /// The PID of the packet that failed to parse, if known
+    pub fn pid(&self) -> Option<u16> {
+        match self {
+            ParseError::Truncated { pid, .. } |
+            ParseError::InvalidValue { pid, .. } |
+            ParseError::TableId { pid, .. } => *pid,
+            ParseError::UnknownPid { pid } | ParseError::UnknownTable { pid, .. } => Some(*pid),
+            ParseError::PacketSize { .. } | ParseError::SyncByte { .. } => None,
+        }
+    }
+
+    /// Attach the PID the failing buffer came from (unless one is already set)
+    pub fn with_pid(mut self, new_pid: u16) -> ParseError {
+        match &mut self {
+            ParseError::Truncated { pid, .. } |
+            ParseError::InvalidValue { pid, .. } |
+            ParseError::TableId { pid, .. } => {
+                pid.get_or_insert(new_pid);
+            },
+            _ => (),
+        }
+        self
+    }
+
+    /// Make the offset relative to an enclosing buffer in which the failing one starts at `base`
+    pub(crate) fn shift(mut self, base: usize) -> ParseError {
+        match &mut self {
+            ParseError::Truncated { offset, .. } |
+            ParseError::InvalidValue { offset, .. } => *offset += base,
+            _ => (),
+        }
+        self
+    }
+}
+
+/// Get the `len` bytes of `field` starting at `offset`, or a Truncated error if `buf` is too short
+pub(crate) fn field_bytes<'a>(buf: &'a [u8], offset: usize, len: usize, field: &'static str)
+    -> Result<&'a [u8], ParseError> {
+    buf.get(offset..offset + len)
+        .ok_or(ParseError::Truncated { pid: None, offset, field })
+}
+
+/// Get the byte holding `field` at `offset`, or a Truncated error if `buf` is too short
+pub(crate) fn field_byte(buf: &[u8], offset: usize, field: &'static str) -> Result<u8, ParseError> {
+    buf.get(offset).copied()
+        .ok_or(ParseError::Truncated { pid: None, offset, field })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pid_and_offset() {
+        let e = field_bytes(&[0; 4], 2, 4, "CRC_32").unwrap_err();
+        assert_eq!(e, ParseError::Truncated { pid: None, offset: 2, field: "CRC_32" });
+        assert_eq!(e.to_string(), "buffer ends before CRC_32 at offset 2");
+        // The section parser's offset made relative to the packet, and the first PID kept
+        let e = e.shift(5).with_pid(0x100).with_pid(0x200);
+        assert_eq!(e.pid(), Some(0x100));
+        assert_eq!(e.to_string(), "PID 0x100: buffer ends before CRC_32 at offset 7");
+
+        assert_eq!(field_byte(&[0xAB], 0, "table_id"), Ok(0xAB));
+        let e = ParseError::InvalidValue { pid: None, offset: 1, field: "section_length", value: 8 }.with_pid(0x11);
+        assert_eq!(e.to_string(), "PID 0x11: invalid section_length 0x8 at offset 1");
+        // Packet level errors have no PID to attach
+        assert_eq!(ParseError::SyncByte { found: 0x48 }.with_pid(0x100).pid(), None);
+        assert_eq!(ParseError::PacketSize { len: 100 }.to_string(), "packet is 100 bytes, expected 188");
+        assert_eq!(ParseError::UnknownTable { pid: 0x11, table_id: 0x4A }.to_string(),
+            "PID 0x11: no parser for table_id 0x4A on this PID");
+    }
+}
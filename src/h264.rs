@@ -0,0 +1,930 @@
+use std::fmt;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::bits::{self, BitReader};
+use crate::codec::Codec;
+use crate::demux::DemuxHandler;
+use crate::error::ParseError;
+use crate::packet::Packet;
+use crate::pes::Pes;
+use crate::psi::pmt::Pmt;
+
+// Constants
+pub const NAL_SLICE: u8 = 1;
+pub const NAL_IDR_SLICE: u8 = 5;
+pub const NAL_SEI: u8 = 6;
+pub const NAL_SPS: u8 = 7;
+pub const NAL_PPS: u8 = 8;
+pub const NAL_AUD: u8 = 9;
+/// profile_idcs whose SPS carries chroma_format_idc, the bit depths and scaling matrices
+const HIGH_PROFILES: [u8; 13] = [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135];
+
+/// Name of a nal_unit_type
+pub fn nal_unit_type_name(nal_unit_type: u8) -> &'static str {
+    match nal_unit_type {
+        0 => "Unspecified",
+        1 => "Coded slice of a non-IDR picture",
+        2..=4 => "Coded slice data partition",
+        5 => "Coded slice of an IDR picture",
+        6 => "SEI",
+        7 => "Sequence parameter set",
+        8 => "Picture parameter set",
+        9 => "Access unit delimiter",
+        10 => "End of sequence",
+        11 => "End of stream",
+        12 => "Filler data",
+        13 => "Sequence parameter set extension",
+        14 => "Prefix NAL unit",
+        15 => "Subset sequence parameter set",
+        19 => "Coded slice of an auxiliary coded picture",
+        20 => "Coded slice extension",
+        _ => "Reserved",
+    }
+}
+
+/// Skip the scaling_list() of `size` coefficients
+fn skip_scaling_list(r: &mut BitReader, size: usize) -> Result<(), ParseError> {
+    let (mut last_scale, mut next_scale) = (8i32, 8i32);
+    for _ in 0..size {
+        if next_scale != 0 {
+            let offset = r.offset();
+            let delta_scale = r.se("delta_scale")?;
+            if !(-128..=127).contains(&delta_scale) {
+                return Err(ParseError::InvalidValue { pid: None, offset, field: "delta_scale", value: delta_scale as u32 });
+            }
+            next_scale = (last_scale + delta_scale + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Ok(())
+}
+
+/// Split the RBSP of a SEI NAL unit into its sei_message()s: (payloadType, payload).
+/// HEVC SEI NAL units share the syntax
+pub fn sei_messages(rbsp: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut messages = vec![];
+    let mut i = 0;
+    // Stop at the rbsp_trailing_bits, which are only a 0x80 byte at the very end (a payloadType
+    // byte can be 0x80 too)
+    while i < rbsp.len() && rbsp[i..] != [0x80] {
+        let mut read_value = || {
+            let mut value = 0u32;
+            while let Some(&b) = rbsp.get(i) {
+                i += 1;
+                value += u32::from(b);
+                if b != 0xFF {
+                    return Some(value);
+                }
+            }
+            None
+        };
+        let (payload_type, payload_size) = match (read_value(), read_value()) {
+            (Some(t), Some(size)) => (t, size as usize),
+            _ => break,
+        };
+        match rbsp.get(i..i + payload_size) {
+            Some(payload) => messages.push((payload_type, payload)),
+            None => break,
+        }
+        i += payload_size;
+    }
+    messages
+}
+
+/// The parts of the VUI that say how to show the pictures
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Vui {
+    /// sar_width and sar_height (from aspect_ratio_idc or given explicitly)
+    pub sample_aspect_ratio: Option<(u16, u16)>,
+    pub video_full_range_flag: bool,
+    pub colour_primaries: Option<u8>,
+    pub transfer_characteristics: Option<u8>,
+    pub matrix_coefficients: Option<u8>,
+    pub num_units_in_tick: Option<u32>,
+    pub time_scale: Option<u32>,
+    pub fixed_frame_rate_flag: bool,
+}
+
+/// The sample aspect ratio of an aspect_ratio_idc (shared by H.264 and HEVC)
+pub fn sample_aspect_ratio(aspect_ratio_idc: u8) -> Option<(u16, u16)> {
+    const SAR: [(u16, u16); 16] = [(1, 1), (12, 11), (10, 11), (16, 11), (40, 33), (24, 11), (20, 11),
+        (32, 11), (80, 33), (18, 11), (15, 11), (64, 33), (160, 99), (4, 3), (3, 2), (2, 1)];
+    SAR.get(usize::from(aspect_ratio_idc).checked_sub(1)?).copied()
+}
+
+impl Vui {
+    /// Parse the vui_parameters() up to the timing info (the HRD and bitstream
+    /// restrictions that follow aren't needed)
+    fn new(r: &mut BitReader) -> Result<Vui, ParseError> {
+        let mut vui = Vui::default();
+        if r.flag("aspect_ratio_info_present_flag")? {
+            let aspect_ratio_idc = r.byte("aspect_ratio_idc")?;
+            vui.sample_aspect_ratio = if aspect_ratio_idc == 255 {
+                Some((r.bits(16, "sar_width")? as u16, r.bits(16, "sar_height")? as u16))
+            } else {
+                sample_aspect_ratio(aspect_ratio_idc)
+            };
+        }
+        if r.flag("overscan_info_present_flag")? {
+            r.skip(1, "overscan_appropriate_flag")?;
+        }
+        if r.flag("video_signal_type_present_flag")? {
+            r.skip(3, "video_format")?;
+            vui.video_full_range_flag = r.flag("video_full_range_flag")?;
+            if r.flag("colour_description_present_flag")? {
+                vui.colour_primaries = Some(r.byte("colour_primaries")?);
+                vui.transfer_characteristics = Some(r.byte("transfer_characteristics")?);
+                vui.matrix_coefficients = Some(r.byte("matrix_coefficients")?);
+            }
+        }
+        if r.flag("chroma_loc_info_present_flag")? {
+            r.ue("chroma_sample_loc_type_top_field")?;
+            r.ue("chroma_sample_loc_type_bottom_field")?;
+        }
+        if r.flag("timing_info_present_flag")? {
+            vui.num_units_in_tick = Some(r.bits(32, "num_units_in_tick")?);
+            vui.time_scale = Some(r.bits(32, "time_scale")?);
+            vui.fixed_frame_rate_flag = r.flag("fixed_frame_rate_flag")?;
+        }
+        Ok(vui)
+    }
+
+    /// Name of the colour_primaries (the ITU-T H.273 code points H.264 and HEVC share)
+    pub fn colour_primaries_name(&self) -> Option<&'static str> {
+        Some(match self.colour_primaries? {
+            1 => "BT.709",
+            4 => "BT.470 System M",
+            5 => "BT.470 System B/G",
+            6 => "BT.601",
+            7 => "SMPTE 240M",
+            8 => "Generic film",
+            9 => "BT.2020",
+            10 => "SMPTE ST 428-1",
+            11 => "DCI-P3",
+            12 => "Display P3",
+            22 => "EBU Tech 3213",
+            _ => "Unknown",
+        })
+    }
+
+    /// Name of the transfer_characteristics (ITU-T H.273)
+    pub fn transfer_characteristics_name(&self) -> Option<&'static str> {
+        Some(match self.transfer_characteristics? {
+            1 => "BT.709",
+            4 => "Gamma 2.2",
+            5 => "Gamma 2.8",
+            6 => "BT.601",
+            7 => "SMPTE 240M",
+            8 => "Linear",
+            11 => "IEC 61966-2-4",
+            12 => "BT.1361",
+            13 => "sRGB",
+            14 => "BT.2020 (10 bit)",
+            15 => "BT.2020 (12 bit)",
+            16 => "PQ (SMPTE ST 2084)",
+            17 => "SMPTE ST 428-1",
+            18 => "HLG (ARIB STD-B67)",
+            _ => "Unknown",
+        })
+    }
+}
+
+/// Sequence parameter set
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sps {
+    pub profile_idc: u8,
+    /// constraint_set0_flag to constraint_set5_flag and the reserved_zero_2bits
+    pub constraint_flags: u8,
+    pub level_idc: u8,
+    pub seq_parameter_set_id: u32,
+    pub chroma_format_idc: u32,
+    pub separate_colour_plane_flag: bool,
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    pub log2_max_frame_num: u32,
+    pub pic_order_cnt_type: u32,
+    /// Only for pic_order_cnt_type 0
+    pub log2_max_pic_order_cnt_lsb: u32,
+    pub max_num_ref_frames: u32,
+    pub pic_width_in_mbs: u32,
+    pub pic_height_in_map_units: u32,
+    /// Whether every picture is a frame (false for interlaced/PAFF/MBAFF streams)
+    pub frame_mbs_only_flag: bool,
+    pub mb_adaptive_frame_field_flag: bool,
+    /// frame_crop_left/right/top/bottom_offset
+    pub frame_crop: Option<(u32, u32, u32, u32)>,
+    pub vui: Option<Vui>,
+}
+
+impl Sps {
+    /// Parse the RBSP of a SPS NAL unit (following its header byte)
+    pub fn new(rbsp: &[u8]) -> Result<Sps, ParseError> {
+        let mut r = BitReader::new(rbsp);
+        let profile_idc = r.byte("profile_idc")?;
+        let constraint_flags = r.byte("constraint_set_flags")?;
+        let level_idc = r.byte("level_idc")?;
+        let seq_parameter_set_id = r.ue_max(31, "seq_parameter_set_id")?;
+        let (mut chroma_format_idc, mut separate_colour_plane_flag) = (1, false);
+        let (mut bit_depth_luma, mut bit_depth_chroma) = (8, 8);
+        if HIGH_PROFILES.contains(&profile_idc) {
+            chroma_format_idc = r.ue_max(3, "chroma_format_idc")?;
+            if chroma_format_idc == 3 {
+                separate_colour_plane_flag = r.flag("separate_colour_plane_flag")?;
+            }
+            bit_depth_luma = 8 + r.ue_max(6, "bit_depth_luma_minus8")?;
+            bit_depth_chroma = 8 + r.ue_max(6, "bit_depth_chroma_minus8")?;
+            r.skip(1, "qpprime_y_zero_transform_bypass_flag")?;
+            if r.flag("seq_scaling_matrix_present_flag")? {
+                for i in 0..if chroma_format_idc != 3 { 8 } else { 12 } {
+                    if r.flag("seq_scaling_list_present_flag")? {
+                        skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                    }
+                }
+            }
+        }
+        let log2_max_frame_num = 4 + r.ue_max(12, "log2_max_frame_num_minus4")?;
+        let pic_order_cnt_type = r.ue_max(2, "pic_order_cnt_type")?;
+        let mut log2_max_pic_order_cnt_lsb = 0;
+        if pic_order_cnt_type == 0 {
+            log2_max_pic_order_cnt_lsb = 4 + r.ue_max(12, "log2_max_pic_order_cnt_lsb_minus4")?;
+        } else if pic_order_cnt_type == 1 {
+            r.skip(1, "delta_pic_order_always_zero_flag")?;
+            r.se("offset_for_non_ref_pic")?;
+            r.se("offset_for_top_to_bottom_field")?;
+            for _ in 0..r.ue_max(255, "num_ref_frames_in_pic_order_cnt_cycle")? {
+                r.se("offset_for_ref_frame")?;
+            }
+        }
+        let max_num_ref_frames = r.ue("max_num_ref_frames")?;
+        r.skip(1, "gaps_in_frame_num_value_allowed_flag")?;
+        let pic_width_in_mbs = 1 + r.ue("pic_width_in_mbs_minus1")?;
+        let pic_height_in_map_units = 1 + r.ue("pic_height_in_map_units_minus1")?;
+        let frame_mbs_only_flag = r.flag("frame_mbs_only_flag")?;
+        let mb_adaptive_frame_field_flag = !frame_mbs_only_flag && r.flag("mb_adaptive_frame_field_flag")?;
+        r.skip(1, "direct_8x8_inference_flag")?;
+        let frame_crop = if r.flag("frame_cropping_flag")? {
+            Some((r.ue("frame_crop_left_offset")?, r.ue("frame_crop_right_offset")?,
+                r.ue("frame_crop_top_offset")?, r.ue("frame_crop_bottom_offset")?))
+        } else {
+            None
+        };
+        let vui = if r.flag("vui_parameters_present_flag")? { Some(Vui::new(&mut r)?) } else { None };
+
+        Ok(Sps {
+            profile_idc,
+            constraint_flags,
+            level_idc,
+            seq_parameter_set_id,
+            chroma_format_idc,
+            separate_colour_plane_flag,
+            bit_depth_luma,
+            bit_depth_chroma,
+            log2_max_frame_num,
+            pic_order_cnt_type,
+            log2_max_pic_order_cnt_lsb,
+            max_num_ref_frames,
+            pic_width_in_mbs,
+            pic_height_in_map_units,
+            frame_mbs_only_flag,
+            mb_adaptive_frame_field_flag,
+            frame_crop,
+            vui,
+        })
+    }
+
+    /// Size of the cropping units in luma samples (horizontally, vertically)
+    fn crop_units(&self) -> (u32, u32) {
+        let frame_factor = if self.frame_mbs_only_flag { 1 } else { 2 };
+        match (self.separate_colour_plane_flag, self.chroma_format_idc) {
+            (true, _) | (_, 0) => (1, frame_factor),
+            (_, 1) => (2, 2 * frame_factor),
+            (_, 2) => (2, frame_factor),
+            _ => (1, frame_factor),
+        }
+    }
+
+    /// Width of the pictures in luma samples, after cropping
+    pub fn width(&self) -> u32 {
+        let (left, right, _, _) = self.frame_crop.unwrap_or_default();
+        let crop = self.crop_units().0.saturating_mul(left.saturating_add(right));
+        self.pic_width_in_mbs.saturating_mul(16).saturating_sub(crop)
+    }
+
+    /// Height of the frames in luma samples, after cropping
+    pub fn height(&self) -> u32 {
+        let (_, _, top, bottom) = self.frame_crop.unwrap_or_default();
+        let frame_factor = if self.frame_mbs_only_flag { 1 } else { 2 };
+        let crop = self.crop_units().1.saturating_mul(top.saturating_add(bottom));
+        self.pic_height_in_map_units.saturating_mul(16 * frame_factor).saturating_sub(crop)
+    }
+
+    /// Frames per second from the VUI timing info (two ticks per frame), if there is any
+    pub fn frame_rate(&self) -> Option<f64> {
+        let vui = self.vui.as_ref()?;
+        match (vui.num_units_in_tick?, vui.time_scale?) {
+            (0, _) => None,
+            (num_units_in_tick, time_scale) => Some(f64::from(time_scale) / (2.0 * f64::from(num_units_in_tick))),
+        }
+    }
+
+    /// Whether the stream can have field pictures or field macroblock pairs
+    pub fn is_interlaced(&self) -> bool {
+        !self.frame_mbs_only_flag
+    }
+
+    pub fn profile_name(&self) -> &'static str {
+        let constraint_set = |n: u8| self.constraint_flags & (0x80 >> n) != 0;
+        match self.profile_idc {
+            66 if constraint_set(1) => "Constrained Baseline",
+            66 => "Baseline",
+            77 => "Main",
+            88 => "Extended",
+            100 if constraint_set(4) && constraint_set(5) => "Constrained High",
+            100 => "High",
+            110 if constraint_set(3) => "High 10 Intra",
+            110 => "High 10",
+            122 if constraint_set(3) => "High 4:2:2 Intra",
+            122 => "High 4:2:2",
+            244 if constraint_set(3) => "High 4:4:4 Intra",
+            244 => "High 4:4:4 Predictive",
+            44 => "CAVLC 4:4:4 Intra",
+            83 => "Scalable Baseline",
+            86 => "Scalable High",
+            118 => "Multiview High",
+            128 => "Stereo High",
+            _ => "Unknown",
+        }
+    }
+
+    /// The level as written, e.g. "4.1" (or "1b")
+    pub fn level_name(&self) -> String {
+        if self.level_idc == 9 || (self.level_idc == 11 && self.constraint_flags & 0x10 != 0 &&
+            matches!(self.profile_idc, 66 | 77 | 88)) {
+            return "1b".to_string();
+        }
+        format!("{}.{}", self.level_idc / 10, self.level_idc % 10)
+    }
+
+    pub fn chroma_format_name(&self) -> &'static str {
+        match self.chroma_format_idc {
+            0 => "4:0:0",
+            1 => "4:2:0",
+            2 => "4:2:2",
+            _ => "4:4:4",
+        }
+    }
+}
+
+/// Picture parameter set (the fields the slice headers depend on)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pps {
+    pub pic_parameter_set_id: u32,
+    pub seq_parameter_set_id: u32,
+    /// CABAC rather than CAVLC
+    pub entropy_coding_mode_flag: bool,
+    pub bottom_field_pic_order_in_frame_present_flag: bool,
+    pub num_slice_groups: u32,
+}
+
+impl Pps {
+    /// Parse the RBSP of a PPS NAL unit (following its header byte)
+    pub fn new(rbsp: &[u8]) -> Result<Pps, ParseError> {
+        let mut r = BitReader::new(rbsp);
+        Ok(Pps {
+            pic_parameter_set_id: r.ue_max(255, "pic_parameter_set_id")?,
+            seq_parameter_set_id: r.ue_max(31, "seq_parameter_set_id")?,
+            entropy_coding_mode_flag: r.flag("entropy_coding_mode_flag")?,
+            bottom_field_pic_order_in_frame_present_flag: r.flag("bottom_field_pic_order_in_frame_present_flag")?,
+            num_slice_groups: 1 + r.ue_max(7, "num_slice_groups_minus1")?,
+        })
+    }
+}
+
+/// The type of a coded picture
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum FrameType {
+    Idr,
+    I,
+    P,
+    B,
+}
+
+impl fmt::Display for FrameType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FrameType::Idr => "IDR",
+            FrameType::I => "I",
+            FrameType::P => "P",
+            FrameType::B => "B",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FrameType {
+    /// The letter of the type in a GOP pattern (IDR pictures are "I" too)
+    pub fn letter(&self) -> char {
+        match self {
+            FrameType::Idr | FrameType::I => 'I',
+            FrameType::P => 'P',
+            FrameType::B => 'B',
+        }
+    }
+}
+
+/// The start of a slice header
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SliceHeader {
+    pub first_mb_in_slice: u32,
+    /// 0 P, 1 B, 2 I, 3 SP, 4 SI (plus 5 if all slices of the picture have the same type)
+    pub slice_type: u32,
+    pub pic_parameter_set_id: u32,
+    pub frame_num: u32,
+    pub field_pic_flag: bool,
+    pub bottom_field_flag: bool,
+    pub idr_pic_id: Option<u32>,
+    /// Only for pic_order_cnt_type 0
+    pub pic_order_cnt_lsb: Option<u32>,
+}
+
+impl SliceHeader {
+    /// Parse the start of the slice header in the RBSP of a slice NAL unit (following its
+    /// header byte), with the parameter sets seen so far
+    pub fn new(rbsp: &[u8], idr: bool, sps: &HashMap<u32, Sps>, pps: &HashMap<u32, Pps>)
+        -> Result<SliceHeader, ParseError> {
+        let mut r = BitReader::new(rbsp);
+        let first_mb_in_slice = r.ue("first_mb_in_slice")?;
+        let slice_type = r.ue_max(9, "slice_type")?;
+        let pic_parameter_set_id = r.ue("pic_parameter_set_id")?;
+        let invalid = |field, value| ParseError::InvalidValue { pid: None, offset: 0, field, value };
+        let pps = pps.get(&pic_parameter_set_id).ok_or_else(|| invalid("pic_parameter_set_id", pic_parameter_set_id))?;
+        let sps = sps.get(&pps.seq_parameter_set_id).ok_or_else(|| invalid("seq_parameter_set_id", pps.seq_parameter_set_id))?;
+
+        if sps.separate_colour_plane_flag {
+            r.skip(2, "colour_plane_id")?;
+        }
+        let frame_num = r.bits(sps.log2_max_frame_num, "frame_num")?;
+        let field_pic_flag = !sps.frame_mbs_only_flag && r.flag("field_pic_flag")?;
+        let bottom_field_flag = field_pic_flag && r.flag("bottom_field_flag")?;
+        let idr_pic_id = if idr { Some(r.ue("idr_pic_id")?) } else { None };
+        let pic_order_cnt_lsb = if sps.pic_order_cnt_type == 0 {
+            Some(r.bits(sps.log2_max_pic_order_cnt_lsb, "pic_order_cnt_lsb")?)
+        } else {
+            None
+        };
+        Ok(SliceHeader {
+            first_mb_in_slice,
+            slice_type,
+            pic_parameter_set_id,
+            frame_num,
+            field_pic_flag,
+            bottom_field_flag,
+            idr_pic_id,
+            pic_order_cnt_lsb,
+        })
+    }
+}
+
+/// A group of pictures: an I picture and the pictures following it (in decoding order)
+/// up to the next one
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Gop {
+    /// Index of the first picture in the stream
+    pub start: u64,
+    /// Whether the GOP starts with an IDR picture
+    pub idr: bool,
+    /// Whether pictures of the GOP refer to the one before (B pictures following the I
+    /// picture in decoding order but preceding it in output order)
+    pub open: bool,
+    /// The types of the pictures in decoding order, e.g. "IPBBPBB"
+    pub pattern: String,
+}
+
+/// The picture being assembled from its slices
+#[derive(Clone, Debug)]
+struct Picture {
+    frame_type: FrameType,
+    frame_num: u32,
+    /// Whether the picture is a single field still waiting for the other one
+    lone_field: Option<bool>,
+    pic_order_cnt_lsb: Option<u32>,
+}
+
+/// What the SPS, PPS and slices of an H.264 stream say about it
+#[derive(Clone, Debug, Default)]
+pub struct Stream {
+    pub pid: u16,
+    pub program_number: u16,
+    /// The last SPS (by seq_parameter_set_id) and PPS (by pic_parameter_set_id)
+    pub sps: HashMap<u32, Sps>,
+    pub pps: HashMap<u32, Pps>,
+    /// The SPS of the last picture
+    pub active_sps: Option<u32>,
+    /// Number of pictures of each type
+    pub frame_counts: BTreeMap<FrameType, u64>,
+    pub gops: Vec<Gop>,
+    /// Number of pictures between consecutive IDR pictures
+    pub idr_intervals: Vec<u64>,
+    pub nal_units: u64,
+    picture: Option<Picture>,
+    frames: u64,
+    last_idr: Option<u64>,
+    /// pic_order_cnt_lsb of the I picture starting the current GOP
+    gop_start_poc: Option<u32>,
+}
+
+impl Stream {
+    fn new(pid: u16, program_number: u16) -> Stream {
+        Stream { pid, program_number, ..Default::default() }
+    }
+
+    /// Handle the NAL units of a PES packet
+    fn push(&mut self, payload: &[u8]) -> Vec<ParseError> {
+        let mut errors = vec![];
+        for nal in bits::annex_b_units(payload) {
+            self.nal_units += 1;
+            let nal_unit_type = nal[0] & 0x1F;
+            let rbsp = bits::rbsp(&nal[1..]);
+            let result = match nal_unit_type {
+                NAL_SPS => Sps::new(&rbsp).map(|sps| { self.sps.insert(sps.seq_parameter_set_id, sps); }),
+                NAL_PPS => Pps::new(&rbsp).map(|pps| { self.pps.insert(pps.pic_parameter_set_id, pps); }),
+                NAL_SLICE | NAL_IDR_SLICE => {
+                    let idr = nal_unit_type == NAL_IDR_SLICE;
+                    SliceHeader::new(&rbsp, idr, &self.sps, &self.pps).map(|slice| self.push_slice(&slice, idr))
+                },
+                NAL_AUD => {
+                    self.finish_picture();
+                    Ok(())
+                },
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                errors.push(e.with_pid(self.pid));
+            }
+        }
+        errors
+    }
+
+    /// Add a slice to the current picture, or start a new one
+    fn push_slice(&mut self, slice: &SliceHeader, idr: bool) {
+        let slice_type = match slice.slice_type % 5 {
+            0 | 3 => FrameType::P,
+            1 => FrameType::B,
+            _ if idr => FrameType::Idr,
+            _ => FrameType::I,
+        };
+        if let Some(sps) = self.pps.get(&slice.pic_parameter_set_id).map(|p| p.seq_parameter_set_id) {
+            self.active_sps = Some(sps);
+        }
+        if slice.first_mb_in_slice == 0 {
+            // The second field of a frame completes the picture rather than starting one
+            let pairs = self.picture.as_ref().is_some_and(|p| {
+                slice.field_pic_flag && p.lone_field == Some(!slice.bottom_field_flag) && p.frame_num == slice.frame_num
+            });
+            if pairs {
+                if let Some(p) = self.picture.as_mut() {
+                    p.lone_field = None;
+                }
+            } else {
+                self.finish_picture();
+                self.picture = Some(Picture {
+                    frame_type: slice_type,
+                    frame_num: slice.frame_num,
+                    lone_field: if slice.field_pic_flag { Some(slice.bottom_field_flag) } else { None },
+                    pic_order_cnt_lsb: slice.pic_order_cnt_lsb,
+                });
+                return;
+            }
+        }
+        // B slices make the picture a B picture, P slices a P picture
+        if let Some(p) = self.picture.as_mut() {
+            if slice_type > p.frame_type && p.frame_type != FrameType::Idr {
+                p.frame_type = slice_type;
+            }
+        }
+    }
+
+    /// Count the current picture and add it to its GOP
+    fn finish_picture(&mut self) {
+        let picture = match self.picture.take() {
+            Some(picture) => picture,
+            None => return,
+        };
+        let index = self.frames;
+        self.frames += 1;
+        *self.frame_counts.entry(picture.frame_type).or_insert(0) += 1;
+
+        match picture.frame_type {
+            FrameType::Idr | FrameType::I => {
+                if picture.frame_type == FrameType::Idr {
+                    if let Some(last) = self.last_idr.replace(index) {
+                        self.idr_intervals.push(index - last);
+                    }
+                }
+                self.gops.push(Gop {
+                    start: index,
+                    idr: picture.frame_type == FrameType::Idr,
+                    open: false,
+                    pattern: String::new(),
+                });
+                self.gop_start_poc = picture.pic_order_cnt_lsb;
+            },
+            FrameType::B => {
+                // A B picture output before the I picture it follows belongs to the previous GOP
+                let leading = match (self.gop_start_poc, picture.pic_order_cnt_lsb, self.max_poc_lsb()) {
+                    (Some(start), Some(poc), Some(max)) => (start + max - poc) % max < max / 2 && poc != start,
+                    _ => false,
+                };
+                if let Some(gop) = self.gops.last_mut() {
+                    if leading && !gop.idr && gop.pattern.chars().all(|c| c != 'P') {
+                        gop.open = true;
+                    }
+                }
+            },
+            FrameType::P => (),
+        }
+        if let Some(gop) = self.gops.last_mut() {
+            gop.pattern.push(picture.frame_type.letter());
+        }
+    }
+
+    /// MaxPicOrderCntLsb of the active SPS (for pic_order_cnt_type 0)
+    fn max_poc_lsb(&self) -> Option<u32> {
+        let sps = self.sps.get(&self.active_sps?)?;
+        if sps.pic_order_cnt_type == 0 { Some(1 << sps.log2_max_pic_order_cnt_lsb) } else { None }
+    }
+
+    /// The SPS of the last picture
+    pub fn get_sps(&self) -> Option<&Sps> {
+        self.sps.get(&self.active_sps?)
+    }
+
+    /// Total number of pictures
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[H.264] PID {:#X} (Program {})", self.pid, self.program_number)?;
+        if let Some(sps) = self.get_sps() {
+            write!(f, ": {} profile, Level {}, {}x{}", sps.profile_name(), sps.level_name(), sps.width(), sps.height())?;
+            if let Some(frame_rate) = sps.frame_rate() {
+                write!(f, ", {:.3} fps", frame_rate)?;
+            }
+            write!(f, ", {}, {} {} bit", if sps.is_interlaced() { "interlaced" } else { "progressive" },
+                sps.chroma_format_name(), sps.bit_depth_luma)?;
+            if let Some((sar_width, sar_height)) = sps.vui.as_ref().and_then(|v| v.sample_aspect_ratio) {
+                write!(f, ", SAR {}:{}", sar_width, sar_height)?;
+            }
+        }
+        let count = |t| self.frame_counts.get(&t).copied().unwrap_or(0);
+        write!(f, "\n\t=> Frames: {} (IDR {}, I {}, P {}, B {}), NAL units: {}", self.frames,
+            count(FrameType::Idr), count(FrameType::I), count(FrameType::P), count(FrameType::B), self.nal_units)?;
+
+        // The last GOP is cut short by the end of the capture, so leave it out of the lengths
+        let complete = &self.gops[..self.gops.len().saturating_sub(1)];
+        let open = self.gops.iter().filter(|g| g.open).count();
+        write!(f, "\n\t=> GOPs: {} ({} closed, {} open)", self.gops.len(), self.gops.len() - open, open)?;
+        if let (Some(min), Some(max)) = (complete.iter().map(|g| g.pattern.len()).min(),
+            complete.iter().map(|g| g.pattern.len()).max()) {
+            if min == max {
+                write!(f, ", Length: {}", min)?;
+            } else {
+                write!(f, ", Length: {}-{}", min, max)?;
+            }
+        }
+        if let (Some(min), Some(max)) = (self.idr_intervals.iter().min(), self.idr_intervals.iter().max()) {
+            if min == max {
+                write!(f, ", IDR interval: {}", min)?;
+            } else {
+                write!(f, ", IDR interval: {}-{}", min, max)?;
+            }
+        }
+        let mut patterns = HashSet::new();
+        for gop in complete.iter().filter(|g| patterns.insert(g.pattern.as_str())).take(4) {
+            write!(f, "\n\t=> GOP {}{}: {}", if gop.idr { "IDR" } else { "I" },
+                if gop.open { " (open)" } else { "" }, gop.pattern)?;
+        }
+        Ok(())
+    }
+}
+
+/// Follows the SPS, PPS and slices of the H.264 streams as a DemuxHandler, working out
+/// each one's format and GOP structure
+#[derive(Clone, Debug, Default)]
+pub struct AvcAnalyzer {
+    streams: BTreeMap<u16, Stream>,
+    offset: u64,
+    errors: Vec<(u64, ParseError)>,
+}
+
+impl AvcAnalyzer {
+    pub fn new() -> AvcAnalyzer {
+        Default::default()
+    }
+
+    /// The H.264 streams found so far by PID
+    pub fn streams(&self) -> &BTreeMap<u16, Stream> {
+        &self.streams
+    }
+
+    /// The offset of every NAL unit that failed to parse (or the packet that completed its
+    /// PES packet), with why
+    pub fn errors(&self) -> &[(u64, ParseError)] {
+        &self.errors
+    }
+}
+
+impl DemuxHandler for AvcAnalyzer {
+    fn on_packet(&mut self, packet: &Packet) {
+        self.offset = packet.offset;
+    }
+
+    fn on_pmt(&mut self, _pid: u16, pmt: &Pmt) {
+        for es in pmt.elementary_streams.iter().filter(|es| es.get_codec() == Codec::H264) {
+            let pid = es.get_elementary_pid();
+            self.streams.entry(pid).or_insert_with(|| Stream::new(pid, pmt.get_program_number()));
+        }
+    }
+
+    fn on_pes(&mut self, pid: u16, pes: &Pes) {
+        if let Some(stream) = self.streams.get_mut(&pid) {
+            let offset = self.offset;
+            self.errors.extend(stream.push(&pes.payload).into_iter().map(|e| (offset, e)));
+        }
+    }
+
+    fn on_end(&mut self) {
+        for stream in self.streams.values_mut() {
+            stream.finish_picture();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, BitWriter};
+
+    /// A High profile 1920x1080 SPS (1088 lines cropped by 8) at 25 fps, progressive or
+    /// with field pictures
+    fn sps_with(frame_mbs_only: bool) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.bits(8, 100).bits(8, 0).bits(8, 40).ue(0);
+        // 4:2:0, 8 bit, no scaling matrices
+        w.ue(1).ue(0).ue(0).flag(false).flag(false);
+        // log2_max_frame_num, pic_order_cnt_type 0 and its log2_max_pic_order_cnt_lsb
+        w.ue(0).ue(0).ue(2);
+        w.ue(4).flag(false).ue(119);
+        if frame_mbs_only {
+            w.ue(67).flag(true);
+        } else {
+            w.ue(33).flag(false).flag(false);
+        }
+        // The cropping units are 2 lines of a frame, 4 of a field pair
+        w.flag(true).flag(true).ue(0).ue(0).ue(0).ue(if frame_mbs_only { 4 } else { 2 });
+        // VUI: 1:1, BT.709, 50 ticks per second
+        w.flag(true).flag(true).bits(8, 1).flag(false);
+        w.flag(true).bits(3, 5).flag(false).flag(true).bits(8, 1).bits(8, 1).bits(8, 1);
+        w.flag(false).flag(true).bits(32, 1000).bits(32, 50000).flag(true);
+        w.rbsp()
+    }
+
+    fn sps() -> Vec<u8> {
+        sps_with(true)
+    }
+
+    #[test]
+    fn sequence_parameter_set() {
+        let sps = Sps::new(&sps()).unwrap();
+        assert_eq!((sps.profile_name(), sps.level_name(), sps.chroma_format_name()), ("High", "4.0".to_string(), "4:2:0"));
+        assert_eq!((sps.width(), sps.height(), sps.frame_rate()), (1920, 1080, Some(25.0)));
+        assert!(!sps.is_interlaced());
+        let vui = sps.vui.unwrap();
+        assert_eq!(vui.sample_aspect_ratio, Some((1, 1)));
+        assert_eq!(vui.colour_primaries_name(), Some("BT.709"));
+
+        let sps = Sps::new(&sps_with(false)).unwrap();
+        assert_eq!((sps.width(), sps.height(), sps.pic_height_in_map_units), (1920, 1080, 34));
+        assert!(sps.is_interlaced());
+    }
+
+    #[test]
+    fn invalid_sps() {
+        let buf = sps();
+        for len in 0..buf.len() {
+            assert!(Sps::new(&buf[..len]).is_err(), "{} bytes", len);
+        }
+        let mut w = BitWriter::default();
+        w.bits(8, 100).bits(8, 0).bits(8, 40).ue(32);
+        assert_eq!(Sps::new(&w.rbsp()),
+            Err(ParseError::InvalidValue { pid: None, offset: 3, field: "seq_parameter_set_id", value: 32 }));
+        let mut w = BitWriter::default();
+        w.bits(8, 100).bits(8, 0).bits(8, 40).ue(0).ue(4);
+        assert_eq!(Sps::new(&w.rbsp()),
+            Err(ParseError::InvalidValue { pid: None, offset: 3, field: "chroma_format_idc", value: 4 }));
+        // A delta_scale of 200 (coded as 399) in the first scaling list
+        let mut w = BitWriter::default();
+        w.bits(8, 100).bits(8, 0).bits(8, 40).ue(0).ue(1).ue(0).ue(0).flag(false).flag(true).flag(true).ue(399);
+        assert_eq!(Sps::new(&w.rbsp()),
+            Err(ParseError::InvalidValue { pid: None, offset: 4, field: "delta_scale", value: 200 }));
+    }
+
+    #[test]
+    fn sei_message_split() {
+        // A payloadType of 0x80 is a message, not the rbsp_trailing_bits
+        let rbsp = [0x80, 0x02, 0xAA, 0xBB, 0xFF, 0x01, 0x01, 0xCC, 0x80];
+        assert_eq!(sei_messages(&rbsp), vec![(0x80, &[0xAA, 0xBB][..]), (0x100, &[0xCC][..])]);
+        assert_eq!(sei_messages(&[0x80]), vec![]);
+        // A payloadSize running past the end
+        assert_eq!(sei_messages(&[0x05, 0x04, 0xAA, 0x80]), vec![]);
+    }
+
+    fn pps() -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.ue(0).ue(0).flag(true).flag(false).ue(0);
+        w.rbsp()
+    }
+
+    /// A slice NAL unit of a whole picture: (slice_type, frame_num, pic_order_cnt_lsb),
+    /// IDR if the slice_type is `IDR`
+    fn slice(picture: (u32, u64, u64)) -> Vec<u8> {
+        field_slice(picture, None)
+    }
+
+    /// A slice NAL unit of a whole field (the bottom one if `bottom_field` is true), or of
+    /// a frame if `bottom_field` is None
+    fn field_slice((slice_type, frame_num, pic_order_cnt_lsb): (u32, u64, u64), bottom_field: Option<bool>) -> Vec<u8> {
+        let idr = slice_type == IDR;
+        let mut w = BitWriter::default();
+        w.ue(0).ue(if idr { 7 } else { slice_type }).ue(0).bits(4, frame_num);
+        if let Some(bottom_field) = bottom_field {
+            w.flag(true).flag(bottom_field);
+        }
+        if idr {
+            w.ue(0);
+        }
+        w.bits(6, pic_order_cnt_lsb);
+        test_util::nal_unit(&[if idr { 0x65 } else { 0x41 }], &w.rbsp())
+    }
+
+    const IDR: u32 = 10;
+    const I: u32 = 7;
+    const P: u32 = 5;
+    const B: u32 = 6;
+
+    #[test]
+    fn gop_structure() {
+        let mut buf = test_util::nal_unit(&[0x67], &sps());
+        buf.extend(test_util::nal_unit(&[0x68], &pps()));
+        // Decoding order, with the pictures in output order by pic_order_cnt_lsb: a closed GOP
+        // (IDR P B B), an open one whose leading B pictures come before its I picture (I B B P B B),
+        // and a closed one starting with an I picture (I P)
+        for picture in [(IDR, 0, 0), (P, 1, 6), (B, 2, 2), (B, 2, 4), (I, 2, 18), (B, 3, 14), (B, 3, 16),
+            (P, 3, 24), (B, 4, 20), (B, 4, 22), (I, 4, 30), (P, 5, 32)] {
+            buf.extend(slice(picture));
+        }
+        let mut stream = Stream::new(0x100, 1);
+        assert_eq!(stream.push(&buf), vec![]);
+        stream.finish_picture();
+
+        let gops: Vec<(bool, bool, &str)> = stream.gops.iter().map(|g| (g.idr, g.open, g.pattern.as_str())).collect();
+        assert_eq!(gops, vec![(true, false, "IPBB"), (false, true, "IBBPBB"), (false, false, "IP")]);
+        assert_eq!(stream.frames(), 12);
+        assert_eq!(stream.to_string(), "[H.264] PID 0x100 (Program 1): High profile, Level 4.0, 1920x1080, \
+            25.000 fps, progressive, 4:2:0 8 bit, SAR 1:1\
+            \n\t=> Frames: 12 (IDR 1, I 2, P 3, B 6), NAL units: 14\
+            \n\t=> GOPs: 3 (2 closed, 1 open), Length: 4-6\
+            \n\t=> GOP IDR: IPBB\
+            \n\t=> GOP I (open): IBBPBB");
+    }
+
+    #[test]
+    fn slice_without_parameter_sets() {
+        let mut stream = Stream::new(0x100, 1);
+        let errors = stream.push(&slice((IDR, 0, 0)));
+        assert_eq!(errors, vec![ParseError::InvalidValue {
+            pid: Some(0x100), offset: 0, field: "pic_parameter_set_id", value: 0,
+        }]);
+        assert_eq!(stream.frames(), 0);
+    }
+
+    #[test]
+    fn field_pairs() {
+        let mut buf = test_util::nal_unit(&[0x67], &sps_with(false));
+        buf.extend(test_util::nal_unit(&[0x68], &pps()));
+        // An IDR field paired with a P field, then P fields of different frames and a B field pair
+        for (picture, bottom_field) in [((IDR, 0, 0), false), ((P, 0, 1), true), ((P, 1, 4), false),
+            ((P, 2, 5), false), ((B, 3, 2), false), ((B, 3, 3), true)] {
+            buf.extend(field_slice(picture, Some(bottom_field)));
+        }
+        let mut stream = Stream::new(0x100, 1);
+        assert_eq!(stream.push(&buf), vec![]);
+        stream.finish_picture();
+        assert_eq!(stream.frames(), 4);
+        assert_eq!(stream.gops[0].pattern, "IPPB");
+    }
+}
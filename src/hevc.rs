@@ -0,0 +1,1022 @@
+use std::fmt;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::bits::{self, BitReader};
+use crate::codec::Codec;
+use crate::demux::DemuxHandler;
+use crate::error::ParseError;
+use crate::h264::{self, FrameType, Gop, Vui};
+use crate::packet::Packet;
+use crate::pes::Pes;
+use crate::psi::pmt::Pmt;
+
+// Constants
+pub const NAL_RASL_N: u8 = 8;
+pub const NAL_RASL_R: u8 = 9;
+pub const NAL_BLA_W_LP: u8 = 16;
+pub const NAL_IDR_W_RADL: u8 = 19;
+pub const NAL_IDR_N_LP: u8 = 20;
+pub const NAL_CRA: u8 = 21;
+pub const NAL_VPS: u8 = 32;
+pub const NAL_SPS: u8 = 33;
+pub const NAL_PPS: u8 = 34;
+pub const NAL_AUD: u8 = 35;
+pub const NAL_PREFIX_SEI: u8 = 39;
+/// SEI payloadTypes of the HDR metadata
+pub const SEI_MASTERING_DISPLAY_COLOUR_VOLUME: u32 = 137;
+pub const SEI_CONTENT_LIGHT_LEVEL_INFO: u32 = 144;
+pub const SEI_ALTERNATIVE_TRANSFER_CHARACTERISTICS: u32 = 147;
+
+/// Name of a nal_unit_type
+pub fn nal_unit_type_name(nal_unit_type: u8) -> &'static str {
+    match nal_unit_type {
+        0 => "TRAIL_N",
+        1 => "TRAIL_R",
+        2 => "TSA_N",
+        3 => "TSA_R",
+        4 => "STSA_N",
+        5 => "STSA_R",
+        6 => "RADL_N",
+        7 => "RADL_R",
+        8 => "RASL_N",
+        9 => "RASL_R",
+        16 => "BLA_W_LP",
+        17 => "BLA_W_RADL",
+        18 => "BLA_N_LP",
+        19 => "IDR_W_RADL",
+        20 => "IDR_N_LP",
+        21 => "CRA_NUT",
+        32 => "VPS_NUT",
+        33 => "SPS_NUT",
+        34 => "PPS_NUT",
+        35 => "AUD_NUT",
+        36 => "EOS_NUT",
+        37 => "EOB_NUT",
+        38 => "FD_NUT",
+        39 => "PREFIX_SEI_NUT",
+        40 => "SUFFIX_SEI_NUT",
+        48..=63 => "Unspecified",
+        _ => "Reserved",
+    }
+}
+
+/// The general profile, tier and level of profile_tier_level()
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProfileTierLevel {
+    pub general_profile_space: u8,
+    /// High tier rather than Main tier
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+    pub general_progressive_source_flag: bool,
+    pub general_interlaced_source_flag: bool,
+    pub general_level_idc: u8,
+}
+
+impl ProfileTierLevel {
+    /// Parse a profile_tier_level(1, max_sub_layers_minus1), skipping the sub-layers
+    fn new(r: &mut BitReader, max_sub_layers_minus1: u32) -> Result<ProfileTierLevel, ParseError> {
+        let general_profile_space = r.bits(2, "general_profile_space")? as u8;
+        let general_tier_flag = r.flag("general_tier_flag")?;
+        let general_profile_idc = r.bits(5, "general_profile_idc")? as u8;
+        let general_profile_compatibility_flags = r.bits(32, "general_profile_compatibility_flag")?;
+        let general_progressive_source_flag = r.flag("general_progressive_source_flag")?;
+        let general_interlaced_source_flag = r.flag("general_interlaced_source_flag")?;
+        // general_non_packed_constraint_flag, general_frame_only_constraint_flag and the
+        // 43 bits of constraint flags and 1 bit that follow them
+        r.skip(46, "general_constraint_flags")?;
+        let general_level_idc = r.byte("general_level_idc")?;
+
+        let mut sub_layers = vec![];
+        for _ in 0..max_sub_layers_minus1 {
+            sub_layers.push((r.flag("sub_layer_profile_present_flag")?, r.flag("sub_layer_level_present_flag")?));
+        }
+        if max_sub_layers_minus1 > 0 {
+            r.skip(2 * (8 - max_sub_layers_minus1 as usize), "reserved_zero_2bits")?;
+        }
+        for (profile_present, level_present) in sub_layers {
+            if profile_present {
+                r.skip(88, "sub_layer_profile")?;
+            }
+            if level_present {
+                r.skip(8, "sub_layer_level_idc")?;
+            }
+        }
+        Ok(ProfileTierLevel {
+            general_profile_space,
+            general_tier_flag,
+            general_profile_idc,
+            general_profile_compatibility_flags,
+            general_progressive_source_flag,
+            general_interlaced_source_flag,
+            general_level_idc,
+        })
+    }
+
+    pub fn profile_name(&self) -> &'static str {
+        // A profile_idc of 0 leaves the profile to the compatibility flags
+        let profile_idc = match self.general_profile_idc {
+            0 => (1..32).find(|j| self.general_profile_compatibility_flags & (1 << (31 - j)) != 0).unwrap_or(0),
+            idc => idc,
+        };
+        match profile_idc {
+            1 => "Main",
+            2 => "Main 10",
+            3 => "Main Still Picture",
+            4 => "Format Range Extensions",
+            5 => "High Throughput",
+            6 => "Multiview Main",
+            7 => "Scalable Main",
+            8 => "3D Main",
+            9 => "Screen Content Coding",
+            10 => "Scalable Format Range Extensions",
+            11 => "High Throughput Screen Content Coding",
+            _ => "Unknown",
+        }
+    }
+
+    pub fn tier_name(&self) -> &'static str {
+        if self.general_tier_flag { "High" } else { "Main" }
+    }
+
+    /// The level as written, e.g. "5.1" (general_level_idc is 30 times it)
+    pub fn level_name(&self) -> String {
+        format!("{}.{}", self.general_level_idc / 30, self.general_level_idc % 30 / 3)
+    }
+}
+
+/// Video parameter set
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Vps {
+    pub vps_video_parameter_set_id: u8,
+    pub vps_max_layers: u32,
+    pub vps_max_sub_layers: u32,
+    pub profile_tier_level: ProfileTierLevel,
+    /// vps_num_units_in_tick and vps_time_scale
+    pub timing: Option<(u32, u32)>,
+}
+
+impl Vps {
+    /// Parse the RBSP of a VPS NAL unit (following its 2 byte header)
+    pub fn new(rbsp: &[u8]) -> Result<Vps, ParseError> {
+        let mut r = BitReader::new(rbsp);
+        let vps_video_parameter_set_id = r.bits(4, "vps_video_parameter_set_id")? as u8;
+        r.skip(2, "vps_base_layer_internal_flag")?;
+        let vps_max_layers = 1 + r.bits(6, "vps_max_layers_minus1")?;
+        let max_sub_layers_minus1 = r.bits(3, "vps_max_sub_layers_minus1")?;
+        r.skip(17, "vps_temporal_id_nesting_flag")?;
+        let profile_tier_level = ProfileTierLevel::new(&mut r, max_sub_layers_minus1)?;
+        let ordering_info_present = r.flag("vps_sub_layer_ordering_info_present_flag")?;
+        for _ in if ordering_info_present { 0 } else { max_sub_layers_minus1 }..=max_sub_layers_minus1 {
+            r.ue("vps_max_dec_pic_buffering_minus1")?;
+            r.ue("vps_max_num_reorder_pics")?;
+            r.ue("vps_max_latency_increase_plus1")?;
+        }
+        let vps_max_layer_id = r.bits(6, "vps_max_layer_id")? as usize;
+        let vps_num_layer_sets_minus1 = r.ue_max(1023, "vps_num_layer_sets_minus1")? as usize;
+        r.skip(vps_num_layer_sets_minus1 * (vps_max_layer_id + 1), "layer_id_included_flag")?;
+        let timing = if r.flag("vps_timing_info_present_flag")? {
+            Some((r.bits(32, "vps_num_units_in_tick")?, r.bits(32, "vps_time_scale")?))
+        } else {
+            None
+        };
+        Ok(Vps {
+            vps_video_parameter_set_id,
+            vps_max_layers,
+            vps_max_sub_layers: max_sub_layers_minus1 + 1,
+            profile_tier_level,
+            timing,
+        })
+    }
+}
+
+/// Skip a scaling_list_data()
+fn skip_scaling_list_data(r: &mut BitReader) -> Result<(), ParseError> {
+    for size_id in 0..4 {
+        for _ in (0..6).step_by(if size_id == 3 { 3 } else { 1 }) {
+            if !r.flag("scaling_list_pred_mode_flag")? {
+                r.ue("scaling_list_pred_matrix_id_delta")?;
+                continue;
+            }
+            if size_id > 1 {
+                r.se("scaling_list_dc_coef_minus8")?;
+            }
+            for _ in 0..(64).min(1 << (4 + (size_id << 1))) {
+                r.se("scaling_list_delta_coef")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Skip the st_ref_pic_set(idx) of a SPS, given NumDeltaPocs of the sets before it
+/// (the one of this set is added)
+fn skip_st_ref_pic_set(r: &mut BitReader, idx: usize, num_delta_pocs: &mut Vec<u32>) -> Result<(), ParseError> {
+    if idx != 0 && r.flag("inter_ref_pic_set_prediction_flag")? {
+        r.skip(1, "delta_rps_sign")?;
+        r.ue("abs_delta_rps_minus1")?;
+        let mut count = 0;
+        for _ in 0..=num_delta_pocs[idx - 1] {
+            let used_by_curr_pic_flag = r.flag("used_by_curr_pic_flag")?;
+            if used_by_curr_pic_flag || r.flag("use_delta_flag")? {
+                count += 1;
+            }
+        }
+        num_delta_pocs.push(count);
+    } else {
+        let num_negative_pics = r.ue_max(16, "num_negative_pics")?;
+        let num_positive_pics = r.ue_max(16, "num_positive_pics")?;
+        for _ in 0..num_negative_pics + num_positive_pics {
+            r.ue("delta_poc_minus1")?;
+            r.skip(1, "used_by_curr_pic_flag")?;
+        }
+        num_delta_pocs.push(num_negative_pics + num_positive_pics);
+    }
+    Ok(())
+}
+
+/// Parse the vui_parameters() up to the timing info, along with field_seq_flag
+fn parse_vui(r: &mut BitReader) -> Result<(Vui, bool), ParseError> {
+    let mut vui = Vui::default();
+    if r.flag("aspect_ratio_info_present_flag")? {
+        let aspect_ratio_idc = r.byte("aspect_ratio_idc")?;
+        vui.sample_aspect_ratio = if aspect_ratio_idc == 255 {
+            Some((r.bits(16, "sar_width")? as u16, r.bits(16, "sar_height")? as u16))
+        } else {
+            h264::sample_aspect_ratio(aspect_ratio_idc)
+        };
+    }
+    if r.flag("overscan_info_present_flag")? {
+        r.skip(1, "overscan_appropriate_flag")?;
+    }
+    if r.flag("video_signal_type_present_flag")? {
+        r.skip(3, "video_format")?;
+        vui.video_full_range_flag = r.flag("video_full_range_flag")?;
+        if r.flag("colour_description_present_flag")? {
+            vui.colour_primaries = Some(r.byte("colour_primaries")?);
+            vui.transfer_characteristics = Some(r.byte("transfer_characteristics")?);
+            vui.matrix_coefficients = Some(r.byte("matrix_coeffs")?);
+        }
+    }
+    if r.flag("chroma_loc_info_present_flag")? {
+        r.ue("chroma_sample_loc_type_top_field")?;
+        r.ue("chroma_sample_loc_type_bottom_field")?;
+    }
+    r.skip(1, "neutral_chroma_indication_flag")?;
+    let field_seq_flag = r.flag("field_seq_flag")?;
+    r.skip(1, "frame_field_info_present_flag")?;
+    if r.flag("default_display_window_flag")? {
+        for _ in 0..4 {
+            r.ue("def_disp_win_offset")?;
+        }
+    }
+    if r.flag("vui_timing_info_present_flag")? {
+        vui.num_units_in_tick = Some(r.bits(32, "vui_num_units_in_tick")?);
+        vui.time_scale = Some(r.bits(32, "vui_time_scale")?);
+    }
+    Ok((vui, field_seq_flag))
+}
+
+/// Sequence parameter set
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sps {
+    pub sps_video_parameter_set_id: u8,
+    pub profile_tier_level: ProfileTierLevel,
+    pub sps_seq_parameter_set_id: u32,
+    pub chroma_format_idc: u32,
+    pub separate_colour_plane_flag: bool,
+    pub pic_width_in_luma_samples: u32,
+    pub pic_height_in_luma_samples: u32,
+    /// conf_win_left/right/top/bottom_offset
+    pub conformance_window: Option<(u32, u32, u32, u32)>,
+    pub bit_depth_luma: u32,
+    pub bit_depth_chroma: u32,
+    pub log2_max_pic_order_cnt_lsb: u32,
+    /// CtbLog2SizeY
+    pub log2_ctb_size: u32,
+    pub vui: Option<Vui>,
+    /// Whether each picture is a field
+    pub field_seq_flag: bool,
+}
+
+impl Sps {
+    /// Parse the RBSP of a SPS NAL unit (following its 2 byte header)
+    pub fn new(rbsp: &[u8]) -> Result<Sps, ParseError> {
+        let mut r = BitReader::new(rbsp);
+        let sps_video_parameter_set_id = r.bits(4, "sps_video_parameter_set_id")? as u8;
+        let max_sub_layers_minus1 = r.bits(3, "sps_max_sub_layers_minus1")?;
+        r.skip(1, "sps_temporal_id_nesting_flag")?;
+        let profile_tier_level = ProfileTierLevel::new(&mut r, max_sub_layers_minus1)?;
+        let sps_seq_parameter_set_id = r.ue_max(15, "sps_seq_parameter_set_id")?;
+        let chroma_format_idc = r.ue_max(3, "chroma_format_idc")?;
+        let separate_colour_plane_flag = chroma_format_idc == 3 && r.flag("separate_colour_plane_flag")?;
+        let pic_width_in_luma_samples = r.ue("pic_width_in_luma_samples")?;
+        let pic_height_in_luma_samples = r.ue("pic_height_in_luma_samples")?;
+        let conformance_window = if r.flag("conformance_window_flag")? {
+            Some((r.ue("conf_win_left_offset")?, r.ue("conf_win_right_offset")?,
+                r.ue("conf_win_top_offset")?, r.ue("conf_win_bottom_offset")?))
+        } else {
+            None
+        };
+        let bit_depth_luma = 8 + r.ue_max(8, "bit_depth_luma_minus8")?;
+        let bit_depth_chroma = 8 + r.ue_max(8, "bit_depth_chroma_minus8")?;
+        let log2_max_pic_order_cnt_lsb = 4 + r.ue_max(12, "log2_max_pic_order_cnt_lsb_minus4")?;
+        let ordering_info_present = r.flag("sps_sub_layer_ordering_info_present_flag")?;
+        for _ in if ordering_info_present { 0 } else { max_sub_layers_minus1 }..=max_sub_layers_minus1 {
+            r.ue("sps_max_dec_pic_buffering_minus1")?;
+            r.ue("sps_max_num_reorder_pics")?;
+            r.ue("sps_max_latency_increase_plus1")?;
+        }
+        let log2_min_cb_size = 3 + r.ue_max(3, "log2_min_luma_coding_block_size_minus3")?;
+        let offset = r.offset();
+        let log2_ctb_size = log2_min_cb_size + r.ue_max(3, "log2_diff_max_min_luma_coding_block_size")?;
+        // CtbLog2SizeY is 4 to 6
+        if !(4..=6).contains(&log2_ctb_size) {
+            return Err(ParseError::InvalidValue { pid: None, offset, field: "log2_diff_max_min_luma_coding_block_size", value: log2_ctb_size });
+        }
+        r.ue("log2_min_luma_transform_block_size_minus2")?;
+        r.ue("log2_diff_max_min_luma_transform_block_size")?;
+        r.ue("max_transform_hierarchy_depth_inter")?;
+        r.ue("max_transform_hierarchy_depth_intra")?;
+        if r.flag("scaling_list_enabled_flag")? && r.flag("sps_scaling_list_data_present_flag")? {
+            skip_scaling_list_data(&mut r)?;
+        }
+        r.skip(2, "sample_adaptive_offset_enabled_flag")?;
+        if r.flag("pcm_enabled_flag")? {
+            r.skip(8, "pcm_sample_bit_depth_minus1")?;
+            r.ue("log2_min_pcm_luma_coding_block_size_minus3")?;
+            r.ue("log2_diff_max_min_pcm_luma_coding_block_size")?;
+            r.skip(1, "pcm_loop_filter_disabled_flag")?;
+        }
+        let num_short_term_ref_pic_sets = r.ue_max(64, "num_short_term_ref_pic_sets")? as usize;
+        let mut num_delta_pocs = vec![];
+        for idx in 0..num_short_term_ref_pic_sets {
+            skip_st_ref_pic_set(&mut r, idx, &mut num_delta_pocs)?;
+        }
+        if r.flag("long_term_ref_pics_present_flag")? {
+            for _ in 0..r.ue_max(32, "num_long_term_ref_pics_sps")? {
+                r.skip(log2_max_pic_order_cnt_lsb as usize + 1, "lt_ref_pic_poc_lsb_sps")?;
+            }
+        }
+        r.skip(2, "sps_temporal_mvp_enabled_flag")?;
+        let (vui, field_seq_flag) = if r.flag("vui_parameters_present_flag")? {
+            let (vui, field_seq_flag) = parse_vui(&mut r)?;
+            (Some(vui), field_seq_flag)
+        } else {
+            (None, false)
+        };
+
+        Ok(Sps {
+            sps_video_parameter_set_id,
+            profile_tier_level,
+            sps_seq_parameter_set_id,
+            chroma_format_idc,
+            separate_colour_plane_flag,
+            pic_width_in_luma_samples,
+            pic_height_in_luma_samples,
+            conformance_window,
+            bit_depth_luma,
+            bit_depth_chroma,
+            log2_max_pic_order_cnt_lsb,
+            log2_ctb_size,
+            vui,
+            field_seq_flag,
+        })
+    }
+
+    /// Size of the conformance window units in luma samples (horizontally, vertically)
+    fn window_units(&self) -> (u32, u32) {
+        match (self.separate_colour_plane_flag, self.chroma_format_idc) {
+            (false, 1) => (2, 2),
+            (false, 2) => (2, 1),
+            _ => (1, 1),
+        }
+    }
+
+    /// Width of the pictures in luma samples, after the conformance window
+    pub fn width(&self) -> u32 {
+        let (left, right, _, _) = self.conformance_window.unwrap_or_default();
+        let window = self.window_units().0.saturating_mul(left.saturating_add(right));
+        self.pic_width_in_luma_samples.saturating_sub(window)
+    }
+
+    /// Height of the pictures in luma samples, after the conformance window
+    pub fn height(&self) -> u32 {
+        let (_, _, top, bottom) = self.conformance_window.unwrap_or_default();
+        let window = self.window_units().1.saturating_mul(top.saturating_add(bottom));
+        self.pic_height_in_luma_samples.saturating_sub(window)
+    }
+
+    /// Pictures per second from the VUI timing info (one tick per picture), if there is any
+    pub fn frame_rate(&self) -> Option<f64> {
+        let vui = self.vui.as_ref()?;
+        match (vui.num_units_in_tick?, vui.time_scale?) {
+            (0, _) => None,
+            (num_units_in_tick, time_scale) => Some(f64::from(time_scale) / f64::from(num_units_in_tick)),
+        }
+    }
+
+    /// PicSizeInCtbsY
+    fn pic_size_in_ctbs(&self) -> u32 {
+        let ctb_size = 1 << self.log2_ctb_size;
+        self.pic_width_in_luma_samples.div_ceil(ctb_size)
+            .saturating_mul(self.pic_height_in_luma_samples.div_ceil(ctb_size))
+    }
+
+    pub fn chroma_format_name(&self) -> &'static str {
+        match self.chroma_format_idc {
+            0 => "4:0:0",
+            1 => "4:2:0",
+            2 => "4:2:2",
+            _ => "4:4:4",
+        }
+    }
+}
+
+/// Picture parameter set (the fields the slice segment headers depend on)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pps {
+    pub pps_pic_parameter_set_id: u32,
+    pub pps_seq_parameter_set_id: u32,
+    pub dependent_slice_segments_enabled_flag: bool,
+    pub output_flag_present_flag: bool,
+    pub num_extra_slice_header_bits: u32,
+}
+
+impl Pps {
+    /// Parse the RBSP of a PPS NAL unit (following its 2 byte header)
+    pub fn new(rbsp: &[u8]) -> Result<Pps, ParseError> {
+        let mut r = BitReader::new(rbsp);
+        Ok(Pps {
+            pps_pic_parameter_set_id: r.ue_max(63, "pps_pic_parameter_set_id")?,
+            pps_seq_parameter_set_id: r.ue_max(15, "pps_seq_parameter_set_id")?,
+            dependent_slice_segments_enabled_flag: r.flag("dependent_slice_segments_enabled_flag")?,
+            output_flag_present_flag: r.flag("output_flag_present_flag")?,
+            num_extra_slice_header_bits: r.bits(3, "num_extra_slice_header_bits")?,
+        })
+    }
+}
+
+/// The start of a slice segment header
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SliceSegmentHeader {
+    pub first_slice_segment_in_pic_flag: bool,
+    pub slice_pic_parameter_set_id: u32,
+    pub dependent_slice_segment_flag: bool,
+    /// 0 B, 1 P, 2 I (None for dependent slice segments, which take the one of their slice)
+    pub slice_type: Option<u32>,
+    /// None for IDR pictures and dependent slice segments
+    pub slice_pic_order_cnt_lsb: Option<u32>,
+}
+
+impl SliceSegmentHeader {
+    /// Parse the start of the slice segment header in the RBSP of a slice NAL unit (following
+    /// its 2 byte header), with the parameter sets seen so far
+    pub fn new(rbsp: &[u8], nal_unit_type: u8, sps: &HashMap<u32, Sps>, pps: &HashMap<u32, Pps>)
+        -> Result<SliceSegmentHeader, ParseError> {
+        let mut r = BitReader::new(rbsp);
+        let first_slice_segment_in_pic_flag = r.flag("first_slice_segment_in_pic_flag")?;
+        if (NAL_BLA_W_LP..=23).contains(&nal_unit_type) {
+            r.skip(1, "no_output_of_prior_pics_flag")?;
+        }
+        let slice_pic_parameter_set_id = r.ue("slice_pic_parameter_set_id")?;
+        let invalid = |field, value| ParseError::InvalidValue { pid: None, offset: 0, field, value };
+        let pps = pps.get(&slice_pic_parameter_set_id).ok_or_else(|| invalid("slice_pic_parameter_set_id", slice_pic_parameter_set_id))?;
+        let sps = sps.get(&pps.pps_seq_parameter_set_id).ok_or_else(|| invalid("pps_seq_parameter_set_id", pps.pps_seq_parameter_set_id))?;
+
+        let mut dependent_slice_segment_flag = false;
+        if !first_slice_segment_in_pic_flag {
+            dependent_slice_segment_flag = pps.dependent_slice_segments_enabled_flag &&
+                r.flag("dependent_slice_segment_flag")?;
+            // Ceil(Log2(PicSizeInCtbsY)) bits
+            let address_bits = 32 - (sps.pic_size_in_ctbs().max(1) - 1).leading_zeros();
+            r.skip(address_bits as usize, "slice_segment_address")?;
+        }
+        let (mut slice_type, mut slice_pic_order_cnt_lsb) = (None, None);
+        if !dependent_slice_segment_flag {
+            r.skip(pps.num_extra_slice_header_bits as usize, "slice_reserved_flag")?;
+            slice_type = Some(r.ue_max(2, "slice_type")?);
+            if pps.output_flag_present_flag {
+                r.skip(1, "pic_output_flag")?;
+            }
+            if sps.separate_colour_plane_flag {
+                r.skip(2, "colour_plane_id")?;
+            }
+            if nal_unit_type != NAL_IDR_W_RADL && nal_unit_type != NAL_IDR_N_LP {
+                slice_pic_order_cnt_lsb = Some(r.bits(sps.log2_max_pic_order_cnt_lsb, "slice_pic_order_cnt_lsb")?);
+            }
+        }
+        Ok(SliceSegmentHeader {
+            first_slice_segment_in_pic_flag,
+            slice_pic_parameter_set_id,
+            dependent_slice_segment_flag,
+            slice_type,
+            slice_pic_order_cnt_lsb,
+        })
+    }
+}
+
+/// What a picture is for random access, from the nal_unit_type of its slices
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum PictureType {
+    Idr,
+    Cra,
+    Bla,
+    /// Random access skipped leading picture
+    Rasl,
+    /// Random access decodable leading picture
+    Radl,
+    /// Any other picture (trailing, TSA, STSA)
+    Trailing,
+}
+
+impl PictureType {
+    pub fn from_nal_unit_type(nal_unit_type: u8) -> PictureType {
+        match nal_unit_type {
+            NAL_IDR_W_RADL | NAL_IDR_N_LP => PictureType::Idr,
+            NAL_CRA => PictureType::Cra,
+            NAL_BLA_W_LP..=18 => PictureType::Bla,
+            NAL_RASL_N | NAL_RASL_R => PictureType::Rasl,
+            6 | 7 => PictureType::Radl,
+            _ => PictureType::Trailing,
+        }
+    }
+
+    /// Intra random access point pictures (where decoding can start)
+    pub fn is_irap(&self) -> bool {
+        matches!(self, PictureType::Idr | PictureType::Cra | PictureType::Bla)
+    }
+}
+
+impl fmt::Display for PictureType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            PictureType::Idr => "IDR",
+            PictureType::Cra => "CRA",
+            PictureType::Bla => "BLA",
+            PictureType::Rasl => "RASL",
+            PictureType::Radl => "RADL",
+            PictureType::Trailing => "Trailing",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// mastering_display_colour_volume SEI
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MasteringDisplay {
+    /// display_primaries_x/y of the green, blue and red primaries in units of 0.00002
+    pub display_primaries: [(u16, u16); 3],
+    pub white_point: (u16, u16),
+    /// In units of 0.0001 cd/m2
+    pub max_display_mastering_luminance: u32,
+    pub min_display_mastering_luminance: u32,
+}
+
+impl MasteringDisplay {
+    pub fn new(payload: &[u8]) -> Result<MasteringDisplay, ParseError> {
+        let mut r = BitReader::new(payload);
+        let mut display_primaries = [(0, 0); 3];
+        for primary in display_primaries.iter_mut() {
+            *primary = (r.bits(16, "display_primaries_x")? as u16, r.bits(16, "display_primaries_y")? as u16);
+        }
+        Ok(MasteringDisplay {
+            display_primaries,
+            white_point: (r.bits(16, "white_point_x")? as u16, r.bits(16, "white_point_y")? as u16),
+            max_display_mastering_luminance: r.bits(32, "max_display_mastering_luminance")?,
+            min_display_mastering_luminance: r.bits(32, "min_display_mastering_luminance")?,
+        })
+    }
+}
+
+impl fmt::Display for MasteringDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let xy = |(x, y): (u16, u16)| format!("({:.4}, {:.4})", f64::from(x) * 0.00002, f64::from(y) * 0.00002);
+        let [g, b, r] = self.display_primaries;
+        write!(f, "R {}, G {}, B {}, White point {}, Luminance {:.4}-{:.0} cd/m2", xy(r), xy(g), xy(b),
+            xy(self.white_point), f64::from(self.min_display_mastering_luminance) * 0.0001,
+            f64::from(self.max_display_mastering_luminance) * 0.0001)
+    }
+}
+
+/// The picture being assembled from its slice segments
+#[derive(Clone, Debug)]
+struct Picture {
+    picture_type: PictureType,
+    frame_type: FrameType,
+}
+
+/// What the parameter sets, SEI and slices of an HEVC stream say about it
+#[derive(Clone, Debug, Default)]
+pub struct Stream {
+    pub pid: u16,
+    pub program_number: u16,
+    /// The last VPS, SPS and PPS by id
+    pub vps: HashMap<u8, Vps>,
+    pub sps: HashMap<u32, Sps>,
+    pub pps: HashMap<u32, Pps>,
+    /// The SPS of the last picture
+    pub active_sps: Option<u32>,
+    pub mastering_display: Option<MasteringDisplay>,
+    /// max_content_light_level and max_pic_average_light_level in cd/m2
+    pub content_light_level: Option<(u16, u16)>,
+    /// preferred_transfer_characteristics of the alternative_transfer_characteristics SEI
+    /// (HLG streams signal 18 there while the VUI says 14 for older decoders)
+    pub preferred_transfer_characteristics: Option<u8>,
+    /// Number of pictures of each kind and of each slice type
+    pub picture_counts: BTreeMap<PictureType, u64>,
+    pub frame_counts: BTreeMap<FrameType, u64>,
+    /// The GOPs, each starting with an IRAP picture; open ones have RASL pictures
+    pub gops: Vec<Gop>,
+    /// Number of pictures between consecutive IRAP pictures
+    pub irap_intervals: Vec<u64>,
+    pub nal_units: u64,
+    picture: Option<Picture>,
+    frames: u64,
+    last_irap: Option<u64>,
+}
+
+impl Stream {
+    fn new(pid: u16, program_number: u16) -> Stream {
+        Stream { pid, program_number, ..Default::default() }
+    }
+
+    /// Handle the NAL units of a PES packet
+    fn push(&mut self, payload: &[u8]) -> Vec<ParseError> {
+        let mut errors = vec![];
+        for nal in bits::annex_b_units(payload) {
+            self.nal_units += 1;
+            let nal_unit_type = (nal[0] >> 1) & 0x3F;
+            let nuh_layer_id = ((nal[0] & 0x01) << 5) | (nal.get(1).unwrap_or(&0) >> 3);
+            if nal.len() < 2 || nuh_layer_id != 0 {
+                continue;
+            }
+            let rbsp = bits::rbsp(&nal[2..]);
+            let result = match nal_unit_type {
+                NAL_VPS => Vps::new(&rbsp).map(|vps| { self.vps.insert(vps.vps_video_parameter_set_id, vps); }),
+                NAL_SPS => Sps::new(&rbsp).map(|sps| { self.sps.insert(sps.sps_seq_parameter_set_id, sps); }),
+                NAL_PPS => Pps::new(&rbsp).map(|pps| { self.pps.insert(pps.pps_pic_parameter_set_id, pps); }),
+                NAL_PREFIX_SEI => self.push_sei(&rbsp),
+                NAL_AUD => {
+                    self.finish_picture();
+                    Ok(())
+                },
+                0..=9 | NAL_BLA_W_LP..=NAL_CRA => SliceSegmentHeader::new(&rbsp, nal_unit_type, &self.sps, &self.pps)
+                    .map(|slice| self.push_slice(&slice, nal_unit_type)),
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                errors.push(e.with_pid(self.pid));
+            }
+        }
+        errors
+    }
+
+    /// Keep the HDR metadata of a prefix SEI
+    fn push_sei(&mut self, rbsp: &[u8]) -> Result<(), ParseError> {
+        for (payload_type, payload) in h264::sei_messages(rbsp) {
+            let mut r = BitReader::new(payload);
+            match payload_type {
+                SEI_MASTERING_DISPLAY_COLOUR_VOLUME => self.mastering_display = Some(MasteringDisplay::new(payload)?),
+                SEI_CONTENT_LIGHT_LEVEL_INFO => self.content_light_level = Some((
+                    r.bits(16, "max_content_light_level")? as u16, r.bits(16, "max_pic_average_light_level")? as u16)),
+                SEI_ALTERNATIVE_TRANSFER_CHARACTERISTICS =>
+                    self.preferred_transfer_characteristics = Some(r.byte("preferred_transfer_characteristics")?),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a slice segment to the current picture, or start a new one
+    fn push_slice(&mut self, slice: &SliceSegmentHeader, nal_unit_type: u8) {
+        let frame_type = match slice.slice_type {
+            Some(0) => FrameType::B,
+            Some(1) => FrameType::P,
+            _ if matches!(nal_unit_type, NAL_IDR_W_RADL | NAL_IDR_N_LP) => FrameType::Idr,
+            _ => FrameType::I,
+        };
+        if let Some(sps) = self.pps.get(&slice.slice_pic_parameter_set_id).map(|p| p.pps_seq_parameter_set_id) {
+            self.active_sps = Some(sps);
+        }
+        if slice.first_slice_segment_in_pic_flag {
+            self.finish_picture();
+            self.picture = Some(Picture { picture_type: PictureType::from_nal_unit_type(nal_unit_type), frame_type });
+        } else if let Some(p) = self.picture.as_mut() {
+            if frame_type > p.frame_type && p.frame_type != FrameType::Idr {
+                p.frame_type = frame_type;
+            }
+        }
+    }
+
+    /// Count the current picture and add it to its GOP
+    fn finish_picture(&mut self) {
+        let picture = match self.picture.take() {
+            Some(picture) => picture,
+            None => return,
+        };
+        let index = self.frames;
+        self.frames += 1;
+        *self.picture_counts.entry(picture.picture_type).or_insert(0) += 1;
+        *self.frame_counts.entry(picture.frame_type).or_insert(0) += 1;
+
+        if picture.picture_type.is_irap() {
+            if let Some(last) = self.last_irap.replace(index) {
+                self.irap_intervals.push(index - last);
+            }
+            self.gops.push(Gop {
+                start: index,
+                idr: picture.picture_type == PictureType::Idr,
+                open: false,
+                pattern: String::new(),
+            });
+        }
+        if let Some(gop) = self.gops.last_mut() {
+            gop.open |= picture.picture_type == PictureType::Rasl;
+            gop.pattern.push(picture.frame_type.letter());
+        }
+    }
+
+    /// The SPS of the last picture
+    pub fn get_sps(&self) -> Option<&Sps> {
+        self.sps.get(&self.active_sps?)
+    }
+
+    /// Total number of pictures
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[HEVC] PID {:#X} (Program {})", self.pid, self.program_number)?;
+        if let Some(sps) = self.get_sps() {
+            let ptl = &sps.profile_tier_level;
+            write!(f, ": {} profile, {} tier, Level {}, {}x{}", ptl.profile_name(), ptl.tier_name(),
+                ptl.level_name(), sps.width(), sps.height())?;
+            if let Some(frame_rate) = sps.frame_rate() {
+                write!(f, ", {:.3} {}", frame_rate, if sps.field_seq_flag { "fields/s" } else { "fps" })?;
+            }
+            write!(f, ", {} {} bit", sps.chroma_format_name(), sps.bit_depth_luma)?;
+            if let Some(vui) = &sps.vui {
+                if let (Some(primaries), Some(transfer)) = (vui.colour_primaries_name(), vui.transfer_characteristics_name()) {
+                    write!(f, "\n\t=> Colour: {} primaries, {} transfer", primaries, transfer)?;
+                    let preferred = Vui { transfer_characteristics: self.preferred_transfer_characteristics, ..Default::default() };
+                    if let Some(name) = preferred.transfer_characteristics_name() {
+                        write!(f, " (preferred {})", name)?;
+                    }
+                    write!(f, ", {} range", if vui.video_full_range_flag { "full" } else { "limited" })?;
+                }
+            }
+        }
+        if let Some(mastering_display) = &self.mastering_display {
+            write!(f, "\n\t=> Mastering display: {}", mastering_display)?;
+        }
+        if let Some((max_cll, max_fall)) = self.content_light_level {
+            write!(f, "\n\t=> MaxCLL: {} cd/m2, MaxFALL: {} cd/m2", max_cll, max_fall)?;
+        }
+
+        write!(f, "\n\t=> Pictures: {} (", self.frames)?;
+        for (i, (picture_type, count)) in self.picture_counts.iter().enumerate() {
+            write!(f, "{}{} {}", if i == 0 { "" } else { ", " }, picture_type, count)?;
+        }
+        let count = |t| self.frame_counts.get(&t).copied().unwrap_or(0);
+        write!(f, "), Slice types: I {}, P {}, B {}, NAL units: {}",
+            count(FrameType::Idr) + count(FrameType::I), count(FrameType::P), count(FrameType::B), self.nal_units)?;
+
+        // The last GOP is cut short by the end of the capture, so leave it out of the lengths
+        let complete = &self.gops[..self.gops.len().saturating_sub(1)];
+        let open = self.gops.iter().filter(|g| g.open).count();
+        write!(f, "\n\t=> GOPs: {} ({} closed, {} open)", self.gops.len(), self.gops.len() - open, open)?;
+        if let (Some(min), Some(max)) = (self.irap_intervals.iter().min(), self.irap_intervals.iter().max()) {
+            if min == max {
+                write!(f, ", IRAP interval: {}", min)?;
+            } else {
+                write!(f, ", IRAP interval: {}-{}", min, max)?;
+            }
+        }
+        let mut patterns = HashSet::new();
+        for gop in complete.iter().filter(|g| patterns.insert(g.pattern.as_str())).take(4) {
+            write!(f, "\n\t=> GOP {}{}: {}", if gop.idr { "IDR" } else { "CRA/BLA" },
+                if gop.open { " (open)" } else { "" }, gop.pattern)?;
+        }
+        Ok(())
+    }
+}
+
+/// Follows the parameter sets, SEI and slices of the HEVC streams as a DemuxHandler,
+/// working out each one's format, HDR signalling and random access points
+#[derive(Clone, Debug, Default)]
+pub struct HevcAnalyzer {
+    streams: BTreeMap<u16, Stream>,
+    offset: u64,
+    errors: Vec<(u64, ParseError)>,
+}
+
+impl HevcAnalyzer {
+    pub fn new() -> HevcAnalyzer {
+        Default::default()
+    }
+
+    /// The HEVC streams found so far by PID
+    pub fn streams(&self) -> &BTreeMap<u16, Stream> {
+        &self.streams
+    }
+
+    /// The offset of every NAL unit that failed to parse (or the packet that completed its
+    /// PES packet), with why
+    pub fn errors(&self) -> &[(u64, ParseError)] {
+        &self.errors
+    }
+}
+
+impl DemuxHandler for HevcAnalyzer {
+    fn on_packet(&mut self, packet: &Packet) {
+        self.offset = packet.offset;
+    }
+
+    fn on_pmt(&mut self, _pid: u16, pmt: &Pmt) {
+        for es in pmt.elementary_streams.iter().filter(|es| es.get_codec() == Codec::Hevc) {
+            let pid = es.get_elementary_pid();
+            self.streams.entry(pid).or_insert_with(|| Stream::new(pid, pmt.get_program_number()));
+        }
+    }
+
+    fn on_pes(&mut self, pid: u16, pes: &Pes) {
+        if let Some(stream) = self.streams.get_mut(&pid) {
+            let offset = self.offset;
+            self.errors.extend(stream.push(&pes.payload).into_iter().map(|e| (offset, e)));
+        }
+    }
+
+    fn on_end(&mut self) {
+        for stream in self.streams.values_mut() {
+            stream.finish_picture();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, BitWriter};
+
+    /// A Main 10 profile_tier_level() at level 4.1 with no sub-layers
+    fn profile_tier_level(w: &mut BitWriter) {
+        w.bits(2, 0).flag(false).bits(5, 2).bits(32, 0x2000_0000).flag(true).flag(false).bits(46, 0).bits(8, 123);
+    }
+
+    fn vps() -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.bits(4, 0).bits(2, 3).bits(6, 0).bits(3, 0).flag(true).bits(16, 0xFFFF);
+        profile_tier_level(&mut w);
+        w.flag(true).ue(4).ue(2).ue(0);
+        w.bits(6, 0).ue(0).flag(true).bits(32, 1).bits(32, 50);
+        // vps_poc_proportional_to_timing_flag, vps_num_hrd_parameters, vps_extension_flag
+        w.flag(false).ue(0).flag(false);
+        w.rbsp()
+    }
+
+    /// A 4:2:0 10 bit 1920x1080 SPS (1088 lines in a conformance window of 1080) at 50 fps,
+    /// with 8x8 coding blocks and CTBs `log2_diff_max_min_cb` times as large
+    fn sps_with(log2_diff_max_min_cb: u32) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.bits(4, 0).bits(3, 0).flag(true);
+        profile_tier_level(&mut w);
+        w.ue(0).ue(1).ue(1920).ue(1088).flag(true).ue(0).ue(0).ue(0).ue(4);
+        w.ue(2).ue(2).ue(4).flag(true).ue(4).ue(2).ue(0);
+        // 4x4 to 32x32 transform blocks
+        w.ue(0).ue(log2_diff_max_min_cb).ue(0).ue(3).ue(0).ue(0);
+        // No scaling lists, AMP and SAO, no PCM
+        w.flag(false).bits(2, 3).flag(false);
+        // One short term reference picture set: the picture before
+        w.ue(1).ue(1).ue(0).ue(0).flag(true);
+        w.flag(false).bits(2, 3);
+        // VUI: 1:1, BT.2020 PQ, 50 pictures per second
+        w.flag(true).flag(true).bits(8, 1).flag(false);
+        w.flag(true).bits(3, 5).flag(false).flag(true).bits(8, 9).bits(8, 16).bits(8, 9);
+        w.flag(false).flag(false).flag(false).flag(false).flag(false);
+        w.flag(true).bits(32, 1).bits(32, 50);
+        w.rbsp()
+    }
+
+    /// `sps_with` 64x64 CTBs
+    fn sps() -> Vec<u8> {
+        sps_with(3)
+    }
+
+    #[test]
+    fn parameter_sets() {
+        let vps = Vps::new(&vps()).unwrap();
+        assert_eq!((vps.vps_max_layers, vps.vps_max_sub_layers, vps.timing), (1, 1, Some((1, 50))));
+        let ptl = &vps.profile_tier_level;
+        assert_eq!((ptl.profile_name(), ptl.tier_name(), ptl.level_name()), ("Main 10", "Main", "4.1".to_string()));
+
+        let sps = Sps::new(&sps()).unwrap();
+        assert_eq!(sps.profile_tier_level, vps.profile_tier_level);
+        assert_eq!((sps.width(), sps.height(), sps.frame_rate()), (1920, 1080, Some(50.0)));
+        assert_eq!((sps.bit_depth_luma, sps.chroma_format_name(), sps.log2_ctb_size), (10, "4:2:0", 6));
+        assert_eq!(sps.pic_size_in_ctbs(), 30 * 17);
+        let vui = sps.vui.unwrap();
+        assert_eq!(vui.transfer_characteristics_name(), Some("PQ (SMPTE ST 2084)"));
+    }
+
+    #[test]
+    fn invalid_parameter_sets() {
+        let buf = vps();
+        for len in 0..buf.len() {
+            assert!(Vps::new(&buf[..len]).is_err(), "{} bytes of the VPS", len);
+        }
+        let buf = sps();
+        for len in 0..buf.len() {
+            assert!(Sps::new(&buf[..len]).is_err(), "{} bytes of the SPS", len);
+        }
+        let mut w = BitWriter::default();
+        w.bits(4, 0).bits(3, 0).flag(true);
+        profile_tier_level(&mut w);
+        w.ue(16);
+        assert_eq!(Sps::new(&w.rbsp()),
+            Err(ParseError::InvalidValue { pid: None, offset: 13, field: "sps_seq_parameter_set_id", value: 16 }));
+        // 8x8 CTBs, smaller than the 16x16 minimum
+        assert_eq!(Sps::new(&sps_with(0)),
+            Err(ParseError::InvalidValue { pid: None, offset: 22, field: "log2_diff_max_min_luma_coding_block_size", value: 3 }));
+    }
+
+    fn pps() -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.ue(0).ue(0).flag(false).flag(false).bits(3, 0);
+        w.rbsp()
+    }
+
+    /// A prefix SEI with the HDR10 mastering display and light levels, preferring HLG
+    fn sei() -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.bits(8, u64::from(SEI_MASTERING_DISPLAY_COLOUR_VOLUME)).bits(8, 24);
+        for value in [13250, 34500, 7500, 3000, 34000, 16000, 15635, 16450] {
+            w.bits(16, value);
+        }
+        w.bits(32, 10_000_000).bits(32, 50);
+        w.bits(8, u64::from(SEI_CONTENT_LIGHT_LEVEL_INFO)).bits(8, 4).bits(16, 1000).bits(16, 400);
+        w.bits(8, u64::from(SEI_ALTERNATIVE_TRANSFER_CHARACTERISTICS)).bits(8, 1).bits(8, 18);
+        w.rbsp()
+    }
+
+    /// A slice segment NAL unit: the first of its picture or the second (at CTB 255)
+    fn slice(nal_unit_type: u8, first: bool, slice_type: u32, pic_order_cnt_lsb: u64) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.flag(first);
+        if (NAL_BLA_W_LP..=23).contains(&nal_unit_type) {
+            w.flag(false);
+        }
+        w.ue(0);
+        if !first {
+            // Ceil(Log2(30 * 17)) bits
+            w.bits(9, 255);
+        }
+        w.ue(slice_type);
+        if nal_unit_type != NAL_IDR_W_RADL && nal_unit_type != NAL_IDR_N_LP {
+            w.bits(8, pic_order_cnt_lsb);
+        }
+        test_util::nal_unit(&[nal_unit_type << 1, 0x01], &w.rbsp())
+    }
+
+    const TRAIL_N: u8 = 0;
+    const TRAIL_R: u8 = 1;
+    const B: u32 = 0;
+    const P: u32 = 1;
+    const I: u32 = 2;
+
+    #[test]
+    fn random_access_points() {
+        let mut buf = test_util::nal_unit(&[NAL_VPS << 1, 0x01], &vps());
+        buf.extend(test_util::nal_unit(&[NAL_SPS << 1, 0x01], &sps()));
+        buf.extend(test_util::nal_unit(&[NAL_PPS << 1, 0x01], &pps()));
+        buf.extend(test_util::nal_unit(&[NAL_PREFIX_SEI << 1, 0x01], &sei()));
+        // A closed GOP from an IDR picture, an open one from a CRA picture with RASL pictures
+        // (output before it), then a CRA picture without any
+        for (nal_unit_type, slice_type, poc) in [(NAL_IDR_W_RADL, I, 0), (TRAIL_R, P, 4), (TRAIL_N, B, 2),
+            (TRAIL_N, B, 3), (NAL_CRA, I, 8), (NAL_RASL_N, B, 6), (NAL_RASL_N, B, 7), (TRAIL_R, P, 12),
+            (NAL_CRA, I, 16), (TRAIL_R, P, 20)] {
+            // Two slices for each picture
+            buf.extend(slice(nal_unit_type, true, slice_type, poc));
+            buf.extend(slice(nal_unit_type, false, slice_type, poc));
+        }
+        let mut stream = Stream::new(0x100, 1);
+        assert_eq!(stream.push(&buf), vec![]);
+        stream.finish_picture();
+
+        let gops: Vec<(bool, bool, &str)> = stream.gops.iter().map(|g| (g.idr, g.open, g.pattern.as_str())).collect();
+        assert_eq!(gops, vec![(true, false, "IPBB"), (false, true, "IBBP"), (false, false, "IP")]);
+        assert_eq!(stream.irap_intervals, vec![4, 4]);
+        assert_eq!(stream.to_string(), "[HEVC] PID 0x100 (Program 1): Main 10 profile, Main tier, Level 4.1, \
+            1920x1080, 50.000 fps, 4:2:0 10 bit\
+            \n\t=> Colour: BT.2020 primaries, PQ (SMPTE ST 2084) transfer (preferred HLG (ARIB STD-B67)), limited range\
+            \n\t=> Mastering display: R (0.6800, 0.3200), G (0.2650, 0.6900), B (0.1500, 0.0600), \
+            White point (0.3127, 0.3290), Luminance 0.0050-1000 cd/m2\
+            \n\t=> MaxCLL: 1000 cd/m2, MaxFALL: 400 cd/m2\
+            \n\t=> Pictures: 10 (IDR 1, CRA 2, RASL 2, Trailing 5), Slice types: I 3, P 3, B 4, NAL units: 24\
+            \n\t=> GOPs: 3 (2 closed, 1 open), IRAP interval: 4\
+            \n\t=> GOP IDR: IPBB\
+            \n\t=> GOP CRA/BLA (open): IBBP");
+    }
+}
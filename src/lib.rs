@@ -1,35 +1,96 @@
+use std::fmt;
 use std::collections::HashMap;
 
+pub mod audio;
+pub mod bits;
+pub mod captions;
+pub mod codec;
+pub mod demux;
+pub mod epg;
+pub mod h264;
+pub mod hevc;
+pub mod error;
+pub mod mpeg2video;
 pub mod mpeg32_crc;
 pub mod packet;
+pub mod pes;
 pub mod psi;
+pub mod reader;
+pub mod scte35;
+pub mod timing;
+pub mod tr101290;
+#[cfg(test)]
+mod test_util;
+
+/// What a PID carries, as learnt from the PSI tables
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PidRole {
+    Pat,
+    Cat,
+    Pmt,
+    Nit,
+    Sdt,
+    Eit,
+    Tdt,
+    Psip,
+    Emm,
+    Elementary,
+    Null,
+}
+
+impl fmt::Display for PidRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            PidRole::Pat => "PAT",
+            PidRole::Cat => "CAT",
+            PidRole::Pmt => "PMT",
+            PidRole::Nit => "NIT",
+            PidRole::Sdt => "SDT/BAT",
+            PidRole::Eit => "EIT",
+            PidRole::Tdt => "TDT/TOT",
+            PidRole::Psip => "ATSC PSIP",
+            PidRole::Emm => "EMM",
+            PidRole::Elementary => "Elementary stream",
+            PidRole::Null => "Null packets",
+        };
+        write!(f, "{}", name)
+    }
+}
 
 #[derive(Copy, Clone, Debug, Default)]
 pub struct PidErrors {
     pub cc_errors: u32,
     pub crc_errors: u32,
+    pub parse_errors: u32,
 }
 
 #[derive(Debug, Default)]
 pub struct PidState {
     pub count: u32,
     pub duplicate_count: u32,
+    /// What the PID carries (None if no table refers to it)
+    pub role: Option<PidRole>,
     pub prev_packet: packet::Packet,
     pub errors: PidErrors,
+    pub sections: psi::section::SectionAssembler,
+    pub pes: pes::PesAssembler,
+    /// Crc of the last section seen for each (table_id, table_id_extension, section_number)
+    pub psi_crcs: HashMap<(u8, u16, u8), u32>,
 }
 
 impl PidState {
     /// Print out all the Pids and there states
     pub fn display_states(states: &HashMap<u16, PidState>) {
-        let total_count: u32 = states.iter().map(|(_,s)| s.count).sum();
-        println!("");
+        let total_count: u32 = states.values().map(|s| s.count).sum();
+        println!();
         println!("PIDs:");
         println!("-----");
         for (pid, state) in states.iter() {
-            println!("[PID] {}, Count: {} ({:2.2}%)", pid, state.count,
+            let role = state.role.map_or(String::new(), |r| format!(" ({})", r));
+            println!("[PID] {}{}, Count: {} ({:2.2}%)", pid, role, state.count,
                 (state.count as f64 / total_count as f64) * 100f64);
-            println!("\t=> Continuity errors: {}, Crc errors: {}",
-                state.errors.cc_errors, state.errors.crc_errors);
+            println!("\t=> Continuity errors: {}, Crc errors: {}, Parse errors: {}",
+                state.errors.cc_errors, state.errors.crc_errors, state.errors.parse_errors);
         }
     }
 }
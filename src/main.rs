@@ -1,20 +1,122 @@
 use std::{
     fs::File,
     io::prelude::*,
-    collections::HashMap,
-    collections::HashSet,
 };
-use mpeg_parser::{PidState, packet::*};
+use mpeg_parser::{
+    PidState,
+    demux::{Demuxer, DemuxHandler},
+    error::ParseError,
+    packet::*,
+    pes::Pes,
+    psi::{cat::Cat, eit::Eit, nit::Nit, pat::Pat, pmt::Pmt, psip::Psip, sdt::Sdt},
+    audio, captions, epg, h264, hevc, mpeg2video, scte35, timing, tr101290,
+};
+
+/// Prints the tables (and optionally the PES packets) as the demuxer finds them
+struct Printer {
+    show_pes: bool,
+}
+
+impl DemuxHandler for Printer {
+    fn on_pat(&mut self, pat: &Pat) {
+        println!("{}", pat);
+    }
+
+    fn on_cat(&mut self, cat: &Cat) {
+        println!("{}", cat);
+    }
+
+    fn on_pmt(&mut self, _pid: u16, pmt: &Pmt) {
+        println!("{}", pmt);
+    }
+
+    fn on_nit(&mut self, _pid: u16, nit: &Nit) {
+        println!("{}", nit);
+    }
+
+    fn on_sdt(&mut self, _pid: u16, sdt: &Sdt) {
+        println!("{}", sdt);
+    }
+
+    fn on_eit(&mut self, _pid: u16, eit: &Eit) {
+        // The schedules are too long to print, they're only exported with --xmltv
+        if eit.is_present_following() {
+            println!("{}", eit);
+        }
+    }
+
+    fn on_psip(&mut self, _pid: u16, psip: &Psip) {
+        // The STT comes every second, it's only used for the time
+        if !matches!(psip, Psip::Stt(_)) {
+            println!("{}", psip);
+        }
+    }
+
+    fn on_pes(&mut self, pid: u16, pes: &Pes) {
+        if self.show_pes {
+            println!("PID {:#X}: {}", pid, pes);
+        }
+    }
+
+    fn on_error(&mut self, offset: u64, error: &ParseError) {
+        eprintln!("Parse error at offset {}: {}", offset, error);
+    }
+
+    fn on_sync_lost(&mut self, offset: u64) {
+        eprintln!("Sync lost at offset {}", offset);
+    }
+
+    fn on_sync_acquired(&mut self, offset: u64, skipped: u64) {
+        if skipped > 0 {
+            eprintln!("Sync acquired at offset {} after skipping {} bytes", offset, skipped);
+        }
+    }
+}
 
 // Usage:
-// mpeg-parser <filename>
+// mpeg-parser [--pes] [--tr101290] [--timing] [--timing-series] [--scte35] [--video] [--audio] [--captions] [--srt <output>] [--vtt <output>] [--xmltv <output>] <filename>
 //
 // Arguments:
 //     - filename
 //         Path to TS stream file
+//     - --pes
+//         Print every reassembled PES packet (with its PTS/DTS)
+//     - --tr101290
+//         Run the ETSI TR 101 290 checks and print their findings
+//     - --timing
+//         Recover the system clock of each program and print its PCR interval, jitter and drift
+//     - --timing-series
+//         Like --timing, also printing the measurements at every PCR
+//     - --scte35
+//         Decode the SCTE-35 cues and print their splice times relative to the program's video
+//     - --video
+//         Parse the MPEG-2, H.264 and HEVC video streams and print their format, HDR signalling and GOP structure
+//     - --audio
+//         Parse the audio frames and print their format, sync errors and format changes
+//     - --captions
+//         Extract the CEA-608/708 captions of the video streams and print a summary of each channel
+//     - --srt <output>, --vtt <output>
+//         Also write the captions to output as SubRip or WebVTT (one file per channel when
+//         there are several, named after the PID and channel)
+//     - --xmltv <output>
+//         Collect the programme guide of the EITs and write it to output as XMLTV
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let filename = if args.len() == 2 { &args[1] } else { "media/fifth-new.ts" };
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let show_pes = args.iter().any(|a| a == "--pes");
+    let run_tr101290 = args.iter().any(|a| a == "--tr101290");
+    let show_series = args.iter().any(|a| a == "--timing-series");
+    let run_timing = show_series || args.iter().any(|a| a == "--timing");
+    let run_scte35 = args.iter().any(|a| a == "--scte35");
+    let run_video = args.iter().any(|a| a == "--video");
+    let run_audio = args.iter().any(|a| a == "--audio");
+    let value = |flag: &str| args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1));
+    let xmltv = value("--xmltv");
+    let srt = value("--srt");
+    let vtt = value("--vtt");
+    let run_captions = srt.is_some() || vtt.is_some() || args.iter().any(|a| a == "--captions");
+    let filename = args.iter().enumerate()
+        .find(|(i, a)| !a.starts_with("--") && (*i == 0 || !matches!(args[i - 1].as_str(), "--xmltv" | "--srt" | "--vtt")))
+        .map_or("media/fifth-new.ts", |(_, f)| f.as_str());
     let mut file = match File::open(filename) {
         Err(e) => {
             println!("File error: {}", e);
@@ -23,48 +125,252 @@ fn main() {
         Ok(f) => f,
     };
 
-    // Move the file pointer to the sync byte
-    if !advance_file_to_sync_byte(&mut file) {
-        eprintln!("Unable to find sync byte in file: {}", filename);
-        std::process::exit(1);
-    }
-
     // State variables
     let mut buffer = [0u8; PACKET_SIZE * 1024];
-    let mut pmt_pids: HashSet<u16> = HashSet::new();
-    let mut pid_states: HashMap<u16, PidState> = HashMap::new();
+    let mut printer = Printer { show_pes };
+    let mut analyzer = tr101290::Analyzer::new(Default::default());
+    let mut clock = timing::ClockAnalyzer::new(Default::default());
+    let mut guide = epg::Guide::new();
+    let mut cues = scte35::CueAnalyzer::new();
+    let mut avc = h264::AvcAnalyzer::new();
+    let mut hevc = hevc::HevcAnalyzer::new();
+    let mut mpeg2 = mpeg2video::Mpeg2VideoAnalyzer::new();
+    let mut sound = audio::AudioAnalyzer::new();
+    let mut captions = captions::CaptionExtractor::new();
+    let mut wall_clock = timing::WallClock::new();
+    let mut demux = Demuxer::new();
+    demux.add_handler(&mut printer);
+    demux.add_handler(&mut wall_clock);
+    if run_tr101290 {
+        demux.add_handler(&mut analyzer);
+        // Assemble the DVB SI tables (NIT, SDT/BAT, EIT, RST, TDT/TOT) so their CRCs are checked
+        for pid in 0x0010..=0x0014 {
+            demux.add_section_pid(pid);
+        }
+    }
+    if run_timing {
+        demux.add_handler(&mut clock);
+    }
+    if xmltv.is_some() {
+        demux.add_handler(&mut guide);
+    }
+    if run_scte35 {
+        demux.add_handler(&mut cues);
+    }
+    if run_video {
+        demux.add_handler(&mut avc);
+        demux.add_handler(&mut hevc);
+        demux.add_handler(&mut mpeg2);
+    }
+    if run_audio {
+        demux.add_handler(&mut sound);
+    }
+    if run_captions {
+        demux.add_handler(&mut captions);
+    }
 
-    'file: loop {
+    loop {
         // Read file in chunks (more efficient to read in larger chunks)
         match file.read(&mut buffer[..]).expect("read failed") {
             // EOF; quit
             0 => break,
-            _n => {
-                // iterate through buffer by the packet size
-                let mut itr = buffer.chunks_exact(PACKET_SIZE);
-                let mut pk = match itr.next() {
-                    None => break 'file,
-                    p => p.unwrap(),
-                };
-
-                'packet: loop {
-                    if let Some(packet) = Packet::new(pk) {
-                        // Update state and errors
-                        packet.update_state(&mut pid_states, &mut pmt_pids);
-
-                        // iterate to next packet
-                        pk = match itr.next() {
-                            None => break 'packet,
-                            p => p.unwrap(),
-                        };
-                    }
-                }
-            },
+            n => demux.push(&buffer[..n]),
+        }
+    }
+    demux.finish();
+
+    let sync = demux.sync_stats();
+    if sync.packets == 0 {
+        eprintln!("Unable to find sync byte in file: {}", filename);
+        std::process::exit(1);
+    }
+    println!();
+    if let Some(format) = demux.packet_format() {
+        println!("Format: {}", format);
+    }
+    println!("Sync: {} packets, {} sync byte errors, {} sync losses, {} bytes skipped",
+        sync.packets, sync.sync_byte_errors, sync.sync_losses, sync.skipped_bytes);
+
+    // Print programs and pids
+    display_programs(&demux);
+    PidState::display_states(demux.pid_states());
+    display_wall_clock(&wall_clock);
+
+    if run_tr101290 {
+        display_tr101290(&analyzer);
+    }
+    if run_timing {
+        display_timing(&clock, show_series);
+    }
+    if run_scte35 {
+        display_cues(&cues);
+    }
+    if run_video {
+        display_video(&avc, &hevc, &mpeg2);
+    }
+    if run_audio {
+        display_audio(&sound);
+    }
+    if run_captions {
+        display_captions(&captions);
+    }
+    for (output, webvtt) in srt.map(|o| (o, false)).into_iter().chain(vtt.map(|o| (o, true))) {
+        if let Err(e) = write_captions(&captions, output, webvtt) {
+            eprintln!("Unable to write {}: {}", output, e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(output) = xmltv {
+        if let Err(e) = File::create(output).and_then(|mut f| guide.write_xmltv(&mut f)) {
+            eprintln!("Unable to write {}: {}", output, e);
+            std::process::exit(1);
         }
     }
-    // Print pids
-    PidState::display_states(&pid_states);
 
     // Return
     std::process::exit(0);
 }
+
+/// Print the programs of the PAT along with their names from the SDT (or the
+/// ATSC VCT, which gives their channel numbers too)
+fn display_programs(demux: &Demuxer) {
+    println!();
+    println!("Programs:");
+    println!("---------");
+    for (program_number, pmt_pid) in demux.programs() {
+        let channel = demux.program_channel(*program_number)
+            .map_or(String::new(), |c| format!(", Channel: {}", c));
+        println!("[Program] {0}{1}, Name: {2}, PMT PID: {3:#X} ({3})", program_number, channel,
+            demux.program_name(*program_number).unwrap_or("-"), pmt_pid);
+    }
+}
+
+/// Print the UTC times of the stream from its TDTs/TOTs (or STTs), along with the local time offsets
+fn display_wall_clock(wall_clock: &timing::WallClock) {
+    let references = wall_clock.references();
+    let (first, last) = match (references.first(), references.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return,
+    };
+    println!();
+    println!("Time:");
+    println!("-----");
+    println!("[UTC] {} (offset {}) to {} (offset {}), {} time tables",
+        first.utc, first.offset, last.utc, last.offset, references.len());
+    for offset in wall_clock.local_time_offsets() {
+        println!("\t=> {}", offset);
+    }
+}
+
+/// Print every TR 101 290 finding followed by the number of each
+fn display_tr101290(analyzer: &tr101290::Analyzer) {
+    println!();
+    println!("TR 101 290:");
+    println!("-----------");
+    for finding in analyzer.findings() {
+        println!("{}", finding);
+    }
+    let mut counts: Vec<_> = analyzer.counts().into_iter().collect();
+    counts.sort();
+    for (indicator, count) in counts {
+        println!("\t=> {}: {}", indicator, count);
+    }
+}
+
+/// Print every SCTE-35 cue along with the sections that failed to parse
+fn display_cues(cues: &scte35::CueAnalyzer) {
+    println!();
+    println!("SCTE-35:");
+    println!("--------");
+    for cue in cues.cues() {
+        println!("{}", cue);
+        println!("{}", cue.section);
+    }
+    for (offset, error) in cues.errors() {
+        println!("[Error] Offset {}: {}", offset, error);
+    }
+}
+
+fn display_video(avc: &h264::AvcAnalyzer, hevc: &hevc::HevcAnalyzer, mpeg2: &mpeg2video::Mpeg2VideoAnalyzer) {
+    println!();
+    println!("Video:");
+    println!("------");
+    for stream in avc.streams().values() {
+        println!("{}", stream);
+    }
+    for stream in hevc.streams().values() {
+        println!("{}", stream);
+    }
+    for stream in mpeg2.streams().values() {
+        println!("{}", stream);
+    }
+    for (offset, error) in avc.errors().iter().chain(hevc.errors()).chain(mpeg2.errors()) {
+        println!("[Error] Offset {}: {}", offset, error);
+    }
+}
+
+fn display_audio(sound: &audio::AudioAnalyzer) {
+    println!();
+    println!("Audio:");
+    println!("------");
+    for stream in sound.streams().values() {
+        println!("{}", stream);
+    }
+    for (offset, error) in sound.errors() {
+        println!("[Error] Offset {}: {}", offset, error);
+    }
+}
+
+fn display_captions(captions: &captions::CaptionExtractor) {
+    println!();
+    println!("Captions:");
+    println!("---------");
+    for stream in captions.streams().values() {
+        println!("{}", stream);
+    }
+}
+
+/// Write every caption channel as SubRip or WebVTT, to output if there's only one and
+/// otherwise to one file per channel with the PID and channel before the extension
+fn write_captions(captions: &captions::CaptionExtractor, output: &str, webvtt: bool) -> std::io::Result<()> {
+    let tracks: Vec<_> = captions.streams().values()
+        .flat_map(|s| s.tracks().into_iter().map(move |(channel, cues)| (s, channel, cues)))
+        .collect();
+    for (stream, channel, cues) in &tracks {
+        let path = if tracks.len() == 1 {
+            output.to_string()
+        } else {
+            let suffix = format!("{:#X}.{}", stream.pid, channel).replace(' ', "");
+            match output.rfind('.') {
+                Some(dot) => format!("{}.{}{}", &output[..dot], suffix, &output[dot..]),
+                None => format!("{}.{}", output, suffix),
+            }
+        };
+        let mut f = File::create(&path)?;
+        let base = stream.first_pts.unwrap_or(0);
+        if webvtt {
+            captions::write_webvtt(cues, base, &mut f)?;
+        } else {
+            captions::write_srt(cues, base, &mut f)?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the clock measurements of each program, optionally with one line per PCR
+fn display_timing(clock: &timing::ClockAnalyzer, show_series: bool) {
+    for program in clock.programs() {
+        println!();
+        println!("{}", program);
+        if !show_series {
+            continue;
+        }
+        println!("offset,pcr,arrival_s,interval_ms,rate_bps,accuracy_ns,jitter_ns,frequency_offset_ppm");
+        for s in &program.samples {
+            let opt = |v: Option<f64>, scale: f64| v.map_or(String::new(), |v| format!("{:.3}", v * scale));
+            println!("{},{},{:.9},{},{},{},{:.1},{}", s.offset, s.pcr, s.arrival,
+                opt(s.interval, 1e3), opt(s.rate, 1.0), opt(s.accuracy, 1e9),
+                s.jitter * 1e9, opt(s.frequency_offset, 1.0));
+        }
+    }
+}
@@ -0,0 +1,718 @@
+use std::fmt;
+use std::collections::{BTreeMap, HashSet};
+use crate::bits::{self, BitReader};
+use crate::codec::Codec;
+use crate::demux::DemuxHandler;
+use crate::error::ParseError;
+use crate::packet::Packet;
+use crate::pes::Pes;
+use crate::psi::pmt::Pmt;
+
+// Constants
+pub const PICTURE_START_CODE: u8 = 0x00;
+pub const USER_DATA_START_CODE: u8 = 0xB2;
+pub const SEQUENCE_HEADER_CODE: u8 = 0xB3;
+pub const EXTENSION_START_CODE: u8 = 0xB5;
+pub const SEQUENCE_END_CODE: u8 = 0xB7;
+pub const GROUP_START_CODE: u8 = 0xB8;
+pub const SEQUENCE_EXTENSION_ID: u8 = 1;
+pub const PICTURE_CODING_EXTENSION_ID: u8 = 8;
+/// picture_structure of a frame picture (1 and 2 are the top and bottom fields)
+pub const FRAME_PICTURE: u8 = 3;
+
+/// Name of a picture_coding_type
+pub fn picture_coding_type_name(picture_coding_type: u8) -> &'static str {
+    match picture_coding_type {
+        1 => "I",
+        2 => "P",
+        3 => "B",
+        4 => "D",
+        _ => "?",
+    }
+}
+
+/// sequence_header()
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SequenceHeader {
+    pub horizontal_size_value: u16,
+    pub vertical_size_value: u16,
+    pub aspect_ratio_information: u8,
+    pub frame_rate_code: u8,
+    /// In units of 400 bit/s (0x3FFFF is variable bitrate in MPEG-1)
+    pub bit_rate_value: u32,
+    /// In units of 16 kbit
+    pub vbv_buffer_size_value: u16,
+    pub constrained_parameters_flag: bool,
+    pub load_intra_quantiser_matrix: bool,
+    pub load_non_intra_quantiser_matrix: bool,
+}
+
+impl SequenceHeader {
+    /// Parse a sequence header (following its start code)
+    pub fn new(buf: &[u8]) -> Result<SequenceHeader, ParseError> {
+        let mut r = BitReader::new(buf);
+        let horizontal_size_value = r.bits(12, "horizontal_size_value")? as u16;
+        let vertical_size_value = r.bits(12, "vertical_size_value")? as u16;
+        let aspect_ratio_information = r.bits(4, "aspect_ratio_information")? as u8;
+        let frame_rate_code = r.bits(4, "frame_rate_code")? as u8;
+        let bit_rate_value = r.bits(18, "bit_rate_value")?;
+        r.skip(1, "marker_bit")?;
+        let vbv_buffer_size_value = r.bits(10, "vbv_buffer_size_value")? as u16;
+        let constrained_parameters_flag = r.flag("constrained_parameters_flag")?;
+        let load_intra_quantiser_matrix = r.flag("load_intra_quantiser_matrix")?;
+        if load_intra_quantiser_matrix {
+            r.skip(64 * 8, "intra_quantiser_matrix")?;
+        }
+        let load_non_intra_quantiser_matrix = r.flag("load_non_intra_quantiser_matrix")?;
+        Ok(SequenceHeader {
+            horizontal_size_value,
+            vertical_size_value,
+            aspect_ratio_information,
+            frame_rate_code,
+            bit_rate_value,
+            vbv_buffer_size_value,
+            constrained_parameters_flag,
+            load_intra_quantiser_matrix,
+            load_non_intra_quantiser_matrix,
+        })
+    }
+
+    /// The frame rate of a frame_rate_code
+    pub fn frame_rate_of(frame_rate_code: u8) -> Option<f64> {
+        Some(match frame_rate_code {
+            1 => 24000.0 / 1001.0,
+            2 => 24.0,
+            3 => 25.0,
+            4 => 30000.0 / 1001.0,
+            5 => 30.0,
+            6 => 50.0,
+            7 => 60000.0 / 1001.0,
+            8 => 60.0,
+            _ => return None,
+        })
+    }
+}
+
+/// sequence_extension() (MPEG-2 only)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SequenceExtension {
+    pub profile_and_level_indication: u8,
+    pub progressive_sequence: bool,
+    pub chroma_format: u8,
+    pub horizontal_size_extension: u8,
+    pub vertical_size_extension: u8,
+    pub bit_rate_extension: u16,
+    pub vbv_buffer_size_extension: u8,
+    pub low_delay: bool,
+    pub frame_rate_extension_n: u8,
+    pub frame_rate_extension_d: u8,
+}
+
+impl SequenceExtension {
+    /// Parse a sequence extension (following its start code)
+    pub fn new(buf: &[u8]) -> Result<SequenceExtension, ParseError> {
+        let mut r = BitReader::new(buf);
+        r.skip(4, "extension_start_code_identifier")?;
+        let profile_and_level_indication = r.byte("profile_and_level_indication")?;
+        let progressive_sequence = r.flag("progressive_sequence")?;
+        let chroma_format = r.bits(2, "chroma_format")? as u8;
+        let horizontal_size_extension = r.bits(2, "horizontal_size_extension")? as u8;
+        let vertical_size_extension = r.bits(2, "vertical_size_extension")? as u8;
+        let bit_rate_extension = r.bits(12, "bit_rate_extension")? as u16;
+        r.skip(1, "marker_bit")?;
+        Ok(SequenceExtension {
+            profile_and_level_indication,
+            progressive_sequence,
+            chroma_format,
+            horizontal_size_extension,
+            vertical_size_extension,
+            bit_rate_extension,
+            vbv_buffer_size_extension: r.byte("vbv_buffer_size_extension")?,
+            low_delay: r.flag("low_delay")?,
+            frame_rate_extension_n: r.bits(2, "frame_rate_extension_n")? as u8,
+            frame_rate_extension_d: r.bits(5, "frame_rate_extension_d")? as u8,
+        })
+    }
+
+    pub fn profile_name(&self) -> &'static str {
+        if self.profile_and_level_indication & 0x80 != 0 {
+            return match self.profile_and_level_indication {
+                0x82 | 0x85 => "4:2:2",
+                0x8A..=0x8E => "Multi-view",
+                _ => "Reserved",
+            };
+        }
+        match (self.profile_and_level_indication >> 4) & 0x07 {
+            1 => "High",
+            2 => "Spatially Scalable",
+            3 => "SNR Scalable",
+            4 => "Main",
+            5 => "Simple",
+            _ => "Reserved",
+        }
+    }
+
+    pub fn level_name(&self) -> &'static str {
+        let level = if self.profile_and_level_indication & 0x80 != 0 {
+            match self.profile_and_level_indication {
+                0x82 | 0x8A => 4,
+                0x85 | 0x8D => 8,
+                0x8B => 6,
+                0x8E => 10,
+                _ => 0,
+            }
+        } else {
+            self.profile_and_level_indication & 0x0F
+        };
+        match level {
+            4 => "High",
+            6 => "High 1440",
+            8 => "Main",
+            10 => "Low",
+            _ => "Reserved",
+        }
+    }
+
+    pub fn chroma_format_name(&self) -> &'static str {
+        match self.chroma_format {
+            1 => "4:2:0",
+            2 => "4:2:2",
+            3 => "4:4:4",
+            _ => "Reserved",
+        }
+    }
+}
+
+/// group_of_pictures_header()
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct GopHeader {
+    pub drop_frame_flag: bool,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub pictures: u8,
+    pub closed_gop: bool,
+    pub broken_link: bool,
+}
+
+impl GopHeader {
+    /// Parse a GOP header (following its start code)
+    pub fn new(buf: &[u8]) -> Result<GopHeader, ParseError> {
+        let mut r = BitReader::new(buf);
+        let drop_frame_flag = r.flag("drop_frame_flag")?;
+        let hours = r.bits(5, "time_code_hours")? as u8;
+        let minutes = r.bits(6, "time_code_minutes")? as u8;
+        r.skip(1, "marker_bit")?;
+        Ok(GopHeader {
+            drop_frame_flag,
+            hours,
+            minutes,
+            seconds: r.bits(6, "time_code_seconds")? as u8,
+            pictures: r.bits(6, "time_code_pictures")? as u8,
+            closed_gop: r.flag("closed_gop")?,
+            broken_link: r.flag("broken_link")?,
+        })
+    }
+}
+
+impl fmt::Display for GopHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}{}{:02}", self.hours, self.minutes, self.seconds,
+            if self.drop_frame_flag { ';' } else { ':' }, self.pictures)
+    }
+}
+
+/// picture_header()
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PictureHeader {
+    pub temporal_reference: u16,
+    pub picture_coding_type: u8,
+    pub vbv_delay: u16,
+}
+
+impl PictureHeader {
+    /// Parse a picture header (following its start code)
+    pub fn new(buf: &[u8]) -> Result<PictureHeader, ParseError> {
+        let mut r = BitReader::new(buf);
+        Ok(PictureHeader {
+            temporal_reference: r.bits(10, "temporal_reference")? as u16,
+            picture_coding_type: r.bits(3, "picture_coding_type")? as u8,
+            vbv_delay: r.bits(16, "vbv_delay")? as u16,
+        })
+    }
+}
+
+/// The fields of a picture_coding_extension() that say how the picture is shown
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PictureCodingExtension {
+    pub picture_structure: u8,
+    pub top_field_first: bool,
+    pub repeat_first_field: bool,
+    pub progressive_frame: bool,
+}
+
+impl PictureCodingExtension {
+    /// Parse a picture coding extension (following its start code)
+    pub fn new(buf: &[u8]) -> Result<PictureCodingExtension, ParseError> {
+        let mut r = BitReader::new(buf);
+        // extension_start_code_identifier, the f_codes and intra_dc_precision
+        r.skip(22, "f_code")?;
+        let picture_structure = r.bits(2, "picture_structure")? as u8;
+        let top_field_first = r.flag("top_field_first")?;
+        r.skip(5, "frame_pred_frame_dct")?;
+        let repeat_first_field = r.flag("repeat_first_field")?;
+        r.skip(1, "chroma_420_type")?;
+        Ok(PictureCodingExtension {
+            picture_structure,
+            top_field_first,
+            repeat_first_field,
+            progressive_frame: r.flag("progressive_frame")?,
+        })
+    }
+}
+
+/// A GOP: the pictures from a GOP header (or an I picture in streams without them) to the next
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Gop {
+    /// Index of the first picture in the stream
+    pub start: u64,
+    pub header: Option<GopHeader>,
+    /// picture_coding_type and temporal_reference of the pictures in decoding order
+    pub pictures: Vec<(u8, u16)>,
+}
+
+impl Gop {
+    /// Whether the temporal_references number the pictures 0 to n - 1 (each once)
+    pub fn is_temporal_reference_valid(&self) -> bool {
+        let references: HashSet<u16> = self.pictures.iter().map(|(_, t)| *t).collect();
+        references.len() == self.pictures.len() &&
+            references.iter().all(|t| usize::from(*t) < self.pictures.len())
+    }
+
+    /// Whether the GOP is known not to refer to the pictures before it
+    pub fn is_closed(&self) -> bool {
+        self.header.is_some_and(|h| h.closed_gop)
+    }
+
+    /// The pictures in decoding order with their temporal_reference, e.g. "I2 B0 B1 P5 B3 B4"
+    pub fn pattern(&self) -> String {
+        self.pictures.iter().map(|(t, r)| format!("{}{}", picture_coding_type_name(*t), r))
+            .collect::<Vec<String>>().join(" ")
+    }
+}
+
+/// What the headers of an MPEG-1/MPEG-2 video stream say about it
+#[derive(Clone, Debug, Default)]
+pub struct Stream {
+    pub pid: u16,
+    pub program_number: u16,
+    pub sequence_header: Option<SequenceHeader>,
+    /// None for MPEG-1 video
+    pub sequence_extension: Option<SequenceExtension>,
+    /// Number of pictures of each picture_coding_type
+    pub picture_counts: BTreeMap<u8, u64>,
+    /// Number of frames shown for three fields (repeat_first_field)
+    pub repeated_fields: u64,
+    pub gops: Vec<Gop>,
+    pub sequence_headers: u64,
+    pictures: u64,
+    /// Whether the stream has GOP headers (otherwise GOPs start at I pictures)
+    has_gop_headers: bool,
+    /// The last picture header, for its picture coding extension
+    picture: Option<PictureHeader>,
+    /// The first field of a field pair waiting for the second one
+    lone_field: Option<(u16, u8)>,
+}
+
+impl Stream {
+    fn new(pid: u16, program_number: u16) -> Stream {
+        Stream { pid, program_number, ..Default::default() }
+    }
+
+    /// Handle the start codes of a PES packet
+    fn push(&mut self, payload: &[u8]) -> Vec<ParseError> {
+        let mut errors = vec![];
+        for unit in bits::start_code_units(payload) {
+            let (start_code, body) = match unit.split_first() {
+                Some((start_code, body)) => (*start_code, body),
+                None => continue,
+            };
+            let result = match start_code {
+                SEQUENCE_HEADER_CODE => SequenceHeader::new(body).map(|header| {
+                    self.sequence_headers += 1;
+                    self.sequence_header = Some(header);
+                }),
+                EXTENSION_START_CODE => match body.first().map(|b| b >> 4) {
+                    Some(SEQUENCE_EXTENSION_ID) => SequenceExtension::new(body).map(|ext| self.sequence_extension = Some(ext)),
+                    Some(PICTURE_CODING_EXTENSION_ID) => PictureCodingExtension::new(body).map(|ext| self.push_picture_extension(&ext)),
+                    _ => Ok(()),
+                },
+                GROUP_START_CODE => GopHeader::new(body).map(|header| {
+                    self.has_gop_headers = true;
+                    self.gops.push(Gop { start: self.pictures, header: Some(header), pictures: vec![] });
+                }),
+                PICTURE_START_CODE => PictureHeader::new(body).map(|header| self.push_picture(header)),
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                errors.push(e.with_pid(self.pid));
+            }
+        }
+        errors
+    }
+
+    /// Count a picture and add it to its GOP (the second field of a frame only completes it)
+    fn push_picture(&mut self, header: PictureHeader) {
+        self.picture = Some(header);
+        if let Some((temporal_reference, _)) = self.lone_field {
+            if temporal_reference == header.temporal_reference {
+                return;
+            }
+            self.lone_field = None;
+        }
+        self.pictures += 1;
+        *self.picture_counts.entry(header.picture_coding_type).or_insert(0) += 1;
+        if header.picture_coding_type == 1 && !self.has_gop_headers {
+            self.gops.push(Gop { start: self.pictures - 1, header: None, pictures: vec![] });
+        }
+        if let Some(gop) = self.gops.last_mut() {
+            gop.pictures.push((header.picture_coding_type, header.temporal_reference));
+        }
+    }
+
+    fn push_picture_extension(&mut self, ext: &PictureCodingExtension) {
+        let picture = match self.picture.take() {
+            Some(picture) => picture,
+            None => return,
+        };
+        if ext.picture_structure != FRAME_PICTURE {
+            // Wait for the other field, unless this one is it
+            self.lone_field = match self.lone_field {
+                Some((_, structure)) if structure != ext.picture_structure => None,
+                _ => Some((picture.temporal_reference, ext.picture_structure)),
+            };
+        } else if ext.repeat_first_field {
+            self.repeated_fields += 1;
+        }
+    }
+
+    /// The frame rate of the sequence (including the MPEG-2 frame_rate_extension)
+    pub fn frame_rate(&self) -> Option<f64> {
+        let rate = SequenceHeader::frame_rate_of(self.sequence_header.as_ref()?.frame_rate_code)?;
+        Some(match &self.sequence_extension {
+            Some(ext) => rate * f64::from(ext.frame_rate_extension_n + 1) / f64::from(ext.frame_rate_extension_d + 1),
+            None => rate,
+        })
+    }
+
+    /// Width and height in samples (including the MPEG-2 size extensions)
+    pub fn size(&self) -> Option<(u32, u32)> {
+        let header = self.sequence_header.as_ref()?;
+        let (h_ext, v_ext) = self.sequence_extension.as_ref()
+            .map_or((0, 0), |e| (u32::from(e.horizontal_size_extension), u32::from(e.vertical_size_extension)));
+        Some((h_ext << 12 | u32::from(header.horizontal_size_value), v_ext << 12 | u32::from(header.vertical_size_value)))
+    }
+
+    /// The bit_rate in bit/s (including the MPEG-2 bit_rate_extension), None if it's
+    /// variable (MPEG-1)
+    pub fn bit_rate(&self) -> Option<u64> {
+        let header = self.sequence_header.as_ref()?;
+        if self.sequence_extension.is_none() && header.bit_rate_value == 0x3FFFF {
+            return None;
+        }
+        let ext = self.sequence_extension.as_ref().map_or(0, |e| u64::from(e.bit_rate_extension));
+        Some((ext << 18 | u64::from(header.bit_rate_value)) * 400)
+    }
+
+    /// The VBV buffer size in bits (including the MPEG-2 vbv_buffer_size_extension)
+    pub fn vbv_buffer_size(&self) -> Option<u64> {
+        let header = self.sequence_header.as_ref()?;
+        let ext = self.sequence_extension.as_ref().map_or(0, |e| u64::from(e.vbv_buffer_size_extension));
+        Some((ext << 10 | u64::from(header.vbv_buffer_size_value)) * 16 * 1024)
+    }
+
+    /// The display aspect ratio (MPEG-2) or pel aspect ratio (MPEG-1)
+    pub fn aspect_ratio_name(&self) -> &'static str {
+        let aspect_ratio_information = self.sequence_header.as_ref().map_or(0, |h| h.aspect_ratio_information);
+        if self.sequence_extension.is_some() {
+            return match aspect_ratio_information {
+                1 => "Square samples",
+                2 => "4:3",
+                3 => "16:9",
+                4 => "2.21:1",
+                _ => "Reserved",
+            };
+        }
+        match aspect_ratio_information {
+            1 => "Pel aspect 1.0",
+            2 => "Pel aspect 0.6735",
+            3 => "Pel aspect 0.7031 (16:9, 625 lines)",
+            4 => "Pel aspect 0.7615",
+            5 => "Pel aspect 0.8055",
+            6 => "Pel aspect 0.8437 (16:9, 525 lines)",
+            7 => "Pel aspect 0.8935",
+            8 => "Pel aspect 0.9157 (CCIR 601, 625 lines)",
+            9 => "Pel aspect 0.9815",
+            10 => "Pel aspect 1.0255",
+            11 => "Pel aspect 1.0695",
+            12 => "Pel aspect 1.0950 (CCIR 601, 525 lines)",
+            13 => "Pel aspect 1.1575",
+            14 => "Pel aspect 1.2015",
+            _ => "Reserved",
+        }
+    }
+
+    /// Total number of pictures (frames or field pairs)
+    pub fn pictures(&self) -> u64 {
+        self.pictures
+    }
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = if self.sequence_extension.is_some() || self.sequence_header.is_none() { "MPEG-2" } else { "MPEG-1" };
+        write!(f, "[{} video] PID {:#X} (Program {})", name, self.pid, self.program_number)?;
+        let mut format = vec![];
+        if let Some(ext) = &self.sequence_extension {
+            format.push(format!("{} profile @ {} level", ext.profile_name(), ext.level_name()));
+        }
+        if let Some((width, height)) = self.size() {
+            format.push(format!("{}x{}, {}", width, height, self.aspect_ratio_name()));
+        }
+        if let Some(frame_rate) = self.frame_rate() {
+            format.push(format!("{:.3} fps", frame_rate));
+        }
+        if let Some(ext) = &self.sequence_extension {
+            format.push(format!("{}, {}", if ext.progressive_sequence { "progressive" } else { "interlaced" },
+                ext.chroma_format_name()));
+        }
+        if !format.is_empty() {
+            write!(f, ": {}", format.join(", "))?;
+        }
+        if let Some(vbv_buffer_size) = self.vbv_buffer_size() {
+            match self.bit_rate() {
+                Some(bit_rate) => write!(f, "\n\t=> Bitrate: {} bit/s", bit_rate)?,
+                None => write!(f, "\n\t=> Bitrate: variable")?,
+            }
+            write!(f, ", VBV buffer: {} bits, Sequence headers: {}", vbv_buffer_size, self.sequence_headers)?;
+        }
+
+        write!(f, "\n\t=> Pictures: {} (", self.pictures)?;
+        for (i, (picture_coding_type, count)) in self.picture_counts.iter().enumerate() {
+            write!(f, "{}{} {}", if i == 0 { "" } else { ", " }, picture_coding_type_name(*picture_coding_type), count)?;
+        }
+        write!(f, ")")?;
+        if self.repeated_fields > 0 {
+            write!(f, ", Repeated fields: {}", self.repeated_fields)?;
+        }
+
+        // The last GOP is cut short by the end of the capture, so leave it out of the lengths
+        let complete = &self.gops[..self.gops.len().saturating_sub(1)];
+        write!(f, "\n\t=> GOPs: {}", self.gops.len())?;
+        // Only GOP headers say whether a GOP is closed
+        let with_header = self.gops.iter().filter(|g| g.header.is_some()).count();
+        let closed = self.gops.iter().filter(|g| g.is_closed()).count();
+        if with_header > 0 {
+            write!(f, " ({} closed, {} open)", closed, with_header - closed)?;
+        }
+        if let (Some(min), Some(max)) = (complete.iter().map(|g| g.pictures.len()).min(),
+            complete.iter().map(|g| g.pictures.len()).max()) {
+            if min == max {
+                write!(f, ", Length: {}", min)?;
+            } else {
+                write!(f, ", Length: {}-{}", min, max)?;
+            }
+        }
+        let broken_links = self.gops.iter().filter(|g| g.header.is_some_and(|h| h.broken_link)).count();
+        if broken_links > 0 {
+            write!(f, ", Broken links: {}", broken_links)?;
+        }
+        let invalid = complete.iter().filter(|g| !g.is_temporal_reference_valid()).count();
+        if invalid > 0 {
+            write!(f, ", GOPs with bad temporal_references: {}", invalid)?;
+        }
+        let mut patterns = HashSet::new();
+        for gop in self.gops.iter().filter(|g| patterns.insert(g.pattern())).take(4) {
+            write!(f, "\n\t=> GOP")?;
+            if let Some(header) = gop.header {
+                write!(f, " {}", header)?;
+            }
+            write!(f, "{}: {}", if gop.is_closed() { " (closed)" } else { "" }, gop.pattern())?;
+        }
+        Ok(())
+    }
+}
+
+/// Follows the headers of the MPEG-1/MPEG-2 video streams as a DemuxHandler, working out
+/// each one's format and GOP structure
+#[derive(Clone, Debug, Default)]
+pub struct Mpeg2VideoAnalyzer {
+    streams: BTreeMap<u16, Stream>,
+    offset: u64,
+    errors: Vec<(u64, ParseError)>,
+}
+
+impl Mpeg2VideoAnalyzer {
+    pub fn new() -> Mpeg2VideoAnalyzer {
+        Default::default()
+    }
+
+    /// The MPEG-1/MPEG-2 video streams found so far by PID
+    pub fn streams(&self) -> &BTreeMap<u16, Stream> {
+        &self.streams
+    }
+
+    /// The offset of every header that failed to parse (or the packet that completed its
+    /// PES packet), with why
+    pub fn errors(&self) -> &[(u64, ParseError)] {
+        &self.errors
+    }
+}
+
+impl DemuxHandler for Mpeg2VideoAnalyzer {
+    fn on_packet(&mut self, packet: &Packet) {
+        self.offset = packet.offset;
+    }
+
+    fn on_pmt(&mut self, _pid: u16, pmt: &Pmt) {
+        for es in &pmt.elementary_streams {
+            if matches!(es.get_codec(), Codec::Mpeg1Video | Codec::Mpeg2Video) {
+                let pid = es.get_elementary_pid();
+                self.streams.entry(pid).or_insert_with(|| Stream::new(pid, pmt.get_program_number()));
+            }
+        }
+    }
+
+    fn on_pes(&mut self, pid: u16, pes: &Pes) {
+        if let Some(stream) = self.streams.get_mut(&pid) {
+            let offset = self.offset;
+            self.errors.extend(stream.push(&pes.payload).into_iter().map(|e| (offset, e)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::BitWriter;
+
+    fn start_code(code: u8, body: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0x00, 0x00, 0x01, code];
+        buf.extend_from_slice(body);
+        buf
+    }
+
+    /// A 720x576 4:3 25 fps sequence header and extension (Main profile @ Main level,
+    /// interlaced, 15 Mbit/s)
+    fn sequence() -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.bits(12, 720).bits(12, 576).bits(4, 2).bits(4, 3).bits(18, 37500).flag(true).bits(10, 112);
+        w.flag(false).flag(false).flag(false);
+        let mut buf = start_code(SEQUENCE_HEADER_CODE, &w.finish());
+        let mut w = BitWriter::default();
+        w.bits(4, u64::from(SEQUENCE_EXTENSION_ID)).bits(8, 0x48).flag(false).bits(2, 1).bits(2, 0).bits(2, 0);
+        w.bits(12, 0).flag(true).bits(8, 0).flag(false).bits(2, 0).bits(5, 0);
+        buf.extend(start_code(EXTENSION_START_CODE, &w.finish()));
+        buf
+    }
+
+    fn gop_header(seconds: u64, closed_gop: bool) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.flag(false).bits(5, 10).bits(6, 0).flag(true).bits(6, seconds).bits(6, 0).flag(closed_gop).flag(false);
+        start_code(GROUP_START_CODE, &w.finish())
+    }
+
+    /// A picture header and its picture coding extension
+    fn picture(picture_coding_type: u64, temporal_reference: u64, picture_structure: u64, repeat_first_field: bool)
+        -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.bits(10, temporal_reference).bits(3, picture_coding_type).bits(16, 0xFFFF);
+        let mut buf = start_code(PICTURE_START_CODE, &w.finish());
+        let mut w = BitWriter::default();
+        w.bits(4, u64::from(PICTURE_CODING_EXTENSION_ID)).bits(16, 0xFFFF).bits(2, 0).bits(2, picture_structure);
+        w.flag(true).bits(5, 0).flag(repeat_first_field).flag(false).flag(picture_structure == 3);
+        buf.extend(start_code(EXTENSION_START_CODE, &w.finish()));
+        buf
+    }
+
+    const I: u64 = 1;
+    const P: u64 = 2;
+    const B: u64 = 3;
+
+    #[test]
+    fn gop_structure() {
+        let mut buf = sequence();
+        // A closed GOP, an open one whose leading B pictures refer to the GOP before, and one
+        // of field pictures
+        buf.extend(gop_header(0, true));
+        for (picture_coding_type, temporal_reference) in [(I, 2), (B, 0), (B, 1), (P, 5), (B, 3), (B, 4)] {
+            buf.extend(picture(picture_coding_type, temporal_reference, FRAME_PICTURE.into(), false));
+        }
+        buf.extend(gop_header(1, false));
+        for (picture_coding_type, temporal_reference) in [(I, 2), (B, 0), (B, 1), (P, 3)] {
+            buf.extend(picture(picture_coding_type, temporal_reference, FRAME_PICTURE.into(), true));
+        }
+        buf.extend(gop_header(2, false));
+        buf.extend(picture(I, 0, 1, false));
+        buf.extend(picture(P, 0, 2, false));
+        buf.extend(picture(P, 1, 1, false));
+        buf.extend(picture(P, 1, 2, false));
+
+        let mut stream = Stream::new(0x100, 1);
+        assert_eq!(stream.push(&buf), vec![]);
+        assert_eq!(stream.size(), Some((720, 576)));
+        assert_eq!((stream.frame_rate(), stream.bit_rate()), (Some(25.0), Some(15_000_000)));
+        assert!(stream.gops.iter().all(|g| g.is_temporal_reference_valid()));
+        assert_eq!(stream.to_string(), "[MPEG-2 video] PID 0x100 (Program 1): Main profile @ Main level, \
+            720x576, 4:3, 25.000 fps, interlaced, 4:2:0\
+            \n\t=> Bitrate: 15000000 bit/s, VBV buffer: 1835008 bits, Sequence headers: 1\
+            \n\t=> Pictures: 12 (I 3, P 3, B 6), Repeated fields: 4\
+            \n\t=> GOPs: 3 (1 closed, 2 open), Length: 4-6\
+            \n\t=> GOP 10:00:00:00 (closed): I2 B0 B1 P5 B3 B4\
+            \n\t=> GOP 10:00:01:00: I2 B0 B1 P3\
+            \n\t=> GOP 10:00:02:00: I0 P1");
+    }
+
+    #[test]
+    fn mpeg1_sequence() {
+        let mut w = BitWriter::default();
+        // Variable bitrate, pel aspect 1.0, 23.976 fps
+        w.bits(12, 352).bits(12, 240).bits(4, 1).bits(4, 1).bits(18, 0x3FFFF).flag(true).bits(10, 20);
+        w.flag(true).flag(false).flag(false);
+        let mut buf = start_code(SEQUENCE_HEADER_CODE, &w.finish());
+        // No GOP headers: the GOPs start at the I pictures
+        for (picture_coding_type, temporal_reference) in [(I, 0), (P, 1), (I, 0), (P, 1)] {
+            let mut w = BitWriter::default();
+            w.bits(10, temporal_reference).bits(3, picture_coding_type).bits(16, 0xFFFF);
+            buf.extend(start_code(PICTURE_START_CODE, &w.finish()));
+        }
+
+        let mut stream = Stream::new(0x100, 1);
+        assert_eq!(stream.push(&buf), vec![]);
+        assert_eq!(stream.to_string(), "[MPEG-1 video] PID 0x100 (Program 1): 352x240, Pel aspect 1.0, 23.976 fps\
+            \n\t=> Bitrate: variable, VBV buffer: 327680 bits, Sequence headers: 1\
+            \n\t=> Pictures: 4 (I 2, P 2)\
+            \n\t=> GOPs: 2, Length: 2\
+            \n\t=> GOP: I0 P1");
+    }
+
+    #[test]
+    fn truncated_headers() {
+        let buf = sequence();
+        for len in 0..8 {
+            assert!(SequenceHeader::new(&buf[4..4 + len]).is_err(), "{} bytes", len);
+        }
+        // load_intra_quantiser_matrix without the matrix
+        let mut header = buf[4..12].to_vec();
+        header[7] |= 0x02;
+        assert_eq!(SequenceHeader::new(&header),
+            Err(ParseError::Truncated { pid: None, offset: 7, field: "intra_quantiser_matrix" }));
+
+        // A picture header cut short is reported without losing the pictures after it
+        let mut buf = start_code(PICTURE_START_CODE, &[0x00]);
+        buf.extend(picture(I, 0, FRAME_PICTURE.into(), false));
+        let mut stream = Stream::new(0x100, 1);
+        assert_eq!(stream.push(&buf), vec![ParseError::Truncated { pid: Some(0x100), offset: 0, field: "temporal_reference" }]);
+        assert_eq!(stream.pictures, 1);
+    }
+}
@@ -1,31 +1,196 @@
-use std::{
-    fmt,
-    fs::File,
-    io::{SeekFrom, prelude::*},
-    collections::HashMap,
-    collections::HashSet,
-};
+use std::fmt;
 use byteorder::{ByteOrder, BigEndian};
-use crate::psi::*;
+use crate::error::{self, ParseError};
 
 // Constants
 pub const SYNC_BYTE_VAL: u8 = 0x47;
 pub const PACKET_SIZE: usize = 188;
 pub const HEADER_SIZE: usize = 4;
 pub const CRC_SIZE: usize = 4;
-const NULL_PACKET_PID: u16 = 0x1FFF;
+pub const SYSTEM_CLOCK_FREQUENCY: u64 = 27_000_000;
+pub const NULL_PACKET_PID: u16 = 0x1FFF;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Packet {
-    transport_error_indicator: bool,
-    payload_unit_start_indicator: bool,
+    pub transport_error_indicator: bool,
+    pub payload_unit_start_indicator: bool,
     pub transport_priority: bool,
     pub pid: u16,
     pub transport_scrambling_control: u8,
     pub adaptation_field_control: u8,
     pub continuity_counter: u8,
-    // TODO: Possibly implement adaptation_field, for now ignore
+    pub adaptation_field: Option<AdaptationField>,
     pub payload: Vec<u8>,
+    /// Byte offset of the packet in the stream (set by the Demuxer)
+    pub offset: u64,
+    /// Header in front of the packet in 192 byte M2TS streams (set by the Demuxer)
+    pub tp_extra_header: Option<TpExtraHeader>,
+}
+
+/// The 4 bytes in front of each packet of a 192 byte M2TS (Blu-ray/AVCHD) stream
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TpExtraHeader {
+    pub copy_permission_indicator: u8,
+    /// 30 bit arrival time of the packet in units of the 27 MHz clock
+    pub arrival_time_stamp: u32,
+}
+
+impl TpExtraHeader {
+    /// Parse the 4 byte TP_extra_header
+    pub fn new(buf: &[u8]) -> TpExtraHeader {
+        TpExtraHeader {
+            copy_permission_indicator: buf[0] >> 6,
+            arrival_time_stamp: BigEndian::read_u32(buf) & 0x3FFF_FFFF,
+        }
+    }
+}
+
+/// 42 bit clock reference (PCR/OPCR) as carried in the adaptation field
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ClockReference {
+    /// 33 bit base in units of 90 kHz
+    pub base: u64,
+    /// 9 bit extension in units of 27 MHz (0..300)
+    pub extension: u16,
+}
+
+impl ClockReference {
+    /// Parse the 6 byte PCR/OPCR field
+    fn new(buf: &[u8]) -> ClockReference {
+        let base = (u64::from(buf[0]) << 25) | (u64::from(buf[1]) << 17) |
+            (u64::from(buf[2]) << 9) | (u64::from(buf[3]) << 1) | (u64::from(buf[4]) >> 7);
+        ClockReference {
+            base,
+            extension: BigEndian::read_u16(&[buf[4] & 0x01, buf[5]]),
+        }
+    }
+
+    /// Full clock value in units of the 27 MHz system clock
+    pub fn value(&self) -> u64 {
+        self.base * 300 + u64::from(self.extension)
+    }
+}
+
+impl fmt::Display for ClockReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({:.6}s)", self.value(), self.value() as f64 / SYSTEM_CLOCK_FREQUENCY as f64)
+    }
+}
+
+/// Legal time window, used by re-multiplexers to schedule packets
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LegalTimeWindow {
+    pub valid: bool,
+    pub offset: u16,
+}
+
+/// Seamless splice information (splice type and the DTS of the next access unit)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SeamlessSplice {
+    pub splice_type: u8,
+    pub dts_next_au: u64,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AdaptationFieldExtension {
+    pub ltw: Option<LegalTimeWindow>,
+    pub piecewise_rate: Option<u32>,
+    pub seamless_splice: Option<SeamlessSplice>,
+}
+
+impl AdaptationFieldExtension {
+    /// Parse an adaptation_field_extension buffer (starting with its length byte)
+    fn new(buf: &[u8]) -> Result<AdaptationFieldExtension, ParseError> {
+        let length = error::field_byte(buf, 0, "adaptation_field_extension_length")? as usize;
+        let mut ext: AdaptationFieldExtension = Default::default();
+        if length == 0 {
+            return Ok(ext);
+        }
+        let buf = error::field_bytes(buf, 0, length + 1, "adaptation_field_extension")?;
+        let flags = buf[1];
+        let mut n = 2;
+        if get_bit_at(flags, 7) {
+            let b = error::field_bytes(buf, n, 2, "ltw_offset")?;
+            ext.ltw = Some(LegalTimeWindow {
+                valid: get_bit_at(b[0], 7),
+                offset: BigEndian::read_u16(&[b[0] & 0x7F, b[1]]),
+            });
+            n += 2;
+        }
+        if get_bit_at(flags, 6) {
+            let b = error::field_bytes(buf, n, 3, "piecewise_rate")?;
+            ext.piecewise_rate = Some(BigEndian::read_u24(&[b[0] & 0x3F, b[1], b[2]]));
+            n += 3;
+        }
+        if get_bit_at(flags, 5) {
+            let b = error::field_bytes(buf, n, 5, "DTS_next_AU")?;
+            ext.seamless_splice = Some(SeamlessSplice {
+                splice_type: b[0] >> 4,
+                dts_next_au: read_timestamp(b),
+            });
+        }
+        Ok(ext)
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AdaptationField {
+    pub length: u8,
+    pub discontinuity_indicator: bool,
+    pub random_access_indicator: bool,
+    pub elementary_stream_priority_indicator: bool,
+    pub pcr: Option<ClockReference>,
+    pub opcr: Option<ClockReference>,
+    pub splice_countdown: Option<i8>,
+    pub transport_private_data: Option<Vec<u8>>,
+    pub extension: Option<AdaptationFieldExtension>,
+}
+
+impl AdaptationField {
+    /// Parse an adaptation field buffer (starting with adaptation_field_length)
+    /// and return Result<AdaptationField, ParseError>
+    pub fn new(buf: &[u8]) -> Result<AdaptationField, ParseError> {
+        let length = error::field_byte(buf, 0, "adaptation_field_length")?;
+        // A zero length field is only used to insert a single stuffing byte
+        if length == 0 {
+            return Ok(Default::default());
+        }
+        let buf = error::field_bytes(buf, 0, length as usize + 1, "adaptation_field")?;
+
+        let flags = buf[1];
+        let mut n = 2;
+        let mut af = AdaptationField {
+            length,
+            discontinuity_indicator: get_bit_at(flags, 7),
+            random_access_indicator: get_bit_at(flags, 6),
+            elementary_stream_priority_indicator: get_bit_at(flags, 5),
+            ..Default::default()
+        };
+        if get_bit_at(flags, 4) {
+            af.pcr = Some(ClockReference::new(error::field_bytes(buf, n, 6, "program_clock_reference")?));
+            n += 6;
+        }
+        if get_bit_at(flags, 3) {
+            af.opcr = Some(ClockReference::new(
+                error::field_bytes(buf, n, 6, "original_program_clock_reference")?));
+            n += 6;
+        }
+        if get_bit_at(flags, 2) {
+            af.splice_countdown = Some(error::field_byte(buf, n, "splice_countdown")? as i8);
+            n += 1;
+        }
+        if get_bit_at(flags, 1) {
+            let data_length = error::field_byte(buf, n, "transport_private_data_length")? as usize;
+            af.transport_private_data = Some(
+                error::field_bytes(buf, n + 1, data_length, "private_data_byte")?.to_vec());
+            n += 1 + data_length;
+        }
+        if get_bit_at(flags, 0) {
+            af.extension = Some(AdaptationFieldExtension::new(&buf[n..])
+                .map_err(|e| e.shift(n))?);
+        }
+        Ok(af)
+    }
 }
 
 impl fmt::Display for Packet {
@@ -48,166 +213,76 @@ impl PartialEq for Packet {
 }
 impl Eq for Packet {}
 
-impl Default for Packet {
-    fn default() -> Self {
-        Packet {
-            transport_error_indicator: false,
-            payload_unit_start_indicator: false,
-            transport_priority: false,
-            pid: 0,
-            transport_scrambling_control: 0,
-            adaptation_field_control: 0,
-            continuity_counter: 0,
-            payload: Vec::default(),
-        }
-    }
-}
-
 impl Packet {
-    /// Parse a packet buffer into a Packet object and return it as Option<Packet>
-    pub fn new(buf: &[u8]) -> Option<Packet> {
-        if buf.len() != PACKET_SIZE || buf[0] != SYNC_BYTE_VAL{
-            return None;
+    /// Parse a packet buffer into a Packet object and return it as Result<Packet, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Packet, ParseError> {
+        if buf.len() != PACKET_SIZE {
+            return Err(ParseError::PacketSize { len: buf.len() });
+        }
+        if buf[0] != SYNC_BYTE_VAL {
+            return Err(ParseError::SyncByte { found: buf[0] });
         }
 
         let pid = BigEndian::read_u16(&[buf[1] & 0x1F, buf[2]]);
         let adaptation_field_control = (buf[3] & 0x30) >> 4;
-        let adaptation_field_len = buf[4] as usize;
+        let adaptation_field = if adaptation_field_control & 0x2 != 0 {
+            Some(AdaptationField::new(&buf[HEADER_SIZE..])
+                .map_err(|e| e.shift(HEADER_SIZE).with_pid(pid))?)
+        } else {
+            None
+        };
         // A payload only exits in the packet if the adaptation_field_control indicates so
-        let payload = if adaptation_field_control == 0x1 {
-            buf[(HEADER_SIZE+1)..].to_vec()
-        } else if adaptation_field_control == 0x3 {
-            buf[(HEADER_SIZE+adaptation_field_len+2)..].to_vec()
+        let payload_start = HEADER_SIZE + adaptation_field.as_ref()
+            .map_or(0, |af| af.length as usize + 1);
+        let payload = if adaptation_field_control & 0x1 != 0 && payload_start < PACKET_SIZE {
+            buf[payload_start..].to_vec()
         } else {
             vec![]
         };
 
-        Some(Packet {
+        Ok(Packet {
             transport_error_indicator: get_bit_at(buf[1], 7),
             payload_unit_start_indicator: get_bit_at(buf[1], 6),
             transport_priority: get_bit_at(buf[1], 5),
-            pid: pid,
+            pid,
             transport_scrambling_control: (buf[3] & 0xC0) >> 6,
-            adaptation_field_control: adaptation_field_control,
+            adaptation_field_control,
             continuity_counter: buf[3] & 0x0F,
-            payload: payload,
+            adaptation_field,
+            payload,
+            offset: 0,
+            tp_extra_header: None,
         })
     }
 
-    /// Update the counts and errors of a PidState object for a given packet
-    pub fn update_state(&self, pid_states: &mut HashMap<u16, crate::PidState>,
-        pmt_pids: &mut HashSet<u16>) {
-        let mut created = false;
-        // Get or create the state
-        let s =
-            match pid_states.get_mut(&self.pid) {
-            Some(state) => state,
-            None => {
-                pid_states.insert(self.pid, crate::PidState {
-                    count: 0,
-                    duplicate_count: 0,
-                    prev_packet: self.clone(),
-                    errors: Default::default(),
-                });
-                created = true;
-                pid_states.get_mut(&self.pid).unwrap()
-            },
-        };
-
-        // Update
-        let is_dup = *self == s.prev_packet;
-        let last_cc = s.prev_packet.continuity_counter;
-        s.count += 1;
-        s.duplicate_count = if is_dup { s.duplicate_count + 1 } else { 0 };
-
-        // Check for continuity errors
-        if !created && self.pid != NULL_PACKET_PID &&
-            self.has_continuity_error(last_cc, s.duplicate_count, is_dup) {
-            s.errors.cc_errors += 1;
-        }
-
-        // Handle packet if it is a psi packet
-        if let Some(psi) = Psi::new(self.payload.as_slice(), &self.pid, &pmt_pids) {
-            self.update_pmt_pids(&psi, pmt_pids);
-            // Display psi info (Only if it is new according to its crc)
-            if let Some(old_psi) = Psi::new(s.prev_packet.payload.as_slice(),
-                &self.pid, &pmt_pids) {
-                let prev_crc = if !created { old_psi.get_crc() } else { 0 };
-                psi.display(prev_crc);
-            }
-
-            // Check for crc errors
-            if psi.get_crc_error() {
-                s.errors.crc_errors += 1;
-            }
-        }
+    /// The PCR carried in this packet's adaptation field, if any
+    pub fn pcr(&self) -> Option<ClockReference> {
+        self.adaptation_field.as_ref()?.pcr
+    }
 
-        // Set the previous packet
-        s.prev_packet = self.clone();
+    /// Whether the discontinuity_indicator is set in this packet's adaptation field
+    pub fn is_discontinuity(&self) -> bool {
+        self.adaptation_field.as_ref().is_some_and(|af| af.discontinuity_indicator)
     }
 
-    /// Update a Vector of PIDs with the PMT PIDs found a given PAT packet.
-    /// If the packet is not a pat, the pmt list won't be touched
-    fn update_pmt_pids(&self, psi: &Psi, pmt_pids: &mut HashSet<u16>) {
-        if let Psi::Pat(pat) = psi {
-            // Add the new pmt pids into our list
-            let new_pmt_pids = pat.get_pmt_pids();
-            pmt_pids.extend(&new_pmt_pids);
-        }
+    /// Whether the random_access_indicator (e.g. a keyframe starts here) is set
+    pub fn is_random_access(&self) -> bool {
+        self.adaptation_field.as_ref().is_some_and(|af| af.random_access_indicator)
     }
 
-    fn has_continuity_error(&self, last_cc: u8, dup_count: u32, is_dup: bool) -> bool {
-        let next_cc = if last_cc == 15 { 0 } else { last_cc + 1};
-        if self.continuity_counter != next_cc {
-            if !(self.adaptation_field_control == 0x0 || self.adaptation_field_control == 0x2) &&
-                !(is_dup && dup_count < 2) {
-                    return true;
-            }
+    /// Whether the continuity_counter doesn't follow `last_cc` of the previous packet on the PID
+    pub(crate) fn has_continuity_error(&self, last_cc: u8, dup_count: u32, is_dup: bool) -> bool {
+        // The counter may legitimately jump when a discontinuity is signalled
+        if self.is_discontinuity() {
+            return false;
         }
-        false
+        let next_cc = if last_cc == 15 { 0 } else { last_cc + 1};
+        self.continuity_counter != next_cc &&
+            !(self.adaptation_field_control == 0x0 || self.adaptation_field_control == 0x2) &&
+            !(is_dup && dup_count < 2)
     }
 }
 
-/// Moves file pointer to sync byte of a transport stream file
-pub fn advance_file_to_sync_byte(file: &mut File) -> bool {
-    // Only look in the first 1024 packets at most
-    // (if we can't find the sync byte by then, this ts file sucks)
-    let mut buffer = [0u8; PACKET_SIZE * 1024];
-    let n = file.read(&mut buffer[..]).expect("Unable to read file.");
-
-    for i in 0..n {
-        // we only found a "potential" sync byte (might be a erroneous sync byte)
-        // confirm it is, in fact, the sync byte by checking the next n packets' first byte
-        // to make sure they are also sync bytes
-        if buffer[i] == SYNC_BYTE_VAL {
-            let mut is_valid = true;
-            println!("Found potential sync byte at index={}", i);
-            for j in 1..=3 {
-                let val_index = i + (j * PACKET_SIZE);
-                if val_index > n {
-                    println!("No sync byte could be found!");
-                    return false;
-                }
-
-                print!("Checking next sync byte at index={}...", val_index);
-                if buffer[val_index] != SYNC_BYTE_VAL {
-                    is_valid = false;
-                    print!(" => !! INVALID !! val=0x{:X?}\n", buffer[val_index]);
-                    break;
-                }
-                print!(" => VALID; val=0x{:X?}\n", buffer[val_index]);
-            }
-            if is_valid {
-                // Seek back to the position we found
-                file.seek(SeekFrom::Start(i as u64)).unwrap();
-                print!("\n");
-                return true;
-            }
-        }
-    }
-    false
-}
-
 /// Gets the bit at position `n`.
 /// Bits are numbered from 0 (least significant) to 7 (most significant).
 pub fn get_bit_at(input: u8, n: u8) -> bool {
@@ -217,3 +292,118 @@ pub fn get_bit_at(input: u8, n: u8) -> bool {
         false
     }
 }
+
+/// Reads a 33 bit timestamp (PTS, DTS or DTS_next_AU) split across 5 bytes with marker bits:
+/// 4 bits prefix, 3 bits [32..30], marker, 15 bits [29..15], marker, 15 bits [14..0], marker
+pub fn read_timestamp(buf: &[u8]) -> u64 {
+    (u64::from(buf[0] & 0x0E) << 29) |
+        (u64::from(buf[1]) << 22) | (u64::from(buf[2] & 0xFE) << 14) |
+        (u64::from(buf[3]) << 7) | (u64::from(buf[4]) >> 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    const PCR: [u8; 6] = [0x91, 0xA2, 0xB3, 0xC4, 0xFF, 0x23];
+    const OPCR: [u8; 6] = [0x55, 0xE6, 0xF7, 0x80, 0xFE, 0x07];
+
+    /// An adaptation field with every optional field: PCR, OPCR, splice_countdown -3,
+    /// private data "abc" and an extension with ltw, piecewise_rate and seamless_splice
+    fn full_adaptation_field() -> Vec<u8> {
+        let mut af = vec![0xDF];
+        af.extend_from_slice(&PCR);
+        af.extend_from_slice(&OPCR);
+        af.push(0xFD);
+        af.extend_from_slice(&[3, b'a', b'b', b'c']);
+        af.extend_from_slice(&[11, 0xFF, 0x92, 0x34, 0xC1, 0x23, 0x45, 0x29, 0x00, 0x01, 0x00, 0x03]);
+        af
+    }
+
+    #[test]
+    fn adaptation_field_only() {
+        // adaptation_field_control 2: the whole packet after the header is the adaptation field
+        let mut af = vec![0x50];
+        af.extend_from_slice(&PCR);
+        let buf = test_util::packet(0x100, false, 3, Some(&af), &[]);
+        let packet = Packet::new(&buf).unwrap();
+        assert_eq!(packet.adaptation_field_control, 2);
+        assert!(packet.payload.is_empty());
+        assert!(packet.is_random_access());
+        assert!(!packet.is_discontinuity());
+        let pcr = packet.pcr().unwrap();
+        assert_eq!((pcr.base, pcr.extension), (0x1_2345_6789, 0x123));
+        assert_eq!(pcr.value(), 1_466_015_503_791);
+        assert_eq!(packet.adaptation_field.unwrap().length, 183);
+    }
+
+    #[test]
+    fn adaptation_field_with_every_field() {
+        let buf = test_util::packet(0x1FF, true, 0, Some(&full_adaptation_field()), &[1, 2, 3]);
+        let packet = Packet::new(&buf).unwrap();
+        assert_eq!(packet.payload, [1, 2, 3]);
+        let af = packet.adaptation_field.unwrap();
+        assert!(af.discontinuity_indicator && af.random_access_indicator && !af.elementary_stream_priority_indicator);
+        assert_eq!(af.opcr.unwrap().value(), 864_720_000_307);
+        assert_eq!(af.splice_countdown, Some(-3));
+        assert_eq!(af.transport_private_data.as_deref(), Some(&b"abc"[..]));
+        assert_eq!(af.extension, Some(AdaptationFieldExtension {
+            ltw: Some(LegalTimeWindow { valid: true, offset: 0x1234 }),
+            piecewise_rate: Some(0x012345),
+            seamless_splice: Some(SeamlessSplice { splice_type: 2, dts_next_au: 0x1_0000_0001 }),
+        }));
+    }
+
+    #[test]
+    fn short_adaptation_fields() {
+        // An adaptation_field_length cutting into each of the optional fields
+        let af = full_adaptation_field();
+        for length in 1..af.len() {
+            let mut buf = test_util::packet(0x100, false, 0, Some(&af), &[0; 100]);
+            buf[4] = length as u8;
+            assert!(matches!(Packet::new(&buf), Err(ParseError::Truncated { .. })), "length {}", length);
+        }
+        let mut buf = test_util::packet(0x100, false, 0, Some(&af), &[0; 100]);
+        buf[4] = af.len() as u8;
+        assert_eq!(Packet::new(&buf).unwrap().payload.len(), 183 - af.len());
+    }
+
+    #[test]
+    fn invalid_packets() {
+        let mut buf = test_util::packet(0x100, false, 0, None, &[0; 184]);
+        assert_eq!(Packet::new(&buf[..187]), Err(ParseError::PacketSize { len: 187 }));
+        buf[0] = 0x48;
+        assert_eq!(Packet::new(&buf), Err(ParseError::SyncByte { found: 0x48 }));
+        // adaptation_field_length running past the end of the packet
+        let mut buf = test_util::packet(0x100, false, 0, None, &[]);
+        buf[3] = 0x30;
+        buf[4] = 190;
+        assert_eq!(Packet::new(&buf), Err(ParseError::Truncated { pid: Some(0x100), offset: 4, field: "adaptation_field" }));
+        // PCR flag set in a one byte adaptation field
+        buf[4] = 1;
+        buf[5] = 0x10;
+        assert!(matches!(Packet::new(&buf), Err(ParseError::Truncated { pid: Some(0x100), field: "program_clock_reference", .. })));
+    }
+
+    #[test]
+    fn continuity() {
+        let packet = |cc, control| Packet { continuity_counter: cc, adaptation_field_control: control, ..Default::default() };
+        // (packet, last_cc, is_dup, dup_count, error)
+        let cases = [
+            (packet(5, 1), 4, false, 0, false),
+            (packet(0, 1), 15, false, 0, false),
+            (packet(6, 1), 4, false, 0, true),
+            (packet(4, 1), 4, true, 1, false),
+            (packet(4, 1), 4, true, 2, true),
+            // No payload: the counter doesn't advance
+            (packet(4, 2), 4, false, 0, false),
+        ];
+        for (packet, last_cc, is_dup, dup_count, error) in cases.iter() {
+            assert_eq!(packet.has_continuity_error(*last_cc, *dup_count, *is_dup), *error, "{:?}", packet);
+        }
+        let mut discontinuity = packet(9, 3);
+        discontinuity.adaptation_field = Some(AdaptationField { discontinuity_indicator: true, ..Default::default() });
+        assert!(!discontinuity.has_continuity_error(4, 0, false));
+    }
+}
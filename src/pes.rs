@@ -0,0 +1,558 @@
+use std::fmt;
+use byteorder::{ByteOrder, BigEndian};
+use crate::packet::{self, ClockReference, Packet};
+use crate::error::{self, ParseError};
+
+// Constants
+pub const PES_START_CODE_PREFIX: [u8; 3] = [0x00, 0x00, 0x01];
+/// Frequency of the PTS/DTS clock
+pub const PES_CLOCK_FREQUENCY: u64 = 90_000;
+/// Size of packet_start_code_prefix, stream_id and PES_packet_length
+const PES_FIXED_HEADER_SIZE: usize = 6;
+/// Largest unbounded PES packet assembled: one no unit start ends is given up on
+pub const MAX_UNBOUNDED_PES_SIZE: usize = 16 * 1024 * 1024;
+
+// Stream ids that are not followed by the optional PES header
+const PROGRAM_STREAM_MAP: u8 = 0xBC;
+const PADDING_STREAM: u8 = 0xBE;
+const PRIVATE_STREAM_2: u8 = 0xBF;
+const ECM_STREAM: u8 = 0xF0;
+const EMM_STREAM: u8 = 0xF1;
+const DSMCC_STREAM: u8 = 0xF2;
+const H222_1_TYPE_E_STREAM: u8 = 0xF8;
+const PROGRAM_STREAM_DIRECTORY: u8 = 0xFF;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TrickMode {
+    FastForward { field_id: u8, intra_slice_refresh: bool, frequency_truncation: u8 },
+    SlowMotion { rep_cntrl: u8 },
+    FreezeFrame { field_id: u8 },
+    FastReverse { field_id: u8, intra_slice_refresh: bool, frequency_truncation: u8 },
+    SlowReverse { rep_cntrl: u8 },
+    Reserved(u8),
+}
+
+impl TrickMode {
+    fn new(b: u8) -> TrickMode {
+        let field_id = (b & 0x18) >> 3;
+        let intra_slice_refresh = packet::get_bit_at(b, 2);
+        let frequency_truncation = b & 0x03;
+        match b >> 5 {
+            0b000 => TrickMode::FastForward { field_id, intra_slice_refresh, frequency_truncation },
+            0b001 => TrickMode::SlowMotion { rep_cntrl: b & 0x1F },
+            0b010 => TrickMode::FreezeFrame { field_id },
+            0b011 => TrickMode::FastReverse { field_id, intra_slice_refresh, frequency_truncation },
+            0b100 => TrickMode::SlowReverse { rep_cntrl: b & 0x1F },
+            x => TrickMode::Reserved(x),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ProgramPacketSequenceCounter {
+    pub counter: u8,
+    pub mpeg1_mpeg2_identifier: bool,
+    pub original_stuff_length: u8,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PStdBuffer {
+    pub scale: bool,
+    pub size: u16,
+}
+
+impl PStdBuffer {
+    /// Buffer size in bytes (units of 1024 bytes for video, 128 for audio)
+    pub fn bytes(&self) -> u32 {
+        u32::from(self.size) * if self.scale { 1024 } else { 128 }
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PesExtension {
+    pub private_data: Option<Vec<u8>>,
+    pub pack_header: Option<Vec<u8>>,
+    pub program_packet_sequence_counter: Option<ProgramPacketSequenceCounter>,
+    pub p_std_buffer: Option<PStdBuffer>,
+    pub stream_id_extension: Option<u8>,
+    /// Remaining bytes of the second extension (TREF or reserved data)
+    pub extension_2_data: Option<Vec<u8>>,
+}
+
+impl PesExtension {
+    /// Parse a PES_extension buffer (starting at its flags byte) that ends at the PES header end
+    fn new(buf: &[u8]) -> Result<PesExtension, ParseError> {
+        let flags = error::field_byte(buf, 0, "PES_extension_flags")?;
+        let mut n = 1;
+        let mut ext: PesExtension = Default::default();
+        if packet::get_bit_at(flags, 7) {
+            ext.private_data = Some(error::field_bytes(buf, n, 16, "PES_private_data")?.to_vec());
+            n += 16;
+        }
+        if packet::get_bit_at(flags, 6) {
+            let length = error::field_byte(buf, n, "pack_field_length")? as usize;
+            ext.pack_header = Some(error::field_bytes(buf, n + 1, length, "pack_header")?.to_vec());
+            n += 1 + length;
+        }
+        if packet::get_bit_at(flags, 5) {
+            let b = error::field_bytes(buf, n, 2, "program_packet_sequence_counter")?;
+            ext.program_packet_sequence_counter = Some(ProgramPacketSequenceCounter {
+                counter: b[0] & 0x7F,
+                mpeg1_mpeg2_identifier: packet::get_bit_at(b[1], 6),
+                original_stuff_length: b[1] & 0x3F,
+            });
+            n += 2;
+        }
+        if packet::get_bit_at(flags, 4) {
+            let b = error::field_bytes(buf, n, 2, "P-STD_buffer_size")?;
+            ext.p_std_buffer = Some(PStdBuffer {
+                scale: packet::get_bit_at(b[0], 5),
+                size: BigEndian::read_u16(&[b[0] & 0x1F, b[1]]),
+            });
+            n += 2;
+        }
+        if packet::get_bit_at(flags, 0) {
+            let length = (error::field_byte(buf, n, "PES_extension_field_length")? & 0x7F) as usize;
+            let data = error::field_bytes(buf, n + 1, length, "PES_extension_field")?;
+            // stream_id_extension_flag == 0 means the extension carries a stream_id_extension
+            if let Some(&b) = data.first() {
+                if !packet::get_bit_at(b, 7) {
+                    ext.stream_id_extension = Some(b & 0x7F);
+                }
+            }
+            ext.extension_2_data = Some(data.get(1..).unwrap_or_default().to_vec());
+        }
+        Ok(ext)
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PesHeader {
+    pub scrambling_control: u8,
+    pub priority: bool,
+    pub data_alignment_indicator: bool,
+    pub copyright: bool,
+    pub original_or_copy: bool,
+    pub header_data_length: u8,
+    pub pts: Option<u64>,
+    pub dts: Option<u64>,
+    pub escr: Option<ClockReference>,
+    pub es_rate: Option<u32>,
+    pub trick_mode: Option<TrickMode>,
+    pub additional_copy_info: Option<u8>,
+    pub previous_pes_packet_crc: Option<u16>,
+    pub extension: Option<PesExtension>,
+}
+
+impl PesHeader {
+    /// Parse the optional PES header (starting after PES_packet_length)
+    fn new(buf: &[u8]) -> Result<PesHeader, ParseError> {
+        let fixed = error::field_bytes(buf, 0, 3, "PES_header_data_length")?;
+        // The header always starts with the '10' marker bits
+        if fixed[0] & 0xC0 != 0x80 {
+            return Err(ParseError::InvalidValue {
+                pid: None, offset: 0, field: "PES header marker bits", value: u32::from(fixed[0] >> 6),
+            });
+        }
+        let flags = fixed[1];
+        let header_data_length = fixed[2];
+        let buf = error::field_bytes(buf, 0, 3 + header_data_length as usize, "PES header")?;
+
+        let mut n = 3;
+        let mut header = PesHeader {
+            scrambling_control: (fixed[0] & 0x30) >> 4,
+            priority: packet::get_bit_at(fixed[0], 3),
+            data_alignment_indicator: packet::get_bit_at(fixed[0], 2),
+            copyright: packet::get_bit_at(fixed[0], 1),
+            original_or_copy: packet::get_bit_at(fixed[0], 0),
+            header_data_length,
+            ..Default::default()
+        };
+        // PTS_DTS_flags: '10' PTS only, '11' PTS and DTS
+        if packet::get_bit_at(flags, 7) {
+            header.pts = Some(packet::read_timestamp(error::field_bytes(buf, n, 5, "PTS")?));
+            n += 5;
+            if packet::get_bit_at(flags, 6) {
+                header.dts = Some(packet::read_timestamp(error::field_bytes(buf, n, 5, "DTS")?));
+                n += 5;
+            }
+        }
+        if packet::get_bit_at(flags, 5) {
+            let b = error::field_bytes(buf, n, 6, "ESCR")?;
+            header.escr = Some(ClockReference {
+                base: (u64::from(b[0] & 0x38) << 27) | (u64::from(b[0] & 0x03) << 28) |
+                    (u64::from(b[1]) << 20) | (u64::from(b[2] & 0xF8) << 12) |
+                    (u64::from(b[2] & 0x03) << 13) | (u64::from(b[3]) << 5) |
+                    (u64::from(b[4]) >> 3),
+                extension: (u16::from(b[4] & 0x03) << 7) | (u16::from(b[5]) >> 1),
+            });
+            n += 6;
+        }
+        if packet::get_bit_at(flags, 4) {
+            let b = error::field_bytes(buf, n, 3, "ES_rate")?;
+            header.es_rate = Some(BigEndian::read_u24(&[b[0] & 0x7F, b[1], b[2]]) >> 1);
+            n += 3;
+        }
+        if packet::get_bit_at(flags, 3) {
+            header.trick_mode = Some(TrickMode::new(error::field_byte(buf, n, "trick_mode_control")?));
+            n += 1;
+        }
+        if packet::get_bit_at(flags, 2) {
+            header.additional_copy_info = Some(error::field_byte(buf, n, "additional_copy_info")? & 0x7F);
+            n += 1;
+        }
+        if packet::get_bit_at(flags, 1) {
+            header.previous_pes_packet_crc = Some(BigEndian::read_u16(
+                error::field_bytes(buf, n, 2, "previous_PES_packet_CRC")?));
+            n += 2;
+        }
+        if packet::get_bit_at(flags, 0) {
+            header.extension = Some(PesExtension::new(&buf[n..]).map_err(|e| e.shift(n))?);
+        }
+        Ok(header)
+    }
+
+    /// Total size of the optional header, including its 3 fixed bytes
+    fn size(&self) -> usize {
+        3 + self.header_data_length as usize
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pes {
+    pub stream_id: u8,
+    /// 0 means unbounded (only allowed for video carried in transport streams)
+    pub pes_packet_length: u16,
+    pub header: Option<PesHeader>,
+    pub payload: Vec<u8>,
+}
+
+impl fmt::Display for Pes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[PES] Stream ID: {:#X} ({}), Length: {}",
+            self.stream_id, self.stream_id_name(), self.pes_packet_length)?;
+        if let Some(pts) = self.pts() {
+            write!(f, ", PTS: {} ({:.6}s)", pts, pts as f64 / PES_CLOCK_FREQUENCY as f64)?;
+        }
+        if let Some(dts) = self.dts() {
+            write!(f, ", DTS: {} ({:.6}s)", dts, dts as f64 / PES_CLOCK_FREQUENCY as f64)?;
+        }
+        write!(f, ", Payload: {} bytes", self.payload.len())
+    }
+}
+
+impl Pes {
+    /// Parse a complete PES packet buffer into a Pes object and return Result<Pes, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Pes, ParseError> {
+        error::field_bytes(buf, 0, PES_FIXED_HEADER_SIZE, "PES_packet_length")?;
+        if buf[0..3] != PES_START_CODE_PREFIX {
+            return Err(ParseError::InvalidValue {
+                pid: None, offset: 0, field: "packet_start_code_prefix",
+                value: BigEndian::read_u24(&buf[0..3]),
+            });
+        }
+        let stream_id = buf[3];
+        let pes_packet_length = BigEndian::read_u16(&buf[4..6]);
+        // Unbounded packets run to the end of the buffer, bounded ones must be all there
+        let end = if pes_packet_length == 0 {
+            buf.len()
+        } else {
+            error::field_bytes(buf, PES_FIXED_HEADER_SIZE, pes_packet_length as usize, "PES_packet_data_byte")?;
+            PES_FIXED_HEADER_SIZE + pes_packet_length as usize
+        };
+
+        let header = if Pes::has_header(stream_id) {
+            Some(PesHeader::new(&buf[PES_FIXED_HEADER_SIZE..end])
+                .map_err(|e| e.shift(PES_FIXED_HEADER_SIZE))?)
+        } else {
+            None
+        };
+        let payload_start = PES_FIXED_HEADER_SIZE + header.as_ref().map_or(0, |h| h.size());
+        Ok(Pes {
+            stream_id,
+            pes_packet_length,
+            header,
+            payload: buf.get(payload_start..end).unwrap_or_default().to_vec(),
+        })
+    }
+
+    pub fn pts(&self) -> Option<u64> {
+        self.header.as_ref()?.pts
+    }
+
+    pub fn dts(&self) -> Option<u64> {
+        self.header.as_ref()?.dts
+    }
+
+    /// Whether stream_id is followed by the optional PES header fields
+    fn has_header(stream_id: u8) -> bool {
+        !matches!(stream_id,
+            PROGRAM_STREAM_MAP | PADDING_STREAM | PRIVATE_STREAM_2 | ECM_STREAM | EMM_STREAM |
+            DSMCC_STREAM | H222_1_TYPE_E_STREAM | PROGRAM_STREAM_DIRECTORY)
+    }
+
+    pub fn stream_id_name(&self) -> &'static str {
+        match self.stream_id {
+            PROGRAM_STREAM_MAP => "Program Stream Map",
+            0xBD => "Private Stream 1",
+            PADDING_STREAM => "Padding",
+            PRIVATE_STREAM_2 => "Private Stream 2",
+            0xC0..=0xDF => "Audio",
+            0xE0..=0xEF => "Video",
+            ECM_STREAM => "ECM",
+            EMM_STREAM => "EMM",
+            DSMCC_STREAM => "DSM-CC",
+            0xF3 => "ISO/IEC 13522",
+            0xF4..=0xF7 => "ITU-T Rec. H.222.1 type A-D",
+            H222_1_TYPE_E_STREAM => "ITU-T Rec. H.222.1 type E",
+            0xF9 => "Ancillary",
+            0xFA => "SL-packetized",
+            0xFB => "FlexMux",
+            0xFC => "Metadata",
+            0xFD => "Extended Stream ID",
+            0xFE => "Reserved",
+            PROGRAM_STREAM_DIRECTORY => "Program Stream Directory",
+            _ => "Unknown",
+        }
+    }
+}
+
+/// Collects the payloads of a single elementary PID into complete PES packets
+#[derive(Clone, Debug, Default)]
+pub struct PesAssembler {
+    buf: Vec<u8>,
+}
+
+impl PesAssembler {
+    /// Feed the payload of a packet and return the PES packets it completes
+    pub fn push(&mut self, packet: &Packet) -> Vec<Result<Pes, ParseError>> {
+        let mut completed: Vec<Result<Pes, ParseError>> = vec![];
+        if packet.payload_unit_start_indicator {
+            // A new unit start ends the previous (unbounded or truncated) packet
+            completed.extend(self.flush());
+            self.buf.extend_from_slice(&packet.payload);
+        } else if self.buf.len() + packet.payload.len() > MAX_UNBOUNDED_PES_SIZE {
+            // Drop it until the next unit start rather than grow without bound
+            self.buf.clear();
+            completed.push(Err(ParseError::InvalidValue {
+                pid: None, offset: 4, field: "PES_packet_length", value: 0,
+            }));
+        } else if !self.buf.is_empty() {
+            self.buf.extend_from_slice(&packet.payload);
+        }
+
+        // Bounded packets are complete once PES_packet_length bytes have arrived
+        if self.buf.len() >= PES_FIXED_HEADER_SIZE {
+            let length = BigEndian::read_u16(&self.buf[4..6]) as usize;
+            if length != 0 && self.buf.len() >= PES_FIXED_HEADER_SIZE + length {
+                completed.extend(self.flush());
+            }
+        }
+        completed
+    }
+
+    /// Return whatever packet is currently being assembled (e.g. at the end of the stream)
+    pub fn flush(&mut self) -> Option<Result<Pes, ParseError>> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let pes = Pes::new(&self.buf);
+        self.buf.clear();
+        Some(pes)
+    }
+
+    /// Discard the packet being assembled (e.g. after a lost packet)
+    pub fn reset(&mut self) {
+        self.buf.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// A PTS or DTS after its 4 bit `prefix`, with the marker bits
+    fn timestamp(prefix: u8, ts: u64) -> [u8; 5] {
+        [(prefix << 4) | ((ts >> 29) & 0x0E) as u8 | 0x01, (ts >> 22) as u8, ((ts >> 14) & 0xFE) as u8 | 0x01,
+            (ts >> 7) as u8, ((ts << 1) & 0xFE) as u8 | 0x01]
+    }
+
+    /// A PES packet with the PTS_DTS_flags.. PES_extension_flag `flags` and their `fields`,
+    /// bounded unless `bounded` is false
+    fn pes_packet(stream_id: u8, bounded: bool, flags: u8, fields: &[u8], payload: &[u8]) -> Vec<u8> {
+        let length = if bounded { 3 + fields.len() + payload.len() } else { 0 };
+        let mut buf = vec![0x00, 0x00, 0x01, stream_id, (length >> 8) as u8, length as u8, 0x84, flags, fields.len() as u8];
+        buf.extend_from_slice(fields);
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    fn packet(unit_start: bool, payload: &[u8]) -> Packet {
+        Packet { payload_unit_start_indicator: unit_start, payload: payload.to_vec(), ..Default::default() }
+    }
+
+    #[test]
+    fn pts_and_dts() {
+        let fields = [timestamp(0x3, 0x1_2345_6789), timestamp(0x1, 0x1_2345_0000)].concat();
+        let pes = Pes::new(&pes_packet(0xE0, true, 0xC0, &fields, &[1, 2, 3])).unwrap();
+        assert_eq!((pes.pts(), pes.dts()), (Some(0x1_2345_6789), Some(0x1_2345_0000)));
+        assert_eq!((pes.pes_packet_length, pes.payload.as_slice()), (16, &[1, 2, 3][..]));
+        assert!(pes.header.as_ref().unwrap().data_alignment_indicator);
+        assert_eq!(pes.to_string(), "[PES] Stream ID: 0xE0 (Video), Length: 16, PTS: 4886718345 (54296.870500s), \
+            DTS: 4886691840 (54296.576000s), Payload: 3 bytes");
+
+        // PTS_DTS_flags '10': no DTS
+        let pes = Pes::new(&pes_packet(0xC0, true, 0x80, &timestamp(0x2, 90_000), &[])).unwrap();
+        assert_eq!((pes.pts(), pes.dts()), (Some(90_000), None));
+    }
+
+    #[test]
+    fn escr_es_rate_and_trick_mode() {
+        let (base, extension) = (0x1_5555_AAAAu64, 0x1A5u16);
+        let mut fields = vec![
+            ((base >> 27) & 0x38) as u8 | 0x04 | ((base >> 28) & 0x03) as u8, (base >> 20) as u8,
+            ((base >> 12) & 0xF8) as u8 | 0x04 | ((base >> 13) & 0x03) as u8, (base >> 5) as u8,
+            ((base << 3) & 0xF8) as u8 | 0x04 | ((extension >> 7) & 0x03) as u8, ((extension << 1) & 0xFE) as u8 | 0x01,
+        ];
+        // ES_rate 0x2ABCDE (in units of 50 bytes/s), fast forward, additional_copy_info and CRC
+        fields.extend_from_slice(&[0x80 | 0x55, 0x79, 0xBD, 0x17, 0xAA, 0xBE, 0xEF]);
+        let pes = Pes::new(&pes_packet(0xE0, false, 0x3E, &fields, &[0xFF])).unwrap();
+        let header = pes.header.unwrap();
+        assert_eq!(header.escr, Some(ClockReference { base, extension }));
+        assert_eq!(header.es_rate, Some(0x2ABCDE));
+        assert_eq!(header.trick_mode, Some(TrickMode::FastForward { field_id: 2, intra_slice_refresh: true, frequency_truncation: 3 }));
+        assert_eq!((header.additional_copy_info, header.previous_pes_packet_crc), (Some(0x2A), Some(0xBEEF)));
+        assert_eq!(pes.payload, [0xFF]);
+
+        let modes = [
+            (0x2A, TrickMode::SlowMotion { rep_cntrl: 0x0A }),
+            (0x50, TrickMode::FreezeFrame { field_id: 2 }),
+            (0x63, TrickMode::FastReverse { field_id: 0, intra_slice_refresh: false, frequency_truncation: 3 }),
+            (0x9F, TrickMode::SlowReverse { rep_cntrl: 0x1F }),
+            (0xE0, TrickMode::Reserved(7)),
+        ];
+        for (b, mode) in modes {
+            assert_eq!(TrickMode::new(b), mode, "{:#X}", b);
+        }
+    }
+
+    #[test]
+    fn extension() {
+        let mut fields = vec![0xFF];
+        fields.extend_from_slice(&[0x11; 16]);
+        fields.extend_from_slice(&[2, 0xAB, 0xCD]);
+        // program_packet_sequence_counter, P-STD buffer and PES_extension_2 with a stream_id_extension
+        fields.extend_from_slice(&[0x95, 0x45, 0x61, 0x00, 0x82, 0x71, 0xAA]);
+        let pes = Pes::new(&pes_packet(0xFD, true, 0x01, &fields, &[0x42])).unwrap();
+        let extension = pes.header.unwrap().extension.unwrap();
+        assert_eq!(extension, PesExtension {
+            private_data: Some(vec![0x11; 16]),
+            pack_header: Some(vec![0xAB, 0xCD]),
+            program_packet_sequence_counter: Some(ProgramPacketSequenceCounter {
+                counter: 0x15, mpeg1_mpeg2_identifier: true, original_stuff_length: 5,
+            }),
+            p_std_buffer: Some(PStdBuffer { scale: true, size: 0x100 }),
+            stream_id_extension: Some(0x71),
+            extension_2_data: Some(vec![0xAA]),
+        });
+        assert_eq!(extension.p_std_buffer.unwrap().bytes(), 256 * 1024);
+        assert_eq!(pes.payload, [0x42]);
+
+        // PES_header_data_length ending inside the P-STD buffer field
+        let mut buf = pes_packet(0xFD, true, 0x01, &fields[..22], &[]);
+        buf[8] = 22;
+        assert_eq!(Pes::new(&buf), Err(ParseError::Truncated { pid: None, offset: 31, field: "P-STD_buffer_size" }));
+    }
+
+    #[test]
+    fn packet_length() {
+        // Bytes past a bounded packet aren't part of it
+        let mut buf = pes_packet(0xC0, true, 0x00, &[], &[1, 2, 3]);
+        buf.extend_from_slice(&[0xFF; 4]);
+        assert_eq!(Pes::new(&buf).unwrap().payload, [1, 2, 3]);
+        assert_eq!(Pes::new(&buf[..10]), Err(ParseError::Truncated { pid: None, offset: 6, field: "PES_packet_data_byte" }));
+
+        // An unbounded one runs to the end of the buffer
+        let buf = pes_packet(0xE0, false, 0x00, &[], &[1, 2, 3, 4, 5]);
+        let pes = Pes::new(&buf).unwrap();
+        assert_eq!((pes.pes_packet_length, pes.payload.len()), (0, 5));
+
+        // Padding has no optional header
+        let pes = Pes::new(&[0x00, 0x00, 0x01, 0xBE, 0x00, 0x02, 0xFF, 0xFF]).unwrap();
+        assert_eq!(pes.stream_id_name(), "Padding");
+        assert_eq!((pes.header, pes.payload), (None, vec![0xFF, 0xFF]));
+
+        assert!(matches!(Pes::new(&[0x00, 0x00, 0x02, 0xE0, 0x00, 0x00]),
+            Err(ParseError::InvalidValue { field: "packet_start_code_prefix", value: 2, .. })));
+        assert!(matches!(Pes::new(&[0x00, 0x00, 0x01, 0xE0, 0x00, 0x00, 0x40, 0x00, 0x00]),
+            Err(ParseError::InvalidValue { offset: 6, field: "PES header marker bits", value: 1, .. })));
+    }
+
+    #[test]
+    fn assembler() {
+        let mut assembler = PesAssembler::default();
+        // The continuation of a packet whose start was missed
+        assert!(assembler.push(&packet(false, &[0xAB; 184])).is_empty());
+
+        // A bounded packet is complete with its last byte
+        let bounded = pes_packet(0xC0, true, 0x80, &timestamp(0x2, 3600), &[0x55; 400]);
+        assert!(assembler.push(&packet(true, &bounded[..184])).is_empty());
+        assert!(assembler.push(&packet(false, &bounded[184..368])).is_empty());
+        let completed = assembler.push(&packet(false, &bounded[368..]));
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].as_ref().unwrap().payload, [0x55; 400]);
+        assert!(assembler.flush().is_none());
+
+        // An unbounded one ends at the next unit start, or at the end of the stream
+        let unbounded = pes_packet(0xE0, false, 0x80, &timestamp(0x2, 7200), &[0x66; 300]);
+        assert!(assembler.push(&packet(true, &unbounded[..184])).is_empty());
+        assert!(assembler.push(&packet(false, &unbounded[184..])).is_empty());
+        let completed = assembler.push(&packet(true, &unbounded));
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].as_ref().unwrap().payload, [0x66; 300]);
+        assert_eq!(assembler.flush().unwrap().unwrap().pts(), Some(7200));
+
+        // A bounded packet cut short by a unit start
+        assert!(assembler.push(&packet(true, &bounded[..184])).is_empty());
+        assert!(matches!(assembler.push(&packet(true, &bounded[..184]))[..],
+            [Err(ParseError::Truncated { field: "PES_packet_data_byte", .. })]));
+        assembler.reset();
+        assert!(assembler.flush().is_none());
+    }
+
+    #[test]
+    fn unbounded_size_cap() {
+        let mut assembler = PesAssembler::default();
+        let start = pes_packet(0xE0, false, 0x00, &[], &[0; 175]);
+        assert!(assembler.push(&packet(true, &start)).is_empty());
+        let continuation = packet(false, &[0; 184]);
+        let mut packets = 1;
+        let completed = loop {
+            let completed = assembler.push(&continuation);
+            packets += 1;
+            if !completed.is_empty() {
+                break completed;
+            }
+        };
+        assert_eq!(packets, MAX_UNBOUNDED_PES_SIZE / 184 + 1);
+        assert!(matches!(completed[..], [Err(ParseError::InvalidValue { field: "PES_packet_length", value: 0, .. })]));
+        // Nothing more is collected until the next unit start
+        assert!(assembler.push(&continuation).is_empty());
+        assert!(assembler.flush().is_none());
+    }
+
+    #[test]
+    fn hostile_headers() {
+        let fields = [&timestamp(0x3, 1000)[..], &timestamp(0x1, 900), &[0x80, 0x00, 0x03, 0x17, 0xAA, 0xBE, 0xEF],
+            &[0xC1, 0x00], &[0x11; 15], &[0x02, 0xAB, 0xCD, 0x82, 0x71, 0xAA]].concat();
+        let valid = pes_packet(0xE0, true, 0xDF, &fields, &[1, 2, 3]);
+        assert!(Pes::new(&valid).is_ok());
+        test_util::hostile_inputs(&valid, |buf| {
+            if let Ok(pes) = Pes::new(buf) {
+                assert!(pes.payload.len() + PES_FIXED_HEADER_SIZE <= buf.len());
+                let _ = pes.to_string();
+            }
+        });
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+use std::fmt::Write;
+use std::collections::HashSet;
+use byteorder::{ByteOrder, BigEndian};
+use super::descriptor::{CaDescriptor, Descriptor};
+use crate::{packet, mpeg32_crc, error::ParseError};
+
+// Constants
+const CAT_TABLE_ID: u8 = 0x01;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cat {
+    section_syntax_indicator: bool,
+    version_number: u8,
+    current_next_indicator: bool,
+    section_number: u8,
+    last_section_number: u8,
+    pub descriptors: Vec<Descriptor>,
+    pub ca_descriptors: Vec<CaDescriptor>,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for Cat {
+    /// Display the cat along with the CA systems it signals
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut ca_str = String::new();
+        for d in &self.ca_descriptors {
+            write!(&mut ca_str,
+                "\n\t=> CA System ID: {0:#06X} ({1}), EMM PID: {2:#X} ({2}), Private data: {3} bytes",
+                d.ca_system_id, d.ca_system_name(), d.ca_pid, d.private_data.len()).unwrap();
+        }
+        write!(f, "[CAT] Version: {0:#X}{1}", self.version_number, ca_str)
+    }
+}
+
+impl Cat {
+    /// Parse a section buffer into a Cat object and return Result<Cat, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Cat, ParseError> {
+        // Calculate length and index fields
+        let end_n = super::check_section(buf, CAT_TABLE_ID)?;
+
+        // The descriptors take up the rest of the section
+        let descriptors = Descriptor::parse_loop(buf, 8, end_n)?;
+        let ca_descriptors = descriptors.iter().filter_map(|d| match d {
+            Descriptor::Ca(ca) => Some(ca.clone()),
+            _ => None,
+        }).collect();
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(Cat {
+            section_syntax_indicator: packet::get_bit_at(buf[1], 7),
+            version_number: (buf[5] & 0x3E) >> 1,
+            current_next_indicator: packet::get_bit_at(buf[5], 0),
+            section_number: buf[6],
+            last_section_number: buf[7],
+            descriptors,
+            ca_descriptors,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+
+    /// Get a list of the EMM PIDs in this CAT
+    pub fn get_emm_pids(&self) -> HashSet<u16> {
+        self.ca_descriptors.iter().map(|d| d.ca_pid).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// A CAT with the CA descriptors of a Conax and a Nagravision system
+    fn cat() -> Vec<u8> {
+        let mut body = test_util::descriptor(0x09, &[0x0B, 0x00, 0xE1, 0x23]);
+        body.extend(test_util::descriptor(0x09, &[0x18, 0x00, 0xE2, 0x00, 0x55]));
+        test_util::section(CAT_TABLE_ID, 0xFFFF, &body)
+    }
+
+    #[test]
+    fn emm_pids() {
+        let cat = Cat::new(&cat()).unwrap();
+        assert!(!cat.crc_error);
+        assert_eq!(cat.get_emm_pids(), [0x123, 0x200].iter().copied().collect());
+        assert_eq!(cat.ca_descriptors[1].ca_system_name(), "Nagravision");
+        assert_eq!(cat.ca_descriptors[1].private_data, [0x55]);
+        assert_eq!(cat.to_string(), "[CAT] Version: 0x0\
+            \n\t=> CA System ID: 0x0B00 (Conax), EMM PID: 0x123 (291), Private data: 0 bytes\
+            \n\t=> CA System ID: 0x1800 (Nagravision), EMM PID: 0x200 (512), Private data: 1 bytes");
+    }
+
+    #[test]
+    fn undecodable_ca_descriptor() {
+        // A CA descriptor too short for a CA_PID is kept, but has no EMM PID
+        let body = test_util::descriptor(0x09, &[0x0B, 0x00]);
+        let cat = Cat::new(&test_util::section(CAT_TABLE_ID, 0xFFFF, &body)).unwrap();
+        assert_eq!(cat.descriptors, vec![Descriptor::Unknown { tag: 0x09, data: vec![0x0B, 0x00] }]);
+        assert!(cat.get_emm_pids().is_empty());
+    }
+
+    #[test]
+    fn invalid_sections() {
+        let buf = cat();
+        for len in 0..buf.len() {
+            assert!(Cat::new(&buf[..len]).is_err(), "{} bytes", len);
+        }
+        let mut pmt = buf.clone();
+        pmt[0] = 0x02;
+        assert_eq!(Cat::new(&pmt), Err(ParseError::TableId { pid: None, expected: CAT_TABLE_ID, found: 0x02 }));
+        let mut short = buf.clone();
+        short[2] = 8;
+        assert_eq!(Cat::new(&short), Err(ParseError::InvalidValue { pid: None, offset: 1, field: "section_length", value: 8 }));
+        // The second descriptor runs into the CRC
+        let mut overrun = buf;
+        overrun[15] = 6;
+        assert_eq!(Cat::new(&overrun), Err(ParseError::Truncated { pid: None, offset: 16, field: "descriptor" }));
+    }
+}
@@ -0,0 +1,1105 @@
+use std::fmt;
+use byteorder::{ByteOrder, BigEndian};
+use crate::{packet, error::{self, ParseError}};
+
+// Constants
+pub const VIDEO_STREAM_DESCRIPTOR_TAG: u8 = 0x02;
+pub const AUDIO_STREAM_DESCRIPTOR_TAG: u8 = 0x03;
+pub const REGISTRATION_DESCRIPTOR_TAG: u8 = 0x05;
+pub const DATA_STREAM_ALIGNMENT_DESCRIPTOR_TAG: u8 = 0x06;
+pub const CA_DESCRIPTOR_TAG: u8 = 0x09;
+pub const ISO_639_LANGUAGE_DESCRIPTOR_TAG: u8 = 0x0A;
+pub const MAXIMUM_BITRATE_DESCRIPTOR_TAG: u8 = 0x0E;
+pub const METADATA_DESCRIPTOR_TAG: u8 = 0x26;
+pub const AVC_VIDEO_DESCRIPTOR_TAG: u8 = 0x28;
+pub const AVC_TIMING_AND_HRD_DESCRIPTOR_TAG: u8 = 0x2A;
+pub const MPEG2_AAC_AUDIO_DESCRIPTOR_TAG: u8 = 0x2B;
+pub const HEVC_VIDEO_DESCRIPTOR_TAG: u8 = 0x38;
+// DVB (ETSI EN 300 468)
+pub const STREAM_IDENTIFIER_DESCRIPTOR_TAG: u8 = 0x52;
+pub const TELETEXT_DESCRIPTOR_TAG: u8 = 0x56;
+pub const SUBTITLING_DESCRIPTOR_TAG: u8 = 0x59;
+pub const DATA_BROADCAST_ID_DESCRIPTOR_TAG: u8 = 0x66;
+pub const AC3_DESCRIPTOR_TAG: u8 = 0x6A;
+pub const APPLICATION_SIGNALLING_DESCRIPTOR_TAG: u8 = 0x6F;
+pub const ENHANCED_AC3_DESCRIPTOR_TAG: u8 = 0x7A;
+pub const DTS_DESCRIPTOR_TAG: u8 = 0x7B;
+pub const AAC_DESCRIPTOR_TAG: u8 = 0x7C;
+pub const EXTENSION_DESCRIPTOR_TAG: u8 = 0x7F;
+/// descriptor_tag_extension of the supplementary_audio_descriptor
+const SUPPLEMENTARY_AUDIO_DESCRIPTOR_TAG_EXTENSION: u8 = 0x06;
+/// metadata_application_format announcing a metadata_application_format_identifier
+const METADATA_APPLICATION_FORMAT_IDENTIFIER: u16 = 0xFFFF;
+/// metadata_format announcing a metadata_format_identifier
+const METADATA_FORMAT_IDENTIFIER: u8 = 0xFF;
+
+/// video_stream_descriptor: the coding parameters of a MPEG-1/2 video stream
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VideoStreamDescriptor {
+    pub multiple_frame_rate_flag: bool,
+    pub frame_rate_code: u8,
+    pub mpeg_1_only_flag: bool,
+    pub constrained_parameter_flag: bool,
+    pub still_picture_flag: bool,
+    /// None for MPEG-1 only streams
+    pub profile_and_level_indication: Option<u8>,
+    pub chroma_format: Option<u8>,
+    pub frame_rate_extension_flag: Option<bool>,
+}
+
+impl VideoStreamDescriptor {
+    fn new(body: &[u8]) -> Result<VideoStreamDescriptor, ParseError> {
+        let b = error::field_byte(body, 0, "frame_rate_code")?;
+        let mpeg_1_only_flag = packet::get_bit_at(b, 2);
+        let extension = if mpeg_1_only_flag {
+            None
+        } else {
+            Some(error::field_bytes(body, 1, 2, "chroma_format")?)
+        };
+        Ok(VideoStreamDescriptor {
+            multiple_frame_rate_flag: packet::get_bit_at(b, 7),
+            frame_rate_code: (b >> 3) & 0x0F,
+            mpeg_1_only_flag,
+            constrained_parameter_flag: packet::get_bit_at(b, 1),
+            still_picture_flag: packet::get_bit_at(b, 0),
+            profile_and_level_indication: extension.map(|e| e[0]),
+            chroma_format: extension.map(|e| e[1] >> 6),
+            frame_rate_extension_flag: extension.map(|e| packet::get_bit_at(e[1], 5)),
+        })
+    }
+
+    pub fn frame_rate_name(&self) -> &'static str {
+        match self.frame_rate_code {
+            1 => "23.976",
+            2 => "24",
+            3 => "25",
+            4 => "29.97",
+            5 => "30",
+            6 => "50",
+            7 => "59.94",
+            8 => "60",
+            _ => "Reserved",
+        }
+    }
+}
+
+/// audio_stream_descriptor: the coding parameters of a MPEG-1/2 audio stream
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AudioStreamDescriptor {
+    pub free_format_flag: bool,
+    /// The ID field of the audio headers (true for MPEG-1, false for the MPEG-2 low sampling rates)
+    pub id: bool,
+    pub layer: u8,
+    pub variable_rate_audio_indicator: bool,
+}
+
+impl AudioStreamDescriptor {
+    fn new(body: &[u8]) -> Result<AudioStreamDescriptor, ParseError> {
+        let b = error::field_byte(body, 0, "layer")?;
+        Ok(AudioStreamDescriptor {
+            free_format_flag: packet::get_bit_at(b, 7),
+            id: packet::get_bit_at(b, 6),
+            layer: (b >> 4) & 0x03,
+            variable_rate_audio_indicator: packet::get_bit_at(b, 3),
+        })
+    }
+}
+
+/// CA_descriptor: the PID carrying the EMMs (in the CAT) or ECMs (in a PMT) of a CA system
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CaDescriptor {
+    pub ca_system_id: u16,
+    pub ca_pid: u16,
+    pub private_data: Vec<u8>,
+}
+
+impl CaDescriptor {
+    fn new(body: &[u8]) -> Result<CaDescriptor, ParseError> {
+        let b = error::field_bytes(body, 0, 4, "CA_PID")?;
+        Ok(CaDescriptor {
+            ca_system_id: BigEndian::read_u16(&b[0..2]),
+            ca_pid: BigEndian::read_u16(&[b[2] & 0x1F, b[3]]),
+            private_data: body[4..].to_vec(),
+        })
+    }
+
+    /// Name of the vendor the CA_system_id range is allocated to
+    pub fn ca_system_name(&self) -> &'static str {
+        match self.ca_system_id {
+            0x0100..=0x01FF => "Seca Mediaguard",
+            0x0500..=0x05FF => "Viaccess",
+            0x0600..=0x06FF => "Irdeto",
+            0x0900..=0x09FF => "NDS Videoguard",
+            0x0B00..=0x0BFF => "Conax",
+            0x0D00..=0x0DFF => "Cryptoworks",
+            0x0E00..=0x0EFF => "PowerVu",
+            0x1700..=0x17FF => "BetaCrypt",
+            0x1800..=0x18FF => "Nagravision",
+            0x2600..=0x26FF => "BISS",
+            0x4A00..=0x4AFF => "DVB allocated",
+            0x5601 => "Verimatrix",
+            _ => "Unknown",
+        }
+    }
+}
+
+/// A language of an ISO_639_language_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Language {
+    /// ISO 639-2 code, e.g. "eng"
+    pub code: String,
+    pub audio_type: u8,
+}
+
+impl Language {
+    pub fn audio_type_name(&self) -> &'static str {
+        match self.audio_type {
+            0x00 => "Undefined",
+            0x01 => "Clean effects",
+            0x02 => "Hearing impaired",
+            0x03 => "Visual impaired commentary",
+            0x04..=0x7F => "User Private",
+            _ => "Reserved",
+        }
+    }
+}
+
+/// AVC_video_descriptor: the profile and level of a H.264 stream
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AvcVideoDescriptor {
+    pub profile_idc: u8,
+    /// constraint_set0_flag to constraint_set5_flag and the AVC_compatible_flags
+    pub constraint_flags: u8,
+    pub level_idc: u8,
+    pub avc_still_present: bool,
+    pub avc_24_hour_picture_flag: bool,
+    pub frame_packing_sei_not_present_flag: bool,
+}
+
+impl AvcVideoDescriptor {
+    fn new(body: &[u8]) -> Result<AvcVideoDescriptor, ParseError> {
+        let b = error::field_bytes(body, 0, 4, "AVC_still_present")?;
+        Ok(AvcVideoDescriptor {
+            profile_idc: b[0],
+            constraint_flags: b[1],
+            level_idc: b[2],
+            avc_still_present: packet::get_bit_at(b[3], 7),
+            avc_24_hour_picture_flag: packet::get_bit_at(b[3], 6),
+            frame_packing_sei_not_present_flag: packet::get_bit_at(b[3], 5),
+        })
+    }
+}
+
+/// AVC_timing_and_HRD_descriptor: the timing of a H.264 stream without VUI timing info
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AvcTimingHrdDescriptor {
+    pub hrd_management_valid_flag: bool,
+    /// The clock the times are in (90 kHz unless N and K are given) and num_units_in_tick
+    pub picture_and_timing_info: Option<PictureTiming>,
+    pub fixed_frame_rate_flag: bool,
+    pub temporal_poc_flag: bool,
+    pub picture_to_display_conversion_flag: bool,
+}
+
+/// The picture and timing info of an AVC_timing_and_HRD_descriptor
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PictureTiming {
+    /// N and K of the clock frequency 27 MHz * N / K (None for 90 kHz)
+    pub n_and_k: Option<(u32, u32)>,
+    pub num_units_in_tick: u32,
+}
+
+impl AvcTimingHrdDescriptor {
+    fn new(body: &[u8]) -> Result<AvcTimingHrdDescriptor, ParseError> {
+        let b = error::field_byte(body, 0, "picture_and_timing_info_present")?;
+        let mut n = 1;
+        let picture_and_timing_info = if packet::get_bit_at(b, 0) {
+            let flag = error::field_byte(body, n, "90kHz_flag")?;
+            n += 1;
+            let n_and_k = if packet::get_bit_at(flag, 7) {
+                None
+            } else {
+                let nk = error::field_bytes(body, n, 8, "K")?;
+                n += 8;
+                Some((BigEndian::read_u32(&nk[0..4]), BigEndian::read_u32(&nk[4..8])))
+            };
+            let num_units_in_tick = BigEndian::read_u32(error::field_bytes(body, n, 4, "num_units_in_tick")?);
+            n += 4;
+            Some(PictureTiming { n_and_k, num_units_in_tick })
+        } else {
+            None
+        };
+        let flags = error::field_byte(body, n, "fixed_frame_rate_flag")?;
+        Ok(AvcTimingHrdDescriptor {
+            hrd_management_valid_flag: packet::get_bit_at(b, 7),
+            picture_and_timing_info,
+            fixed_frame_rate_flag: packet::get_bit_at(flags, 7),
+            temporal_poc_flag: packet::get_bit_at(flags, 6),
+            picture_to_display_conversion_flag: packet::get_bit_at(flags, 5),
+        })
+    }
+}
+
+/// MPEG-2_AAC_audio_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mpeg2AacDescriptor {
+    pub profile: u8,
+    pub channel_configuration: u8,
+    pub additional_information: u8,
+}
+
+impl Mpeg2AacDescriptor {
+    fn new(body: &[u8]) -> Result<Mpeg2AacDescriptor, ParseError> {
+        let b = error::field_bytes(body, 0, 3, "MPEG-2_AAC_additional_information")?;
+        Ok(Mpeg2AacDescriptor { profile: b[0], channel_configuration: b[1], additional_information: b[2] })
+    }
+
+    pub fn profile_name(&self) -> &'static str {
+        match self.profile {
+            0 => "Main",
+            1 => "LC",
+            2 => "SSR",
+            _ => "Reserved",
+        }
+    }
+}
+
+/// HEVC_video_descriptor: the profile, tier and level of a H.265 stream
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HevcVideoDescriptor {
+    pub profile_space: u8,
+    pub tier_flag: bool,
+    pub profile_idc: u8,
+    pub profile_compatibility_indication: u32,
+    pub progressive_source_flag: bool,
+    pub interlaced_source_flag: bool,
+    pub non_packed_constraint_flag: bool,
+    pub frame_only_constraint_flag: bool,
+    pub level_idc: u8,
+    pub hevc_still_present_flag: bool,
+    pub hevc_24hr_picture_present_flag: bool,
+    pub sub_pic_hrd_params_not_present_flag: bool,
+    pub hdr_wcg_idc: u8,
+    /// temporal_id_min and temporal_id_max, if the stream is a subset of the temporal layers
+    pub temporal_layers: Option<(u8, u8)>,
+}
+
+impl HevcVideoDescriptor {
+    fn new(body: &[u8]) -> Result<HevcVideoDescriptor, ParseError> {
+        let b = error::field_bytes(body, 0, 13, "HEVC_still_present_flag")?;
+        let temporal_layers = if packet::get_bit_at(b[12], 7) {
+            let t = error::field_bytes(body, 13, 2, "temporal_id_max")?;
+            Some((t[0] >> 5, t[1] >> 5))
+        } else {
+            None
+        };
+        Ok(HevcVideoDescriptor {
+            profile_space: b[0] >> 6,
+            tier_flag: packet::get_bit_at(b[0], 5),
+            profile_idc: b[0] & 0x1F,
+            profile_compatibility_indication: BigEndian::read_u32(&b[1..5]),
+            progressive_source_flag: packet::get_bit_at(b[5], 7),
+            interlaced_source_flag: packet::get_bit_at(b[5], 6),
+            non_packed_constraint_flag: packet::get_bit_at(b[5], 5),
+            frame_only_constraint_flag: packet::get_bit_at(b[5], 4),
+            level_idc: b[11],
+            hevc_still_present_flag: packet::get_bit_at(b[12], 6),
+            hevc_24hr_picture_present_flag: packet::get_bit_at(b[12], 5),
+            sub_pic_hrd_params_not_present_flag: packet::get_bit_at(b[12], 4),
+            hdr_wcg_idc: b[12] & 0x03,
+            temporal_layers,
+        })
+    }
+}
+
+/// Read the ISO 639 language code at `buf[0..3]`
+fn language_code(buf: &[u8]) -> String {
+    String::from_utf8_lossy(&buf[0..3]).into_owned()
+}
+
+/// A page of a teletext_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TeletextPage {
+    pub language: String,
+    pub teletext_type: u8,
+    pub magazine_number: u8,
+    /// BCD coded page number (e.g. 0x88 for page 888 with magazine 8)
+    pub page_number: u8,
+}
+
+impl TeletextPage {
+    pub fn teletext_type_name(&self) -> &'static str {
+        match self.teletext_type {
+            0x01 => "Initial page",
+            0x02 => "Subtitle page",
+            0x03 => "Additional information page",
+            0x04 => "Programme schedule page",
+            0x05 => "Subtitle page for hearing impaired",
+            _ => "Reserved",
+        }
+    }
+
+    /// The page as shown on a TV, e.g. 888 (magazine 0 is shown as 8)
+    pub fn page(&self) -> u16 {
+        let magazine = if self.magazine_number == 0 { 8 } else { u16::from(self.magazine_number) };
+        magazine * 100 + u16::from(self.page_number >> 4) * 10 + u16::from(self.page_number & 0x0F)
+    }
+}
+
+/// A subtitle service of a subtitling_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Subtitle {
+    pub language: String,
+    /// component_type of stream_content 0x03 (e.g. 0x10 for normal DVB subtitles, 0x20 for hard of hearing)
+    pub subtitling_type: u8,
+    pub composition_page_id: u16,
+    pub ancillary_page_id: u16,
+}
+
+/// AC-3_descriptor or enhanced_AC-3_descriptor (whose substreams and mixinfoexists the
+/// AC-3_descriptor doesn't have)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Ac3Descriptor {
+    pub component_type: Option<u8>,
+    pub bsid: Option<u8>,
+    pub mainid: Option<u8>,
+    pub asvc: Option<u8>,
+    pub mix_info_exists: bool,
+    pub substream1: Option<u8>,
+    pub substream2: Option<u8>,
+    pub substream3: Option<u8>,
+    pub additional_info: Vec<u8>,
+}
+
+impl Ac3Descriptor {
+    fn new(body: &[u8], enhanced: bool) -> Result<Ac3Descriptor, ParseError> {
+        let flags = error::field_byte(body, 0, "AC-3 flags")?;
+        let mut n = 1;
+        let mut field = |bit: u8, name: &'static str| -> Result<Option<u8>, ParseError> {
+            if !packet::get_bit_at(flags, bit) {
+                return Ok(None);
+            }
+            n += 1;
+            error::field_byte(body, n - 1, name).map(Some)
+        };
+        let component_type = field(7, "component_type")?;
+        let bsid = field(6, "bsid")?;
+        let mainid = field(5, "mainid")?;
+        let asvc = field(4, "asvc")?;
+        let (substream1, substream2, substream3) = if enhanced {
+            (field(2, "substream1")?, field(1, "substream2")?, field(0, "substream3")?)
+        } else {
+            (None, None, None)
+        };
+        Ok(Ac3Descriptor {
+            component_type,
+            bsid,
+            mainid,
+            asvc,
+            mix_info_exists: enhanced && packet::get_bit_at(flags, 3),
+            substream1,
+            substream2,
+            substream3,
+            additional_info: body[n..].to_vec(),
+        })
+    }
+
+    /// The number of channels the component_type gives, as text (e.g. "2/0 stereo")
+    pub fn channels_name(&self) -> Option<&'static str> {
+        Some(match self.component_type? & 0x07 {
+            0 => "1/0 mono",
+            1 => "1+1 dual mono",
+            2 => "2/0 stereo",
+            3 => "2/0 Dolby surround",
+            4 => "multichannel > 2",
+            5 => "multichannel > 5.1",
+            6 => "multiple substreams",
+            _ => "Reserved",
+        })
+    }
+}
+
+/// DTS_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DtsDescriptor {
+    pub sample_rate_code: u8,
+    pub bit_rate_code: u8,
+    pub nblks: u8,
+    pub fsize: u16,
+    pub surround_mode: u8,
+    pub lfe_flag: bool,
+    pub extended_surround_flag: u8,
+    pub additional_info: Vec<u8>,
+}
+
+impl DtsDescriptor {
+    fn new(body: &[u8]) -> Result<DtsDescriptor, ParseError> {
+        let b = error::field_bytes(body, 0, 5, "extended_surround_flag")?;
+        let bits = (u64::from(b[0]) << 32) | u64::from(BigEndian::read_u32(&b[1..5]));
+        Ok(DtsDescriptor {
+            sample_rate_code: (bits >> 36) as u8 & 0x0F,
+            bit_rate_code: (bits >> 30) as u8 & 0x3F,
+            nblks: (bits >> 23) as u8 & 0x7F,
+            fsize: (bits >> 9) as u16 & 0x3FFF,
+            surround_mode: (bits >> 3) as u8 & 0x3F,
+            lfe_flag: bits & 0x04 != 0,
+            extended_surround_flag: bits as u8 & 0x03,
+            additional_info: body[5..].to_vec(),
+        })
+    }
+}
+
+/// AAC_descriptor: the MPEG-4 audio profile and level of a HE-AAC/AAC stream
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AacDescriptor {
+    pub profile_and_level: u8,
+    pub saoc_de_flag: bool,
+    /// component_type of stream_content 0x06, if given
+    pub aac_type: Option<u8>,
+    pub additional_info: Vec<u8>,
+}
+
+impl AacDescriptor {
+    fn new(body: &[u8]) -> Result<AacDescriptor, ParseError> {
+        let profile_and_level = error::field_byte(body, 0, "profile_and_level")?;
+        let (flags, aac_type, n) = match body.get(1) {
+            None => (0, None, 1),
+            Some(&flags) if packet::get_bit_at(flags, 7) => (flags, Some(error::field_byte(body, 2, "AAC_type")?), 3),
+            Some(&flags) => (flags, None, 2),
+        };
+        Ok(AacDescriptor {
+            profile_and_level,
+            saoc_de_flag: packet::get_bit_at(flags, 6),
+            aac_type,
+            additional_info: body[n..].to_vec(),
+        })
+    }
+}
+
+/// supplementary_audio_descriptor (an extension descriptor): what an audio stream adds,
+/// e.g. audio description for the visually impaired
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SupplementaryAudioDescriptor {
+    /// Whether the stream is complete on its own rather than mixed with the main audio
+    pub mix_type: bool,
+    pub editorial_classification: u8,
+    pub language: Option<String>,
+    pub private_data: Vec<u8>,
+}
+
+impl SupplementaryAudioDescriptor {
+    /// Parse the body following the descriptor_tag_extension
+    fn new(body: &[u8]) -> Result<SupplementaryAudioDescriptor, ParseError> {
+        let b = error::field_byte(body, 0, "editorial_classification")?;
+        let (language, n) = if packet::get_bit_at(b, 0) {
+            (Some(language_code(error::field_bytes(body, 1, 3, "ISO_639_language_code")?)), 4)
+        } else {
+            (None, 1)
+        };
+        Ok(SupplementaryAudioDescriptor {
+            mix_type: packet::get_bit_at(b, 7),
+            editorial_classification: (b >> 2) & 0x1F,
+            language,
+            private_data: body[n..].to_vec(),
+        })
+    }
+
+    pub fn editorial_classification_name(&self) -> &'static str {
+        match self.editorial_classification {
+            0x00 => "Main audio",
+            0x01 => "Audio description for the visually impaired",
+            0x02 => "Clean audio for the hearing impaired",
+            0x03 => "Spoken subtitles for the visually impaired",
+            0x04 => "Dependent parametric data stream",
+            0x17..=0x1F => "User defined",
+            _ => "Reserved",
+        }
+    }
+}
+
+/// An application of an application_signalling_descriptor
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Application {
+    pub application_type: u16,
+    pub ait_version_number: u8,
+}
+
+/// A descriptor of a PSI/SI table, decoded if it is one of the common MPEG-2 or DVB ones
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Descriptor {
+    VideoStream(VideoStreamDescriptor),
+    AudioStream(AudioStreamDescriptor),
+    /// The format_identifier (e.g. b"CUEI" or b"AC-3") and additional_identification_info
+    Registration { format_identifier: [u8; 4], additional_identification_info: Vec<u8> },
+    DataStreamAlignment { alignment_type: u8 },
+    Ca(CaDescriptor),
+    Iso639Language(Vec<Language>),
+    /// In units of 50 bytes/s
+    MaximumBitrate { maximum_bitrate: u32 },
+    /// The format of the metadata carried, with its identifier (e.g. b"ID3 ") when it is 0xFF
+    Metadata { metadata_application_format: u16, metadata_format: u8, metadata_format_identifier: Option<[u8; 4]> },
+    AvcVideo(AvcVideoDescriptor),
+    AvcTimingHrd(AvcTimingHrdDescriptor),
+    Mpeg2Aac(Mpeg2AacDescriptor),
+    HevcVideo(HevcVideoDescriptor),
+    /// Links the stream to the components of the EIT and SDT
+    StreamIdentifier { component_tag: u8 },
+    Teletext(Vec<TeletextPage>),
+    Subtitling(Vec<Subtitle>),
+    DataBroadcastId { data_broadcast_id: u16, id_selector: Vec<u8> },
+    Ac3(Ac3Descriptor),
+    ApplicationSignalling(Vec<Application>),
+    EnhancedAc3(Ac3Descriptor),
+    Dts(DtsDescriptor),
+    Aac(AacDescriptor),
+    SupplementaryAudio(SupplementaryAudioDescriptor),
+    /// Any other descriptor (or one whose body couldn't be decoded), with its body as it is
+    Unknown { tag: u8, data: Vec<u8> },
+}
+
+impl fmt::Display for Descriptor {
+    /// Display the tag of the descriptor along with what it was decoded into
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tag: {:#X} ({})", self.tag(), self.tag_name())?;
+        match self {
+            Descriptor::VideoStream(d) => {
+                write!(f, ", Frame rate: {}", d.frame_rate_name())?;
+                if let Some(profile_and_level) = d.profile_and_level_indication {
+                    write!(f, ", Profile and level: {:#X}", profile_and_level)?;
+                }
+                Ok(())
+            },
+            Descriptor::AudioStream(d) => write!(f, ", Layer: {}, ID: {}", d.layer, d.id as u8),
+            Descriptor::Registration { format_identifier, .. } =>
+                write!(f, ", Format identifier: {}", String::from_utf8_lossy(format_identifier)),
+            Descriptor::DataStreamAlignment { alignment_type } =>
+                write!(f, ", Alignment type: {:#X}", alignment_type),
+            Descriptor::Ca(d) => write!(f, ", CA system ID: {:#06X} ({}), CA PID: {:#X}",
+                d.ca_system_id, d.ca_system_name(), d.ca_pid),
+            Descriptor::Iso639Language(languages) => {
+                let languages: Vec<String> = languages.iter()
+                    .map(|l| format!("{} ({})", l.code, l.audio_type_name()))
+                    .collect();
+                write!(f, ", Languages: {}", languages.join(", "))
+            },
+            Descriptor::MaximumBitrate { .. } =>
+                write!(f, ", Maximum bitrate: {} bits/s", self.maximum_bitrate().unwrap_or(0)),
+            Descriptor::Metadata { metadata_format, metadata_format_identifier, .. } => match metadata_format_identifier {
+                Some(identifier) => write!(f, ", Metadata format: {}", String::from_utf8_lossy(identifier)),
+                None => write!(f, ", Metadata format: {:#X}", metadata_format),
+            },
+            Descriptor::AvcVideo(d) => write!(f, ", Profile: {}, Level: {}", d.profile_idc, d.level_idc),
+            Descriptor::AvcTimingHrd(d) => write!(f, ", Fixed frame rate: {}", d.fixed_frame_rate_flag),
+            Descriptor::Mpeg2Aac(d) => write!(f, ", Profile: {}, Channel configuration: {}",
+                d.profile_name(), d.channel_configuration),
+            Descriptor::HevcVideo(d) => write!(f, ", Profile: {}, Tier: {}, Level: {}",
+                d.profile_idc, if d.tier_flag { "High" } else { "Main" }, d.level_idc),
+            Descriptor::StreamIdentifier { component_tag } => write!(f, ", Component tag: {:#X}", component_tag),
+            Descriptor::Teletext(pages) => {
+                let pages: Vec<String> = pages.iter()
+                    .map(|p| format!("{} {} ({})", p.language, p.page(), p.teletext_type_name()))
+                    .collect();
+                write!(f, ", Pages: {}", pages.join(", "))
+            },
+            Descriptor::Subtitling(subtitles) => {
+                let subtitles: Vec<String> = subtitles.iter()
+                    .map(|s| format!("{} (type {:#X}, page {})", s.language, s.subtitling_type, s.composition_page_id))
+                    .collect();
+                write!(f, ", Subtitles: {}", subtitles.join(", "))
+            },
+            Descriptor::DataBroadcastId { data_broadcast_id, .. } =>
+                write!(f, ", Data broadcast ID: {:#06X}", data_broadcast_id),
+            Descriptor::Ac3(d) | Descriptor::EnhancedAc3(d) => match d.channels_name() {
+                Some(channels) => write!(f, ", Channels: {}", channels),
+                None => Ok(()),
+            },
+            Descriptor::ApplicationSignalling(applications) => {
+                let applications: Vec<String> = applications.iter()
+                    .map(|a| format!("{:#X} (AIT version {})", a.application_type, a.ait_version_number))
+                    .collect();
+                write!(f, ", Applications: {}", applications.join(", "))
+            },
+            Descriptor::Dts(d) => write!(f, ", Sample rate code: {}, Bit rate code: {}, LFE: {}",
+                d.sample_rate_code, d.bit_rate_code, d.lfe_flag),
+            Descriptor::Aac(d) => write!(f, ", Profile and level: {:#X}", d.profile_and_level),
+            Descriptor::SupplementaryAudio(d) => {
+                write!(f, ", {}", d.editorial_classification_name())?;
+                if let Some(language) = &d.language {
+                    write!(f, ", Language: {}", language)?;
+                }
+                Ok(())
+            },
+            Descriptor::Unknown { data, .. } => write!(f, ", length: {}", data.len()),
+        }
+    }
+}
+
+impl Descriptor {
+    /// Decode the descriptor with tag `tag` and body `body`
+    pub fn new(tag: u8, body: &[u8]) -> Result<Descriptor, ParseError> {
+        Ok(match tag {
+            VIDEO_STREAM_DESCRIPTOR_TAG => Descriptor::VideoStream(VideoStreamDescriptor::new(body)?),
+            AUDIO_STREAM_DESCRIPTOR_TAG => Descriptor::AudioStream(AudioStreamDescriptor::new(body)?),
+            REGISTRATION_DESCRIPTOR_TAG => {
+                let b = error::field_bytes(body, 0, 4, "format_identifier")?;
+                Descriptor::Registration {
+                    format_identifier: [b[0], b[1], b[2], b[3]],
+                    additional_identification_info: body[4..].to_vec(),
+                }
+            },
+            DATA_STREAM_ALIGNMENT_DESCRIPTOR_TAG =>
+                Descriptor::DataStreamAlignment { alignment_type: error::field_byte(body, 0, "alignment_type")? },
+            CA_DESCRIPTOR_TAG => Descriptor::Ca(CaDescriptor::new(body)?),
+            ISO_639_LANGUAGE_DESCRIPTOR_TAG => Descriptor::Iso639Language(body.chunks_exact(4).map(|b| Language {
+                code: language_code(b),
+                audio_type: b[3],
+            }).collect()),
+            MAXIMUM_BITRATE_DESCRIPTOR_TAG => {
+                let b = error::field_bytes(body, 0, 3, "maximum_bitrate")?;
+                Descriptor::MaximumBitrate { maximum_bitrate: BigEndian::read_u24(b) & 0x3F_FFFF }
+            },
+            METADATA_DESCRIPTOR_TAG => {
+                let b = error::field_bytes(body, 0, 2, "metadata_application_format")?;
+                let metadata_application_format = BigEndian::read_u16(b);
+                let n = if metadata_application_format == METADATA_APPLICATION_FORMAT_IDENTIFIER { 6 } else { 2 };
+                let metadata_format = error::field_byte(body, n, "metadata_format")?;
+                let metadata_format_identifier = if metadata_format == METADATA_FORMAT_IDENTIFIER {
+                    let b = error::field_bytes(body, n + 1, 4, "metadata_format_identifier")?;
+                    Some([b[0], b[1], b[2], b[3]])
+                } else {
+                    None
+                };
+                Descriptor::Metadata { metadata_application_format, metadata_format, metadata_format_identifier }
+            },
+            AVC_VIDEO_DESCRIPTOR_TAG => Descriptor::AvcVideo(AvcVideoDescriptor::new(body)?),
+            AVC_TIMING_AND_HRD_DESCRIPTOR_TAG => Descriptor::AvcTimingHrd(AvcTimingHrdDescriptor::new(body)?),
+            MPEG2_AAC_AUDIO_DESCRIPTOR_TAG => Descriptor::Mpeg2Aac(Mpeg2AacDescriptor::new(body)?),
+            HEVC_VIDEO_DESCRIPTOR_TAG => Descriptor::HevcVideo(HevcVideoDescriptor::new(body)?),
+            STREAM_IDENTIFIER_DESCRIPTOR_TAG =>
+                Descriptor::StreamIdentifier { component_tag: error::field_byte(body, 0, "component_tag")? },
+            TELETEXT_DESCRIPTOR_TAG => Descriptor::Teletext(body.chunks_exact(5).map(|b| TeletextPage {
+                language: language_code(b),
+                teletext_type: b[3] >> 3,
+                magazine_number: b[3] & 0x07,
+                page_number: b[4],
+            }).collect()),
+            SUBTITLING_DESCRIPTOR_TAG => Descriptor::Subtitling(body.chunks_exact(8).map(|b| Subtitle {
+                language: language_code(b),
+                subtitling_type: b[3],
+                composition_page_id: BigEndian::read_u16(&b[4..6]),
+                ancillary_page_id: BigEndian::read_u16(&b[6..8]),
+            }).collect()),
+            DATA_BROADCAST_ID_DESCRIPTOR_TAG => Descriptor::DataBroadcastId {
+                data_broadcast_id: BigEndian::read_u16(error::field_bytes(body, 0, 2, "data_broadcast_id")?),
+                id_selector: body[2..].to_vec(),
+            },
+            AC3_DESCRIPTOR_TAG => Descriptor::Ac3(Ac3Descriptor::new(body, false)?),
+            APPLICATION_SIGNALLING_DESCRIPTOR_TAG =>
+                Descriptor::ApplicationSignalling(body.chunks_exact(3).map(|b| Application {
+                    application_type: BigEndian::read_u16(&b[0..2]) & 0x7FFF,
+                    ait_version_number: b[2] & 0x1F,
+                }).collect()),
+            ENHANCED_AC3_DESCRIPTOR_TAG => Descriptor::EnhancedAc3(Ac3Descriptor::new(body, true)?),
+            DTS_DESCRIPTOR_TAG => Descriptor::Dts(DtsDescriptor::new(body)?),
+            AAC_DESCRIPTOR_TAG => Descriptor::Aac(AacDescriptor::new(body)?),
+            EXTENSION_DESCRIPTOR_TAG if body.first() == Some(&SUPPLEMENTARY_AUDIO_DESCRIPTOR_TAG_EXTENSION) =>
+                Descriptor::SupplementaryAudio(SupplementaryAudioDescriptor::new(&body[1..]).map_err(|e| e.shift(1))?),
+            _ => Descriptor::Unknown { tag, data: body.to_vec() },
+        })
+    }
+
+    /// Parse the descriptors found in `buf[start..end]`, failing only if they overrun `end`
+    pub(crate) fn parse_loop(buf: &[u8], start: usize, end: usize) -> Result<Vec<Descriptor>, ParseError> {
+        let buf = error::field_bytes(buf, 0, end, "descriptors")?;
+        let mut n = start;
+        let mut descriptors = vec![];
+        while n < end {
+            let header = error::field_bytes(buf, n, 2, "descriptor_length")?;
+            let body = error::field_bytes(buf, n + 2, header[1] as usize, "descriptor")?;
+            // A body that can't be decoded is kept as it is rather than losing the whole table
+            descriptors.push(Descriptor::new(header[0], body)
+                .unwrap_or_else(|_| Descriptor::Unknown { tag: header[0], data: body.to_vec() }));
+            n += 2 + body.len();
+        }
+        Ok(descriptors)
+    }
+
+    pub fn tag(&self) -> u8 {
+        match self {
+            Descriptor::VideoStream(_) => VIDEO_STREAM_DESCRIPTOR_TAG,
+            Descriptor::AudioStream(_) => AUDIO_STREAM_DESCRIPTOR_TAG,
+            Descriptor::Registration { .. } => REGISTRATION_DESCRIPTOR_TAG,
+            Descriptor::DataStreamAlignment { .. } => DATA_STREAM_ALIGNMENT_DESCRIPTOR_TAG,
+            Descriptor::Ca(_) => CA_DESCRIPTOR_TAG,
+            Descriptor::Iso639Language(_) => ISO_639_LANGUAGE_DESCRIPTOR_TAG,
+            Descriptor::MaximumBitrate { .. } => MAXIMUM_BITRATE_DESCRIPTOR_TAG,
+            Descriptor::Metadata { .. } => METADATA_DESCRIPTOR_TAG,
+            Descriptor::AvcVideo(_) => AVC_VIDEO_DESCRIPTOR_TAG,
+            Descriptor::AvcTimingHrd(_) => AVC_TIMING_AND_HRD_DESCRIPTOR_TAG,
+            Descriptor::Mpeg2Aac(_) => MPEG2_AAC_AUDIO_DESCRIPTOR_TAG,
+            Descriptor::HevcVideo(_) => HEVC_VIDEO_DESCRIPTOR_TAG,
+            Descriptor::StreamIdentifier { .. } => STREAM_IDENTIFIER_DESCRIPTOR_TAG,
+            Descriptor::Teletext(_) => TELETEXT_DESCRIPTOR_TAG,
+            Descriptor::Subtitling(_) => SUBTITLING_DESCRIPTOR_TAG,
+            Descriptor::DataBroadcastId { .. } => DATA_BROADCAST_ID_DESCRIPTOR_TAG,
+            Descriptor::Ac3(_) => AC3_DESCRIPTOR_TAG,
+            Descriptor::ApplicationSignalling(_) => APPLICATION_SIGNALLING_DESCRIPTOR_TAG,
+            Descriptor::EnhancedAc3(_) => ENHANCED_AC3_DESCRIPTOR_TAG,
+            Descriptor::Dts(_) => DTS_DESCRIPTOR_TAG,
+            Descriptor::Aac(_) => AAC_DESCRIPTOR_TAG,
+            Descriptor::SupplementaryAudio(_) => EXTENSION_DESCRIPTOR_TAG,
+            Descriptor::Unknown { tag, .. } => *tag,
+        }
+    }
+
+    /// The maximum_bitrate of a maximum_bitrate_descriptor in bits/s
+    pub fn maximum_bitrate(&self) -> Option<u32> {
+        match self {
+            Descriptor::MaximumBitrate { maximum_bitrate } => Some(maximum_bitrate * 50 * 8),
+            _ => None,
+        }
+    }
+
+    /// Name of the tag, from ISO/IEC 13818-1 up to 63 and ETSI EN 300 468 above
+    // TODO: Look into making something more efficient (maybe a macro)
+    pub fn tag_name(&self) -> &'static str {
+        match self.tag() {
+            0 => "Reserved",
+            1 => "Reserved",
+            2 => "Video Stream",
+            3 => "Audio Stream",
+            4 => "Hierarchy",
+            5 => "Registration",
+            6 => "Data Stream Alignment",
+            7 => "Target Background Grid",
+            8 => "Video Window",
+            9 => "CA",
+            10 => "ISO 639 Language",
+            11 => "System Clock",
+            12 => "Multiplex Buffer Utilization",
+            13 => "Copyright",
+            14 => "Maximum Bitrate",
+            15 => "Private Data Indicator",
+            16 => "Smoothing Buffer",
+            17 => "STD",
+            18 => "IBP",
+            19..=26 => "Defined in ISO/IEC 13818-6",
+            27 => "MPEG-4 Video",
+            28 => "MPEG-4 Audio",
+            29 => "IOD",
+            30 => "SL",
+            31 => "FMC",
+            32 => "External ES ID",
+            33 => "MuxCode",
+            34 => "FmxBufferSize",
+            35 => "Multiplexbuffer",
+            36 => "Content Labeling",
+            37 => "Metadata Pointer",
+            38 => "Metadata",
+            39 => "Metadata STD",
+            40 => "AVC Video",
+            41 => "IPMP",
+            42 => "AVC Timing and HRD",
+            43 => "MPEG-2 AAC Audio",
+            44 => "FlexMuxTiming",
+            56 => "HEVC Video",
+            45..=63 => "ITU-T Rec. H.222.0 | ISO/IEC 13818-1 Reserved",
+            64 => "Network Name",
+            65 => "Service List",
+            66 => "Stuffing",
+            67 => "Satellite Delivery System",
+            68 => "Cable Delivery System",
+            69 => "VBI Data",
+            70 => "VBI Teletext",
+            71 => "Bouquet Name",
+            72 => "Service",
+            73 => "Country Availability",
+            74 => "Linkage",
+            75 => "NVOD Reference",
+            76 => "Time Shifted Service",
+            77 => "Short Event",
+            78 => "Extended Event",
+            79 => "Time Shifted Event",
+            80 => "Component",
+            81 => "Mosaic",
+            82 => "Stream Identifier",
+            83 => "CA Identifier",
+            84 => "Content",
+            85 => "Parental Rating",
+            86 => "Teletext",
+            87 => "Telephone",
+            88 => "Local Time Offset",
+            89 => "Subtitling",
+            90 => "Terrestrial Delivery System",
+            91 => "Multilingual Network Name",
+            92 => "Multilingual Bouquet Name",
+            93 => "Multilingual Service Name",
+            94 => "Multilingual Component",
+            95 => "Private Data Specifier",
+            96 => "Service Move",
+            97 => "Short Smoothing Buffer",
+            98 => "Frequency List",
+            99 => "Partial Transport Stream",
+            100 => "Data Broadcast",
+            101 => "Scrambling",
+            102 => "Data Broadcast ID",
+            103 => "Transport Stream",
+            104 => "DSNG",
+            105 => "PDC",
+            106 => "AC-3",
+            107 => "Ancillary Data",
+            108 => "Cell List",
+            109 => "Cell Frequency Link",
+            110 => "Announcement Support",
+            111 => "Application Signalling",
+            112 => "Adaptation Field Data",
+            113 => "Service Identifier",
+            114 => "Service Availability",
+            115 => "Default Authority",
+            116 => "Related Content",
+            117 => "TVA ID",
+            118 => "Content Identifier",
+            119 => "Time Slice FEC Identifier",
+            120 => "ECM Repetition Rate",
+            121 => "S2 Satellite Delivery System",
+            122 => "Enhanced AC-3",
+            123 => "DTS",
+            124 => "AAC",
+            125 => "XAIT Location",
+            126 => "FTA Content Management",
+            127 => "Extension",
+            128..=254 => "User Private",
+            255 => "Forbidden",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// (tag, body, what it decodes to)
+    fn vectors() -> Vec<(u8, Vec<u8>, Descriptor)> {
+        vec![
+            (0x02, vec![0x18, 0x48, 0x5F], Descriptor::VideoStream(VideoStreamDescriptor {
+                multiple_frame_rate_flag: false,
+                frame_rate_code: 3,
+                mpeg_1_only_flag: false,
+                constrained_parameter_flag: false,
+                still_picture_flag: false,
+                profile_and_level_indication: Some(0x48),
+                chroma_format: Some(1),
+                frame_rate_extension_flag: Some(false),
+            })),
+            (0x03, vec![0x58], Descriptor::AudioStream(AudioStreamDescriptor {
+                free_format_flag: false,
+                id: true,
+                layer: 1,
+                variable_rate_audio_indicator: true,
+            })),
+            (0x05, b"CUEI\xFF".to_vec(), Descriptor::Registration {
+                format_identifier: *b"CUEI",
+                additional_identification_info: vec![0xFF],
+            }),
+            (0x06, vec![0x01], Descriptor::DataStreamAlignment { alignment_type: 1 }),
+            (0x09, vec![0x0B, 0x00, 0xE1, 0x23, 0xAA], Descriptor::Ca(CaDescriptor {
+                ca_system_id: 0x0B00,
+                ca_pid: 0x123,
+                private_data: vec![0xAA],
+            })),
+            (0x0A, b"eng\x01deu\x00".to_vec(), Descriptor::Iso639Language(vec![
+                Language { code: "eng".into(), audio_type: 1 },
+                Language { code: "deu".into(), audio_type: 0 },
+            ])),
+            (0x0E, vec![0xC0, 0x27, 0x10], Descriptor::MaximumBitrate { maximum_bitrate: 10_000 }),
+            (0x26, b"\xFF\xFFID3 \xFFID3 \x00\x0F".to_vec(), Descriptor::Metadata {
+                metadata_application_format: 0xFFFF,
+                metadata_format: 0xFF,
+                metadata_format_identifier: Some(*b"ID3 "),
+            }),
+            (0x26, vec![0x01, 0x00, 0x10, 0x00], Descriptor::Metadata {
+                metadata_application_format: 0x0100,
+                metadata_format: 0x10,
+                metadata_format_identifier: None,
+            }),
+            (0x28, vec![100, 0x0C, 40, 0x3F], Descriptor::AvcVideo(AvcVideoDescriptor {
+                profile_idc: 100,
+                constraint_flags: 0x0C,
+                level_idc: 40,
+                avc_still_present: false,
+                avc_24_hour_picture_flag: false,
+                frame_packing_sei_not_present_flag: true,
+            })),
+            (0x2A, vec![0x81, 0x80, 0x00, 0x00, 0x0E, 0x10, 0xE0], Descriptor::AvcTimingHrd(AvcTimingHrdDescriptor {
+                hrd_management_valid_flag: true,
+                picture_and_timing_info: Some(PictureTiming { n_and_k: None, num_units_in_tick: 3600 }),
+                fixed_frame_rate_flag: true,
+                temporal_poc_flag: true,
+                picture_to_display_conversion_flag: true,
+            })),
+            (0x2B, vec![1, 2, 0], Descriptor::Mpeg2Aac(Mpeg2AacDescriptor {
+                profile: 1,
+                channel_configuration: 2,
+                additional_information: 0,
+            })),
+            (0x38, vec![0x22, 0x20, 0, 0, 0, 0x90, 0, 0, 0, 0, 0, 153, 0x82, 0x3F, 0x7F],
+                Descriptor::HevcVideo(HevcVideoDescriptor {
+                    profile_space: 0,
+                    tier_flag: true,
+                    profile_idc: 2,
+                    profile_compatibility_indication: 0x2000_0000,
+                    progressive_source_flag: true,
+                    interlaced_source_flag: false,
+                    non_packed_constraint_flag: false,
+                    frame_only_constraint_flag: true,
+                    level_idc: 153,
+                    hevc_still_present_flag: false,
+                    hevc_24hr_picture_present_flag: false,
+                    sub_pic_hrd_params_not_present_flag: false,
+                    hdr_wcg_idc: 2,
+                    temporal_layers: Some((1, 3)),
+                })),
+            (0x99, vec![1, 2], Descriptor::Unknown { tag: 0x99, data: vec![1, 2] }),
+        ]
+    }
+
+    #[test]
+    fn known_descriptors() {
+        for (tag, body, expected) in vectors() {
+            assert_eq!(Descriptor::new(tag, &body), Ok(expected.clone()), "tag {:#X}", tag);
+            assert_eq!(expected.tag(), tag);
+        }
+    }
+
+    #[test]
+    fn dvb_descriptors() {
+        for (tag, body, expected) in [
+            (0x52, vec![0x05], Descriptor::StreamIdentifier { component_tag: 5 }),
+            (0x56, b"eng\x09\x00eng\x10\x88".to_vec(), Descriptor::Teletext(vec![
+                TeletextPage { language: "eng".into(), teletext_type: 1, magazine_number: 1, page_number: 0 },
+                TeletextPage { language: "eng".into(), teletext_type: 2, magazine_number: 0, page_number: 0x88 },
+            ])),
+            (0x59, b"fra\x10\x00\x01\x00\x02".to_vec(), Descriptor::Subtitling(vec![
+                Subtitle { language: "fra".into(), subtitling_type: 0x10, composition_page_id: 1, ancillary_page_id: 2 },
+            ])),
+            (0x66, vec![0x01, 0x06, 0xAB], Descriptor::DataBroadcastId { data_broadcast_id: 0x0106, id_selector: vec![0xAB] }),
+            (0x6A, vec![0xC0, 0x42, 0x08], Descriptor::Ac3(Ac3Descriptor {
+                component_type: Some(0x42),
+                bsid: Some(8),
+                mainid: None,
+                asvc: None,
+                mix_info_exists: false,
+                substream1: None,
+                substream2: None,
+                substream3: None,
+                additional_info: vec![],
+            })),
+            (0x6F, vec![0x80, 0x10, 0xE3], Descriptor::ApplicationSignalling(vec![
+                Application { application_type: 0x10, ait_version_number: 3 },
+            ])),
+            (0x7A, vec![0x8C, 0x44, 0x55, 0x01], Descriptor::EnhancedAc3(Ac3Descriptor {
+                component_type: Some(0x44),
+                bsid: None,
+                mainid: None,
+                asvc: None,
+                mix_info_exists: true,
+                substream1: Some(0x55),
+                substream2: None,
+                substream3: None,
+                additional_info: vec![0x01],
+            })),
+            (0x7B, vec![0xD3, 0xC7, 0x8F, 0xFE, 0x4C], Descriptor::Dts(DtsDescriptor {
+                sample_rate_code: 13,
+                bit_rate_code: 15,
+                nblks: 15,
+                fsize: 2047,
+                surround_mode: 9,
+                lfe_flag: true,
+                extended_surround_flag: 0,
+                additional_info: vec![],
+            })),
+            (0x7C, vec![0x58, 0x80, 0x03], Descriptor::Aac(AacDescriptor {
+                profile_and_level: 0x58,
+                saoc_de_flag: false,
+                aac_type: Some(3),
+                additional_info: vec![],
+            })),
+            (0x7F, b"\x06\x85eng".to_vec(), Descriptor::SupplementaryAudio(SupplementaryAudioDescriptor {
+                mix_type: true,
+                editorial_classification: 1,
+                language: Some("eng".into()),
+                private_data: vec![],
+            })),
+            // Other extension descriptors are left undecoded
+            (0x7F, vec![0x07, 0x01], Descriptor::Unknown { tag: 0x7F, data: vec![0x07, 0x01] }),
+        ] {
+            assert_eq!(Descriptor::new(tag, &body), Ok(expected.clone()), "tag {:#X}", tag);
+            assert_eq!(expected.tag(), tag);
+        }
+    }
+
+    #[test]
+    fn dvb_names() {
+        let ac3 = |component_type| Ac3Descriptor::new(&[0x80, component_type], false).unwrap();
+        assert_eq!(ac3(0x40).channels_name(), Some("1/0 mono"));
+        assert_eq!(ac3(0x41).channels_name(), Some("1+1 dual mono"));
+        assert_eq!(ac3(0x42).channels_name(), Some("2/0 stereo"));
+        let page = TeletextPage { language: "eng".into(), teletext_type: 2, magazine_number: 0, page_number: 0x88 };
+        assert_eq!(page.page(), 888);
+    }
+
+    #[test]
+    fn short_dvb_bodies() {
+        for tag in [0x52, 0x66, 0x6A, 0x7A, 0x7B, 0x7C] {
+            assert!(matches!(Descriptor::new(tag, &[]), Err(ParseError::Truncated { offset: 0, .. })), "tag {:#X}", tag);
+        }
+        // An AC-3_descriptor whose flags announce more fields than there are
+        assert_eq!(Descriptor::new(0x6A, &[0xF0, 0x42]),
+            Err(ParseError::Truncated { pid: None, offset: 2, field: "bsid" }));
+        // A supplementary_audio_descriptor announcing a language it doesn't have
+        assert_eq!(Descriptor::new(0x7F, &[0x06, 0x85]),
+            Err(ParseError::Truncated { pid: None, offset: 2, field: "ISO_639_language_code" }));
+    }
+
+    #[test]
+    fn short_bodies() {
+        // Every descriptor with fixed fields fails on an empty body
+        for tag in [0x02, 0x03, 0x05, 0x06, 0x09, 0x0E, 0x26, 0x28, 0x2A, 0x2B, 0x38] {
+            assert!(matches!(Descriptor::new(tag, &[]), Err(ParseError::Truncated { offset: 0, .. })), "tag {:#X}", tag);
+        }
+        // A video_stream_descriptor without the MPEG-2 fields its flag announces
+        assert_eq!(Descriptor::new(0x02, &[0x18]), Err(ParseError::Truncated { pid: None, offset: 1, field: "chroma_format" }));
+        // An AVC_timing_and_HRD_descriptor cut short in N and K
+        assert_eq!(Descriptor::new(0x2A, &[0x01, 0x00, 0x00, 0x00]),
+            Err(ParseError::Truncated { pid: None, offset: 2, field: "K" }));
+        // A metadata_descriptor announcing a metadata_format_identifier it doesn't have
+        assert_eq!(Descriptor::new(0x26, &[0x01, 0x00, 0xFF, 0x49]),
+            Err(ParseError::Truncated { pid: None, offset: 3, field: "metadata_format_identifier" }));
+        // The temporal layers of a HEVC_video_descriptor are missing
+        assert_eq!(Descriptor::new(0x38, &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x80]),
+            Err(ParseError::Truncated { pid: None, offset: 13, field: "temporal_id_max" }));
+    }
+
+    #[test]
+    fn descriptor_loop() {
+        let mut buf = vec![0xEE, 0xEE];
+        buf.extend(test_util::descriptor(0x05, b"CUEI"));
+        // Too short to decode: kept as it is
+        buf.extend(test_util::descriptor(0x09, &[0x0B]));
+        buf.extend(test_util::descriptor(0x99, &[]));
+        let end = buf.len();
+        assert_eq!(Descriptor::parse_loop(&buf, 2, end), Ok(vec![
+            Descriptor::Registration { format_identifier: *b"CUEI", additional_identification_info: vec![] },
+            Descriptor::Unknown { tag: 0x09, data: vec![0x0B] },
+            Descriptor::Unknown { tag: 0x99, data: vec![] },
+        ]));
+        // A descriptor_length running past the end of the loop
+        buf.extend(test_util::descriptor(0x06, &[1]));
+        assert_eq!(Descriptor::parse_loop(&buf, 2, buf.len() - 1),
+            Err(ParseError::Truncated { pid: None, offset: end + 2, field: "descriptor" }));
+        // A loop running past the end of the buffer
+        assert_eq!(Descriptor::parse_loop(&buf, 2, buf.len() + 3),
+            Err(ParseError::Truncated { pid: None, offset: 0, field: "descriptors" }));
+    }
+}
@@ -0,0 +1,394 @@
+use std::fmt;
+use std::fmt::Write;
+use byteorder::{ByteOrder, BigEndian};
+use super::{text, time::{self, UtcTime}, Descriptor};
+use crate::{packet, mpeg32_crc, error::{self, ParseError}};
+
+// Constants
+/// Present/following of this transport stream
+pub const EIT_PF_ACTUAL_TABLE_ID: u8 = 0x4E;
+/// Last schedule table_id (of other transport streams)
+pub const EIT_SCHEDULE_OTHER_LAST_TABLE_ID: u8 = 0x6F;
+const EIT_PF_OTHER_TABLE_ID: u8 = 0x4F;
+const EIT_SCHEDULE_ACTUAL_TABLE_IDS: std::ops::RangeInclusive<u8> = 0x50..=0x5F;
+const SHORT_EVENT_DESCRIPTOR_TAG: u8 = 0x4D;
+const EXTENDED_EVENT_DESCRIPTOR_TAG: u8 = 0x4E;
+const CONTENT_DESCRIPTOR_TAG: u8 = 0x54;
+const PARENTAL_RATING_DESCRIPTOR_TAG: u8 = 0x55;
+
+/// A genre from a content_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Content {
+    pub level_1: u8,
+    pub level_2: u8,
+    pub user_byte: u8,
+}
+
+impl Content {
+    /// Name of the content_nibble_level_1 (ETSI EN 300 468 table 28)
+    pub fn genre(&self) -> &'static str {
+        match self.level_1 {
+            0x1 => "Movie/Drama",
+            0x2 => "News/Current affairs",
+            0x3 => "Show/Game show",
+            0x4 => "Sports",
+            0x5 => "Children's/Youth programmes",
+            0x6 => "Music/Ballet/Dance",
+            0x7 => "Arts/Culture",
+            0x8 => "Social/Political issues/Economics",
+            0x9 => "Education/Science/Factual topics",
+            0xA => "Leisure hobbies",
+            0xB => "Special characteristics",
+            0xF => "User defined",
+            _ => "Undefined",
+        }
+    }
+}
+
+/// A rating from a parental_rating_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParentalRating {
+    pub country_code: String,
+    pub rating: u8,
+}
+
+impl ParentalRating {
+    /// The minimum age the rating is for, if it is an age rather than broadcaster defined
+    pub fn minimum_age(&self) -> Option<u8> {
+        match self.rating {
+            0x01..=0x0F => Some(self.rating + 3),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Event {
+    pub event_id: u16,
+    /// None if undefined (e.g. for a NVOD reference event)
+    pub start_time: Option<UtcTime>,
+    /// Duration in seconds
+    pub duration: u32,
+    pub running_status: u8,
+    pub free_ca_mode: bool,
+    pub descriptors: Vec<Descriptor>,
+    /// ISO 639 language of the short_event_descriptor
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub short_text: Option<String>,
+    /// Text of the extended_event_descriptors, joined up
+    pub extended_text: Option<String>,
+    /// (description, item) pairs of the extended_event_descriptors
+    pub extended_items: Vec<(String, String)>,
+    pub content: Vec<Content>,
+    pub parental_ratings: Vec<ParentalRating>,
+}
+
+impl Event {
+    /// Parse the event starting at `buf[n]` and return it along with its size
+    fn new(buf: &[u8], n: usize) -> Result<(Event, usize), ParseError> {
+        let header = error::field_bytes(buf, n, 12, "descriptors_loop_length")?;
+        let end = n + 12 + super::loop_length(header, 10, "descriptors_loop_length")?;
+
+        let mut event = Event {
+            event_id: BigEndian::read_u16(&header[0..2]),
+            start_time: UtcTime::from_mjd_bcd(&header[2..7]),
+            duration: time::bcd_hms(&header[7..10]).unwrap_or(0),
+            running_status: header[10] >> 5,
+            free_ca_mode: packet::get_bit_at(header[10], 4),
+            descriptors: Descriptor::parse_loop(buf, n + 12, end)?,
+            language: None,
+            title: None,
+            short_text: None,
+            extended_text: None,
+            extended_items: vec![],
+            content: vec![],
+            parental_ratings: vec![],
+        };
+        // A malformed short or extended event descriptor is skipped (its bytes are still in `descriptors`)
+        for (tag, body) in super::descriptor_bodies(buf, n + 12, end)? {
+            match tag {
+                SHORT_EVENT_DESCRIPTOR_TAG => if let Some((name, short_text)) = short_event(body) {
+                    event.language = Some(String::from_utf8_lossy(&body[0..3]).into_owned());
+                    event.title = Some(text::decode(name));
+                    event.short_text = Some(text::decode(short_text));
+                },
+                EXTENDED_EVENT_DESCRIPTOR_TAG => if let Some((items, extended_text)) = extended_event(body) {
+                    event.extended_items.extend(items.into_iter()
+                        .map(|(description, item)| (text::decode(description), text::decode(item))));
+                    event.extended_text.get_or_insert_with(String::new)
+                        .push_str(&text::decode(extended_text));
+                },
+                CONTENT_DESCRIPTOR_TAG => {
+                    event.content.extend(body.chunks_exact(2).map(|b| Content {
+                        level_1: b[0] >> 4,
+                        level_2: b[0] & 0x0F,
+                        user_byte: b[1],
+                    }));
+                },
+                PARENTAL_RATING_DESCRIPTOR_TAG => {
+                    event.parental_ratings.extend(body.chunks_exact(4).map(|b| ParentalRating {
+                        country_code: String::from_utf8_lossy(&b[0..3]).into_owned(),
+                        rating: b[3],
+                    }));
+                },
+                _ => (),
+            }
+        }
+        Ok((event, end - n))
+    }
+
+    /// End of the event, if its start is known
+    pub fn end_time(&self) -> Option<UtcTime> {
+        self.start_time.map(|t| t.add_seconds(i64::from(self.duration)))
+    }
+}
+
+/// The event name and text of a short_event_descriptor body
+fn short_event(body: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (name, rest) = length_prefixed(body.get(3..)?)?;
+    let (short_text, _) = length_prefixed(rest)?;
+    Some((name, short_text))
+}
+
+/// The (description, item) pairs and the text of an extended_event_descriptor
+type ExtendedEvent<'a> = (Vec<(&'a [u8], &'a [u8])>, &'a [u8]);
+
+/// Split an extended_event_descriptor body into its items and text
+fn extended_event(body: &[u8]) -> Option<ExtendedEvent<'_>> {
+    let (mut items, rest) = length_prefixed(body.get(4..)?)?;
+    let mut pairs = vec![];
+    while !items.is_empty() {
+        let (description, rest) = length_prefixed(items)?;
+        let (item, rest) = length_prefixed(rest)?;
+        pairs.push((description, item));
+        items = rest;
+    }
+    let (extended_text, _) = length_prefixed(rest)?;
+    Some((pairs, extended_text))
+}
+
+/// Split off the bytes preceded by an 8 bit length at the start of `buf`
+fn length_prefixed(buf: &[u8]) -> Option<(&[u8], &[u8])> {
+    let length = *buf.first()? as usize;
+    let field = buf.get(1..1 + length)?;
+    Some((field, &buf[1 + length..]))
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Eit {
+    table_id: u8,
+    service_id: u16,
+    version_number: u8,
+    current_next_indicator: bool,
+    section_number: u8,
+    last_section_number: u8,
+    transport_stream_id: u16,
+    original_network_id: u16,
+    segment_last_section_number: u8,
+    last_table_id: u8,
+    pub events: Vec<Event>,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for Eit {
+    /// Display the eit along with its events
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut event_str = String::new();
+        for e in &self.events {
+            let (h, m, s) = (e.duration / 3600, e.duration / 60 % 60, e.duration % 60);
+            write!(&mut event_str, "\n\t=> Event ID: {0:#X} ({0}), Start: {1}, Duration: {2:02}:{3:02}:{4:02}, Title: {5}",
+                e.event_id, e.start_time.map_or("-".to_string(), |t| t.to_string()), h, m, s,
+                e.title.as_deref().unwrap_or("-")).unwrap();
+            for c in &e.content {
+                write!(&mut event_str, "\n\t\t=> Genre: {}", c.genre()).unwrap();
+            }
+            for r in &e.parental_ratings {
+                if let Some(age) = r.minimum_age() {
+                    write!(&mut event_str, "\n\t\t=> Rating: {} ({})", age, r.country_code).unwrap();
+                }
+            }
+        }
+        write!(f, "[EIT] Service ID: {0:#X} ({0}), Transport Stream ID: {1:#X} ({1}), {2}{3}, Version: {4:#X}{5}",
+            self.service_id, self.transport_stream_id,
+            if self.is_present_following() { "Present/following" } else { "Schedule" },
+            if self.is_actual() { "" } else { " (other)" }, self.version_number, event_str)
+    }
+}
+
+impl Eit {
+    /// Whether `table_id` is one of the EIT tables
+    pub fn is_eit_table(table_id: u8) -> bool {
+        (EIT_PF_ACTUAL_TABLE_ID..=EIT_SCHEDULE_OTHER_LAST_TABLE_ID).contains(&table_id)
+    }
+
+    /// Parse a section buffer into a Eit object and return Result<Eit, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Eit, ParseError> {
+        // Calculate length and index fields
+        let table_id = match buf.first() {
+            Some(&id) if Eit::is_eit_table(id) => id,
+            _ => EIT_PF_ACTUAL_TABLE_ID,
+        };
+        let end_n = super::check_section(buf, table_id)?;
+        let header = error::field_bytes(&buf[..end_n], 8, 6, "last_table_id")?;
+
+        // Get the events
+        let mut n = 14;
+        let mut events = vec![];
+        while n < end_n {
+            let (event, size) = Event::new(&buf[..end_n], n)?;
+            events.push(event);
+            n += size;
+        }
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(Eit {
+            table_id,
+            service_id: BigEndian::read_u16(&buf[3..5]),
+            version_number: (buf[5] & 0x3E) >> 1,
+            current_next_indicator: packet::get_bit_at(buf[5], 0),
+            section_number: buf[6],
+            last_section_number: buf[7],
+            transport_stream_id: BigEndian::read_u16(&header[0..2]),
+            original_network_id: BigEndian::read_u16(&header[2..4]),
+            segment_last_section_number: header[4],
+            last_table_id: header[5],
+            events,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+
+    /// Whether this describes the events of this transport stream (not another one)
+    pub fn is_actual(&self) -> bool {
+        self.table_id == EIT_PF_ACTUAL_TABLE_ID || EIT_SCHEDULE_ACTUAL_TABLE_IDS.contains(&self.table_id)
+    }
+
+    /// Whether this is the present/following table rather than the schedule
+    pub fn is_present_following(&self) -> bool {
+        self.table_id == EIT_PF_ACTUAL_TABLE_ID || self.table_id == EIT_PF_OTHER_TABLE_ID
+    }
+
+    pub fn get_service_id(&self) -> u16 {
+        self.service_id
+    }
+
+    pub fn get_transport_stream_id(&self) -> u16 {
+        self.transport_stream_id
+    }
+
+    pub fn get_original_network_id(&self) -> u16 {
+        self.original_network_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// 2024-01-15 20:30:00
+    const START: [u8; 5] = [0xEB, 0xA4, 0x20, 0x30, 0x00];
+
+    /// A running event of 01:45:00
+    fn event(event_id: u16, start: [u8; 5], descriptors: &[u8]) -> Vec<u8> {
+        let mut buf = event_id.to_be_bytes().to_vec();
+        buf.extend_from_slice(&start);
+        buf.extend_from_slice(&[0x01, 0x45, 0x00]);
+        buf.extend_from_slice(&[0x80 | (descriptors.len() >> 8) as u8, descriptors.len() as u8]);
+        buf.extend_from_slice(descriptors);
+        buf
+    }
+
+    /// An EIT of service 0x10 of transport stream 1 and original network 2
+    fn eit(table_id: u8, events: &[u8]) -> Vec<u8> {
+        let mut body = vec![0x00, 0x01, 0x00, 0x02, 0x00, table_id];
+        body.extend_from_slice(events);
+        test_util::section(table_id, 0x0010, &body)
+    }
+
+    #[test]
+    fn events() {
+        let mut descriptors = test_util::descriptor(SHORT_EVENT_DESCRIPTOR_TAG, b"eng\x05Title\x04Text");
+        descriptors.extend(test_util::descriptor(EXTENDED_EVENT_DESCRIPTOR_TAG,
+            b"\x00eng\x0D\x08Director\x03Bob\x05More "));
+        descriptors.extend(test_util::descriptor(EXTENDED_EVENT_DESCRIPTOR_TAG, b"\x11eng\x00\x04text"));
+        descriptors.extend(test_util::descriptor(CONTENT_DESCRIPTOR_TAG, &[0x10, 0x00, 0x40, 0x01]));
+        descriptors.extend(test_util::descriptor(PARENTAL_RATING_DESCRIPTOR_TAG, b"GBR\x09"));
+        let mut events = event(0x100, START, &descriptors);
+        // A short_event_descriptor whose text runs past its end
+        events.extend(event(0x101, START, &test_util::descriptor(SHORT_EVENT_DESCRIPTOR_TAG, b"eng\x05Title\x09Te")));
+
+        let eit = Eit::new(&eit(EIT_PF_ACTUAL_TABLE_ID, &events)).unwrap();
+        assert!(eit.is_actual() && eit.is_present_following() && !eit.crc_error);
+        assert_eq!((eit.get_service_id(), eit.get_transport_stream_id(), eit.get_original_network_id()), (0x10, 1, 2));
+        assert_eq!(eit.events.len(), 2);
+
+        let e = &eit.events[0];
+        assert_eq!(e.event_id, 0x100);
+        assert_eq!(e.start_time.unwrap().to_string(), "2024-01-15 20:30:00 UTC");
+        assert_eq!(e.end_time().unwrap().to_string(), "2024-01-15 22:15:00 UTC");
+        assert_eq!((e.duration, e.running_status, e.free_ca_mode), (6300, 4, false));
+        assert_eq!((e.language.as_deref(), e.title.as_deref(), e.short_text.as_deref()),
+            (Some("eng"), Some("Title"), Some("Text")));
+        assert_eq!(e.extended_items, vec![("Director".to_string(), "Bob".to_string())]);
+        assert_eq!(e.extended_text.as_deref(), Some("More text"));
+        assert_eq!(e.content.iter().map(|c| c.genre()).collect::<Vec<_>>(), vec!["Movie/Drama", "Sports"]);
+        assert_eq!(e.parental_ratings[0].minimum_age(), Some(12));
+
+        // The malformed descriptor is skipped, not the event
+        let e = &eit.events[1];
+        assert_eq!(e.event_id, 0x101);
+        assert_eq!(e.title, None);
+        assert_eq!(e.descriptors.len(), 1);
+
+        assert_eq!(eit.to_string(), "[EIT] Service ID: 0x10 (16), Transport Stream ID: 0x1 (1), Present/following, Version: 0x0\
+            \n\t=> Event ID: 0x100 (256), Start: 2024-01-15 20:30:00 UTC, Duration: 01:45:00, Title: Title\
+            \n\t\t=> Genre: Movie/Drama\n\t\t=> Genre: Sports\n\t\t=> Rating: 12 (GBR)\
+            \n\t=> Event ID: 0x101 (257), Start: 2024-01-15 20:30:00 UTC, Duration: 01:45:00, Title: -");
+    }
+
+    #[test]
+    fn tables() {
+        for (table_id, actual, present_following) in [
+            (0x4E, true, true),
+            (0x4F, false, true),
+            (0x50, true, false),
+            (0x5F, true, false),
+            (0x60, false, false),
+            (0x6F, false, false),
+        ] {
+            let eit = Eit::new(&eit(table_id, &[])).unwrap();
+            assert_eq!((eit.is_actual(), eit.is_present_following()), (actual, present_following), "{:#X}", table_id);
+        }
+        assert!(!Eit::is_eit_table(0x4D) && !Eit::is_eit_table(0x70));
+    }
+
+    #[test]
+    fn undefined_start_time() {
+        // An NVOD reference event has no start time, so no end either
+        let eit = Eit::new(&eit(0x50, &event(1, [0xFF; 5], &[]))).unwrap();
+        assert_eq!((eit.events[0].start_time, eit.events[0].end_time()), (None, None));
+        assert!(eit.to_string().contains("Start: -, Duration: 01:45:00"), "{}", eit);
+    }
+
+    #[test]
+    fn invalid_sections() {
+        let buf = eit(EIT_PF_ACTUAL_TABLE_ID, &event(1, START, &[]));
+        for len in 0..buf.len() {
+            assert!(Eit::new(&buf[..len]).is_err(), "{} bytes", len);
+        }
+        let mut sdt = buf.clone();
+        sdt[0] = 0x42;
+        assert_eq!(Eit::new(&sdt), Err(ParseError::TableId { pid: None, expected: EIT_PF_ACTUAL_TABLE_ID, found: 0x42 }));
+        // An event's descriptors running into the CRC
+        let mut descriptors_overrun = buf;
+        descriptors_overrun[25] = 0x04;
+        assert_eq!(Eit::new(&descriptors_overrun), Err(ParseError::Truncated { pid: None, offset: 0, field: "descriptors" }));
+        // An event cut short by the end of the section
+        let events = &event(1, START, &[])[..8];
+        assert_eq!(Eit::new(&eit(0x50, events)), Err(ParseError::Truncated { pid: None, offset: 14, field: "descriptors_loop_length" }));
+    }
+}
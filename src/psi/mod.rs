@@ -1,8 +1,22 @@
 use std::fmt;
 use std::collections::HashSet;
+use byteorder::{ByteOrder, BigEndian};
+use crate::{codec::Codec, error::{self, ParseError}, packet};
 
+pub mod cat;
+pub mod descriptor;
+pub mod eit;
+pub mod nit;
 pub mod pat;
 pub mod pmt;
+pub mod psip;
+pub mod sdt;
+pub mod section;
+pub mod tdt;
+pub mod text;
+pub mod time;
+
+pub use descriptor::Descriptor;
 
 /// Start index of the psi section:
 /// The index starting immediately following "section_length" field
@@ -11,71 +25,213 @@ const PSI_SEC_START_INDEX: u16 = 3;
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Psi {
     Pat(pat::Pat),
+    Cat(cat::Cat),
     Pmt(pmt::Pmt),
+    Nit(nit::Nit),
+    Sdt(sdt::Sdt),
+    Eit(eit::Eit),
+    Tdt(tdt::Tdt),
+    Tot(tdt::Tot),
+    Psip(psip::Psip),
 }
 
 impl fmt::Display for Psi {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &*self {
+        match self {
             Psi::Pat(p) => write!(f, "{}", p),
+            Psi::Cat(p) => write!(f, "{}", p),
             Psi::Pmt(p) => write!(f, "{}", p),
+            Psi::Nit(p) => write!(f, "{}", p),
+            Psi::Sdt(p) => write!(f, "{}", p),
+            Psi::Eit(p) => write!(f, "{}", p),
+            Psi::Tdt(p) => write!(f, "{}", p),
+            Psi::Tot(p) => write!(f, "{}", p),
+            Psi::Psip(p) => write!(f, "{}", p),
         }
     }
 }
 
 impl Psi {
-    /// Parse a buffer into a Psi object
-    pub fn new(buf: &[u8], pid: &u16, pmt_pids: &HashSet<u16>) -> Option<Psi> {
-        // generate the PSI struct according to the pid value
+    /// Parse a section buffer into a Psi object
+    pub fn new(buf: &[u8], pid: &u16, pmt_pids: &HashSet<u16>, network_pid: u16, psip_pids: &HashSet<u16>)
+        -> Result<Psi, ParseError> {
+        let table_id = error::field_byte(buf, 0, "table_id").map_err(|e| e.with_pid(*pid))?;
+        // generate the PSI struct according to the pid value (and table_id on the SI PIDs
+        // that carry other tables as well)
         match pid {
-            x if Psi::is_pat(&x) => Some(Psi::Pat(pat::Pat::new(&buf)?)),
-            x if Psi::is_pmt(&x, &pmt_pids) => Some(Psi::Pmt(pmt::Pmt::new(&buf)?)),
-            _ => None,
+            x if Psi::is_pat(x) => Ok(Psi::Pat(pat::Pat::new(buf).map_err(|e| e.with_pid(*pid))?)),
+            x if Psi::is_cat(x) => Ok(Psi::Cat(cat::Cat::new(buf).map_err(|e| e.with_pid(*pid))?)),
+            x if Psi::is_pmt(x, pmt_pids) => Ok(Psi::Pmt(pmt::Pmt::new(buf).map_err(|e| e.with_pid(*pid))?)),
+            x if *x == network_pid => match table_id {
+                nit::NIT_ACTUAL_TABLE_ID | nit::NIT_OTHER_TABLE_ID =>
+                    Ok(Psi::Nit(nit::Nit::new(buf).map_err(|e| e.with_pid(*pid))?)),
+                _ => Err(ParseError::UnknownTable { pid: *pid, table_id }),
+            },
+            x if Psi::is_sdt(x) => match table_id {
+                sdt::SDT_ACTUAL_TABLE_ID | sdt::SDT_OTHER_TABLE_ID =>
+                    Ok(Psi::Sdt(sdt::Sdt::new(buf).map_err(|e| e.with_pid(*pid))?)),
+                _ => Err(ParseError::UnknownTable { pid: *pid, table_id }),
+            },
+            x if Psi::is_eit(x) => match table_id {
+                id if eit::Eit::is_eit_table(id) =>
+                    Ok(Psi::Eit(eit::Eit::new(buf).map_err(|e| e.with_pid(*pid))?)),
+                _ => Err(ParseError::UnknownTable { pid: *pid, table_id }),
+            },
+            x if Psi::is_tdt(x) => match table_id {
+                tdt::TDT_TABLE_ID => Ok(Psi::Tdt(tdt::Tdt::new(buf).map_err(|e| e.with_pid(*pid))?)),
+                tdt::TOT_TABLE_ID => Ok(Psi::Tot(tdt::Tot::new(buf).map_err(|e| e.with_pid(*pid))?)),
+                _ => Err(ParseError::UnknownTable { pid: *pid, table_id }),
+            },
+            x if psip::Psip::is_base_pid(x) || psip_pids.contains(x) => Ok(Psi::Psip(psip::Psip::new(buf, pid)?)),
+            _ => Err(ParseError::UnknownPid { pid: *pid }),
         }
     }
 
+    /// The CRC of the section (0 for the TDT, which has none)
     pub fn get_crc(&self) -> u32 {
-        match &*self {
+        match self {
             Psi::Pat(p) => p.crc,
+            Psi::Cat(p) => p.crc,
             Psi::Pmt(p) => p.crc,
+            Psi::Nit(p) => p.crc,
+            Psi::Sdt(p) => p.crc,
+            Psi::Eit(p) => p.crc,
+            Psi::Tdt(_) => 0,
+            Psi::Tot(p) => p.crc,
+            Psi::Psip(p) => p.get_crc(),
         }
     }
 
     pub fn get_crc_error(&self) -> bool {
-        match &*self {
+        match self {
             Psi::Pat(p) => p.crc_error,
+            Psi::Cat(p) => p.crc_error,
             Psi::Pmt(p) => p.crc_error,
+            Psi::Nit(p) => p.crc_error,
+            Psi::Sdt(p) => p.crc_error,
+            Psi::Eit(p) => p.crc_error,
+            Psi::Tdt(_) => false,
+            Psi::Tot(p) => p.crc_error,
+            Psi::Psip(p) => p.get_crc_error(),
         }
     }
 
     pub fn display(&self, prev_crc: u32) {
         if self.get_crc() != prev_crc {
-            match &*self {
+            match self {
                 Psi::Pat(p) => println!("{}", p),
+                Psi::Cat(p) => println!("{}", p),
                 Psi::Pmt(p) => println!("{}", p),
+                Psi::Nit(p) => println!("{}", p),
+                Psi::Sdt(p) => println!("{}", p),
+                Psi::Eit(p) => println!("{}", p),
+                Psi::Tdt(p) => println!("{}", p),
+                Psi::Tot(p) => println!("{}", p),
+                Psi::Psip(p) => println!("{}", p),
             }
         }
     }
 
     pub fn is_pat(pid: &u16) -> bool { *pid == 0x0 }
+    pub fn is_cat(pid: &u16) -> bool { *pid == 0x1 }
+    pub fn is_sdt(pid: &u16) -> bool { *pid == 0x11 }
+    pub fn is_eit(pid: &u16) -> bool { *pid == 0x12 }
+    pub fn is_tdt(pid: &u16) -> bool { *pid == 0x14 }
     pub fn is_network_program_elementary(pid: &u16) -> bool { *pid >= 0x0010 && *pid <= 0x1FFE }
     fn is_pmt(pid: &u16, pmt_pids: &HashSet<u16>) -> bool {
-        if Psi::is_network_program_elementary(&pid) &&
-            pmt_pids.contains(&pid) {
-            true
-        } else { false }
+        Psi::is_network_program_elementary(pid) && pmt_pids.contains(pid)
+    }
+}
+
+
+/// Check the table_id and section_length of a long form section and return
+/// the index of its CRC (i.e. the end of the table data)
+pub(crate) fn check_section(buf: &[u8], table_id: u8) -> Result<usize, ParseError> {
+    let found = error::field_byte(buf, 0, "table_id")?;
+    if found != table_id {
+        return Err(ParseError::TableId { pid: None, expected: table_id, found });
+    }
+    let header = error::field_bytes(buf, 1, 2, "section_length")?;
+    let section_length = (usize::from(header[0] & 0x0F) << 8) | usize::from(header[1]);
+    // The fields up to last_section_number and the CRC must fit in the section
+    if section_length < 5 + packet::CRC_SIZE {
+        return Err(ParseError::InvalidValue {
+            pid: None, offset: 1, field: "section_length", value: section_length as u32,
+        });
+    }
+    let section_end = PSI_SEC_START_INDEX as usize + section_length;
+    error::field_bytes(buf, 0, section_end, "section_length")?;
+    Ok(section_end - packet::CRC_SIZE)
+}
+
+/// Split the descriptors found in `buf[start..end]` into their tags and bodies
+fn descriptor_bodies(buf: &[u8], start: usize, end: usize) -> Result<Vec<(u8, &[u8])>, ParseError> {
+    let buf = error::field_bytes(buf, 0, end, "descriptors")?;
+    let mut n = start;
+    let mut descriptors = vec![];
+    while n < end {
+        let header = error::field_bytes(buf, n, 2, "descriptor_length")?;
+        let body = error::field_bytes(buf, n + 2, header[1] as usize, "descriptor")?;
+        descriptors.push((header[0], body));
+        n += 2 + body.len();
     }
+    Ok(descriptors)
+}
+
+/// Read the 12 bit length field (e.g. a descriptors loop length) at `buf[n..n + 2]`
+fn loop_length(buf: &[u8], n: usize, field: &'static str) -> Result<usize, ParseError> {
+    let b = error::field_bytes(buf, n, 2, field)?;
+    Ok(BigEndian::read_u16(&[b[0] & 0x0F, b[1]]) as usize)
 }
 
+/// Get the format_identifier of the registration_descriptor among `descriptors`, if any
+fn format_identifier(descriptors: &[Descriptor]) -> Option<[u8; 4]> {
+    descriptors.iter().find_map(|d| match d {
+        Descriptor::Registration { format_identifier, .. } => Some(*format_identifier),
+        _ => None,
+    })
+}
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ElementaryStream {
     stream_type: u8,
     elementary_pid: u16,
-    pub descriptors: Vec<VideoStreamDescriptor>,
+    pub descriptors: Vec<Descriptor>,
 }
 
 impl ElementaryStream {
+    pub fn get_stream_type(&self) -> u8 {
+        self.stream_type
+    }
+
+    pub fn get_elementary_pid(&self) -> u16 {
+        self.elementary_pid
+    }
+
+    /// The format_identifier of the registration_descriptor (e.g. b"CUEI"), if any
+    pub fn get_format_identifier(&self) -> Option<[u8; 4]> {
+        format_identifier(&self.descriptors)
+    }
+
+    /// What the stream is coded with, from its stream_type and descriptors
+    pub fn get_codec(&self) -> Codec {
+        Codec::new(self.stream_type, &self.descriptors)
+    }
+
+    /// The first language of the ISO_639_language_descriptor (e.g. of an audio stream), if any
+    pub fn get_language(&self) -> Option<&str> {
+        self.descriptors.iter().find_map(|d| match d {
+            Descriptor::Iso639Language(languages) => languages.first().map(|l| l.code.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Whether the stream carries sections (private sections or SCTE-35 cues) rather
+    /// than PES packets
+    pub fn carries_sections(&self) -> bool {
+        matches!(self.stream_type, 0x05 | 0x86) || self.get_format_identifier() == Some(*b"CUEI")
+    }
+
     // TODO: Look into making something more efficient (maybe a macro)
     pub fn to_string(&self) -> &'static str {
         match self.stream_type {
@@ -96,66 +252,68 @@ impl ElementaryStream {
             0x0E => "MPEG-2 Auxiliary streams",
             0x0F => "MPEG-2 Audio with ADTS transport syntax",
             0x10 => "MPEG-4 Visual",
-            0x11 => "MPEG-4 Audio",
-            0x12 => "ISO/IEC 14496-1",
-            0x13 => "ISO/IEC 14496-1",
-            0x14 => "ISO/IEC 13818-6",
-            0x15..=0x7F => "ITU-T Rec. H.222.0 | ISO/IEC 13818-1 Reserved",
+            0x11 => "MPEG-4 Audio (LATM)",
+            0x12 => "MPEG-4 SL-packetized stream",
+            0x13 => "MPEG-4 FlexMux stream",
+            0x14 => "DSM-CC synchronized download",
+            0x15 => "Metadata in PES packets",
+            0x16 => "Metadata in metadata sections",
+            0x17 => "Metadata in DSM-CC data carousel",
+            0x18 => "Metadata in DSM-CC object carousel",
+            0x19 => "Metadata in DSM-CC synchronized download",
+            0x1A => "IPMP stream",
+            0x1B => "H.264/AVC video",
+            0x1C => "MPEG-4 Audio without transport syntax",
+            0x1D => "MPEG-4 Text",
+            0x1E => "Auxiliary video (ISO/IEC 23002-3)",
+            0x1F => "H.264/SVC sub-bitstream",
+            0x20 => "H.264/MVC sub-bitstream",
+            0x21 => "JPEG 2000 video",
+            0x22 => "MPEG-2 additional view (stereoscopic)",
+            0x23 => "H.264 additional view (stereoscopic)",
+            0x24 => "H.265/HEVC video",
+            0x25 => "HEVC temporal video subset",
+            0x26 => "H.264/MVCD sub-bitstream",
+            0x27 => "Timeline and External Media Information",
+            0x28..=0x2B => "HEVC enhancement sub-partition",
+            0x2C => "Green access units",
+            0x2D => "MPEG-H 3D Audio main stream",
+            0x2E => "MPEG-H 3D Audio auxiliary stream",
+            0x2F => "Quality access units",
+            0x30 => "Media Orchestration access units",
+            0x31 => "HEVC motion constrained tile set substream",
+            0x32 => "JPEG XS video",
+            0x33 => "H.266/VVC video",
+            0x34 => "VVC temporal video subset",
+            0x35 => "EVC video",
+            0x36..=0x7E => "ITU-T Rec. H.222.0 | ISO/IEC 13818-1 Reserved",
+            0x7F => "IPMP stream",
+            0x81 => "AC-3 audio (ATSC)",
+            0x86 => "SCTE-35 splice information",
+            0x87 => "E-AC-3 audio (ATSC)",
+            0xEA => "VC-1 video",
             0x80..=0xFF => "User Private",
         }
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct VideoStreamDescriptor {
-    tag: u8,
-    length: u8,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
 
-impl VideoStreamDescriptor {
-    // TODO: Look into making something more efficient (maybe a macro)
-    pub fn to_string(&self) -> &'static str {
-        match self.tag {
-            0 => "Reserved",
-            1 => "Reserved",
-            2 => "Video Stream",
-            3 => "Audio Stream",
-            4 => "Hierarchy",
-            5 => "Registration",
-            6 => "Data Stream Alignment",
-            7 => "Target Background Grid",
-            8 => "Video Window",
-            9 => "CA",
-            10 => "ISO 639 Language",
-            11 => "System Clock",
-            12 => "Multiplex Buffer Utilization",
-            13 => "Copyright",
-            14 => "Maximum Bitrate",
-            15 => "Private Data Indicator",
-            16 => "Smoothing Buffer",
-            17 => "STD",
-            18 => "IBP",
-            19..=26 => "Defined in ISO/IEC 13818-6",
-            27 => "MPEG-4 Video",
-            28 => "MPEG-4 Audio",
-            29 => "IOD",
-            30 => "SL",
-            31 => "FMC",
-            32 => "External ES ID",
-            33 => "MuxCode",
-            34 => "FmxBufferSize",
-            35 => "Multiplexbuffer",
-            36 => "Content Labeling",
-            37 => "Metadata Pointer",
-            38 => "Metadata",
-            39 => "Metadata STD",
-            40 => "AVC Video",
-            41 => "IPMP",
-            42 => "AVC Timing and HRD",
-            43 => "MPEG-2 AAC Audio",
-            44 => "FlexMuxTiming",
-            45..=63 => "ITU-T Rec. H.222.0 | ISO/IEC 13818-1 Reserved",
-            64..=255 => "User Private",
-        }
+    #[test]
+    fn errors_carry_the_pid() {
+        let (pmt_pids, psip_pids) = ([0x100].iter().copied().collect(), HashSet::new());
+        let parse = |buf: &[u8], pid: u16| Psi::new(buf, &pid, &pmt_pids, 0x10, &psip_pids);
+
+        let pat = test_util::section(0x00, 1, &[0x00, 0x01, 0xE1, 0x00]);
+        assert!(matches!(parse(&pat, 0x00), Ok(Psi::Pat(_))));
+        assert_eq!(parse(&pat[..10], 0x00), Err(ParseError::Truncated { pid: Some(0x00), offset: 0, field: "section_length" }));
+        assert_eq!(parse(&pat, 0x100), Err(ParseError::TableId { pid: Some(0x100), expected: 0x02, found: 0x00 }));
+        assert_eq!(parse(&[], 0x11), Err(ParseError::Truncated { pid: Some(0x11), offset: 0, field: "table_id" }));
+        // A BAT on the SDT PID, and a PID no table was seen for
+        assert_eq!(parse(&test_util::section(0x4A, 1, &[]), 0x11), Err(ParseError::UnknownTable { pid: 0x11, table_id: 0x4A }));
+        assert_eq!(parse(&pat, 0x200), Err(ParseError::UnknownPid { pid: 0x200 }));
     }
 }
@@ -0,0 +1,465 @@
+use std::fmt;
+use std::fmt::Write;
+use byteorder::{ByteOrder, BigEndian};
+use super::{text, Descriptor};
+use crate::{packet, mpeg32_crc, error::{self, ParseError}};
+
+// Constants
+pub const NIT_ACTUAL_TABLE_ID: u8 = 0x40;
+pub const NIT_OTHER_TABLE_ID: u8 = 0x41;
+const NETWORK_NAME_DESCRIPTOR_TAG: u8 = 0x40;
+const SERVICE_LIST_DESCRIPTOR_TAG: u8 = 0x41;
+const SATELLITE_DELIVERY_DESCRIPTOR_TAG: u8 = 0x43;
+const CABLE_DELIVERY_DESCRIPTOR_TAG: u8 = 0x44;
+const TERRESTRIAL_DELIVERY_DESCRIPTOR_TAG: u8 = 0x5A;
+const LOGICAL_CHANNEL_DESCRIPTOR_TAG: u8 = 0x83;
+/// All three delivery system descriptors have 11 byte bodies
+const DELIVERY_DESCRIPTOR_LENGTH: usize = 11;
+
+/// FEC_inner code rates (ETSI EN 300 468 table 35)
+const FEC_INNER: [&str; 16] = [
+    "not defined", "1/2", "2/3", "3/4", "5/6", "7/8", "8/9", "3/5",
+    "4/5", "9/10", "reserved", "reserved", "reserved", "reserved", "reserved", "none",
+];
+
+/// satellite_delivery_system_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SatelliteDelivery {
+    /// Frequency in units of 10 kHz
+    pub frequency: u32,
+    /// Orbital position in tenths of a degree
+    pub orbital_position: u16,
+    pub west_east_flag: bool,
+    pub polarization: u8,
+    pub roll_off: u8,
+    /// DVB-S2 instead of DVB-S
+    pub s2: bool,
+    pub modulation_type: u8,
+    /// Symbol rate in units of 100 symbols/s
+    pub symbol_rate: u32,
+    pub fec_inner: u8,
+}
+
+/// cable_delivery_system_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CableDelivery {
+    /// Frequency in units of 100 Hz
+    pub frequency: u32,
+    pub fec_outer: u8,
+    pub modulation: u8,
+    /// Symbol rate in units of 100 symbols/s
+    pub symbol_rate: u32,
+    pub fec_inner: u8,
+}
+
+/// terrestrial_delivery_system_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TerrestrialDelivery {
+    /// Centre frequency in units of 10 Hz
+    pub centre_frequency: u32,
+    pub bandwidth: u8,
+    pub priority: bool,
+    pub constellation: u8,
+    pub hierarchy_information: u8,
+    pub code_rate_hp: u8,
+    pub code_rate_lp: u8,
+    pub guard_interval: u8,
+    pub transmission_mode: u8,
+    pub other_frequency_flag: bool,
+}
+
+/// How and where a transport stream is delivered
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DeliverySystem {
+    Satellite(SatelliteDelivery),
+    Cable(CableDelivery),
+    Terrestrial(TerrestrialDelivery),
+}
+
+impl fmt::Display for DeliverySystem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeliverySystem::Satellite(s) => {
+                let polarization = ["H", "V", "L", "R"][s.polarization as usize];
+                let modulation = ["auto", "QPSK", "8PSK", "16QAM"][s.modulation_type as usize];
+                write!(f, "Satellite: {:.5} GHz {}, {:.1}{}, {} {}, {:.4} Msym/s, FEC {}",
+                    f64::from(s.frequency) / 1e5, polarization,
+                    f64::from(s.orbital_position) / 10.0, if s.west_east_flag { "E" } else { "W" },
+                    if s.s2 { "DVB-S2" } else { "DVB-S" }, modulation,
+                    f64::from(s.symbol_rate) / 1e4, FEC_INNER[s.fec_inner as usize])
+            },
+            DeliverySystem::Cable(c) => {
+                let modulation = match c.modulation {
+                    0x01 => "16-QAM",
+                    0x02 => "32-QAM",
+                    0x03 => "64-QAM",
+                    0x04 => "128-QAM",
+                    0x05 => "256-QAM",
+                    _ => "unknown modulation",
+                };
+                write!(f, "Cable: {:.4} MHz, {}, {:.4} Msym/s, FEC {}",
+                    f64::from(c.frequency) / 1e4, modulation,
+                    f64::from(c.symbol_rate) / 1e4, FEC_INNER[c.fec_inner as usize])
+            },
+            DeliverySystem::Terrestrial(t) => {
+                let bandwidth = match t.bandwidth {
+                    0 => "8 MHz",
+                    1 => "7 MHz",
+                    2 => "6 MHz",
+                    3 => "5 MHz",
+                    _ => "reserved bandwidth",
+                };
+                let constellation = ["QPSK", "16-QAM", "64-QAM", "reserved"][t.constellation as usize];
+                let guard_interval = ["1/32", "1/16", "1/8", "1/4"][t.guard_interval as usize];
+                let mode = ["2k", "8k", "4k", "reserved"][t.transmission_mode as usize];
+                write!(f, "Terrestrial: {:.5} MHz, {}, {}, guard interval {}, {} mode",
+                    f64::from(t.centre_frequency) / 1e5, bandwidth, constellation, guard_interval, mode)
+            },
+        }
+    }
+}
+
+/// A service listed in a service_list_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ServiceListEntry {
+    pub service_id: u16,
+    pub service_type: u8,
+}
+
+/// The channel number a service is shown on (EACEM/E-Book logical_channel_descriptor)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LogicalChannel {
+    pub service_id: u16,
+    pub visible_service_flag: bool,
+    pub logical_channel_number: u16,
+}
+
+/// An entry of the transport stream loop
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TransportStreamInfo {
+    pub transport_stream_id: u16,
+    pub original_network_id: u16,
+    pub descriptors: Vec<Descriptor>,
+    pub delivery_system: Option<DeliverySystem>,
+    pub services: Vec<ServiceListEntry>,
+    pub logical_channels: Vec<LogicalChannel>,
+}
+
+impl TransportStreamInfo {
+    /// Parse the entry starting at `buf[n]` and return it along with its size
+    fn new(buf: &[u8], n: usize) -> Result<(TransportStreamInfo, usize), ParseError> {
+        let header = error::field_bytes(buf, n, 6, "transport_descriptors_length")?;
+        let end = n + 6 + super::loop_length(header, 4, "transport_descriptors_length")?;
+
+        let mut info = TransportStreamInfo {
+            transport_stream_id: BigEndian::read_u16(&header[0..2]),
+            original_network_id: BigEndian::read_u16(&header[2..4]),
+            descriptors: Descriptor::parse_loop(buf, n + 6, end)?,
+            delivery_system: None,
+            services: vec![],
+            logical_channels: vec![],
+        };
+        for (tag, body) in super::descriptor_bodies(buf, n + 6, end)? {
+            match tag {
+                // A short one is skipped (its bytes are still in `descriptors`)
+                SATELLITE_DELIVERY_DESCRIPTOR_TAG |
+                CABLE_DELIVERY_DESCRIPTOR_TAG |
+                TERRESTRIAL_DELIVERY_DESCRIPTOR_TAG if body.len() >= DELIVERY_DESCRIPTOR_LENGTH => {
+                    info.delivery_system = Some(parse_delivery_system(tag, body));
+                },
+                SERVICE_LIST_DESCRIPTOR_TAG => {
+                    info.services.extend(body.chunks_exact(3).map(|b| ServiceListEntry {
+                        service_id: BigEndian::read_u16(&b[0..2]),
+                        service_type: b[2],
+                    }));
+                },
+                LOGICAL_CHANNEL_DESCRIPTOR_TAG => {
+                    info.logical_channels.extend(body.chunks_exact(4).map(|b| LogicalChannel {
+                        service_id: BigEndian::read_u16(&b[0..2]),
+                        visible_service_flag: packet::get_bit_at(b[2], 7),
+                        logical_channel_number: BigEndian::read_u16(&[b[2] & 0x03, b[3]]),
+                    }));
+                },
+                _ => (),
+            }
+        }
+        Ok((info, end - n))
+    }
+}
+
+/// Parse the body of a satellite, cable or terrestrial delivery system descriptor
+fn parse_delivery_system(tag: u8, b: &[u8]) -> DeliverySystem {
+    match tag {
+        SATELLITE_DELIVERY_DESCRIPTOR_TAG => DeliverySystem::Satellite(SatelliteDelivery {
+            frequency: bcd(BigEndian::read_u32(&b[0..4]), 8),
+            orbital_position: bcd(u32::from(BigEndian::read_u16(&b[4..6])), 4) as u16,
+            west_east_flag: packet::get_bit_at(b[6], 7),
+            polarization: (b[6] >> 5) & 0x03,
+            roll_off: (b[6] >> 3) & 0x03,
+            s2: packet::get_bit_at(b[6], 2),
+            modulation_type: b[6] & 0x03,
+            symbol_rate: bcd(BigEndian::read_u32(&b[7..11]) >> 4, 7),
+            fec_inner: b[10] & 0x0F,
+        }),
+        CABLE_DELIVERY_DESCRIPTOR_TAG => DeliverySystem::Cable(CableDelivery {
+            frequency: bcd(BigEndian::read_u32(&b[0..4]), 8),
+            fec_outer: b[5] & 0x0F,
+            modulation: b[6],
+            symbol_rate: bcd(BigEndian::read_u32(&b[7..11]) >> 4, 7),
+            fec_inner: b[10] & 0x0F,
+        }),
+        _ => DeliverySystem::Terrestrial(TerrestrialDelivery {
+            centre_frequency: BigEndian::read_u32(&b[0..4]),
+            bandwidth: b[4] >> 5,
+            priority: packet::get_bit_at(b[4], 4),
+            constellation: b[5] >> 6,
+            hierarchy_information: (b[5] >> 3) & 0x07,
+            code_rate_hp: b[5] & 0x07,
+            code_rate_lp: b[6] >> 5,
+            guard_interval: (b[6] >> 3) & 0x03,
+            transmission_mode: (b[6] >> 1) & 0x03,
+            other_frequency_flag: packet::get_bit_at(b[6], 0),
+        }),
+    }
+}
+
+/// Convert the lowest `digits` binary coded decimal digits of `value`
+fn bcd(value: u32, digits: u32) -> u32 {
+    (0..digits).rev().fold(0, |acc, i| acc * 10 + ((value >> (i * 4)) & 0x0F))
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Nit {
+    table_id: u8,
+    network_id: u16,
+    version_number: u8,
+    current_next_indicator: bool,
+    section_number: u8,
+    last_section_number: u8,
+    pub network_name: Option<String>,
+    pub descriptors: Vec<Descriptor>,
+    pub transport_streams: Vec<TransportStreamInfo>,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for Nit {
+    /// Display the nit along with the transport streams of the network
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut ts_str = String::new();
+        for ts in &self.transport_streams {
+            write!(&mut ts_str,
+                "\n\t=> Transport Stream ID: {0:#X} ({0}), Original Network ID: {1:#X} ({1})",
+                ts.transport_stream_id, ts.original_network_id).unwrap();
+            if let Some(d) = &ts.delivery_system {
+                write!(&mut ts_str, "\n\t\t=> {}", d).unwrap();
+            }
+            for s in &ts.services {
+                let lcn = ts.logical_channels.iter().find(|l| l.service_id == s.service_id);
+                write!(&mut ts_str, "\n\t\t=> Service ID: {0:#X} ({0}), Type: {1:#X}",
+                    s.service_id, s.service_type).unwrap();
+                if let Some(l) = lcn {
+                    write!(&mut ts_str, ", LCN: {}{}", l.logical_channel_number,
+                        if l.visible_service_flag { "" } else { " (hidden)" }).unwrap();
+                }
+            }
+        }
+        write!(f, "[NIT] Network ID: {0:#X} ({0}){1}, Version: {2:#X}, Name: {3}{4}",
+            self.network_id, if self.is_actual() { "" } else { " (other)" }, self.version_number,
+            self.network_name.as_deref().unwrap_or("-"), ts_str)
+    }
+}
+
+impl Nit {
+    /// Parse a section buffer into a Nit object and return Result<Nit, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Nit, ParseError> {
+        // Calculate length and index fields
+        let table_id = match buf.first() {
+            Some(&NIT_OTHER_TABLE_ID) => NIT_OTHER_TABLE_ID,
+            _ => NIT_ACTUAL_TABLE_ID,
+        };
+        let end_n = super::check_section(buf, table_id)?;
+        let buf = &buf[..end_n + packet::CRC_SIZE];
+
+        // Network descriptors
+        let descriptors_end = 10 + super::loop_length(&buf[..end_n], 8, "network_descriptors_length")?;
+        let descriptors = Descriptor::parse_loop(&buf[..end_n], 10, descriptors_end)?;
+        let network_name = super::descriptor_bodies(&buf[..end_n], 10, descriptors_end)?
+            .into_iter()
+            .find(|(tag, _)| *tag == NETWORK_NAME_DESCRIPTOR_TAG)
+            .map(|(_, body)| text::decode(body));
+
+        // Transport stream loop
+        let loop_end = descriptors_end + 2 +
+            super::loop_length(&buf[..end_n], descriptors_end, "transport_stream_loop_length")?;
+        let ts_buf = error::field_bytes(&buf[..end_n], 0, loop_end, "transport_stream_loop")?;
+        let mut n = descriptors_end + 2;
+        let mut transport_streams = vec![];
+        while n < loop_end {
+            let (info, size) = TransportStreamInfo::new(ts_buf, n)?;
+            transport_streams.push(info);
+            n += size;
+        }
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(Nit {
+            table_id,
+            network_id: BigEndian::read_u16(&buf[3..5]),
+            version_number: (buf[5] & 0x3E) >> 1,
+            current_next_indicator: packet::get_bit_at(buf[5], 0),
+            section_number: buf[6],
+            last_section_number: buf[7],
+            network_name,
+            descriptors,
+            transport_streams,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+
+    /// Whether this describes the network the stream is from (not another one)
+    pub fn is_actual(&self) -> bool {
+        self.table_id == NIT_ACTUAL_TABLE_ID
+    }
+
+    pub fn get_network_id(&self) -> u16 {
+        self.network_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// A NIT of network 0x3001 named "Net" around the transport stream loop `ts_loop`
+    fn nit(table_id: u8, ts_loop: &[u8]) -> Vec<u8> {
+        let network_descriptors = test_util::descriptor(NETWORK_NAME_DESCRIPTOR_TAG, b"Net");
+        let mut body = vec![0xF0, network_descriptors.len() as u8];
+        body.extend(network_descriptors);
+        body.extend_from_slice(&[0xF0, ts_loop.len() as u8]);
+        body.extend_from_slice(ts_loop);
+        test_util::section(table_id, 0x3001, &body)
+    }
+
+    /// A transport stream loop entry of original network 2
+    fn transport_stream(transport_stream_id: u16, descriptors: &[u8]) -> Vec<u8> {
+        let mut buf = transport_stream_id.to_be_bytes().to_vec();
+        buf.extend_from_slice(&[0x00, 0x02, 0xF0, descriptors.len() as u8]);
+        buf.extend_from_slice(descriptors);
+        buf
+    }
+
+    #[test]
+    fn transport_streams() {
+        let mut descriptors = test_util::descriptor(SATELLITE_DELIVERY_DESCRIPTOR_TAG,
+            &[0x01, 0x17, 0x50, 0x00, 0x01, 0x92, 0xA6, 0x02, 0x75, 0x00, 0x03]);
+        descriptors.extend(test_util::descriptor(SERVICE_LIST_DESCRIPTOR_TAG, &[0x00, 0x01, 0x01, 0x00, 0x02, 0x02]));
+        descriptors.extend(test_util::descriptor(LOGICAL_CHANNEL_DESCRIPTOR_TAG, &[0x00, 0x01, 0xFC, 0x05]));
+        let mut ts_loop = transport_stream(1, &descriptors);
+        // A cable delivery descriptor too short to decode
+        ts_loop.extend(transport_stream(3, &test_util::descriptor(CABLE_DELIVERY_DESCRIPTOR_TAG, &[0x03, 0x46, 0x00])));
+
+        let nit = Nit::new(&nit(NIT_ACTUAL_TABLE_ID, &ts_loop)).unwrap();
+        assert!(nit.is_actual() && !nit.crc_error);
+        assert_eq!(nit.get_network_id(), 0x3001);
+        assert_eq!(nit.network_name.as_deref(), Some("Net"));
+        assert_eq!(nit.transport_streams.len(), 2);
+
+        let ts = &nit.transport_streams[0];
+        assert_eq!((ts.transport_stream_id, ts.original_network_id), (1, 2));
+        let delivery = ts.delivery_system.as_ref().unwrap();
+        assert_eq!(*delivery, DeliverySystem::Satellite(SatelliteDelivery {
+            frequency: 1_175_000,
+            orbital_position: 192,
+            west_east_flag: true,
+            polarization: 1,
+            roll_off: 0,
+            s2: true,
+            modulation_type: 2,
+            symbol_rate: 275_000,
+            fec_inner: 3,
+        }));
+        assert_eq!(delivery.to_string(), "Satellite: 11.75000 GHz V, 19.2E, DVB-S2 8PSK, 27.5000 Msym/s, FEC 3/4");
+        assert_eq!(ts.services, vec![
+            ServiceListEntry { service_id: 1, service_type: 1 },
+            ServiceListEntry { service_id: 2, service_type: 2 },
+        ]);
+        assert_eq!(ts.logical_channels, vec![
+            LogicalChannel { service_id: 1, visible_service_flag: true, logical_channel_number: 5 },
+        ]);
+
+        // The short descriptor is skipped, not the transport stream
+        let ts = &nit.transport_streams[1];
+        assert_eq!(ts.transport_stream_id, 3);
+        assert_eq!(ts.delivery_system, None);
+        assert_eq!(ts.descriptors.len(), 1);
+
+        // Only the services with a logical channel show one
+        assert!(nit.to_string().contains("Service ID: 0x1 (1), Type: 0x1, LCN: 5\n\t\t=> Service ID: 0x2 (2), Type: 0x2\n"), "{}", nit);
+    }
+
+    #[test]
+    fn cable_and_terrestrial_delivery() {
+        let mut ts_loop = transport_stream(1, &test_util::descriptor(CABLE_DELIVERY_DESCRIPTOR_TAG,
+            &[0x03, 0x46, 0x00, 0x00, 0xFF, 0xF2, 0x03, 0x00, 0x69, 0x00, 0x05]));
+        ts_loop.extend(transport_stream(2, &test_util::descriptor(TERRESTRIAL_DELIVERY_DESCRIPTOR_TAG,
+            &[0x02, 0xDF, 0x79, 0x40, 0x1F, 0x82, 0x12, 0xFF, 0xFF, 0xFF, 0xFF])));
+        let nit = Nit::new(&nit(NIT_OTHER_TABLE_ID, &ts_loop)).unwrap();
+        assert!(!nit.is_actual());
+
+        let cable = nit.transport_streams[0].delivery_system.as_ref().unwrap();
+        assert_eq!(*cable, DeliverySystem::Cable(CableDelivery {
+            frequency: 3_460_000,
+            fec_outer: 2,
+            modulation: 3,
+            symbol_rate: 69_000,
+            fec_inner: 5,
+        }));
+        assert_eq!(cable.to_string(), "Cable: 346.0000 MHz, 64-QAM, 6.9000 Msym/s, FEC 7/8");
+
+        let terrestrial = nit.transport_streams[1].delivery_system.as_ref().unwrap();
+        assert_eq!(*terrestrial, DeliverySystem::Terrestrial(TerrestrialDelivery {
+            centre_frequency: 48_200_000,
+            bandwidth: 0,
+            priority: true,
+            constellation: 2,
+            hierarchy_information: 0,
+            code_rate_hp: 2,
+            code_rate_lp: 0,
+            guard_interval: 2,
+            transmission_mode: 1,
+            other_frequency_flag: false,
+        }));
+        assert_eq!(terrestrial.to_string(), "Terrestrial: 482.00000 MHz, 8 MHz, 64-QAM, guard interval 1/8, 8k mode");
+        assert!(nit.to_string().starts_with("[NIT] Network ID: 0x3001 (12289) (other), Version: 0x0, Name: Net"), "{}", nit);
+    }
+
+    #[test]
+    fn bcd_values() {
+        assert_eq!(bcd(0x0117_5000, 8), 1_175_000);
+        assert_eq!(bcd(0x0275_0003 >> 4, 7), 275_000);
+    }
+
+    #[test]
+    fn invalid_sections() {
+        let buf = nit(NIT_ACTUAL_TABLE_ID, &transport_stream(1, &test_util::descriptor(0x41, &[0x00, 0x01, 0x01])));
+        for len in 0..buf.len() {
+            assert!(Nit::new(&buf[..len]).is_err(), "{} bytes", len);
+        }
+        let mut sdt = buf.clone();
+        sdt[0] = 0x42;
+        assert_eq!(Nit::new(&sdt), Err(ParseError::TableId { pid: None, expected: NIT_ACTUAL_TABLE_ID, found: 0x42 }));
+        // Each of the loops running into the CRC
+        let mut network_descriptors = buf.clone();
+        network_descriptors[9] = 0x20;
+        assert_eq!(Nit::new(&network_descriptors), Err(ParseError::Truncated { pid: None, offset: 0, field: "descriptors" }));
+        let mut ts_loop = buf.clone();
+        ts_loop[16] += 1;
+        assert_eq!(Nit::new(&ts_loop), Err(ParseError::Truncated { pid: None, offset: 0, field: "transport_stream_loop" }));
+        let mut ts_descriptors = buf;
+        ts_descriptors[22] += 4;
+        assert_eq!(Nit::new(&ts_descriptors), Err(ParseError::Truncated { pid: None, offset: 0, field: "descriptors" }));
+    }
+}
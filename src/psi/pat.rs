@@ -2,7 +2,7 @@ use std::fmt;
 use std::fmt::Write;
 use std::collections::HashSet;
 use byteorder::{ByteOrder, BigEndian};
-use crate::{packet, mpeg32_crc};
+use crate::{packet, mpeg32_crc, error::{self, ParseError}};
 
 // Constants
 const PAT_TABLE_ID: u8 = 0x0;
@@ -53,45 +53,42 @@ impl fmt::Display for Pat {
 }
 
 impl Pat {
-    /// Parse a data_byte buffer into a Pat object and return Option<Pat>
-    pub fn new(buf: &[u8]) -> Option<Pat> {
-        if buf.len() == 0 || buf[0] != PAT_TABLE_ID {
-            return None;
-        }
+    /// Parse a section buffer into a Pat object and return Result<Pat, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Pat, ParseError> {
         // Calculate length and index fields
+        let end_n = super::check_section(buf, PAT_TABLE_ID)?;
         let section_length = BigEndian::read_u16(&[buf[1] & 0x0F, buf[2]]);
-        let section_end = (super::PSI_SEC_START_INDEX + section_length) as usize;
 
         // Get program info
         let mut n = 8;
-        let end_n = section_end - packet::CRC_SIZE;
         let mut prog_infos: Vec<ProgramInfo> = vec![];
         while n < end_n {
-            let program_number = BigEndian::read_u16(&[buf[n], buf[n+1]]);
+            let b = error::field_bytes(&buf[..end_n], n, 4, "program_map_PID")?;
+            let program_number = BigEndian::read_u16(&b[0..2]);
             prog_infos.push(ProgramInfo {
-                program_number: program_number,
+                program_number,
                 program_info_type: if program_number == 0 {
                     ProgramInfoType::Network
                 } else {
                     ProgramInfoType::ProgramMap
                 },
-                pid: BigEndian::read_u16(&[buf[n+2] & 0x1F, buf[n+3]]),
+                pid: BigEndian::read_u16(&[b[2] & 0x1F, b[3]]),
             });
             n += 4;
         }
 
-        let crc = BigEndian::read_u32(&buf[end_n..=section_end]);
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
         let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
-        Some(Pat {
+        Ok(Pat {
             syntax_section_indicator: packet::get_bit_at(buf[1], 7),
-            section_length: section_length,
+            section_length,
             transport_stream_id: BigEndian::read_u16(&[buf[3], buf[4]]),
             version_number: (buf[5] & 0x3E) >> 1,
             current_next_indicator: packet::get_bit_at(buf[5], 0),
             section_number: buf[6],
             last_section_number: buf[7],
             program_info: prog_infos,
-            crc: crc,
+            crc,
             crc_error: crc != exp_crc,
         })
     }
@@ -106,4 +103,76 @@ impl Pat {
         }
         p
     }
+
+    /// Get the (program_number, PMT PID) of every program in this PAT packet
+    pub fn get_programs(&self) -> Vec<(u16, u16)> {
+        self.program_info.iter()
+            .filter(|i| i.program_info_type == ProgramInfoType::ProgramMap)
+            .map(|i| (i.program_number, i.pid))
+            .collect()
+    }
+
+    pub fn get_version_number(&self) -> u8 {
+        self.version_number
+    }
+
+    pub fn get_section_number(&self) -> u8 {
+        self.section_number
+    }
+
+    /// Get the network PID (of the NIT) if this PAT gives one
+    pub fn get_network_pid(&self) -> Option<u16> {
+        self.program_info.iter()
+            .find(|i| i.program_info_type == ProgramInfoType::Network)
+            .map(|i| i.pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn pat() -> Vec<u8> {
+        test_util::section(PAT_TABLE_ID, 0x1234, &[0x00, 0x00, 0xE0, 0x10, 0x00, 0x01, 0xE1, 0x00, 0x00, 0x02, 0xE1, 0x10])
+    }
+
+    #[test]
+    fn programs() {
+        let pat = Pat::new(&pat()).unwrap();
+        assert_eq!(pat.transport_stream_id, 0x1234);
+        assert!(!pat.crc_error);
+        assert_eq!(pat.get_programs(), vec![(1, 0x100), (2, 0x110)]);
+        assert_eq!(pat.get_pmt_pids(), [0x100, 0x110].iter().copied().collect());
+        assert_eq!(pat.get_network_pid(), Some(0x10));
+    }
+
+    #[test]
+    fn crc_error() {
+        let mut buf = pat();
+        buf[8] ^= 0x01;
+        assert!(Pat::new(&buf).unwrap().crc_error);
+    }
+
+    #[test]
+    fn invalid_sections() {
+        let buf = pat();
+        for len in 0..buf.len() {
+            assert!(Pat::new(&buf[..len]).is_err(), "{} bytes", len);
+        }
+        assert_eq!(Pat::new(&test_util::section(0x02, 1, &[])),
+            Err(ParseError::TableId { pid: None, expected: PAT_TABLE_ID, found: 0x02 }));
+        // section_length running past the end of the buffer
+        let mut oversized = buf.clone();
+        oversized[2] += 4;
+        assert_eq!(Pat::new(&oversized), Err(ParseError::Truncated { pid: None, offset: 0, field: "section_length" }));
+        // section_length too short to hold the header and CRC
+        let mut short = buf;
+        short[1] &= 0xF0;
+        short[2] = 8;
+        assert!(matches!(Pat::new(&short), Err(ParseError::InvalidValue { field: "section_length", value: 8, .. })));
+        // A program loop that isn't a whole number of programs
+        let partial = test_util::section(PAT_TABLE_ID, 1, &[0x00, 0x01, 0xE1, 0x00, 0x00, 0x02]);
+        assert_eq!(Pat::new(&partial), Err(ParseError::Truncated { pid: None, offset: 12, field: "program_map_PID" }));
+    }
 }
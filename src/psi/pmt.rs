@@ -1,8 +1,9 @@
 use std::fmt;
 use std::fmt::Write;
+use std::collections::HashSet;
 use byteorder::{ByteOrder, BigEndian};
-use super::{VideoStreamDescriptor,ElementaryStream};
-use crate::{packet, mpeg32_crc};
+use super::{Descriptor,ElementaryStream};
+use crate::{codec::Codec, packet, mpeg32_crc, error::{self, ParseError}};
 
 // Constants
 const PMT_TABLE_ID: u8 = 0x02;
@@ -17,7 +18,7 @@ pub struct Pmt {
     last_section_number: u8,
     pcr_pid: u16,
     program_info_length: u16,
-    pub descriptors: Vec<VideoStreamDescriptor>,
+    pub descriptors: Vec<Descriptor>,
     pub elementary_streams: Vec<ElementaryStream>,
     pub crc: u32,
     pub crc_error: bool,
@@ -30,19 +31,18 @@ impl fmt::Display for Pmt {
         let mut elem_str = String::new();
         // Generate top level descriptors string
         for i in &self.descriptors {
-            write!(&mut des_str,
-                "\n\t=> Descriptor Tag: {0:#X} ({1}), length: {2}",
-                i.tag, i.to_string(), i.length).unwrap();
+            write!(&mut des_str, "\n\t=> Descriptor {}", i).unwrap();
         }
         // Generate elementary streams string
         for i in &self.elementary_streams {
             write!(&mut elem_str,
                 "\n\t=> Steam Type: {0:#X} ({1}), Elementary PID: {2:#X}",
                 i.stream_type, i.to_string(), i.elementary_pid).unwrap();
+            if !matches!(i.get_codec(), Codec::Unknown(_)) {
+                write!(&mut elem_str, ", Codec: {}", i.get_codec()).unwrap();
+            }
             for j in &i.descriptors {
-                write!(&mut elem_str,
-                    "\n\t\t=> Descriptor Tag: {0:#X} ({1}), length: {2}",
-                    j.tag, j.to_string(), j.length).unwrap();
+                write!(&mut elem_str, "\n\t\t=> Descriptor {}", j).unwrap();
             }
         }
         write!(f, "[PMT] Program Number: {0}, Version: {1}, PCR PID: {2:#X} ({2}){3}{4}",
@@ -51,75 +51,134 @@ impl fmt::Display for Pmt {
 }
 
 impl Pmt {
-    /// Parse a data_byte buffer into a Pmt object and return Option<Pmt>
-    pub fn new(buf: &[u8]) -> Option<Pmt> {
-        if buf.len() == 0 || buf[0] != PMT_TABLE_ID {
-            return None;
-        }
+    /// Parse a section buffer into a Pmt object and return Result<Pmt, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Pmt, ParseError> {
         // Calculate length and index fields
-        let section_length = BigEndian::read_u16(&[buf[1] & 0x0F, buf[2]]);
-        let program_info_length = BigEndian::read_u16(&[buf[10] & 0x0F, buf[11]]);
-        let section_end = (super::PSI_SEC_START_INDEX + section_length) as usize;
+        let end_n1 = super::check_section(buf, PMT_TABLE_ID)?;
+        let header = error::field_bytes(&buf[..end_n1], 8, 4, "program_info_length")?;
+        let program_info_length = BigEndian::read_u16(&[header[2] & 0x0F, header[3]]);
 
         // Get Top level descriptors
-        let mut n = 12;
+        let n = 12;
         let end_n = n + program_info_length as usize;
-        let mut descriptors: Vec<VideoStreamDescriptor> = vec![];
-        while n < end_n {
-            let len_n = buf[n+1];
-            descriptors.push(VideoStreamDescriptor {
-                tag: buf[n],
-                length: len_n,
-            });
-            n += 2 + len_n as usize;
-        }
+        let descriptors = Descriptor::parse_loop(&buf[..end_n1], n, end_n)?;
 
         // Get Stream info
         let mut n1 = end_n;
-        let end_n1 = section_end - packet::CRC_SIZE;
         let mut elementary_streams: Vec<ElementaryStream> = vec![];
         while n1 < end_n1 {
-            let stream_type = buf[n1];
-            let elementary_pid = BigEndian::read_u16(&[buf[n1+1] & 0x1F, buf[n1+2]]);
-            let es_info_length = BigEndian::read_u16(&[buf[n1+3] & 0x0F, buf[n1+4]]);
+            let b = error::field_bytes(&buf[..end_n1], n1, 5, "ES_info_length")?;
+            let stream_type = b[0];
+            let elementary_pid = BigEndian::read_u16(&[b[1] & 0x1F, b[2]]);
+            let es_info_length = BigEndian::read_u16(&[b[3] & 0x0F, b[4]]);
 
             // Get Bottom level descriptors
-            let mut n2 = n1 + 5;
+            let n2 = n1 + 5;
             let end_n2 = n2 + es_info_length as usize;
-            let mut elementary_stream_descriptors: Vec<VideoStreamDescriptor> = vec![];
-            while n2 < end_n2 {
-                let len_n2 = buf[n2+1];
-                elementary_stream_descriptors.push(VideoStreamDescriptor {
-                    tag: buf[n2],
-                    length: len_n2,
-                });
-                n2 += 2 + len_n2 as usize;
-            }
+            let elementary_stream_descriptors =
+                Descriptor::parse_loop(&buf[..end_n1], n2, end_n2)?;
 
             elementary_streams.push(ElementaryStream {
-                stream_type: stream_type,
-                elementary_pid: elementary_pid,
+                stream_type,
+                elementary_pid,
                 descriptors: elementary_stream_descriptors,
             });
 
             n1 = end_n2;
         }
 
-        let crc = BigEndian::read_u32(&buf[end_n1..=section_end]);
+        let crc = BigEndian::read_u32(&buf[end_n1..end_n1 + packet::CRC_SIZE]);
         let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n1]);
-        Some(Pmt {
+        Ok(Pmt {
             section_syntax_indicator: packet::get_bit_at(buf[1], 7),
             program_number: BigEndian::read_u16(&[buf[3], buf[4]]),
             version_number: (buf[5] & 0x3E) >> 1,
             current_next_indicator: packet::get_bit_at(buf[5], 0),
             section_number: buf[6],
             last_section_number: buf[7],
-            pcr_pid: BigEndian::read_u16(&[buf[8] & 0x1F, buf[9]]),
-            program_info_length: program_info_length,
-            descriptors: descriptors,
-            elementary_streams: elementary_streams,
-            crc: crc,
+            pcr_pid: BigEndian::read_u16(&[header[0] & 0x1F, header[1]]),
+            program_info_length,
+            descriptors,
+            elementary_streams,
+            crc,
             crc_error: crc != exp_crc,
         })
     }
+
+    /// Get a list of the elementary stream PIDs in this PMT
+    pub fn get_es_pids(&self) -> HashSet<u16> {
+        self.elementary_streams.iter().map(|es| es.elementary_pid).collect()
+    }
+
+    pub fn get_program_number(&self) -> u16 {
+        self.program_number
+    }
+
+    /// The format_identifier of the registration_descriptor of the program (e.g. b"CUEI"), if any
+    pub fn get_format_identifier(&self) -> Option<[u8; 4]> {
+        super::format_identifier(&self.descriptors)
+    }
+
+    /// Get the PID carrying the PCRs of the program (0x1FFF if there are none)
+    pub fn get_pcr_pid(&self) -> u16 {
+        self.pcr_pid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn pmt(stream_descriptors: &[u8]) -> Vec<u8> {
+        let mut body = vec![0xE1, 0x00];
+        let program_descriptors = test_util::descriptor(0x05, b"CUEI");
+        body.extend_from_slice(&[0xF0, program_descriptors.len() as u8]);
+        body.extend(program_descriptors);
+        body.extend_from_slice(&[0x1B, 0xE1, 0x00, 0xF0, 0x00]);
+        body.extend_from_slice(&[0x06, 0xE1, 0x01, 0xF0, stream_descriptors.len() as u8]);
+        body.extend_from_slice(stream_descriptors);
+        test_util::section(PMT_TABLE_ID, 7, &body)
+    }
+
+    #[test]
+    fn streams() {
+        let pmt = Pmt::new(&pmt(&test_util::descriptor(0x6A, &[0x00]))).unwrap();
+        assert_eq!((pmt.get_program_number(), pmt.get_pcr_pid()), (7, 0x100));
+        assert!(!pmt.crc_error);
+        assert_eq!(pmt.descriptors.len(), 1);
+        let streams: Vec<_> = pmt.elementary_streams.iter()
+            .map(|es| (es.get_stream_type(), es.get_elementary_pid(), es.descriptors.len())).collect();
+        assert_eq!(streams, vec![(0x1B, 0x100, 0), (0x06, 0x101, 1)]);
+        let codecs: Vec<_> = pmt.elementary_streams.iter().map(|es| es.get_codec()).collect();
+        assert_eq!(codecs, vec![Codec::H264, Codec::Ac3]);
+        assert_eq!(pmt.get_es_pids(), [0x100, 0x101].iter().copied().collect());
+    }
+
+    #[test]
+    fn undecodable_descriptor() {
+        // A CA_descriptor too short to decode doesn't cost the PMT its streams
+        let pmt = Pmt::new(&pmt(&test_util::descriptor(0x09, &[0x01]))).unwrap();
+        assert_eq!(pmt.elementary_streams.len(), 2);
+        assert_eq!(pmt.elementary_streams[1].descriptors, vec![Descriptor::Unknown { tag: 0x09, data: vec![0x01] }]);
+    }
+
+    #[test]
+    fn invalid_sections() {
+        let buf = pmt(&[]);
+        for len in 0..buf.len() {
+            assert!(Pmt::new(&buf[..len]).is_err(), "{} bytes", len);
+        }
+        let mut oversized = buf.clone();
+        oversized[2] += 1;
+        assert!(matches!(Pmt::new(&oversized), Err(ParseError::Truncated { field: "section_length", .. })));
+        // program_info_length and ES_info_length running into the CRC
+        let mut program_info_overrun = buf.clone();
+        program_info_overrun[11] = 0x30;
+        assert_eq!(Pmt::new(&program_info_overrun), Err(ParseError::Truncated { pid: None, offset: 0, field: "descriptors" }));
+        let mut es_info_overrun = buf;
+        let es_info_length = es_info_overrun.len() - 5;
+        es_info_overrun[es_info_length] = 0x20;
+        assert_eq!(Pmt::new(&es_info_overrun), Err(ParseError::Truncated { pid: None, offset: 0, field: "descriptors" }));
+    }
 }
@@ -0,0 +1,115 @@
+use std::fmt;
+use std::fmt::Write;
+use byteorder::{ByteOrder, BigEndian};
+use super::{text::MultipleString, PROTOCOL_VERSION_INDEX, EIT_TABLE_ID};
+use crate::psi::{self, time::UtcTime, Descriptor};
+use crate::{packet, mpeg32_crc, error::{self, ParseError}};
+
+/// An event of an ATSC EIT
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Event {
+    pub event_id: u16,
+    /// Seconds since 1980-01-06 00:00:00 GPS time
+    pub start_time: u32,
+    /// Where the ETT with the description of the event is (0 if there is none)
+    pub etm_location: u8,
+    /// Duration in seconds
+    pub length_in_seconds: u32,
+    pub title: MultipleString,
+    pub descriptors: Vec<Descriptor>,
+}
+
+impl Event {
+    /// Parse the event starting at `buf[n]` and return it along with its size
+    fn new(buf: &[u8], n: usize) -> Result<(Event, usize), ParseError> {
+        let header = error::field_bytes(buf, n, 10, "title_length")?;
+        let title_length = header[9] as usize;
+        let title = super::multiple_string(buf, n + 10, title_length, "title_text")?;
+        let descriptors_start = n + 12 + title_length;
+        let end = descriptors_start + psi::loop_length(buf, descriptors_start - 2, "descriptors_length")?;
+
+        let event = Event {
+            event_id: BigEndian::read_u16(&header[0..2]) & 0x3FFF,
+            start_time: BigEndian::read_u32(&header[2..6]),
+            etm_location: (header[6] >> 4) & 0x03,
+            length_in_seconds: (u32::from(header[6] & 0x0F) << 16) | u32::from(BigEndian::read_u16(&header[7..9])),
+            title,
+            descriptors: Descriptor::parse_loop(buf, descriptors_start, end)?,
+        };
+        Ok((event, end - n))
+    }
+
+    /// The start of the event in UTC, given the GPS-UTC offset of the STT
+    pub fn start_utc(&self, gps_utc_offset: u8) -> UtcTime {
+        UtcTime::from_gps(self.start_time, gps_utc_offset)
+    }
+}
+
+/// ATSC Event Information Table: the events of a virtual channel over 3 hours
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Eit {
+    source_id: u16,
+    version_number: u8,
+    current_next_indicator: bool,
+    section_number: u8,
+    last_section_number: u8,
+    protocol_version: u8,
+    pub events: Vec<Event>,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for Eit {
+    /// Display the eit along with its events (with their start in GPS time)
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut event_str = String::new();
+        for e in &self.events {
+            // Without the GPS-UTC offset of the STT, the start can only be given in GPS time
+            let start = e.start_utc(0);
+            let ((year, month, day), (hour, minute, second)) = (start.date(), start.time_of_day());
+            let d = e.length_in_seconds;
+            write!(&mut event_str, "\n\t=> Event ID: {0:#X} ({0}), Start: {1:04}-{2:02}-{3:02} {4:02}:{5:02}:{6:02} GPS, Duration: {7:02}:{8:02}:{9:02}, Title: {10}",
+                e.event_id, year, month, day, hour, minute, second, d / 3600, d / 60 % 60, d % 60, e.title).unwrap();
+        }
+        write!(f, "[EIT (ATSC)] Source ID: {0:#X} ({0}), Version: {1:#X}{2}",
+            self.source_id, self.version_number, event_str)
+    }
+}
+
+impl Eit {
+    /// Parse a section buffer into a Eit object and return Result<Eit, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Eit, ParseError> {
+        // Calculate length and index fields
+        let end_n = psi::check_section(buf, EIT_TABLE_ID)?;
+        let section = &buf[..end_n];
+        let header = error::field_bytes(section, PROTOCOL_VERSION_INDEX, 2, "num_events_in_section")?;
+
+        // Get the events
+        let mut n = 10;
+        let mut events = vec![];
+        for _ in 0..header[1] {
+            let (event, size) = Event::new(section, n)?;
+            events.push(event);
+            n += size;
+        }
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(Eit {
+            source_id: BigEndian::read_u16(&buf[3..5]),
+            version_number: (buf[5] & 0x3E) >> 1,
+            current_next_indicator: packet::get_bit_at(buf[5], 0),
+            section_number: buf[6],
+            last_section_number: buf[7],
+            protocol_version: header[0],
+            events,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+
+    /// The virtual channel the events are on (see `Channel::source_id`)
+    pub fn get_source_id(&self) -> u16 {
+        self.source_id
+    }
+}
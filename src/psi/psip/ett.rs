@@ -0,0 +1,63 @@
+use std::fmt;
+use byteorder::{ByteOrder, BigEndian};
+use super::{text::MultipleString, PROTOCOL_VERSION_INDEX, ETT_TABLE_ID};
+use crate::psi;
+use crate::{packet, mpeg32_crc, error::{self, ParseError}};
+
+/// Extended Text Table: the description of a virtual channel or one of its events
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Ett {
+    version_number: u8,
+    protocol_version: u8,
+    /// source_id, event_id and whether it describes an event rather than the channel
+    pub etm_id: u32,
+    pub extended_text_message: MultipleString,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for Ett {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get_event_id() {
+            Some(event_id) => write!(f, "[ETT] Source ID: {0:#X} ({0}), Event ID: {1:#X} ({1}), Text: {2}",
+                self.get_source_id(), event_id, self.extended_text_message),
+            None => write!(f, "[ETT] Source ID: {0:#X} ({0}), Text: {1}",
+                self.get_source_id(), self.extended_text_message),
+        }
+    }
+}
+
+impl Ett {
+    /// Parse a section buffer into a Ett object and return Result<Ett, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Ett, ParseError> {
+        // Calculate length and index fields
+        let end_n = psi::check_section(buf, ETT_TABLE_ID)?;
+        let header = error::field_bytes(&buf[..end_n], PROTOCOL_VERSION_INDEX, 5, "ETM_id")?;
+        let start = PROTOCOL_VERSION_INDEX + 5;
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(Ett {
+            version_number: (buf[5] & 0x3E) >> 1,
+            protocol_version: header[0],
+            etm_id: BigEndian::read_u32(&header[1..5]),
+            extended_text_message: super::multiple_string(buf, start, end_n - start, "extended_text_message")?,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+
+    /// The virtual channel the text is about (see `Channel::source_id`)
+    pub fn get_source_id(&self) -> u16 {
+        (self.etm_id >> 16) as u16
+    }
+
+    /// The event the text describes, or None if it describes the channel
+    pub fn get_event_id(&self) -> Option<u16> {
+        if self.etm_id & 0x03 == 0x02 {
+            Some(((self.etm_id >> 2) & 0x3FFF) as u16)
+        } else {
+            None
+        }
+    }
+}
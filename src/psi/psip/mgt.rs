@@ -0,0 +1,120 @@
+use std::fmt;
+use std::fmt::Write;
+use byteorder::{ByteOrder, BigEndian};
+use super::{PROTOCOL_VERSION_INDEX, MGT_TABLE_ID};
+use crate::psi::{self, Descriptor};
+use crate::{packet, mpeg32_crc, error::{self, ParseError}};
+
+/// An entry of the MGT: where a table is found and its current version
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TableDefinition {
+    pub table_type: u16,
+    pub table_type_pid: u16,
+    pub table_type_version_number: u8,
+    /// Total size of the sections of the table
+    pub number_bytes: u32,
+    pub descriptors: Vec<Descriptor>,
+}
+
+impl TableDefinition {
+    /// What the table_type (ATSC A/65 table 6.3) says the table is
+    pub fn table_type_name(&self) -> String {
+        match self.table_type {
+            0x0000 => "TVCT (current)".to_string(),
+            0x0001 => "TVCT (next)".to_string(),
+            0x0002 => "CVCT (current)".to_string(),
+            0x0003 => "CVCT (next)".to_string(),
+            0x0004 => "Channel ETT".to_string(),
+            0x0005 => "DCCSCT".to_string(),
+            t @ 0x0100..=0x017F => format!("EIT-{}", t - 0x0100),
+            t @ 0x0200..=0x027F => format!("Event ETT-{}", t - 0x0200),
+            t @ 0x0301..=0x03FF => format!("RRT (region {})", t - 0x0300),
+            t @ 0x1400..=0x14FF => format!("DCCT (id {})", t - 0x1400),
+            _ => "Reserved".to_string(),
+        }
+    }
+
+    /// Whether the table is one of the EITs
+    pub fn is_eit(&self) -> bool {
+        (0x0100..=0x017F).contains(&self.table_type)
+    }
+
+    /// Whether the table is one of the ETTs (of the channels or events)
+    pub fn is_ett(&self) -> bool {
+        self.table_type == 0x0004 || (0x0200..=0x027F).contains(&self.table_type)
+    }
+}
+
+/// Master Guide Table: the versions, sizes and PIDs of the other PSIP tables
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mgt {
+    version_number: u8,
+    current_next_indicator: bool,
+    protocol_version: u8,
+    pub tables: Vec<TableDefinition>,
+    pub descriptors: Vec<Descriptor>,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for Mgt {
+    /// Display the mgt along with the tables it defines
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut table_str = String::new();
+        for t in &self.tables {
+            write!(&mut table_str, "\n\t=> Table type: {:#06X} ({}), PID: {:#X}, Version: {:#X}, Bytes: {}",
+                t.table_type, t.table_type_name(), t.table_type_pid, t.table_type_version_number,
+                t.number_bytes).unwrap();
+        }
+        write!(f, "[MGT] Version: {:#X}, Protocol version: {}{}", self.version_number,
+            self.protocol_version, table_str)
+    }
+}
+
+impl Mgt {
+    /// Parse a section buffer into a Mgt object and return Result<Mgt, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Mgt, ParseError> {
+        // Calculate length and index fields
+        let end_n = psi::check_section(buf, MGT_TABLE_ID)?;
+        let section = &buf[..end_n];
+        let header = error::field_bytes(section, PROTOCOL_VERSION_INDEX, 3, "tables_defined")?;
+
+        // Get the table definitions
+        let mut n = 11;
+        let mut tables = vec![];
+        for _ in 0..BigEndian::read_u16(&header[1..3]) {
+            let entry = error::field_bytes(section, n, 11, "table_type_descriptors_length")?;
+            let end = n + 11 + psi::loop_length(entry, 9, "table_type_descriptors_length")?;
+            tables.push(TableDefinition {
+                table_type: BigEndian::read_u16(&entry[0..2]),
+                table_type_pid: BigEndian::read_u16(&entry[2..4]) & 0x1FFF,
+                table_type_version_number: entry[4] & 0x1F,
+                number_bytes: BigEndian::read_u32(&entry[5..9]),
+                descriptors: Descriptor::parse_loop(section, n + 11, end)?,
+            });
+            n = end;
+        }
+        let end = n + 2 + psi::loop_length(section, n, "descriptors_length")?;
+        let descriptors = Descriptor::parse_loop(section, n + 2, end)?;
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(Mgt {
+            version_number: (buf[5] & 0x3E) >> 1,
+            current_next_indicator: packet::get_bit_at(buf[5], 0),
+            protocol_version: header[0],
+            tables,
+            descriptors,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+
+    /// The PIDs of the EITs and ETTs
+    pub fn get_event_pids(&self) -> Vec<u16> {
+        self.tables.iter()
+            .filter(|t| t.is_eit() || t.is_ett())
+            .map(|t| t.table_type_pid)
+            .collect()
+    }
+}
@@ -0,0 +1,273 @@
+use std::fmt;
+use crate::error::{self, ParseError};
+
+pub mod eit;
+pub mod ett;
+pub mod mgt;
+pub mod rrt;
+pub mod stt;
+pub mod text;
+pub mod vct;
+
+// Constants
+/// PID of the MGT, VCTs, STT and RRT. The PIDs of the EITs and ETTs are given by the MGT
+pub const PSIP_BASE_PID: u16 = 0x1FFB;
+pub const MGT_TABLE_ID: u8 = 0xC7;
+pub const TVCT_TABLE_ID: u8 = 0xC8;
+pub const CVCT_TABLE_ID: u8 = 0xC9;
+pub const RRT_TABLE_ID: u8 = 0xCA;
+pub const EIT_TABLE_ID: u8 = 0xCB;
+pub const ETT_TABLE_ID: u8 = 0xCC;
+pub const STT_TABLE_ID: u8 = 0xCD;
+/// Index of the protocol_version field every PSIP table starts with
+const PROTOCOL_VERSION_INDEX: usize = 8;
+
+/// The ATSC A/65 Program and System Information Protocol tables
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Psip {
+    Mgt(mgt::Mgt),
+    Vct(vct::Vct),
+    Stt(stt::Stt),
+    Rrt(rrt::Rrt),
+    Eit(eit::Eit),
+    Ett(ett::Ett),
+}
+
+impl fmt::Display for Psip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Psip::Mgt(p) => write!(f, "{}", p),
+            Psip::Vct(p) => write!(f, "{}", p),
+            Psip::Stt(p) => write!(f, "{}", p),
+            Psip::Rrt(p) => write!(f, "{}", p),
+            Psip::Eit(p) => write!(f, "{}", p),
+            Psip::Ett(p) => write!(f, "{}", p),
+        }
+    }
+}
+
+impl Psip {
+    /// Parse a section found on a PSIP PID into a Psip object
+    pub fn new(buf: &[u8], pid: &u16) -> Result<Psip, ParseError> {
+        let table_id = error::field_byte(buf, 0, "table_id").map_err(|e| e.with_pid(*pid))?;
+        let psip = match table_id {
+            MGT_TABLE_ID => mgt::Mgt::new(buf).map(Psip::Mgt),
+            TVCT_TABLE_ID | CVCT_TABLE_ID => vct::Vct::new(buf).map(Psip::Vct),
+            STT_TABLE_ID => stt::Stt::new(buf).map(Psip::Stt),
+            RRT_TABLE_ID => rrt::Rrt::new(buf).map(Psip::Rrt),
+            EIT_TABLE_ID => eit::Eit::new(buf).map(Psip::Eit),
+            ETT_TABLE_ID => ett::Ett::new(buf).map(Psip::Ett),
+            _ => return Err(ParseError::UnknownTable { pid: *pid, table_id }),
+        };
+        psip.map_err(|e| e.with_pid(*pid))
+    }
+
+    pub fn get_crc(&self) -> u32 {
+        match self {
+            Psip::Mgt(p) => p.crc,
+            Psip::Vct(p) => p.crc,
+            Psip::Stt(p) => p.crc,
+            Psip::Rrt(p) => p.crc,
+            Psip::Eit(p) => p.crc,
+            Psip::Ett(p) => p.crc,
+        }
+    }
+
+    pub fn get_crc_error(&self) -> bool {
+        match self {
+            Psip::Mgt(p) => p.crc_error,
+            Psip::Vct(p) => p.crc_error,
+            Psip::Stt(p) => p.crc_error,
+            Psip::Rrt(p) => p.crc_error,
+            Psip::Eit(p) => p.crc_error,
+            Psip::Ett(p) => p.crc_error,
+        }
+    }
+
+    pub fn is_base_pid(pid: &u16) -> bool { *pid == PSIP_BASE_PID }
+}
+
+/// Get the 10 bit length whose upper bits are in `buf[n]`
+fn length_10(buf: &[u8], n: usize, field: &'static str) -> Result<usize, ParseError> {
+    let b = error::field_bytes(buf, n, 2, field)?;
+    Ok((usize::from(b[0] & 0x03) << 8) | usize::from(b[1]))
+}
+
+/// Parse the multiple_string_structure of `length` bytes at `buf[n]`
+fn multiple_string(buf: &[u8], n: usize, length: usize, field: &'static str)
+    -> Result<text::MultipleString, ParseError> {
+    text::MultipleString::new(error::field_bytes(buf, n, length, field)?).map_err(|e| e.shift(n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// A multiple_string_structure with `text` in English, uncompressed
+    fn string(text: &str) -> Vec<u8> {
+        let mut buf = vec![1];
+        buf.extend_from_slice(b"eng");
+        buf.extend_from_slice(&[1, 0, 0, text.len() as u8]);
+        buf.extend_from_slice(text.as_bytes());
+        buf
+    }
+
+    /// `string(text)` preceded by its length
+    fn length_prefixed(text: &str) -> Vec<u8> {
+        let s = string(text);
+        let mut buf = vec![s.len() as u8];
+        buf.extend(s);
+        buf
+    }
+
+    /// 2024-01-15 00:00:00 UTC, with GPS 18 seconds ahead
+    const SYSTEM_TIME: u32 = 1_389_312_018;
+
+    fn mgt() -> Vec<u8> {
+        let mut body = vec![0x00, 0x00, 0x02];
+        body.extend_from_slice(&[0x00, 0x00, 0xFF, 0xFB, 0xE1, 0x00, 0x00, 0x00, 100, 0xF0, 0x00]);
+        body.extend_from_slice(&[0x01, 0x00, 0xFD, 0x00, 0xE2, 0x00, 0x00, 0x00, 200, 0xF0, 0x00]);
+        body.extend_from_slice(&[0xF0, 0x00]);
+        test_util::section(MGT_TABLE_ID, 0x0000, &body)
+    }
+
+    fn tvct() -> Vec<u8> {
+        let mut body = vec![0x00, 0x01];
+        body.extend("WXYZ\0\0\0".encode_utf16().flat_map(u16::to_be_bytes));
+        // 7.1, 8-VSB, program 3, ATSC digital television, source 0x10
+        body.extend_from_slice(&[0xF0, 0x1C, 0x01, 0x04, 0, 0, 0, 0, 0x00, 0x01, 0x00, 0x03,
+            0x00, 0xC2, 0x00, 0x10]);
+        // extended_channel_name_descriptor
+        let long_name = test_util::descriptor(0xA0, &string("WXYZ News"));
+        body.extend_from_slice(&[0xFC, long_name.len() as u8]);
+        body.extend(long_name);
+        body.extend_from_slice(&[0xFC, 0x00]);
+        test_util::section(TVCT_TABLE_ID, 0x0001, &body)
+    }
+
+    fn stt() -> Vec<u8> {
+        let mut body = vec![0x00];
+        body.extend_from_slice(&SYSTEM_TIME.to_be_bytes());
+        body.extend_from_slice(&[18, 0x80, 0x00]);
+        test_util::section(STT_TABLE_ID, 0x0000, &body)
+    }
+
+    fn rrt() -> Vec<u8> {
+        let mut body = vec![0x00];
+        body.extend(length_prefixed("US"));
+        body.push(1);
+        body.extend(length_prefixed("Age"));
+        // Graduated, 2 values
+        body.push(0xF2);
+        for text in ["G", "General", "PG", "Parental guidance"] {
+            body.extend(length_prefixed(text));
+        }
+        body.extend_from_slice(&[0xFC, 0x00]);
+        test_util::section(RRT_TABLE_ID, 0xFF01, &body)
+    }
+
+    fn eit() -> Vec<u8> {
+        let mut body = vec![0x00, 0x01, 0xC0, 0x05];
+        body.extend_from_slice(&SYSTEM_TIME.to_be_bytes());
+        // ETM in the ETT PID, one hour
+        body.extend_from_slice(&[0xD0, 0x0E, 0x10]);
+        body.extend(length_prefixed("News"));
+        body.extend_from_slice(&[0xF0, 0x00]);
+        test_util::section(EIT_TABLE_ID, 0x0010, &body)
+    }
+
+    fn ett() -> Vec<u8> {
+        // Source 0x10, event 5
+        let mut body = vec![0x00, 0x00, 0x10, 0x00, 0x16];
+        body.extend(string("Evening news"));
+        test_util::section(ETT_TABLE_ID, 0x0000, &body)
+    }
+
+    fn vectors() -> Vec<(Vec<u8>, &'static str)> {
+        vec![
+            (mgt(), "[MGT] Version: 0x0, Protocol version: 0\
+                \n\t=> Table type: 0x0000 (TVCT (current)), PID: 0x1FFB, Version: 0x1, Bytes: 100\
+                \n\t=> Table type: 0x0100 (EIT-0), PID: 0x1D00, Version: 0x2, Bytes: 200"),
+            (tvct(), "[TVCT] Transport Stream ID: 0x1 (1), Version: 0x0\
+                \n\t=> Channel: 7.1, Name: WXYZ, Program: 3, Source ID: 0x10, Type: 0x2 (ATSC digital television), Modulation: 8-VSB"),
+            (stt(), "[STT] Time: 2024-01-15 00:00:00 UTC, GPS-UTC offset: 18s, Daylight saving: in effect"),
+            (rrt(), "[RRT] Region: 1, Name: US, Version: 0x0\n\t=> Dimension: Age, Values: G, PG"),
+            (eit(), "[EIT (ATSC)] Source ID: 0x10 (16), Version: 0x0\
+                \n\t=> Event ID: 0x5 (5), Start: 2024-01-15 00:00:18 GPS, Duration: 01:00:00, Title: News"),
+            (ett(), "[ETT] Source ID: 0x10 (16), Event ID: 0x5 (5), Text: Evening news"),
+        ]
+    }
+
+    #[test]
+    fn tables() {
+        for (buf, expected) in vectors() {
+            let psip = Psip::new(&buf, &PSIP_BASE_PID).unwrap();
+            assert_eq!(psip.to_string(), expected);
+            assert!(!psip.get_crc_error(), "{}", expected);
+        }
+        match Psip::new(&mgt(), &PSIP_BASE_PID).unwrap() {
+            Psip::Mgt(mgt) => assert_eq!(mgt.get_event_pids(), vec![0x1D00]),
+            p => panic!("{}", p),
+        }
+        match Psip::new(&tvct(), &PSIP_BASE_PID).unwrap() {
+            Psip::Vct(vct) => {
+                assert!(!vct.is_cable());
+                assert_eq!(vct.channels[0].long_name.as_ref().unwrap().get("eng"), Some("WXYZ News"));
+            },
+            p => panic!("{}", p),
+        }
+        match Psip::new(&eit(), &PSIP_BASE_PID).unwrap() {
+            Psip::Eit(eit) => assert_eq!(eit.events[0].start_utc(18).to_string(), "2024-01-15 00:00:00 UTC"),
+            p => panic!("{}", p),
+        }
+    }
+
+    #[test]
+    fn unknown_table() {
+        let buf = test_util::section(0xC5, 0x0000, &[0x00]);
+        assert_eq!(Psip::new(&buf, &PSIP_BASE_PID),
+            Err(ParseError::UnknownTable { pid: PSIP_BASE_PID, table_id: 0xC5 }));
+    }
+
+
+    #[test]
+    fn invalid_sections() {
+        for (buf, expected) in vectors() {
+            for len in 0..buf.len() {
+                assert!(Psip::new(&buf[..len], &PSIP_BASE_PID).is_err(), "{} bytes of {}", len, expected);
+            }
+        }
+        assert_eq!(mgt::Mgt::new(&tvct()), Err(ParseError::TableId { pid: None, expected: MGT_TABLE_ID, found: TVCT_TABLE_ID }));
+        assert_eq!(Psip::new(&[], &PSIP_BASE_PID),
+            Err(ParseError::Truncated { pid: Some(PSIP_BASE_PID), offset: 0, field: "table_id" }));
+    }
+
+    #[test]
+    fn counts_past_the_section() {
+        // A third table definition in an MGT holding two
+        let mut buf = mgt();
+        buf[10] = 3;
+        assert_eq!(Psip::new(&test_util::with_crc(buf[..buf.len() - 4].to_vec()), &PSIP_BASE_PID),
+            Err(ParseError::Truncated { pid: Some(PSIP_BASE_PID), offset: 33, field: "table_type_descriptors_length" }));
+        // A second channel in a TVCT holding one
+        let mut buf = tvct();
+        buf[9] = 2;
+        assert_eq!(Psip::new(&test_util::with_crc(buf[..buf.len() - 4].to_vec()), &PSIP_BASE_PID),
+            Err(ParseError::Truncated { pid: Some(PSIP_BASE_PID), offset: 61, field: "descriptors_length" }));
+    }
+
+    #[test]
+    fn strings_past_their_length() {
+        // An EIT title segment running past title_length
+        let mut buf = eit();
+        buf[27] = 0x20;
+        assert_eq!(Psip::new(&test_util::with_crc(buf[..buf.len() - 4].to_vec()), &PSIP_BASE_PID),
+            Err(ParseError::Truncated { pid: Some(PSIP_BASE_PID), offset: 28, field: "compressed_string_byte" }));
+        // An ETT with a second string missing
+        let mut buf = ett();
+        buf[13] = 2;
+        assert_eq!(Psip::new(&test_util::with_crc(buf[..buf.len() - 4].to_vec()), &PSIP_BASE_PID),
+            Err(ParseError::Truncated { pid: Some(PSIP_BASE_PID), offset: 33, field: "number_segments" }));
+    }
+}
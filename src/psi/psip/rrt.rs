@@ -0,0 +1,105 @@
+use std::fmt;
+use std::fmt::Write;
+use byteorder::{ByteOrder, BigEndian};
+use super::{text::MultipleString, PROTOCOL_VERSION_INDEX, RRT_TABLE_ID};
+use crate::psi::{self, Descriptor};
+use crate::{packet, mpeg32_crc, error::{self, ParseError}};
+
+/// A rating value of a dimension, e.g. "TV-14"
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RatingValue {
+    pub abbrev_rating_value: MultipleString,
+    pub rating_value: MultipleString,
+}
+
+/// A rating dimension, e.g. age or violence
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Dimension {
+    pub dimension_name: MultipleString,
+    /// Whether the values are increasing levels of the same thing
+    pub graduated_scale: bool,
+    pub values: Vec<RatingValue>,
+}
+
+/// Read the multiple_string_structure at `buf[*n]` preceded by its 8 bit length and move past it
+fn length_prefixed(buf: &[u8], n: &mut usize, field: &'static str) -> Result<MultipleString, ParseError> {
+    let length = error::field_byte(buf, *n, field)? as usize;
+    let string = super::multiple_string(buf, *n + 1, length, field)?;
+    *n += 1 + length;
+    Ok(string)
+}
+
+/// Rating Region Table: the content advisory ratings of a region
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rrt {
+    rating_region: u8,
+    version_number: u8,
+    protocol_version: u8,
+    pub rating_region_name: MultipleString,
+    pub dimensions: Vec<Dimension>,
+    pub descriptors: Vec<Descriptor>,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for Rrt {
+    /// Display the rrt along with its dimensions and their values
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut dimension_str = String::new();
+        for d in &self.dimensions {
+            let values: Vec<String> = d.values.iter().map(|v| v.abbrev_rating_value.to_string()).collect();
+            write!(&mut dimension_str, "\n\t=> Dimension: {}, Values: {}", d.dimension_name,
+                values.join(", ")).unwrap();
+        }
+        write!(f, "[RRT] Region: {}, Name: {}, Version: {:#X}{}", self.rating_region,
+            self.rating_region_name, self.version_number, dimension_str)
+    }
+}
+
+impl Rrt {
+    /// Parse a section buffer into a Rrt object and return Result<Rrt, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Rrt, ParseError> {
+        // Calculate length and index fields
+        let end_n = psi::check_section(buf, RRT_TABLE_ID)?;
+        let section = &buf[..end_n];
+        let protocol_version = error::field_byte(section, PROTOCOL_VERSION_INDEX, "protocol_version")?;
+        let mut n = PROTOCOL_VERSION_INDEX + 1;
+        let rating_region_name = length_prefixed(section, &mut n, "rating_region_name_text")?;
+
+        // Get the dimensions
+        let dimensions_defined = error::field_byte(section, n, "dimensions_defined")?;
+        n += 1;
+        let mut dimensions = vec![];
+        for _ in 0..dimensions_defined {
+            let dimension_name = length_prefixed(section, &mut n, "dimension_name_text")?;
+            let flags = error::field_byte(section, n, "values_defined")?;
+            n += 1;
+            let mut values = vec![];
+            for _ in 0..flags & 0x0F {
+                let abbrev_rating_value = length_prefixed(section, &mut n, "abbrev_rating_value_text")?;
+                let rating_value = length_prefixed(section, &mut n, "rating_value_text")?;
+                values.push(RatingValue { abbrev_rating_value, rating_value });
+            }
+            dimensions.push(Dimension {
+                dimension_name,
+                graduated_scale: packet::get_bit_at(flags, 4),
+                values,
+            });
+        }
+        let end = n + 2 + super::length_10(section, n, "descriptors_length")?;
+        let descriptors = Descriptor::parse_loop(section, n + 2, end)?;
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(Rrt {
+            rating_region: buf[4],
+            version_number: (buf[5] & 0x3E) >> 1,
+            protocol_version,
+            rating_region_name,
+            dimensions,
+            descriptors,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+}
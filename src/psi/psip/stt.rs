@@ -0,0 +1,57 @@
+use std::fmt;
+use byteorder::{ByteOrder, BigEndian};
+use super::{PROTOCOL_VERSION_INDEX, STT_TABLE_ID};
+use crate::psi::{self, time::UtcTime, Descriptor};
+use crate::{packet, mpeg32_crc, error::{self, ParseError}};
+
+/// System Time Table: the current GPS time and the offset of UTC from it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Stt {
+    protocol_version: u8,
+    /// Seconds since 1980-01-06 00:00:00 GPS time
+    pub system_time: u32,
+    /// Leap seconds GPS time is ahead of UTC
+    pub gps_utc_offset: u8,
+    pub daylight_saving_status: bool,
+    /// Day of the month (1..=31) and local hour daylight saving time changes at, 0 if not this month
+    pub daylight_saving_day: u8,
+    pub daylight_saving_hour: u8,
+    pub descriptors: Vec<Descriptor>,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for Stt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[STT] Time: {}, GPS-UTC offset: {}s, Daylight saving: {}", self.utc_time(),
+            self.gps_utc_offset, if self.daylight_saving_status { "in effect" } else { "not in effect" })
+    }
+}
+
+impl Stt {
+    /// Parse a section buffer into a Stt object and return Result<Stt, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Stt, ParseError> {
+        // Calculate length and index fields
+        let end_n = psi::check_section(buf, STT_TABLE_ID)?;
+        let header = error::field_bytes(&buf[..end_n], PROTOCOL_VERSION_INDEX, 8, "daylight_saving")?;
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(Stt {
+            protocol_version: header[0],
+            system_time: BigEndian::read_u32(&header[1..5]),
+            gps_utc_offset: header[5],
+            daylight_saving_status: packet::get_bit_at(header[6], 7),
+            daylight_saving_day: header[6] & 0x1F,
+            daylight_saving_hour: header[7],
+            descriptors: Descriptor::parse_loop(buf, PROTOCOL_VERSION_INDEX + 8, end_n)?,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+
+    /// The system time in UTC
+    pub fn utc_time(&self) -> UtcTime {
+        UtcTime::from_gps(self.system_time, self.gps_utc_offset)
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+use crate::error::{self, ParseError};
+
+// Constants
+/// Mode selecting UTF-16 for the whole segment
+const MODE_UTF16: u8 = 0x3F;
+/// Highest mode selecting a page of the Unicode BMP for each byte
+const MODE_LAST_PAGE: u8 = 0x33;
+
+/// A multiple_string_structure (ATSC A/65 6.10): the same text in one or more languages
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MultipleString {
+    /// (ISO 639 language, text) pairs
+    pub strings: Vec<(String, String)>,
+}
+
+impl fmt::Display for MultipleString {
+    /// Display the first string
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.strings.first().map_or("", |(_, text)| text.as_str()))
+    }
+}
+
+impl MultipleString {
+    /// Parse a multiple_string_structure filling `buf`.
+    ///
+    /// Only uncompressed segments are decoded; the Huffman compressed ones
+    /// (A/65 Annex C) are left out of the text
+    pub fn new(buf: &[u8]) -> Result<MultipleString, ParseError> {
+        let mut strings = vec![];
+        if buf.is_empty() {
+            return Ok(MultipleString { strings });
+        }
+        let mut n = 1;
+        for _ in 0..buf[0] {
+            let header = error::field_bytes(buf, n, 4, "number_segments")?;
+            let language = String::from_utf8_lossy(&header[0..3]).into_owned();
+            n += 4;
+            let mut text = String::new();
+            for _ in 0..header[3] {
+                let segment = error::field_bytes(buf, n, 3, "number_bytes")?;
+                let (compression_type, mode) = (segment[0], segment[1]);
+                let bytes = error::field_bytes(buf, n + 3, segment[2] as usize, "compressed_string_byte")?;
+                if compression_type == 0 {
+                    text.push_str(&decode_segment(mode, bytes));
+                }
+                n += 3 + bytes.len();
+            }
+            strings.push((language, text));
+        }
+        Ok(MultipleString { strings })
+    }
+
+    /// The text in `language` (ISO 639), if there is one
+    pub fn get(&self, language: &str) -> Option<&str> {
+        self.strings.iter().find(|(l, _)| l == language).map(|(_, text)| text.as_str())
+    }
+}
+
+/// Decode an uncompressed segment: the mode is either UTF-16 or the upper byte
+/// of the Unicode characters the bytes stand for
+fn decode_segment(mode: u8, bytes: &[u8]) -> String {
+    match mode {
+        MODE_UTF16 => {
+            let units = bytes.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+            char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+        },
+        0x00..=MODE_LAST_PAGE => bytes.iter()
+            .filter_map(|&b| char::from_u32(u32::from(mode) << 8 | u32::from(b)))
+            .collect(),
+        _ => String::new(),
+    }
+}
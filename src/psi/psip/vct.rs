@@ -0,0 +1,200 @@
+use std::fmt;
+use std::fmt::Write;
+use byteorder::{ByteOrder, BigEndian};
+use super::{text::MultipleString, PROTOCOL_VERSION_INDEX, TVCT_TABLE_ID, CVCT_TABLE_ID};
+use crate::psi::{self, Descriptor};
+use crate::{packet, mpeg32_crc, error::{self, ParseError}};
+
+// Constants
+const EXTENDED_CHANNEL_NAME_DESCRIPTOR_TAG: u8 = 0xA0;
+/// Size of a channel up to its descriptors
+const CHANNEL_SIZE: usize = 32;
+
+/// A virtual channel of a TVCT or CVCT
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Channel {
+    pub short_name: String,
+    pub major_channel_number: u16,
+    pub minor_channel_number: u16,
+    pub modulation_mode: u8,
+    pub carrier_frequency: u32,
+    pub channel_tsid: u16,
+    pub program_number: u16,
+    pub etm_location: u8,
+    pub access_controlled: bool,
+    pub hidden: bool,
+    /// Only meaningful in a CVCT
+    pub path_select: bool,
+    /// Only meaningful in a CVCT
+    pub out_of_band: bool,
+    pub hide_guide: bool,
+    pub service_type: u8,
+    pub source_id: u16,
+    pub descriptors: Vec<Descriptor>,
+    /// From the extended_channel_name_descriptor, if there is one
+    pub long_name: Option<MultipleString>,
+}
+
+impl Channel {
+    /// Parse the channel starting at `buf[n]` and return it along with its size
+    fn new(buf: &[u8], n: usize) -> Result<(Channel, usize), ParseError> {
+        let header = error::field_bytes(buf, n, CHANNEL_SIZE, "descriptors_length")?;
+        let end = n + CHANNEL_SIZE + super::length_10(header, 30, "descriptors_length")?;
+
+        // The short name is 7 UTF-16 code units, padded with nulls
+        let units = header[0..14].chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+        let short_name: String = char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .take_while(|c| *c != '\0')
+            .collect();
+        let mut long_name = None;
+        for (tag, body) in psi::descriptor_bodies(buf, n + CHANNEL_SIZE, end)? {
+            if tag == EXTENDED_CHANNEL_NAME_DESCRIPTOR_TAG {
+                long_name = Some(MultipleString::new(body)?);
+            }
+        }
+        let channel = Channel {
+            short_name,
+            major_channel_number: (u16::from(header[14] & 0x0F) << 6) | u16::from(header[15] >> 2),
+            minor_channel_number: (u16::from(header[15] & 0x03) << 8) | u16::from(header[16]),
+            modulation_mode: header[17],
+            carrier_frequency: BigEndian::read_u32(&header[18..22]),
+            channel_tsid: BigEndian::read_u16(&header[22..24]),
+            program_number: BigEndian::read_u16(&header[24..26]),
+            etm_location: header[26] >> 6,
+            access_controlled: packet::get_bit_at(header[26], 5),
+            hidden: packet::get_bit_at(header[26], 4),
+            path_select: packet::get_bit_at(header[26], 3),
+            out_of_band: packet::get_bit_at(header[26], 2),
+            hide_guide: packet::get_bit_at(header[26], 1),
+            service_type: header[27] & 0x3F,
+            source_id: BigEndian::read_u16(&header[28..30]),
+            descriptors: Descriptor::parse_loop(buf, n + CHANNEL_SIZE, end)?,
+            long_name,
+        };
+        Ok((channel, end - n))
+    }
+
+    /// The channel number as major.minor (or just the number of a one-part channel)
+    pub fn channel_number(&self) -> String {
+        // A major number with its upper 6 bits set (1008 and up) marks a one-part channel number
+        if self.major_channel_number & 0x3F0 == 0x3F0 {
+            let number = (u32::from(self.major_channel_number & 0x0F) << 10) | u32::from(self.minor_channel_number);
+            return number.to_string();
+        }
+        format!("{}.{}", self.major_channel_number, self.minor_channel_number)
+    }
+
+    /// What the service_type (ATSC A/65 table 6.7) says the channel is
+    pub fn service_type_name(&self) -> &'static str {
+        match self.service_type {
+            0x01 => "Analog television",
+            0x02 => "ATSC digital television",
+            0x03 => "ATSC audio",
+            0x04 => "ATSC data only service",
+            0x05 => "ATSC software download service",
+            0x06 => "Unassociated/small screen service",
+            0x07 => "Parameterized service",
+            0x08 => "ATSC NRT service",
+            0x09 => "Extended parameterized service",
+            _ => "Reserved",
+        }
+    }
+
+    /// What the modulation_mode (ATSC A/65 table 6.5) says the carrier is
+    pub fn modulation_mode_name(&self) -> &'static str {
+        match self.modulation_mode {
+            0x01 => "Analog",
+            0x02 => "SCTE mode 1 (64-QAM)",
+            0x03 => "SCTE mode 2 (256-QAM)",
+            0x04 => "8-VSB",
+            0x05 => "16-VSB",
+            0x80..=0xFF => "User private",
+            _ => "Reserved",
+        }
+    }
+}
+
+/// Terrestrial or Cable Virtual Channel Table: the channel numbers and names of the programs
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Vct {
+    table_id: u8,
+    transport_stream_id: u16,
+    version_number: u8,
+    current_next_indicator: bool,
+    section_number: u8,
+    last_section_number: u8,
+    protocol_version: u8,
+    pub channels: Vec<Channel>,
+    pub descriptors: Vec<Descriptor>,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for Vct {
+    /// Display the vct along with its channels
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut channel_str = String::new();
+        for c in &self.channels {
+            write!(&mut channel_str,
+                "\n\t=> Channel: {}, Name: {}, Program: {}, Source ID: {:#X}, Type: {:#X} ({}), Modulation: {}{}{}",
+                c.channel_number(), c.short_name, c.program_number, c.source_id, c.service_type,
+                c.service_type_name(), c.modulation_mode_name(),
+                if c.access_controlled { ", Scrambled" } else { "" },
+                if c.hidden { ", Hidden" } else { "" }).unwrap();
+        }
+        write!(f, "[{}] Transport Stream ID: {2:#X} ({2}), Version: {3:#X}{1}",
+            if self.is_cable() { "CVCT" } else { "TVCT" }, channel_str,
+            self.transport_stream_id, self.version_number)
+    }
+}
+
+impl Vct {
+    /// Parse a section buffer into a Vct object and return Result<Vct, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Vct, ParseError> {
+        // Calculate length and index fields
+        let table_id = match buf.first() {
+            Some(&CVCT_TABLE_ID) => CVCT_TABLE_ID,
+            _ => TVCT_TABLE_ID,
+        };
+        let end_n = psi::check_section(buf, table_id)?;
+        let section = &buf[..end_n];
+        let header = error::field_bytes(section, PROTOCOL_VERSION_INDEX, 2, "num_channels_in_section")?;
+
+        // Get the channels
+        let mut n = 10;
+        let mut channels = vec![];
+        for _ in 0..header[1] {
+            let (channel, size) = Channel::new(section, n)?;
+            channels.push(channel);
+            n += size;
+        }
+        let end = n + 2 + super::length_10(section, n, "additional_descriptors_length")?;
+        let descriptors = Descriptor::parse_loop(section, n + 2, end)?;
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(Vct {
+            table_id,
+            transport_stream_id: BigEndian::read_u16(&buf[3..5]),
+            version_number: (buf[5] & 0x3E) >> 1,
+            current_next_indicator: packet::get_bit_at(buf[5], 0),
+            section_number: buf[6],
+            last_section_number: buf[7],
+            protocol_version: header[0],
+            channels,
+            descriptors,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+
+    /// Whether this is a CVCT rather than a TVCT
+    pub fn is_cable(&self) -> bool {
+        self.table_id == CVCT_TABLE_ID
+    }
+
+    pub fn get_transport_stream_id(&self) -> u16 {
+        self.transport_stream_id
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt;
+use std::fmt::Write;
+use byteorder::{ByteOrder, BigEndian};
+use super::{text, Descriptor};
+use crate::{packet, mpeg32_crc, error::{self, ParseError}};
+
+// Constants
+pub const SDT_ACTUAL_TABLE_ID: u8 = 0x42;
+pub const SDT_OTHER_TABLE_ID: u8 = 0x46;
+const SERVICE_DESCRIPTOR_TAG: u8 = 0x48;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Service {
+    pub service_id: u16,
+    pub eit_schedule_flag: bool,
+    pub eit_present_following_flag: bool,
+    pub running_status: u8,
+    pub free_ca_mode: bool,
+    pub descriptors: Vec<Descriptor>,
+    /// From the service_descriptor, if there is one
+    pub service_type: Option<u8>,
+    pub provider_name: Option<String>,
+    pub service_name: Option<String>,
+}
+
+impl Service {
+    /// Parse the service starting at `buf[n]` and return it along with its size
+    fn new(buf: &[u8], n: usize) -> Result<(Service, usize), ParseError> {
+        let header = error::field_bytes(buf, n, 5, "descriptors_loop_length")?;
+        let end = n + 5 + super::loop_length(header, 3, "descriptors_loop_length")?;
+
+        let mut service = Service {
+            service_id: BigEndian::read_u16(&header[0..2]),
+            eit_schedule_flag: packet::get_bit_at(header[2], 1),
+            eit_present_following_flag: packet::get_bit_at(header[2], 0),
+            running_status: header[3] >> 5,
+            free_ca_mode: packet::get_bit_at(header[3], 4),
+            descriptors: Descriptor::parse_loop(buf, n + 5, end)?,
+            service_type: None,
+            provider_name: None,
+            service_name: None,
+        };
+        for (tag, body) in super::descriptor_bodies(buf, n + 5, end)? {
+            if tag != SERVICE_DESCRIPTOR_TAG {
+                continue;
+            }
+            // service_type, then the provider and service names, each preceded by its length
+            let provider_end = 2 + *body.get(1).unwrap_or(&0) as usize;
+            let name_end = provider_end + 1 + *body.get(provider_end).unwrap_or(&0) as usize;
+            // A short one is skipped (its bytes are still in `descriptors`)
+            if body.len() < name_end {
+                continue;
+            }
+            service.service_type = Some(body[0]);
+            service.provider_name = Some(text::decode(&body[2..provider_end]));
+            service.service_name = Some(text::decode(&body[provider_end + 1..name_end]));
+        }
+        Ok((service, end - n))
+    }
+
+    pub fn running_status_name(&self) -> &'static str {
+        match self.running_status {
+            0 => "Undefined",
+            1 => "Not running",
+            2 => "Starts in a few seconds",
+            3 => "Pausing",
+            4 => "Running",
+            5 => "Service off-air",
+            _ => "Reserved",
+        }
+    }
+
+    /// What the service_type (ETSI EN 300 468 table 87) says the service is
+    pub fn service_type_name(&self) -> &'static str {
+        match self.service_type {
+            None => "Unknown",
+            Some(0x01) => "Digital television",
+            Some(0x02) => "Digital radio sound",
+            Some(0x03) => "Teletext",
+            Some(0x04) => "NVOD reference",
+            Some(0x05) => "NVOD time-shifted",
+            Some(0x06) => "Mosaic",
+            Some(0x07) => "FM radio",
+            Some(0x0A) => "Advanced codec digital radio sound",
+            Some(0x0C) => "Data broadcast",
+            Some(0x10) => "DVB MHP",
+            Some(0x11) => "MPEG-2 HD digital television",
+            Some(0x16) => "Advanced codec SD digital television",
+            Some(0x19) => "Advanced codec HD digital television",
+            Some(0x1F) => "HEVC digital television",
+            Some(0x20) => "HEVC UHD digital television",
+            Some(0x80..=0xFE) => "User defined",
+            Some(_) => "Reserved",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sdt {
+    table_id: u8,
+    transport_stream_id: u16,
+    version_number: u8,
+    current_next_indicator: bool,
+    section_number: u8,
+    last_section_number: u8,
+    original_network_id: u16,
+    pub services: Vec<Service>,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for Sdt {
+    /// Display the sdt along with the names of its services
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut service_str = String::new();
+        for s in &self.services {
+            write!(&mut service_str,
+                "\n\t=> Service ID: {0:#X} ({0}), Name: {1}, Provider: {2}, Type: {3:#X} ({4}), {5}{6}",
+                s.service_id, s.service_name.as_deref().unwrap_or("-"),
+                s.provider_name.as_deref().unwrap_or("-"), s.service_type.unwrap_or(0),
+                s.service_type_name(), s.running_status_name(),
+                if s.free_ca_mode { ", Scrambled" } else { "" }).unwrap();
+        }
+        write!(f, "[SDT] Transport Stream ID: {0:#X} ({0}){1}, Original Network ID: {2:#X} ({2}), Version: {3:#X}{4}",
+            self.transport_stream_id, if self.is_actual() { "" } else { " (other)" },
+            self.original_network_id, self.version_number, service_str)
+    }
+}
+
+impl Sdt {
+    /// Parse a section buffer into a Sdt object and return Result<Sdt, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Sdt, ParseError> {
+        // Calculate length and index fields
+        let table_id = match buf.first() {
+            Some(&SDT_OTHER_TABLE_ID) => SDT_OTHER_TABLE_ID,
+            _ => SDT_ACTUAL_TABLE_ID,
+        };
+        let end_n = super::check_section(buf, table_id)?;
+        let header = error::field_bytes(&buf[..end_n], 8, 3, "original_network_id")?;
+        let original_network_id = BigEndian::read_u16(&header[0..2]);
+
+        // Get the services
+        let mut n = 11;
+        let mut services = vec![];
+        while n < end_n {
+            let (service, size) = Service::new(&buf[..end_n], n)?;
+            services.push(service);
+            n += size;
+        }
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(Sdt {
+            table_id,
+            transport_stream_id: BigEndian::read_u16(&buf[3..5]),
+            version_number: (buf[5] & 0x3E) >> 1,
+            current_next_indicator: packet::get_bit_at(buf[5], 0),
+            section_number: buf[6],
+            last_section_number: buf[7],
+            original_network_id,
+            services,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+
+    /// Whether this describes the services of this transport stream (not another one)
+    pub fn is_actual(&self) -> bool {
+        self.table_id == SDT_ACTUAL_TABLE_ID
+    }
+
+    pub fn get_transport_stream_id(&self) -> u16 {
+        self.transport_stream_id
+    }
+
+    pub fn get_original_network_id(&self) -> u16 {
+        self.original_network_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// A service entry with the EIT flags set, running and scrambled
+    fn service(service_id: u16, descriptors: &[u8]) -> Vec<u8> {
+        let mut buf = service_id.to_be_bytes().to_vec();
+        buf.extend_from_slice(&[0xFF, 0x90 | (descriptors.len() >> 8) as u8, descriptors.len() as u8]);
+        buf.extend_from_slice(descriptors);
+        buf
+    }
+
+    /// An SDT of transport stream 1 and original network 2
+    fn sdt(table_id: u8, services: &[u8]) -> Vec<u8> {
+        let mut body = vec![0x00, 0x02, 0xFF];
+        body.extend_from_slice(services);
+        test_util::section(table_id, 0x0001, &body)
+    }
+
+    #[test]
+    fn services() {
+        let mut services = service(1, &test_util::descriptor(SERVICE_DESCRIPTOR_TAG, b"\x19\x03Pro\x04Chan"));
+        // The names run past the end of the service_descriptor
+        services.extend(service(2, &test_util::descriptor(SERVICE_DESCRIPTOR_TAG, b"\x01\x03Pro\x09Ch")));
+        let sdt = Sdt::new(&sdt(SDT_ACTUAL_TABLE_ID, &services)).unwrap();
+        assert!(sdt.is_actual() && !sdt.crc_error);
+        assert_eq!((sdt.get_transport_stream_id(), sdt.get_original_network_id()), (1, 2));
+        assert_eq!(sdt.services.len(), 2);
+
+        let s = &sdt.services[0];
+        assert_eq!(s.service_id, 1);
+        assert!(s.eit_schedule_flag && s.eit_present_following_flag && s.free_ca_mode);
+        assert_eq!(s.running_status_name(), "Running");
+        assert_eq!(s.service_type_name(), "Advanced codec HD digital television");
+        assert_eq!(s.provider_name.as_deref(), Some("Pro"));
+        assert_eq!(s.service_name.as_deref(), Some("Chan"));
+
+        // The malformed service_descriptor is skipped, not the service
+        let s = &sdt.services[1];
+        assert_eq!(s.service_id, 2);
+        assert_eq!((s.service_type, s.service_name.as_deref()), (None, None));
+        assert_eq!(s.descriptors.len(), 1);
+    }
+
+    #[test]
+    fn names_in_other_character_tables() {
+        // A provider name in ISO 8859-5 and a service name in UTF-8
+        let services = service(7, &test_util::descriptor(SERVICE_DESCRIPTOR_TAG, b"\x01\x04\x01\xB4\xDE\xDC\x06\x15Caf\xC3\xA9"));
+        let sdt = Sdt::new(&sdt(SDT_OTHER_TABLE_ID, &services)).unwrap();
+        assert!(!sdt.is_actual());
+        assert_eq!(sdt.services[0].provider_name.as_deref(), Some("Дом"));
+        assert_eq!(sdt.services[0].service_name.as_deref(), Some("Café"));
+        assert_eq!(sdt.to_string(), "[SDT] Transport Stream ID: 0x1 (1) (other), Original Network ID: 0x2 (2), Version: 0x0\
+            \n\t=> Service ID: 0x7 (7), Name: Café, Provider: Дом, Type: 0x1 (Digital television), Running, Scrambled");
+    }
+
+    #[test]
+    fn invalid_sections() {
+        let buf = sdt(SDT_ACTUAL_TABLE_ID, &service(1, &[]));
+        for len in 0..buf.len() {
+            assert!(Sdt::new(&buf[..len]).is_err(), "{} bytes", len);
+        }
+        let mut nit = buf.clone();
+        nit[0] = 0x40;
+        assert_eq!(Sdt::new(&nit), Err(ParseError::TableId { pid: None, expected: SDT_ACTUAL_TABLE_ID, found: 0x40 }));
+        // A service's descriptors running into the CRC
+        let mut descriptors_overrun = buf.clone();
+        descriptors_overrun[15] = 0x04;
+        assert_eq!(Sdt::new(&descriptors_overrun), Err(ParseError::Truncated { pid: None, offset: 0, field: "descriptors" }));
+        // A service entry cut short by the end of the section
+        let mut body = vec![0x00, 0x02, 0xFF];
+        body.extend_from_slice(&[0x00, 0x01, 0xFF]);
+        assert_eq!(Sdt::new(&test_util::section(SDT_ACTUAL_TABLE_ID, 1, &body)),
+            Err(ParseError::Truncated { pid: None, offset: 11, field: "descriptors_loop_length" }));
+    }
+}
@@ -0,0 +1,181 @@
+use byteorder::{ByteOrder, BigEndian};
+use crate::packet::Packet;
+
+/// Largest possible section (3 header bytes + 12 bit section_length)
+const MAX_SECTION_SIZE: usize = 4096;
+/// table_id value used to stuff the rest of a packet after the last section
+const STUFFING_TABLE_ID: u8 = 0xFF;
+
+/// Reassembles the PSI sections carried on a single PID.
+///
+/// Sections may span several transport packets and one packet may hold the end of
+/// a section followed by any number of new ones. The start of the first new section
+/// in a packet is given by the pointer_field, which is only present when the
+/// payload_unit_start_indicator is set.
+#[derive(Clone, Debug, Default)]
+pub struct SectionAssembler {
+    buf: Vec<u8>,
+    /// Whether `buf` holds the start of a section (we've seen a unit start)
+    synced: bool,
+}
+
+impl SectionAssembler {
+    /// Feed the payload of a packet and return all the sections it completes
+    pub fn push(&mut self, packet: &Packet) -> Vec<Vec<u8>> {
+        let mut sections: Vec<Vec<u8>> = vec![];
+        let mut data = packet.payload.as_slice();
+        if data.is_empty() {
+            return sections;
+        }
+
+        if packet.payload_unit_start_indicator {
+            let pointer_field = data[0] as usize;
+            let split = (pointer_field + 1).min(data.len());
+            // Bytes before the pointed to position finish the previous section
+            if self.synced {
+                self.buf.extend_from_slice(&data[1..split]);
+                self.drain(&mut sections);
+            }
+            self.buf.clear();
+            self.synced = true;
+            data = &data[split..];
+        } else if !self.synced {
+            // Wait for the start of a section
+            return sections;
+        }
+
+        self.buf.extend_from_slice(data);
+        self.drain(&mut sections);
+        sections
+    }
+
+    /// Discard any partially assembled section (e.g. after a lost packet)
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.synced = false;
+    }
+
+    /// Move every complete section from the buffer into `sections`
+    fn drain(&mut self, sections: &mut Vec<Vec<u8>>) {
+        while self.synced && !self.buf.is_empty() {
+            // The rest of the packet is stuffing; the next section starts with a new unit
+            if self.buf[0] == STUFFING_TABLE_ID {
+                self.reset();
+                break;
+            }
+            if self.buf.len() < 3 {
+                break;
+            }
+            let section_size = section_size(&self.buf);
+            if section_size > MAX_SECTION_SIZE {
+                self.reset();
+                break;
+            }
+            if self.buf.len() < section_size {
+                break;
+            }
+            sections.push(self.buf.drain(..section_size).collect());
+        }
+    }
+}
+
+/// Total size of a section (header included) according to its section_length field
+fn section_size(buf: &[u8]) -> usize {
+    3 + ((usize::from(buf[1] & 0x0F) << 8) | usize::from(buf[2]))
+}
+
+/// Identify a section by its table_id, table_id_extension and section_number
+pub fn section_key(buf: &[u8]) -> (u8, u16, u8) {
+    if buf.len() < 8 {
+        return (buf.first().copied().unwrap_or(0), 0, 0);
+    }
+    (buf[0], BigEndian::read_u16(&buf[3..5]), buf[6])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn push(assembler: &mut SectionAssembler, unit_start: bool, payload: &[u8]) -> Vec<Vec<u8>> {
+        let packet = Packet::new(&test_util::packet(0x100, unit_start, 0, None, payload)).unwrap();
+        assembler.push(&packet)
+    }
+
+    #[test]
+    fn sections_across_packets() {
+        let section = test_util::section(0x02, 1, &[0xAB; 300]);
+        let mut assembler = SectionAssembler::default();
+        let mut first = vec![0];
+        first.extend_from_slice(&section[..183]);
+        assert!(push(&mut assembler, true, &first).is_empty());
+        // The end of the section, then a new one and stuffing
+        let next = test_util::section(0x02, 2, &[]);
+        let mut second = vec![(section.len() - 183) as u8];
+        second.extend_from_slice(&section[183..]);
+        second.extend_from_slice(&next);
+        assert_eq!(push(&mut assembler, true, &second), vec![section, next]);
+    }
+
+    #[test]
+    fn several_sections_in_one_packet() {
+        let sections: Vec<_> = (0..3).map(|i| test_util::section(0x42, i, &[i as u8; 10])).collect();
+        let mut payload = vec![0];
+        sections.iter().for_each(|s| payload.extend_from_slice(s));
+        let mut assembler = SectionAssembler::default();
+        assert_eq!(push(&mut assembler, true, &payload), sections);
+    }
+
+    #[test]
+    fn waits_for_a_unit_start() {
+        let mut assembler = SectionAssembler::default();
+        let section = test_util::section(0x00, 1, &[0, 1, 0xE1, 0x00]);
+        assert!(push(&mut assembler, false, &section).is_empty());
+        // A pointer_field skipping the end of a section that was never started
+        let mut payload = vec![3, 1, 2, 3];
+        payload.extend_from_slice(&section);
+        assert_eq!(push(&mut assembler, true, &payload), vec![section]);
+    }
+
+    #[test]
+    fn lost_packet() {
+        let section = test_util::section(0x02, 1, &[0xAB; 300]);
+        let mut assembler = SectionAssembler::default();
+        let mut first = vec![0];
+        first.extend_from_slice(&section[..183]);
+        push(&mut assembler, true, &first);
+        assembler.reset();
+        assert!(push(&mut assembler, false, &section[183..]).is_empty());
+    }
+
+    #[test]
+    fn oversized_section_length() {
+        let mut assembler = SectionAssembler::default();
+        // section_length 0xFFF is past the 4096 byte maximum
+        let mut payload = vec![0, 0x02, 0xBF, 0xFF];
+        payload.extend_from_slice(&[0; 50]);
+        assert!(push(&mut assembler, true, &payload).is_empty());
+        // The bytes that follow aren't taken for a section until the next unit start
+        assert!(push(&mut assembler, false, &test_util::section(0x02, 1, &[])).is_empty());
+        let section = test_util::section(0x02, 1, &[]);
+        let mut payload = vec![0];
+        payload.extend_from_slice(&section);
+        assert_eq!(push(&mut assembler, true, &payload), vec![section]);
+    }
+
+    #[test]
+    fn hostile_payloads() {
+        let mut valid = vec![0];
+        valid.extend(test_util::section(0x02, 1, &[0xAB; 20]));
+        test_util::hostile_inputs(&valid, |payload| {
+            let mut assembler = SectionAssembler::default();
+            let payload = &payload[..payload.len().min(184)];
+            for unit_start in [true, false] {
+                for section in push(&mut assembler, unit_start, payload) {
+                    assert!(section.len() <= MAX_SECTION_SIZE);
+                    let _ = section_key(&section);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,237 @@
+use std::fmt;
+use std::fmt::Write;
+use byteorder::{ByteOrder, BigEndian};
+use super::{time::{self, UtcTime}, Descriptor};
+use crate::{packet, mpeg32_crc, error::{self, ParseError}};
+
+// Constants
+pub const TDT_TABLE_ID: u8 = 0x70;
+pub const TOT_TABLE_ID: u8 = 0x73;
+const LOCAL_TIME_OFFSET_DESCRIPTOR_TAG: u8 = 0x58;
+
+/// Read the UTC_time of a TDT or TOT
+fn utc_time(buf: &[u8]) -> Result<UtcTime, ParseError> {
+    let field = error::field_bytes(buf, 3, 5, "UTC_time")?;
+    UtcTime::from_mjd_bcd(field).ok_or(ParseError::InvalidValue {
+        pid: None, offset: 3, field: "UTC_time", value: BigEndian::read_u32(&field[1..5]),
+    })
+}
+
+/// Parse 4 BCD digits hhmm into minutes
+fn bcd_hm(buf: &[u8]) -> Option<i32> {
+    time::bcd_hms(&[buf[0], buf[1], 0]).map(|s| s as i32 / 60)
+}
+
+/// Format an offset in minutes as +hh:mm
+fn format_offset(minutes: i32) -> String {
+    format!("{}{:02}:{:02}", if minutes < 0 { '-' } else { '+' }, minutes.abs() / 60, minutes.abs() % 60)
+}
+
+/// An entry of a local_time_offset_descriptor
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LocalTimeOffset {
+    pub country_code: String,
+    pub country_region_id: u8,
+    /// Offset of local time from UTC in minutes
+    pub local_time_offset: i32,
+    /// When the offset changes to `next_time_offset` (e.g. daylight saving time)
+    pub time_of_change: Option<UtcTime>,
+    pub next_time_offset: i32,
+}
+
+impl fmt::Display for LocalTimeOffset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Country: {}, Region: {}, Offset: {}", self.country_code, self.country_region_id,
+            format_offset(self.local_time_offset))?;
+        if let Some(change) = self.time_of_change {
+            write!(f, ", Changes to {} at {}", format_offset(self.next_time_offset), change)?;
+        }
+        Ok(())
+    }
+}
+
+impl LocalTimeOffset {
+    /// Parse the entries of a local_time_offset_descriptor body
+    fn parse_loop(body: &[u8]) -> Vec<LocalTimeOffset> {
+        body.chunks_exact(13)
+            .filter_map(|b| {
+                let sign = if packet::get_bit_at(b[3], 0) { -1 } else { 1 };
+                Some(LocalTimeOffset {
+                    country_code: String::from_utf8_lossy(&b[0..3]).into_owned(),
+                    country_region_id: b[3] >> 2,
+                    local_time_offset: sign * bcd_hm(&b[4..6])?,
+                    time_of_change: UtcTime::from_mjd_bcd(&b[6..11]),
+                    next_time_offset: sign * bcd_hm(&b[11..13])?,
+                })
+            })
+            .collect()
+    }
+
+    /// The local time at `utc`, taking the time of change into account
+    pub fn local_time(&self, utc: UtcTime) -> UtcTime {
+        let offset = match self.time_of_change {
+            Some(change) if utc >= change => self.next_time_offset,
+            _ => self.local_time_offset,
+        };
+        utc.add_seconds(i64::from(offset) * 60)
+    }
+}
+
+/// Time and Date Table: the current UTC time
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tdt {
+    pub utc_time: UtcTime,
+}
+
+impl fmt::Display for Tdt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[TDT] Time: {}", self.utc_time)
+    }
+}
+
+impl Tdt {
+    /// Parse a section buffer into a Tdt object and return Result<Tdt, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Tdt, ParseError> {
+        let found = error::field_byte(buf, 0, "table_id")?;
+        if found != TDT_TABLE_ID {
+            return Err(ParseError::TableId { pid: None, expected: TDT_TABLE_ID, found });
+        }
+        Ok(Tdt { utc_time: utc_time(buf)? })
+    }
+}
+
+/// Time Offset Table: the current UTC time along with the offsets of local time
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tot {
+    pub utc_time: UtcTime,
+    pub descriptors: Vec<Descriptor>,
+    /// From the local_time_offset_descriptors
+    pub local_time_offsets: Vec<LocalTimeOffset>,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for Tot {
+    /// Display the tot along with its local time offsets
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut offset_str = String::new();
+        for o in &self.local_time_offsets {
+            write!(&mut offset_str, "\n\t=> {}", o).unwrap();
+        }
+        write!(f, "[TOT] Time: {}{}", self.utc_time, offset_str)
+    }
+}
+
+impl Tot {
+    /// Parse a section buffer into a Tot object and return Result<Tot, ParseError>
+    pub fn new(buf: &[u8]) -> Result<Tot, ParseError> {
+        // Calculate length and index fields
+        let end_n = super::check_section(buf, TOT_TABLE_ID)?;
+        let utc_time = utc_time(buf)?;
+        let header = error::field_bytes(&buf[..end_n], 8, 2, "descriptors_loop_length")?;
+        let end = 10 + super::loop_length(header, 0, "descriptors_loop_length")?;
+
+        let mut local_time_offsets = vec![];
+        for (tag, body) in super::descriptor_bodies(&buf[..end_n], 10, end)? {
+            if tag == LOCAL_TIME_OFFSET_DESCRIPTOR_TAG {
+                local_time_offsets.extend(LocalTimeOffset::parse_loop(body));
+            }
+        }
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(Tot {
+            utc_time,
+            descriptors: Descriptor::parse_loop(buf, 10, end)?,
+            local_time_offsets,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// A TOT at 2024-01-15 12:34:56 with a local_time_offset_descriptor holding `offsets`
+    fn tot(offsets: &[u8]) -> Vec<u8> {
+        let descriptors = test_util::descriptor(LOCAL_TIME_OFFSET_DESCRIPTOR_TAG, offsets);
+        let section_length = 5 + 2 + descriptors.len() + 4;
+        let mut buf = vec![TOT_TABLE_ID, 0x70 | (section_length >> 8) as u8, section_length as u8,
+            0xEB, 0xA4, 0x12, 0x34, 0x56, 0xF0, descriptors.len() as u8];
+        buf.extend(descriptors);
+        test_util::with_crc(buf)
+    }
+
+    fn utc(mjd_bcd: &[u8]) -> UtcTime {
+        UtcTime::from_mjd_bcd(mjd_bcd).unwrap()
+    }
+
+    #[test]
+    fn tdt() {
+        let tdt = Tdt::new(&[TDT_TABLE_ID, 0x70, 0x05, 0xEB, 0xA4, 0x12, 0x34, 0x56]).unwrap();
+        assert_eq!(tdt.to_string(), "[TDT] Time: 2024-01-15 12:34:56 UTC");
+        assert_eq!(Tdt::new(&[TDT_TABLE_ID, 0x70, 0x05, 0xEB, 0xA4, 0x12, 0x3A, 0x56]),
+            Err(ParseError::InvalidValue { pid: None, offset: 3, field: "UTC_time", value: 0xA412_3A56 }));
+        assert_eq!(Tdt::new(&[TOT_TABLE_ID, 0x70, 0x05, 0xEB, 0xA4, 0x12, 0x34, 0x56]),
+            Err(ParseError::TableId { pid: None, expected: TDT_TABLE_ID, found: TOT_TABLE_ID }));
+        assert_eq!(Tdt::new(&[TDT_TABLE_ID, 0x70, 0x05, 0xEB, 0xA4, 0x12, 0x34]),
+            Err(ParseError::Truncated { pid: None, offset: 3, field: "UTC_time" }));
+    }
+
+    #[test]
+    fn tot_local_time_offsets() {
+        // GBR region 0: +00:00, changing to +01:00 at 2024-03-31 01:00:00
+        let tot = Tot::new(&tot(b"GBR\x02\x00\x00\xEB\xF0\x01\x00\x00\x01\x00")).unwrap();
+        assert!(!tot.crc_error);
+        assert_eq!(tot.descriptors.len(), 1);
+        assert_eq!(tot.to_string(), "[TOT] Time: 2024-01-15 12:34:56 UTC\
+            \n\t=> Country: GBR, Region: 0, Offset: +00:00, Changes to +01:00 at 2024-03-31 01:00:00 UTC");
+        let offset = &tot.local_time_offsets[0];
+        assert_eq!(offset.local_time(tot.utc_time), tot.utc_time);
+        assert_eq!(offset.local_time(utc(b"\xEB\xF0\x00\x59\x59")).to_string(), "2024-03-31 00:59:59 UTC");
+        assert_eq!(offset.local_time(utc(b"\xEB\xF0\x01\x00\x00")).to_string(), "2024-03-31 02:00:00 UTC");
+    }
+
+    #[test]
+    fn negative_offsets() {
+        // USA region 1: -05:00, changing to -04:00 at 2024-03-10 07:00:00, then a
+        // region with an undefined time of change
+        let tot = Tot::new(&tot(b"USA\x07\x05\x00\xEB\xDB\x07\x00\x00\x04\x00\
+            USA\x0B\x08\x00\xFF\xFF\xFF\xFF\xFF\x08\x00")).unwrap();
+        let offsets = &tot.local_time_offsets;
+        assert_eq!(offsets.iter().map(|o| o.to_string()).collect::<Vec<_>>(), vec![
+            "Country: USA, Region: 1, Offset: -05:00, Changes to -04:00 at 2024-03-10 07:00:00 UTC",
+            "Country: USA, Region: 2, Offset: -08:00",
+        ]);
+        assert_eq!((offsets[0].local_time_offset, offsets[0].next_time_offset), (-300, -240));
+        // Going back across midnight, and the hour skipped at the change
+        assert_eq!(offsets[0].local_time(tot.utc_time).to_string(), "2024-01-15 07:34:56 UTC");
+        assert_eq!(offsets[0].local_time(utc(b"\xEB\xDB\x03\x00\x00")).to_string(), "2024-03-09 22:00:00 UTC");
+        assert_eq!(offsets[0].local_time(utc(b"\xEB\xDB\x06\x59\x59")).to_string(), "2024-03-10 01:59:59 UTC");
+        assert_eq!(offsets[0].local_time(utc(b"\xEB\xDB\x07\x00\x00")).to_string(), "2024-03-10 03:00:00 UTC");
+        assert_eq!(offsets[1].time_of_change, None);
+        assert_eq!(offsets[1].local_time(utc(b"\xEB\xDB\x07\x00\x00")).to_string(), "2024-03-09 23:00:00 UTC");
+    }
+
+    #[test]
+    fn invalid_sections() {
+        // An entry with an offset that isn't BCD is dropped, and so is the incomplete one after it
+        let dropped = Tot::new(&tot(b"FRA\x02\x01\x0A\xFF\xFF\xFF\xFF\xFF\x01\x00DEU")).unwrap();
+        assert_eq!(dropped.local_time_offsets, vec![]);
+
+        let buf = tot(b"GBR\x02\x00\x00\xEB\xF0\x01\x00\x00\x01\x00");
+        for len in 0..buf.len() {
+            assert!(Tot::new(&buf[..len]).is_err(), "{} bytes", len);
+        }
+        let mut bad_time = buf.clone();
+        bad_time[5] = 0x24;
+        assert_eq!(Tot::new(&bad_time),
+            Err(ParseError::InvalidValue { pid: None, offset: 3, field: "UTC_time", value: 0xA424_3456 }));
+        let mut descriptors_overrun = buf;
+        descriptors_overrun[9] += 1;
+        assert_eq!(Tot::new(&descriptors_overrun), Err(ParseError::Truncated { pid: None, offset: 0, field: "descriptors" }));
+    }
+}
@@ -0,0 +1,271 @@
+// Upper halves (0xA0..=0xFF) of the ISO 8859 parts that can't be derived from
+// a neighbouring one. '\0' marks bytes the part leaves undefined
+const ISO_8859_2: &str = "\u{A0}Ą˘Ł¤ĽŚ§¨ŠŞŤŹ\u{AD}ŽŻ°ą˛ł´ľśˇ¸šşťź˝žż\
+    ŔÁÂĂÄĹĆÇČÉĘËĚÍÎĎĐŃŇÓÔŐÖ×ŘŮÚŰÜÝŢßŕáâăäĺćçčéęëěíîďđńňóôőö÷řůúűüýţ˙";
+const ISO_8859_3: &str = "\u{A0}Ħ˘£¤\0Ĥ§¨İŞĞĴ\u{AD}\0Ż°ħ²³´µĥ·¸ışğĵ½\0ż\
+    ÀÁÂ\0ÄĊĈÇÈÉÊËÌÍÎÏ\0ÑÒÓÔĠÖ×ĜÙÚÛÜŬŜßàáâ\0äċĉçèéêëìíîï\0ñòóôġö÷ĝùúûüŭŝ˙";
+const ISO_8859_4: &str = "\u{A0}ĄĸŖ¤ĨĻ§¨ŠĒĢŦ\u{AD}Ž¯°ą˛ŗ´ĩļˇ¸šēģŧŊžŋ\
+    ĀÁÂÃÄÅÆĮČÉĘËĖÍÎĪĐŅŌĶÔÕÖ×ØŲÚÛÜŨŪßāáâãäåæįčéęëėíîīđņōķôõö÷øųúûüũū˙";
+const ISO_8859_10: &str = "\u{A0}ĄĒĢĪĨĶ§ĻĐŠŦŽ\u{AD}ŪŊ°ąēģīĩķ·ļđšŧž―ūŋ\
+    ĀÁÂÃÄÅÆĮČÉĘËĖÍÎÏÐŅŌÓÔÕÖŨØŲÚÛÜÝÞßāáâãäåæįčéęëėíîïðņōóôõöũøųúûüýþĸ";
+const ISO_8859_13: &str = "\u{A0}”¢£¤„¦§Ø©Ŗ«¬\u{AD}®Æ°±²³“µ¶·ø¹ŗ»¼½¾æ\
+    ĄĮĀĆÄÅĘĒČÉŹĖĢĶĪĻŠŃŅÓŌÕÖ×ŲŁŚŪÜŻŽßąįāćäåęēčéźėģķīļšńņóōõö÷ųłśūüżž’";
+const ISO_8859_14: &str = "\u{A0}Ḃḃ£ĊċḊ§Ẁ©ẂḋỲ\u{AD}®ŸḞḟĠġṀṁ¶ṖẁṗẃṠỳẄẅṡ\
+    ÀÁÂÃÄÅÆÇÈÉÊËÌÍÎÏŴÑÒÓÔÕÖṪØÙÚÛÜÝŶßàáâãäåæçèéêëìíîïŵñòóôõöṫøùúûüýŷÿ";
+
+/// Upper half (0xA0..=0xFF) of ISO 6937, with the euro sign at 0xA4 as in the DVB
+/// version of the table. The diacritical marks at 0xC1..=0xCF are handled separately
+const ISO_6937: &str = "\u{A0}¡¢£€¥#§¤‘“«←↑→↓°±²³×µ¶·÷’”»¼½¾¿\
+    \0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0―¹®©™♪¬¦\0\0\0\0⅛⅜⅝⅞\
+    ΩÆĐªĦ\0ĲĿŁØŒºÞŦŊŉĸæđðħıĳŀłøœßþŧŋ\u{AD}";
+
+/// Letters the ISO 6937 diacritical marks combine with, and the resulting characters
+const DIACRITICS: [(u8, &str, &str); 13] = [
+    (0xC1, "AEIOUaeiou", "ÀÈÌÒÙàèìòù"),
+    (0xC2, "ACEILNORSUYZacegilnorsuyz", "ÁĆÉÍĹŃÓŔŚÚÝŹáćéǵíĺńóŕśúýź"),
+    (0xC3, "ACEGHIJOSUWYaceghijosuwy", "ÂĈÊĜĤÎĴÔŜÛŴŶâĉêĝĥîĵôŝûŵŷ"),
+    (0xC4, "AINOUainou", "ÃĨÑÕŨãĩñõũ"),
+    (0xC5, "AEIOUaeiou", "ĀĒĪŌŪāēīōū"),
+    (0xC6, "AGUagu", "ĂĞŬăğŭ"),
+    (0xC7, "CEGIZcegz", "ĊĖĠİŻċėġż"),
+    (0xC8, "AEIOUYaeiouy", "ÄËÏÖÜŸäëïöüÿ"),
+    (0xCA, "AUau", "ÅŮåů"),
+    (0xCB, "CGKLNRSTcgklnrst", "ÇĢĶĻŅŖŞŢçģķļņŗşţ"),
+    (0xCD, "OUou", "ŐŰőű"),
+    (0xCE, "AEIUaeiu", "ĄĘĮŲąęįų"),
+    (0xCF, "CDELNRSTZcdelnrstz", "ČĎĚĽŇŘŠŤŽčďěľňřšťž"),
+];
+
+/// Combining characters for the diacritical marks 0xC1..=0xCF (for letters without
+/// a precomposed form)
+const COMBINING: [char; 15] = [
+    '\u{300}', '\u{301}', '\u{302}', '\u{303}', '\u{304}', '\u{306}', '\u{307}', '\u{308}',
+    '\u{308}', '\u{30A}', '\u{327}', '\u{332}', '\u{30B}', '\u{328}', '\u{30C}',
+];
+
+/// The CR/LF control code; the other control codes (0x80..=0x9F) only change emphasis
+const CR_LF: u32 = 0x8A;
+
+/// Decode a DVB text field (ETSI EN 300 468 Annex A).
+///
+/// The first byte may select the character table: ISO 6937 is the default, and
+/// ISO 8859 parts, UCS-2 and UTF-8 are supported. The multi-byte Asian tables are
+/// decoded as ISO 8859-1. Control codes are dropped, apart from CR/LF which
+/// becomes a line break.
+pub fn decode(buf: &[u8]) -> String {
+    match buf.first() {
+        None => String::new(),
+        Some(0x20..=0xFF) => decode_6937(buf),
+        // 0x01..=0x0B select ISO 8859-5..=15
+        Some(&b @ 0x01..=0x0B) => decode_8859(&buf[1..], b + 4),
+        Some(0x10) if buf.len() >= 3 => decode_8859(&buf[3..], buf[2]),
+        Some(0x11) => decode_ucs2(&buf[1..]),
+        Some(0x15) => String::from_utf8_lossy(&buf[1..]).chars().filter_map(control_code).collect(),
+        Some(0x1F) if buf.len() >= 2 => decode_8859(&buf[2..], 1),
+        Some(_) => decode_8859(&buf[1..], 1),
+    }
+}
+
+/// Map the DVB control codes (0x80..=0x9F, or their 0xE080.. forms in two byte
+/// tables) to a line break or nothing
+fn control_code(c: char) -> Option<char> {
+    match u32::from(c) {
+        CR_LF | 0xE08A => Some('\n'),
+        0x80..=0x9F | 0xE080..=0xE09F => None,
+        _ => Some(c),
+    }
+}
+
+fn decode_6937(buf: &[u8]) -> String {
+    let mut text = String::new();
+    let mut i = 0;
+    while i < buf.len() {
+        let b = buf[i];
+        i += 1;
+        if (0xC1..=0xCF).contains(&b) {
+            // A diacritical mark applies to the letter after it
+            let letter = match buf.get(i) {
+                Some(&l) if l.is_ascii_alphabetic() => char::from(l),
+                _ => continue,
+            };
+            i += 1;
+            let composed = DIACRITICS.iter()
+                .find(|(mark, _, _)| *mark == b)
+                .and_then(|(_, letters, composed)| {
+                    letters.chars().position(|c| c == letter).and_then(|n| composed.chars().nth(n))
+                });
+            match composed {
+                Some(c) => text.push(c),
+                None => {
+                    text.push(letter);
+                    text.push(COMBINING[(b - 0xC1) as usize]);
+                },
+            }
+            continue;
+        }
+        let c = match b {
+            0x00..=0x9F => control_code(char::from(b)),
+            _ => upper_half(ISO_6937, b),
+        };
+        text.extend(c);
+    }
+    text
+}
+
+fn decode_8859(buf: &[u8], part: u8) -> String {
+    buf.iter()
+        .filter_map(|&b| match b {
+            0x00..=0x9F => control_code(char::from(b)),
+            _ => iso_8859(part, b),
+        })
+        .collect()
+}
+
+fn decode_ucs2(buf: &[u8]) -> String {
+    let units = buf.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    char::decode_utf16(units)
+        .filter_map(|c| control_code(c.unwrap_or(char::REPLACEMENT_CHARACTER)))
+        .collect()
+}
+
+/// Character `b` (0xA0..=0xFF) of a table given as its upper half
+fn upper_half(table: &str, b: u8) -> Option<char> {
+    table.chars().nth((b - 0xA0) as usize).filter(|c| *c != '\0')
+}
+
+/// Character `b` (0xA0..=0xFF) of ISO 8859 `part`
+fn iso_8859(part: u8, b: u8) -> Option<char> {
+    let latin1 = char::from(b);
+    let code = u32::from(b);
+    match part {
+        2 => upper_half(ISO_8859_2, b),
+        3 => upper_half(ISO_8859_3, b),
+        4 => upper_half(ISO_8859_4, b),
+        // Cyrillic
+        5 => match b {
+            0xA0 | 0xAD => Some(latin1),
+            0xF0 => Some('№'),
+            0xFD => Some('§'),
+            _ => char::from_u32(0x0360 + code),
+        },
+        // Arabic
+        6 => match b {
+            0xA0 | 0xA4 | 0xAD => Some(latin1),
+            0xAC | 0xBB | 0xBF => char::from_u32(0x0560 + code),
+            0xC1..=0xDA | 0xE0..=0xF2 => char::from_u32(0x0560 + code),
+            _ => None,
+        },
+        // Greek
+        7 => match b {
+            0xA1 => Some('‘'),
+            0xA2 => Some('’'),
+            0xA4 => Some('€'),
+            0xA5 => Some('₯'),
+            0xAA => Some('ͺ'),
+            0xAF => Some('―'),
+            0xAE | 0xD2 | 0xFF => None,
+            0xA0..=0xB3 | 0xB7 | 0xBB | 0xBD => Some(latin1),
+            _ => char::from_u32(0x02D0 + code),
+        },
+        // Hebrew
+        8 => match b {
+            0xAA => Some('×'),
+            0xBA => Some('÷'),
+            0xDF => Some('‗'),
+            0xE0..=0xFA => char::from_u32(0x04F0 + code),
+            0xFD => Some('\u{200E}'),
+            0xFE => Some('\u{200F}'),
+            0xA0 | 0xA2..=0xBE => Some(latin1),
+            _ => None,
+        },
+        // Turkish
+        9 => match b {
+            0xD0 => Some('Ğ'),
+            0xDD => Some('İ'),
+            0xDE => Some('Ş'),
+            0xF0 => Some('ğ'),
+            0xFD => Some('ı'),
+            0xFE => Some('ş'),
+            _ => Some(latin1),
+        },
+        10 => upper_half(ISO_8859_10, b),
+        // Thai
+        11 => match b {
+            0xA0 => Some(latin1),
+            0xA1..=0xDA | 0xDF..=0xFB => char::from_u32(0x0D60 + code),
+            _ => None,
+        },
+        13 => upper_half(ISO_8859_13, b),
+        14 => upper_half(ISO_8859_14, b),
+        15 => match b {
+            0xA4 => Some('€'),
+            0xA6 => Some('Š'),
+            0xA8 => Some('š'),
+            0xB4 => Some('Ž'),
+            0xB8 => Some('ž'),
+            0xBC => Some('Œ'),
+            0xBD => Some('œ'),
+            0xBE => Some('Ÿ'),
+            _ => Some(latin1),
+        },
+        _ => Some(latin1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn character_tables() {
+        for (buf, expected) in [
+            (&b""[..], ""),
+            (b"News at Ten", "News at Ten"),
+            // ISO 6937: diacritical marks, a mark without a precomposed form, the euro sign
+            (b"Caf\xC2e \xC8uber \xC2q \xA4", "Café über q\u{301} €"),
+            (b"Line\x8Anext\x86", "Line\nnext"),
+            (b"\x01\xB4\xDE\xDC", "Дом"),
+            (b"\x03\xC1\xE8\xDE\xED\xE1", "Αθήνα"),
+            (b"\x0B\xA4 \xBC", "€ Œ"),
+            (b"\x10\x00\x02\xA3", "Ł"),
+            (b"\x11\x00H\x00\xE9\x20\xAC\xE0\x8A", "Hé€\n"),
+            (b"\x15Caf\xC3\xA9", "Café"),
+            (b"\x13\xE9", "é"),
+        ] {
+            assert_eq!(decode(buf), expected, "{:02X?}", buf);
+        }
+    }
+
+    #[test]
+    fn tables_are_complete() {
+        for table in [ISO_8859_2, ISO_8859_3, ISO_8859_4, ISO_8859_10, ISO_8859_13, ISO_8859_14, ISO_6937] {
+            assert_eq!(table.chars().count(), 0x60);
+        }
+        for (_, letters, composed) in DIACRITICS {
+            assert_eq!(letters.chars().count(), composed.chars().count(), "{}", letters);
+        }
+        for part in 0..=16 {
+            for b in 0xA0..=0xFF {
+                let _ = iso_8859(part, b);
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_text() {
+        for (buf, expected) in [
+            // Table selectors cut short
+            (&b"\x10"[..], ""),
+            (b"\x1F", ""),
+            // An odd UCS-2 byte and a lone surrogate
+            (b"\x11\x00A\x00", "A"),
+            (b"\x11\xD8\x00\x00A", "\u{FFFD}A"),
+            (b"\x15Caf\xFF", "Caf\u{FFFD}"),
+            // Diacritical marks with no letter after them
+            (b"Caf\xC2", "Caf"),
+            (b"\xC21", "1"),
+        ] {
+            assert_eq!(decode(buf), expected, "{:02X?}", buf);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+
+// Constants
+/// Modified Julian Date of 1970-01-01
+const MJD_UNIX_EPOCH: i64 = 40587;
+const SECONDS_PER_DAY: i64 = 86400;
+/// Seconds from 1970-01-01 to the GPS epoch 1980-01-06 00:00:00 UTC
+const GPS_UNIX_EPOCH: i64 = 315_964_800;
+
+/// A UTC time as carried by the DVB SI tables (16 bit MJD then 6 BCD digits hhmmss)
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct UtcTime {
+    /// Seconds since 1970-01-01 00:00:00 UTC
+    pub unix_seconds: i64,
+}
+
+impl fmt::Display for UtcTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (year, month, day) = self.date();
+        let (hour, minute, second) = self.time_of_day();
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, hour, minute, second)
+    }
+}
+
+impl UtcTime {
+    /// Parse a 5 byte MJD/BCD time, or None if it is undefined (all ones) or malformed
+    pub fn from_mjd_bcd(buf: &[u8]) -> Option<UtcTime> {
+        if buf.len() < 5 || buf[..5].iter().all(|b| *b == 0xFF) {
+            return None;
+        }
+        let mjd = i64::from(u16::from_be_bytes([buf[0], buf[1]]));
+        let seconds = bcd_hms(&buf[2..5])?;
+        // Unlike a duration, a time of day can't go past 23:59:59
+        if i64::from(seconds) >= SECONDS_PER_DAY {
+            return None;
+        }
+        Some(UtcTime { unix_seconds: (mjd - MJD_UNIX_EPOCH) * SECONDS_PER_DAY + i64::from(seconds) })
+    }
+
+    /// Convert a GPS time (seconds since 1980-01-06, as in ATSC PSIP) to UTC, given the
+    /// number of leap seconds GPS is ahead of UTC
+    pub fn from_gps(gps_seconds: u32, gps_utc_offset: u8) -> UtcTime {
+        UtcTime { unix_seconds: GPS_UNIX_EPOCH + i64::from(gps_seconds) - i64::from(gps_utc_offset) }
+    }
+
+    /// (year, month, day)
+    pub fn date(&self) -> (i64, u32, u32) {
+        // Days to civil date, from Howard Hinnant's date algorithms
+        let z = self.unix_seconds.div_euclid(SECONDS_PER_DAY) + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        (year, month, day)
+    }
+
+    /// (hour, minute, second)
+    pub fn time_of_day(&self) -> (u32, u32, u32) {
+        let s = self.unix_seconds.rem_euclid(SECONDS_PER_DAY) as u32;
+        (s / 3600, s / 60 % 60, s % 60)
+    }
+
+    /// The time `seconds` later
+    pub fn add_seconds(&self, seconds: i64) -> UtcTime {
+        UtcTime { unix_seconds: self.unix_seconds + seconds }
+    }
+
+    /// The time in the XMLTV format (YYYYMMDDhhmmss +0000)
+    pub fn xmltv(&self) -> String {
+        let (year, month, day) = self.date();
+        let (hour, minute, second) = self.time_of_day();
+        format!("{:04}{:02}{:02}{:02}{:02}{:02} +0000", year, month, day, hour, minute, second)
+    }
+}
+
+/// Parse 6 BCD digits hhmmss (e.g. a duration) into seconds, or None if they aren't BCD
+pub fn bcd_hms(buf: &[u8]) -> Option<u32> {
+    let digit = |b: u8| if b <= 9 { Some(u32::from(b)) } else { None };
+    let mut parts = [0u32; 3];
+    for (part, b) in parts.iter_mut().zip(buf.iter().take(3)) {
+        *part = digit(b >> 4)? * 10 + digit(b & 0x0F)?;
+    }
+    if buf.len() < 3 || parts[1] > 59 || parts[2] > 59 {
+        return None;
+    }
+    Some(parts[0] * 3600 + parts[1] * 60 + parts[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mjd_bcd() {
+        for (buf, expected) in [
+            (&b"\xC0\x79\x12\x45\x00"[..], Some("1993-10-13 12:45:00 UTC")),
+            (b"\x9E\x8B\x00\x00\x00", Some("1970-01-01 00:00:00 UTC")),
+            (b"\xEB\xF0\x23\x59\x59", Some("2024-03-31 23:59:59 UTC")),
+            (b"\xFF\xFF\xFF\xFF\xFF", None),
+            (b"\xEB\xF0\x23\x60\x00", None),
+            (b"\xEB\xF0\x2A\x00\x00", None),
+            // Durations can go past 23 hours, times of day can't
+            (b"\xEB\xF0\x24\x00\x00", None),
+            (b"\xEB\xF0\x99\x59\x59", None),
+            (b"\xEB\xF0\x23\x59", None),
+        ] {
+            assert_eq!(UtcTime::from_mjd_bcd(buf).map(|t| t.to_string()).as_deref(), expected, "{:02X?}", buf);
+        }
+        let time = UtcTime::from_mjd_bcd(b"\xC0\x79\x12\x45\x00").unwrap();
+        assert_eq!(time.xmltv(), "19931013124500 +0000");
+        assert_eq!(time.add_seconds(12 * 3600).to_string(), "1993-10-14 00:45:00 UTC");
+    }
+
+    #[test]
+    fn gps() {
+        assert_eq!(UtcTime::from_gps(0, 0).to_string(), "1980-01-06 00:00:00 UTC");
+        // 2024-01-15 00:00:00 UTC, with GPS 18 seconds ahead
+        assert_eq!(UtcTime::from_gps(1_389_312_018, 18).to_string(), "2024-01-15 00:00:00 UTC");
+    }
+
+    #[test]
+    fn bcd() {
+        assert_eq!(bcd_hms(&[0x01, 0x45, 0x30]), Some(6330));
+        assert_eq!(bcd_hms(&[0x99, 0x59, 0x59]), Some(359_999));
+        assert_eq!(bcd_hms(&[0x01, 0x45]), None);
+        assert_eq!(bcd_hms(&[0x01, 0x4A, 0x00]), None);
+        assert_eq!(bcd_hms(&[0x01, 0x00, 0x60]), None);
+    }
+}
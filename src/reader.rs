@@ -0,0 +1,379 @@
+use std::fmt;
+use crate::packet::{PACKET_SIZE, SYNC_BYTE_VAL, TpExtraHeader};
+
+/// Number of consecutive sync bytes needed to (re)acquire sync
+const SYNC_ACQUIRE_COUNT: usize = 5;
+/// Number of consecutive corrupted sync bytes after which sync is lost
+const SYNC_LOSS_COUNT: u32 = 2;
+
+/// Size of the TP_extra_header in front of each M2TS packet
+const TP_EXTRA_HEADER_SIZE: usize = 4;
+/// Size of the Reed-Solomon parity bytes after each 204 byte packet
+const RS_PARITY_SIZE: usize = 16;
+
+/// How transport packets are laid out in the stream
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PacketFormat {
+    /// Plain 188 byte packets
+    Ts,
+    /// 192 byte Blu-ray/AVCHD packets: a 4 byte TP_extra_header then the packet
+    M2ts,
+    /// 204 byte packets: the packet then 16 Reed-Solomon parity bytes
+    Rs,
+}
+
+impl fmt::Display for PacketFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PacketFormat::Ts => write!(f, "TS ({} byte packets)", self.size()),
+            PacketFormat::M2ts => write!(f, "M2TS ({} byte packets)", self.size()),
+            PacketFormat::Rs => write!(f, "TS with Reed-Solomon parity ({} byte packets)", self.size()),
+        }
+    }
+}
+
+impl PacketFormat {
+    /// Size of a packet including any extra header or parity bytes
+    pub fn size(&self) -> usize {
+        match self {
+            PacketFormat::Ts => PACKET_SIZE,
+            PacketFormat::M2ts => TP_EXTRA_HEADER_SIZE + PACKET_SIZE,
+            PacketFormat::Rs => PACKET_SIZE + RS_PARITY_SIZE,
+        }
+    }
+
+    /// Index of the sync byte in a packet
+    pub fn sync_offset(&self) -> usize {
+        match self {
+            PacketFormat::M2ts => TP_EXTRA_HEADER_SIZE,
+            PacketFormat::Ts | PacketFormat::Rs => 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SyncStats {
+    /// Packets handed out (including ones with a corrupted sync byte)
+    pub packets: u64,
+    /// Packets whose sync byte was corrupted while in sync
+    pub sync_byte_errors: u64,
+    /// Number of times sync was lost after having been acquired
+    pub sync_losses: u32,
+    /// Bytes thrown away while searching for sync
+    pub skipped_bytes: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ReadEvent {
+    /// A 188 byte packet whose sync byte is at stream `offset`, along with the
+    /// TP_extra_header that precedes it in M2TS streams
+    Packet { offset: u64, data: Vec<u8>, extra_header: Option<TpExtraHeader> },
+    /// Sync was lost at `offset`; the reader is searching for the next sync byte
+    SyncLost { offset: u64 },
+    /// Sync was (re)acquired on a `format` packet at `offset` after skipping `skipped` bytes
+    SyncAcquired { offset: u64, skipped: u64, format: PacketFormat },
+}
+
+/// Splits a stream fed in arbitrary chunks into packets, keeping track of sync.
+///
+/// Sync is acquired once a sync byte is followed by enough sync bytes at packet
+/// intervals and is lost after consecutive corrupted sync bytes, at which point
+/// the reader searches for the next confirmed sync byte. The packet size (188, 192
+/// or 204 bytes) is detected from the interval between the sync bytes.
+#[derive(Clone, Debug, Default)]
+pub struct PacketReader {
+    buf: Vec<u8>,
+    /// Index in `buf` of the first unread byte
+    pos: usize,
+    /// Stream offset of `buf[pos]`
+    offset: u64,
+    synced: bool,
+    /// Format of the packets, once sync has been acquired
+    format: Option<PacketFormat>,
+    /// Consecutive corrupted sync bytes seen while in sync
+    bad_syncs: u32,
+    /// Bytes skipped since sync was last lost
+    skipped: u64,
+    /// No more data will be pushed, so don't wait for it
+    eof: bool,
+    stats: SyncStats,
+}
+
+impl PacketReader {
+    pub fn new() -> PacketReader {
+        Default::default()
+    }
+
+    /// Add the next chunk of the stream
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Signal the end of the stream: the last packets are read without waiting for
+    /// more data, and bytes that can't be confirmed as packets any more are skipped
+    pub fn finish(&mut self) {
+        self.eof = true;
+    }
+
+    pub fn stats(&self) -> &SyncStats {
+        &self.stats
+    }
+
+    /// The detected packet format (None until sync is first acquired)
+    pub fn format(&self) -> Option<PacketFormat> {
+        self.format
+    }
+
+    /// Get the next event, or None if more data is needed
+    pub fn next_event(&mut self) -> Option<ReadEvent> {
+        let format = match self.format {
+            Some(format) if self.synced => format,
+            _ => return self.acquire_sync(),
+        };
+        let remaining = self.data().len();
+        if remaining < format.size() {
+            if self.eof && remaining > 0 {
+                // Trailing partial packet
+                self.skip(remaining);
+            }
+            return None;
+        }
+
+        if self.data()[format.sync_offset()] == SYNC_BYTE_VAL {
+            self.bad_syncs = 0;
+        } else {
+            self.stats.sync_byte_errors += 1;
+            self.bad_syncs += 1;
+            if self.bad_syncs >= SYNC_LOSS_COUNT {
+                self.synced = false;
+                self.stats.sync_losses += 1;
+                return Some(ReadEvent::SyncLost { offset: self.offset });
+            }
+        }
+        Some(self.take_packet(format))
+    }
+
+    /// Search the buffer for a confirmed sync byte, dropping the bytes before it
+    fn acquire_sync(&mut self) -> Option<ReadEvent> {
+        match self.find_sync() {
+            Ok((n, format)) => {
+                self.skip(n);
+                self.synced = true;
+                self.format = Some(format);
+                self.bad_syncs = 0;
+                let skipped = self.skipped;
+                self.skipped = 0;
+                Some(ReadEvent::SyncAcquired {
+                    offset: self.offset + format.sync_offset() as u64,
+                    skipped,
+                    format,
+                })
+            },
+            Err(n) => {
+                self.skip(n);
+                None
+            },
+        }
+    }
+
+    /// Ok((index, format)) of the start of the first packet with a confirmed sync byte,
+    /// or Err(index) up to which the buffer can't hold a sync byte if more data is needed
+    fn find_sync(&self) -> Result<(usize, PacketFormat), usize> {
+        // Try the format we've already seen first, in case the stream resyncs at another
+        let mut formats = vec![PacketFormat::Ts, PacketFormat::M2ts, PacketFormat::Rs];
+        if let Some(format) = self.format {
+            formats.retain(|f| *f != format);
+            formats.insert(0, format);
+        }
+
+        let data = self.data();
+        for i in 0..data.len() {
+            if data[i] != SYNC_BYTE_VAL {
+                continue;
+            }
+            for format in formats.iter().filter(|f| i >= f.sync_offset()) {
+                let mut confirmed = true;
+                for k in 1..SYNC_ACQUIRE_COUNT {
+                    match data.get(i + k * format.size()) {
+                        Some(&SYNC_BYTE_VAL) => (),
+                        Some(_) => {
+                            confirmed = false;
+                            break;
+                        },
+                        // Too close to the end of the stream to be confirmed: the bytes are skipped
+                        None if self.eof => {
+                            confirmed = false;
+                            break;
+                        },
+                        // Keep the bytes an extra header could be in
+                        None => return Err(i.saturating_sub(TP_EXTRA_HEADER_SIZE)),
+                    }
+                }
+                if confirmed {
+                    return Ok((i - format.sync_offset(), *format));
+                }
+            }
+        }
+        if self.eof {
+            Err(data.len())
+        } else {
+            Err(data.len().saturating_sub(TP_EXTRA_HEADER_SIZE))
+        }
+    }
+
+    /// The unread part of the buffer
+    fn data(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    fn take_packet(&mut self, format: PacketFormat) -> ReadEvent {
+        let start = format.sync_offset();
+        let data = self.data()[start..start + PACKET_SIZE].to_vec();
+        let extra_header = match format {
+            PacketFormat::M2ts => Some(TpExtraHeader::new(&self.data()[..TP_EXTRA_HEADER_SIZE])),
+            PacketFormat::Ts | PacketFormat::Rs => None,
+        };
+        let offset = self.offset + start as u64;
+        self.pos += format.size();
+        self.offset += format.size() as u64;
+        self.stats.packets += 1;
+        ReadEvent::Packet { offset, data, extra_header }
+    }
+
+    fn skip(&mut self, n: usize) {
+        self.pos += n;
+        self.offset += n as u64;
+        self.skipped += n as u64;
+        self.stats.skipped_bytes += n as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn ts_packet(n: u8) -> Vec<u8> {
+        test_util::packet(0x100, false, n & 0x0F, None, &[n; 184])
+    }
+
+    /// Feed `stream` in `chunk` byte pieces and describe the events
+    fn read(stream: &[u8], chunk: usize) -> (Vec<String>, SyncStats) {
+        let mut reader = PacketReader::new();
+        let mut events = vec![];
+        let mut describe = |reader: &mut PacketReader| {
+            while let Some(event) = reader.next_event() {
+                events.push(match event {
+                    ReadEvent::Packet { offset, data, .. } => format!("packet {} {}", offset, data[4]),
+                    ReadEvent::SyncLost { offset } => format!("lost {}", offset),
+                    ReadEvent::SyncAcquired { offset, skipped, format } => format!("sync {} {} {:?}", offset, skipped, format),
+                });
+            }
+        };
+        for data in stream.chunks(chunk) {
+            reader.push(data);
+            describe(&mut reader);
+        }
+        reader.finish();
+        describe(&mut reader);
+        (events, *reader.stats())
+    }
+
+    #[test]
+    fn resync() {
+        // Garbage with unconfirmed sync bytes, then packets 0 to 13 with the sync
+        // bytes of 6 and 7 corrupted
+        let mut stream = vec![0x00; 100];
+        stream[10] = SYNC_BYTE_VAL;
+        stream[50] = SYNC_BYTE_VAL;
+        for n in 0..14 {
+            let mut packet = ts_packet(n);
+            if n == 6 || n == 7 {
+                packet[0] = 0x07;
+            }
+            stream.extend(packet);
+        }
+        let mut expected = vec!["sync 100 100 Ts".to_string()];
+        expected.extend((0..7).map(|n| format!("packet {} {}", 100 + 188 * n, n)));
+        expected.push("lost 1416".to_string());
+        expected.push("sync 1604 188 Ts".to_string());
+        expected.extend((8..14).map(|n| format!("packet {} {}", 100 + 188 * n, n)));
+
+        for chunk in [1, 100, 1000, stream.len()] {
+            let (events, stats) = read(&stream, chunk);
+            assert_eq!(events, expected, "{} byte chunks", chunk);
+            assert_eq!(stats, SyncStats { packets: 13, sync_byte_errors: 2, sync_losses: 1, skipped_bytes: 288 });
+        }
+    }
+
+    #[test]
+    fn end_of_stream() {
+        // A stray sync byte with a packet's worth of bytes after it isn't a packet
+        let mut stream = vec![0x00; 30];
+        stream.push(SYNC_BYTE_VAL);
+        stream.extend_from_slice(&[0x00; 200]);
+        assert_eq!(read(&stream, 64), (vec![], SyncStats { skipped_bytes: 231, ..Default::default() }));
+
+        // Packets after which the stream ends before sync is confirmed are skipped too
+        let stream: Vec<u8> = (0..3).flat_map(ts_packet).collect();
+        assert_eq!(read(&stream, 188).1, SyncStats { skipped_bytes: 3 * 188, ..Default::default() });
+
+        // Once in sync, the last packets are read and a partial one skipped
+        let mut stream: Vec<u8> = (0..6).flat_map(ts_packet).collect();
+        stream.extend_from_slice(&ts_packet(6)[..100]);
+        let (events, stats) = read(&stream, 500);
+        assert_eq!(events.len(), 7);
+        assert_eq!(stats, SyncStats { packets: 6, skipped_bytes: 100, ..Default::default() });
+    }
+
+    #[test]
+    fn m2ts() {
+        // copy_permission_indicator 1 and a 30 bit arrival_time_stamp in front of each packet
+        let ats = |n: u32| 0x3FFF_0000 + 1000 * n;
+        let stream: Vec<u8> = (0..8).flat_map(|n| [(0x4000_0000 | ats(n)).to_be_bytes().to_vec(), ts_packet(n as u8)].concat()).collect();
+        let mut reader = PacketReader::new();
+        reader.push(&stream);
+        reader.finish();
+        assert_eq!(reader.next_event(), Some(ReadEvent::SyncAcquired { offset: 4, skipped: 0, format: PacketFormat::M2ts }));
+        for n in 0..8 {
+            match reader.next_event() {
+                Some(ReadEvent::Packet { offset, data, extra_header }) => {
+                    assert_eq!((offset, data), (4 + 192 * u64::from(n), ts_packet(n as u8)));
+                    assert_eq!(extra_header, Some(TpExtraHeader { copy_permission_indicator: 1, arrival_time_stamp: ats(n) }));
+                },
+                event => panic!("{:?}", event),
+            }
+        }
+        assert_eq!(reader.next_event(), None);
+        assert_eq!(reader.format(), Some(PacketFormat::M2ts));
+    }
+
+    #[test]
+    fn reed_solomon() {
+        let stream: Vec<u8> = (0..8).flat_map(|n| [ts_packet(n), test_util::garbage(u32::from(n), RS_PARITY_SIZE)].concat()).collect();
+        let (events, stats) = read(&stream, 1000);
+        let mut expected = vec!["sync 0 0 Rs".to_string()];
+        expected.extend((0..8).map(|n| format!("packet {} {}", 204 * n, n)));
+        assert_eq!(events, expected);
+        assert_eq!(stats, SyncStats { packets: 8, ..Default::default() });
+        assert_eq!(PacketFormat::Rs.to_string(), "TS with Reed-Solomon parity (204 byte packets)");
+    }
+
+    #[test]
+    fn format_change() {
+        // 188 byte packets, then the stream switches to 204 byte ones
+        let mut stream: Vec<u8> = (0..6).flat_map(ts_packet).collect();
+        stream.extend((6..16).flat_map(|n| [ts_packet(n), vec![0x00; RS_PARITY_SIZE]].concat()));
+        let (events, stats) = read(&stream, 1000);
+        let mut expected = vec!["sync 0 0 Ts".to_string()];
+        expected.extend((0..7).map(|n| format!("packet {} {}", 188 * n, n)));
+        // The first 204 byte packet still reads as a 188 byte one, the bytes after it have
+        // a corrupted sync byte and the next ones lose the sync, which packet 8 regains
+        expected.extend(["packet 1316 0", "lost 1504", "sync 1536 32 Rs"].iter().map(|e| e.to_string()));
+        expected.extend((8..16).map(|n| format!("packet {} {}", 1128 + 204 * (n - 6), n)));
+        assert_eq!(events, expected);
+        assert_eq!(stats, SyncStats { packets: 16, sync_byte_errors: 2, sync_losses: 1, skipped_bytes: 32 });
+    }
+}
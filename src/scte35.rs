@@ -0,0 +1,829 @@
+use std::fmt;
+use std::fmt::Write;
+use std::collections::HashMap;
+use byteorder::{ByteOrder, BigEndian};
+use crate::demux::DemuxHandler;
+use crate::packet::{self, Packet};
+use crate::pes::{Pes, PES_CLOCK_FREQUENCY};
+use crate::psi::{self, pmt::Pmt};
+use crate::{mpeg32_crc, error::{self, ParseError}};
+
+// Constants
+pub const SPLICE_INFO_TABLE_ID: u8 = 0xFC;
+pub const SCTE35_STREAM_TYPE: u8 = 0x86;
+/// The identifier of the registration descriptor and of the SCTE-35 splice descriptors
+pub const CUEI_IDENTIFIER: [u8; 4] = *b"CUEI";
+const SEGMENTATION_DESCRIPTOR_TAG: u8 = 0x02;
+const AVAIL_DESCRIPTOR_TAG: u8 = 0x00;
+/// splice_command_length of legacy encoders that don't fill it in
+const UNKNOWN_COMMAND_LENGTH: usize = 0xFFF;
+/// PTSs wrap around after 2^33 ticks of the 90 kHz clock
+const PTS_MODULUS: u64 = 1 << 33;
+
+/// Read the 33 bit time (e.g. a pts_time) held in the low bit of `buf[0]` and `buf[1..5]`
+fn read_33(buf: &[u8]) -> u64 {
+    (u64::from(buf[0] & 0x01) << 32) | u64::from(BigEndian::read_u32(&buf[1..5]))
+}
+
+/// Read the splice_time() at `buf[*n]` and move past it (None if no time is specified)
+fn splice_time(buf: &[u8], n: &mut usize) -> Result<Option<u64>, ParseError> {
+    let b = error::field_byte(buf, *n, "time_specified_flag")?;
+    if packet::get_bit_at(b, 7) {
+        let pts_time = read_33(error::field_bytes(buf, *n, 5, "pts_time")?);
+        *n += 5;
+        Ok(Some(pts_time))
+    } else {
+        *n += 1;
+        Ok(None)
+    }
+}
+
+/// A break_duration()
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BreakDuration {
+    /// Whether the splicer returns to the network at the end of the break by itself
+    pub auto_return: bool,
+    /// In units of the 90 kHz clock
+    pub duration: u64,
+}
+
+impl BreakDuration {
+    /// Read the break_duration() at `buf[*n]` and move past it
+    fn new(buf: &[u8], n: &mut usize) -> Result<BreakDuration, ParseError> {
+        let b = error::field_bytes(buf, *n, 5, "break_duration")?;
+        *n += 5;
+        Ok(BreakDuration { auto_return: packet::get_bit_at(b[0], 7), duration: read_33(b) })
+    }
+}
+
+impl fmt::Display for BreakDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.3}s{}", self.duration as f64 / PES_CLOCK_FREQUENCY as f64,
+            if self.auto_return { " (auto return)" } else { "" })
+    }
+}
+
+/// The fields following splice_event_cancel_indicator, shared by splice_insert()
+/// and the splices of splice_schedule()
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Splice {
+    /// Whether the splice leaves the network (the start of a break) rather than returns to it
+    pub out_of_network: bool,
+    /// Whether the whole program is spliced at once rather than component by component
+    pub program_splice: bool,
+    /// Whether the splice happens as soon as possible rather than at a given time
+    pub splice_immediate: bool,
+    /// pts_time of splice_insert() or utc_splice_time of splice_schedule(), if the whole
+    /// program is spliced at a given time
+    pub splice_time: Option<u64>,
+    /// The component_tag and time of each component, if they are spliced separately
+    pub components: Vec<(u8, Option<u64>)>,
+    pub break_duration: Option<BreakDuration>,
+    pub unique_program_id: u16,
+    pub avail_num: u8,
+    pub avails_expected: u8,
+}
+
+/// A splice_insert() or one of the splices of a splice_schedule()
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SpliceEvent {
+    pub splice_event_id: u32,
+    /// None if the event cancels an earlier one with the same splice_event_id
+    pub splice: Option<Splice>,
+}
+
+impl SpliceEvent {
+    /// Read the splice_insert() (or the splice of a splice_schedule(), whose times are
+    /// UTC seconds rather than splice_time()s) at `buf[*n]` and move past it
+    fn new(buf: &[u8], n: &mut usize, scheduled: bool) -> Result<SpliceEvent, ParseError> {
+        let header = error::field_bytes(buf, *n, 5, "splice_event_cancel_indicator")?;
+        let splice_event_id = BigEndian::read_u32(header);
+        *n += 5;
+        if packet::get_bit_at(header[4], 7) {
+            return Ok(SpliceEvent { splice_event_id, splice: None });
+        }
+
+        let flags = error::field_byte(buf, *n, "out_of_network_indicator")?;
+        *n += 1;
+        let program_splice = packet::get_bit_at(flags, 6);
+        let duration_flag = packet::get_bit_at(flags, 5);
+        let splice_immediate = !scheduled && packet::get_bit_at(flags, 4);
+        let time = |n: &mut usize| -> Result<Option<u64>, ParseError> {
+            if scheduled {
+                let t = error::field_bytes(buf, *n, 4, "utc_splice_time")?;
+                *n += 4;
+                Ok(Some(u64::from(BigEndian::read_u32(t))))
+            } else if splice_immediate {
+                Ok(None)
+            } else {
+                splice_time(buf, n)
+            }
+        };
+
+        let mut splice_time = None;
+        let mut components = vec![];
+        if program_splice {
+            splice_time = time(n)?;
+        } else {
+            let component_count = error::field_byte(buf, *n, "component_count")?;
+            *n += 1;
+            for _ in 0..component_count {
+                let component_tag = error::field_byte(buf, *n, "component_tag")?;
+                *n += 1;
+                components.push((component_tag, time(n)?));
+            }
+        }
+        let break_duration = if duration_flag { Some(BreakDuration::new(buf, n)?) } else { None };
+        let b = error::field_bytes(buf, *n, 4, "avails_expected")?;
+        *n += 4;
+
+        Ok(SpliceEvent {
+            splice_event_id,
+            splice: Some(Splice {
+                out_of_network: packet::get_bit_at(flags, 7),
+                program_splice,
+                splice_immediate,
+                splice_time,
+                components,
+                break_duration,
+                unique_program_id: BigEndian::read_u16(&b[0..2]),
+                avail_num: b[2],
+                avails_expected: b[3],
+            }),
+        })
+    }
+}
+
+impl fmt::Display for SpliceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Event ID: {:#X}", self.splice_event_id)?;
+        let splice = match &self.splice {
+            Some(splice) => splice,
+            None => return write!(f, ", Cancelled"),
+        };
+        write!(f, ", {}", if splice.out_of_network { "Out of network" } else { "Back to network" })?;
+        if splice.splice_immediate {
+            write!(f, ", Immediate")?;
+        }
+        if let Some(time) = splice.splice_time {
+            write!(f, ", Time: {}", time)?;
+        }
+        for (tag, time) in &splice.components {
+            write!(f, ", Component {:#X}", tag)?;
+            if let Some(time) = time {
+                write!(f, " at {}", time)?;
+            }
+        }
+        if let Some(duration) = &splice.break_duration {
+            write!(f, ", Duration: {}", duration)?;
+        }
+        write!(f, ", Program ID: {}, Avail: {}/{}", splice.unique_program_id, splice.avail_num,
+            splice.avails_expected)
+    }
+}
+
+/// The splice command of a splice_info_section
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SpliceCommand {
+    Null,
+    /// Splices at UTC times (seconds since 1980-01-06 00:00:00 GPS time) well in advance
+    Schedule(Vec<SpliceEvent>),
+    Insert(SpliceEvent),
+    /// A point in time given by the splice descriptors (None if it is "now")
+    TimeSignal(Option<u64>),
+    BandwidthReservation,
+    Private { identifier: u32, private_bytes: Vec<u8> },
+    Unknown { splice_command_type: u8, bytes: Vec<u8> },
+}
+
+impl SpliceCommand {
+    /// Parse the command of type `splice_command_type` at `buf[n..end]` and return it along
+    /// with its size (`end` is None if the splice_command_length isn't given)
+    fn new(buf: &[u8], splice_command_type: u8, n: usize, end: Option<usize>)
+        -> Result<(SpliceCommand, usize), ParseError> {
+        let mut i = n;
+        let command = match splice_command_type {
+            0x00 => SpliceCommand::Null,
+            0x04 => {
+                let splice_count = error::field_byte(buf, i, "splice_count")?;
+                i += 1;
+                let mut splices = vec![];
+                for _ in 0..splice_count {
+                    splices.push(SpliceEvent::new(buf, &mut i, true)?);
+                }
+                SpliceCommand::Schedule(splices)
+            },
+            0x05 => SpliceCommand::Insert(SpliceEvent::new(buf, &mut i, false)?),
+            0x06 => SpliceCommand::TimeSignal(splice_time(buf, &mut i)?),
+            0x07 => SpliceCommand::BandwidthReservation,
+            0xFF => {
+                let end = end.unwrap_or(buf.len());
+                let b = error::field_bytes(buf, n, 4, "identifier")?;
+                i = end.max(n + 4);
+                SpliceCommand::Private {
+                    identifier: BigEndian::read_u32(b),
+                    private_bytes: error::field_bytes(buf, n + 4, i - n - 4, "private_byte")?.to_vec(),
+                }
+            },
+            _ => {
+                // Without a length there is no telling where an unknown command ends
+                let end = end.ok_or(ParseError::InvalidValue {
+                    pid: None, offset: 13, field: "splice_command_type", value: u32::from(splice_command_type),
+                })?;
+                i = end;
+                SpliceCommand::Unknown {
+                    splice_command_type,
+                    bytes: error::field_bytes(buf, n, end - n, "splice_command")?.to_vec(),
+                }
+            },
+        };
+        Ok((command, end.unwrap_or(i) - n))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpliceCommand::Null => "splice_null",
+            SpliceCommand::Schedule(_) => "splice_schedule",
+            SpliceCommand::Insert(_) => "splice_insert",
+            SpliceCommand::TimeSignal(_) => "time_signal",
+            SpliceCommand::BandwidthReservation => "bandwidth_reservation",
+            SpliceCommand::Private { .. } => "private_command",
+            SpliceCommand::Unknown { .. } => "reserved",
+        }
+    }
+
+    /// The pts_time of the command (before the pts_adjustment), if it has one for the whole program
+    pub fn pts_time(&self) -> Option<u64> {
+        match self {
+            SpliceCommand::Insert(event) => event.splice.as_ref()?.splice_time,
+            SpliceCommand::TimeSignal(time) => *time,
+            _ => None,
+        }
+    }
+}
+
+/// The sub-segments of the placement opportunity and ad block segmentation types
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SubSegment {
+    pub sub_segment_num: u8,
+    pub sub_segments_expected: u8,
+}
+
+/// The delivery restrictions of a segment (only present if delivery is restricted)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DeliveryRestrictions {
+    pub web_delivery_allowed: bool,
+    pub no_regional_blackout: bool,
+    pub archive_allowed: bool,
+    pub device_restrictions: u8,
+}
+
+/// The fields of a segmentation_descriptor following segmentation_event_cancel_indicator
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Segment {
+    pub program_segmentation: bool,
+    pub delivery_restrictions: Option<DeliveryRestrictions>,
+    /// The component_tag and pts_offset of each component, if they are segmented separately
+    pub components: Vec<(u8, u64)>,
+    /// In units of the 90 kHz clock
+    pub segmentation_duration: Option<u64>,
+    pub segmentation_upid_type: u8,
+    pub segmentation_upid: Vec<u8>,
+    pub segmentation_type_id: u8,
+    pub segment_num: u8,
+    pub segments_expected: u8,
+    pub sub_segment: Option<SubSegment>,
+}
+
+impl Segment {
+    /// Parse the segment of the segmentation_descriptor body `buf` starting at `buf[n]`
+    fn new(buf: &[u8], mut n: usize) -> Result<Segment, ParseError> {
+        let flags = error::field_byte(buf, n, "program_segmentation_flag")?;
+        n += 1;
+        let program_segmentation = packet::get_bit_at(flags, 7);
+        let delivery_restrictions = if packet::get_bit_at(flags, 5) {
+            None
+        } else {
+            Some(DeliveryRestrictions {
+                web_delivery_allowed: packet::get_bit_at(flags, 4),
+                no_regional_blackout: packet::get_bit_at(flags, 3),
+                archive_allowed: packet::get_bit_at(flags, 2),
+                device_restrictions: flags & 0x03,
+            })
+        };
+
+        let mut components = vec![];
+        if !program_segmentation {
+            let component_count = error::field_byte(buf, n, "component_count")?;
+            n += 1;
+            for _ in 0..component_count {
+                let b = error::field_bytes(buf, n, 6, "pts_offset")?;
+                components.push((b[0], read_33(&b[1..6])));
+                n += 6;
+            }
+        }
+        let segmentation_duration = if packet::get_bit_at(flags, 6) {
+            let b = error::field_bytes(buf, n, 5, "segmentation_duration")?;
+            n += 5;
+            Some((u64::from(b[0]) << 32) | u64::from(BigEndian::read_u32(&b[1..5])))
+        } else {
+            None
+        };
+        let upid = error::field_bytes(buf, n, 2, "segmentation_upid_length")?;
+        let segmentation_upid = error::field_bytes(buf, n + 2, upid[1] as usize, "segmentation_upid")?.to_vec();
+        n += 2 + segmentation_upid.len();
+        let b = error::field_bytes(buf, n, 3, "segments_expected")?;
+        let segmentation_type_id = b[0];
+
+        // Older encoders leave out the sub-segment fields, so only read them if they are there
+        let sub_segment = match (segmentation_type_id, buf.get(n + 3..n + 5)) {
+            (0x34 | 0x36 | 0x38 | 0x3A | 0x44 | 0x46, Some(s)) =>
+                Some(SubSegment { sub_segment_num: s[0], sub_segments_expected: s[1] }),
+            _ => None,
+        };
+
+        Ok(Segment {
+            program_segmentation,
+            delivery_restrictions,
+            components,
+            segmentation_duration,
+            segmentation_upid_type: upid[0],
+            segmentation_upid,
+            segmentation_type_id,
+            segment_num: b[1],
+            segments_expected: b[2],
+            sub_segment,
+        })
+    }
+
+    pub fn segmentation_type_name(&self) -> &'static str {
+        match self.segmentation_type_id {
+            0x00 => "Not Indicated",
+            0x01 => "Content Identification",
+            0x10 => "Program Start",
+            0x11 => "Program End",
+            0x12 => "Program Early Termination",
+            0x13 => "Program Breakaway",
+            0x14 => "Program Resumption",
+            0x15 => "Program Runover Planned",
+            0x16 => "Program Runover Unplanned",
+            0x17 => "Program Overlap Start",
+            0x18 => "Program Blackout Override",
+            0x19 => "Program Join",
+            0x20 => "Chapter Start",
+            0x21 => "Chapter End",
+            0x22 => "Break Start",
+            0x23 => "Break End",
+            0x24 => "Opening Credit Start",
+            0x25 => "Opening Credit End",
+            0x26 => "Closing Credit Start",
+            0x27 => "Closing Credit End",
+            0x30 => "Provider Advertisement Start",
+            0x31 => "Provider Advertisement End",
+            0x32 => "Distributor Advertisement Start",
+            0x33 => "Distributor Advertisement End",
+            0x34 => "Provider Placement Opportunity Start",
+            0x35 => "Provider Placement Opportunity End",
+            0x36 => "Distributor Placement Opportunity Start",
+            0x37 => "Distributor Placement Opportunity End",
+            0x38 => "Provider Overlay Placement Opportunity Start",
+            0x39 => "Provider Overlay Placement Opportunity End",
+            0x3A => "Distributor Overlay Placement Opportunity Start",
+            0x3B => "Distributor Overlay Placement Opportunity End",
+            0x3C => "Provider Promo Start",
+            0x3D => "Provider Promo End",
+            0x3E => "Distributor Promo Start",
+            0x3F => "Distributor Promo End",
+            0x40 => "Unscheduled Event Start",
+            0x41 => "Unscheduled Event End",
+            0x42 => "Alternate Content Opportunity Start",
+            0x43 => "Alternate Content Opportunity End",
+            0x44 => "Provider Ad Block Start",
+            0x45 => "Provider Ad Block End",
+            0x46 => "Distributor Ad Block Start",
+            0x47 => "Distributor Ad Block End",
+            0x50 => "Network Start",
+            0x51 => "Network End",
+            _ => "Reserved",
+        }
+    }
+}
+
+/// A splice descriptor of a splice_info_section
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SpliceDescriptor {
+    Avail { provider_avail_id: u32 },
+    /// segmentation_event_id and the segment (None if the descriptor cancels an earlier one)
+    Segmentation { segmentation_event_id: u32, segment: Option<Segment> },
+    /// Any other descriptor, or one with an identifier other than "CUEI"
+    Other { tag: u8, identifier: [u8; 4], data: Vec<u8> },
+}
+
+impl SpliceDescriptor {
+    /// Parse the splice descriptors found in `buf[start..end]`
+    fn parse_loop(buf: &[u8], start: usize, end: usize) -> Result<Vec<SpliceDescriptor>, ParseError> {
+        let buf = error::field_bytes(buf, 0, end, "splice_descriptors")?;
+        let mut n = start;
+        let mut descriptors = vec![];
+        while n < end {
+            let header = error::field_bytes(buf, n, 2, "descriptor_length")?;
+            let (tag, body) = (header[0], error::field_bytes(buf, n + 2, header[1] as usize, "splice_descriptor")?);
+            let b = error::field_bytes(body, 0, 4, "identifier").map_err(|e| e.shift(n + 2))?;
+            let identifier = [b[0], b[1], b[2], b[3]];
+            let descriptor = match (tag, identifier) {
+                (AVAIL_DESCRIPTOR_TAG, CUEI_IDENTIFIER) => SpliceDescriptor::Avail {
+                    provider_avail_id: BigEndian::read_u32(error::field_bytes(body, 4, 4, "provider_avail_id")
+                        .map_err(|e| e.shift(n + 2))?),
+                },
+                (SEGMENTATION_DESCRIPTOR_TAG, CUEI_IDENTIFIER) => {
+                    let b = error::field_bytes(body, 4, 5, "segmentation_event_cancel_indicator")
+                        .map_err(|e| e.shift(n + 2))?;
+                    let segment = if packet::get_bit_at(b[4], 7) {
+                        None
+                    } else {
+                        Some(Segment::new(body, 9).map_err(|e| e.shift(n + 2))?)
+                    };
+                    SpliceDescriptor::Segmentation { segmentation_event_id: BigEndian::read_u32(&b[0..4]), segment }
+                },
+                _ => SpliceDescriptor::Other { tag, identifier, data: body[4..].to_vec() },
+            };
+            descriptors.push(descriptor);
+            n += 2 + body.len();
+        }
+        Ok(descriptors)
+    }
+}
+
+impl fmt::Display for SpliceDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpliceDescriptor::Avail { provider_avail_id } =>
+                write!(f, "Avail: Provider Avail ID: {:#X}", provider_avail_id),
+            SpliceDescriptor::Segmentation { segmentation_event_id, segment: None } =>
+                write!(f, "Segmentation: Event ID: {:#X}, Cancelled", segmentation_event_id),
+            SpliceDescriptor::Segmentation { segmentation_event_id, segment: Some(s) } => {
+                write!(f, "Segmentation: Event ID: {:#X}, Type: {:#X} ({}), Segment: {}/{}",
+                    segmentation_event_id, s.segmentation_type_id, s.segmentation_type_name(),
+                    s.segment_num, s.segments_expected)?;
+                if let Some(sub) = s.sub_segment {
+                    write!(f, ", Sub-segment: {}/{}", sub.sub_segment_num, sub.sub_segments_expected)?;
+                }
+                if let Some(duration) = s.segmentation_duration {
+                    write!(f, ", Duration: {:.3}s", duration as f64 / PES_CLOCK_FREQUENCY as f64)?;
+                }
+                if !s.segmentation_upid.is_empty() {
+                    let upid: String = s.segmentation_upid.iter().map(|b| format!("{:02X}", b)).collect();
+                    write!(f, ", UPID type {:#X}: {}", s.segmentation_upid_type, upid)?;
+                }
+                Ok(())
+            },
+            SpliceDescriptor::Other { tag, identifier, data } =>
+                write!(f, "Tag: {:#X} ({}), length: {}", tag, String::from_utf8_lossy(identifier), data.len()),
+        }
+    }
+}
+
+/// splice_info_section: a SCTE-35 cue
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SpliceInfoSection {
+    protocol_version: u8,
+    /// Whether the command and descriptors are encrypted (and so not decoded)
+    pub encrypted_packet: bool,
+    pub encryption_algorithm: u8,
+    /// Added to every pts_time of the section (e.g. by a splicer that shifted the stream)
+    pub pts_adjustment: u64,
+    pub cw_index: u8,
+    pub tier: u16,
+    pub splice_command_type: u8,
+    /// None if the section is encrypted
+    pub splice_command: Option<SpliceCommand>,
+    pub descriptors: Vec<SpliceDescriptor>,
+    pub crc: u32,
+    pub crc_error: bool,
+}
+
+impl fmt::Display for SpliceInfoSection {
+    /// Display the section along with its command and descriptors
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut command_str = String::new();
+        match &self.splice_command {
+            None => write!(&mut command_str, "\n\t=> Encrypted (algorithm {}, cw_index {})",
+                self.encryption_algorithm, self.cw_index).unwrap(),
+            Some(SpliceCommand::Insert(event)) => write!(&mut command_str, "\n\t=> {}", event).unwrap(),
+            Some(SpliceCommand::Schedule(events)) => for event in events {
+                write!(&mut command_str, "\n\t=> {}", event).unwrap();
+            },
+            Some(SpliceCommand::TimeSignal(Some(time))) =>
+                write!(&mut command_str, "\n\t=> Time: {}", time).unwrap(),
+            Some(_) => (),
+        }
+        for d in &self.descriptors {
+            write!(&mut command_str, "\n\t=> {}", d).unwrap();
+        }
+        let name = self.splice_command.as_ref().map_or("encrypted", |c| c.name());
+        write!(f, "[SCTE-35] Command: {:#X} ({}), PTS adjustment: {}, Tier: {:#X}{}",
+            self.splice_command_type, name, self.pts_adjustment, self.tier, command_str)
+    }
+}
+
+impl SpliceInfoSection {
+    /// Parse a section buffer into a SpliceInfoSection object and return Result<SpliceInfoSection, ParseError>
+    pub fn new(buf: &[u8]) -> Result<SpliceInfoSection, ParseError> {
+        // Calculate length and index fields
+        let end_n = psi::check_section(buf, SPLICE_INFO_TABLE_ID)?;
+        let section = &buf[..end_n];
+        let header = error::field_bytes(section, 3, 11, "splice_command_type")?;
+        let encrypted_packet = packet::get_bit_at(header[1], 7);
+        let splice_command_type = header[10];
+        let splice_command_length = (usize::from(header[8] & 0x0F) << 8) | usize::from(header[9]);
+        let n = 14;
+
+        // The command and descriptors can't be decoded without the key
+        let (splice_command, descriptors) = if encrypted_packet {
+            (None, vec![])
+        } else {
+            let end = if splice_command_length == UNKNOWN_COMMAND_LENGTH { None } else { Some(n + splice_command_length) };
+            let (command, size) = SpliceCommand::new(section, splice_command_type, n, end)?;
+            let b = error::field_bytes(section, n + size, 2, "descriptor_loop_length")?;
+            let start = n + size + 2;
+            let descriptors = SpliceDescriptor::parse_loop(section, start, start + BigEndian::read_u16(b) as usize)?;
+            (Some(command), descriptors)
+        };
+
+        let crc = BigEndian::read_u32(&buf[end_n..end_n + packet::CRC_SIZE]);
+        let exp_crc = mpeg32_crc::crc32_mpeg(&buf[0..end_n]);
+        Ok(SpliceInfoSection {
+            protocol_version: header[0],
+            encrypted_packet,
+            encryption_algorithm: (header[1] >> 1) & 0x3F,
+            pts_adjustment: read_33(&header[1..6]),
+            cw_index: header[6],
+            tier: (BigEndian::read_u16(&header[7..9]) >> 4) & 0x0FFF,
+            splice_command_type,
+            splice_command,
+            descriptors,
+            crc,
+            crc_error: crc != exp_crc,
+        })
+    }
+
+    /// The time of the splice on the PTS clock of the program (pts_time plus the
+    /// pts_adjustment), if the command gives one for the whole program
+    pub fn splice_pts(&self) -> Option<u64> {
+        let pts_time = self.splice_command.as_ref()?.pts_time()?;
+        Some((pts_time + self.pts_adjustment) % PTS_MODULUS)
+    }
+}
+
+/// A splice_info_section along with where it was found
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cue {
+    pub pid: u16,
+    /// The program whose PMT lists the PID (None if the PID was added by hand)
+    pub program_number: Option<u16>,
+    /// Stream offset of the packet completing the section
+    pub offset: u64,
+    pub section: SpliceInfoSection,
+    /// PTS of the last video PES packet of the program before the cue
+    pub video_pts: Option<u64>,
+}
+
+impl Cue {
+    /// How far (in seconds) the splice is ahead of the video of the program, if the
+    /// cue has a splice time and the video has been seen
+    pub fn lead(&self) -> Option<f64> {
+        let splice_pts = self.section.splice_pts()?;
+        let video_pts = self.video_pts?;
+        // Take the shortest way around the 33 bit wrap
+        let mut diff = (splice_pts + PTS_MODULUS - video_pts) % PTS_MODULUS;
+        if diff >= PTS_MODULUS / 2 {
+            diff = diff.wrapping_sub(PTS_MODULUS);
+        }
+        Some(diff as i64 as f64 / PES_CLOCK_FREQUENCY as f64)
+    }
+}
+
+impl fmt::Display for Cue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[CUE] PID {:#X}", self.pid)?;
+        if let Some(program_number) = self.program_number {
+            write!(f, " (Program {})", program_number)?;
+        }
+        write!(f, " at offset {}: {}", self.offset,
+            self.section.splice_command.as_ref().map_or("encrypted", |c| c.name()))?;
+        if let Some(pts) = self.section.splice_pts() {
+            write!(f, ", Splice PTS: {} ({:.6}s)", pts, pts as f64 / PES_CLOCK_FREQUENCY as f64)?;
+        }
+        if let Some(lead) = self.lead() {
+            write!(f, ", {:+.3}s from video", lead)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the SCTE-35 cues of the stream as a DemuxHandler, along with the
+/// PTS of the program's video when each one arrives
+#[derive(Clone, Debug, Default)]
+pub struct CueAnalyzer {
+    /// Program of each SCTE-35 PID
+    cue_pids: HashMap<u16, u16>,
+    /// Video PID of each program
+    video_pids: HashMap<u16, u16>,
+    /// Last PTS of each video PID
+    video_pts: HashMap<u16, u64>,
+    offset: u64,
+    cues: Vec<Cue>,
+    errors: Vec<(u64, ParseError)>,
+}
+
+impl CueAnalyzer {
+    pub fn new() -> CueAnalyzer {
+        Default::default()
+    }
+
+    /// Whether the elementary stream of `pmt` carries SCTE-35 cues: it has the SCTE-35
+    /// stream_type or a CUEI registration descriptor (on its own or on the program)
+    pub fn is_cue_stream(pmt: &Pmt, es: &psi::ElementaryStream) -> bool {
+        es.get_stream_type() == SCTE35_STREAM_TYPE || es.get_format_identifier() == Some(CUEI_IDENTIFIER) ||
+            (pmt.get_format_identifier() == Some(CUEI_IDENTIFIER) && es.carries_sections())
+    }
+
+    /// The SCTE-35 PIDs found so far along with their programs
+    pub fn cue_pids(&self) -> &HashMap<u16, u16> {
+        &self.cue_pids
+    }
+
+    /// Every cue in the order it arrived
+    pub fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+
+    /// The offset of every section of a SCTE-35 PID that failed to parse, with why
+    pub fn errors(&self) -> &[(u64, ParseError)] {
+        &self.errors
+    }
+}
+
+impl DemuxHandler for CueAnalyzer {
+    fn on_packet(&mut self, packet: &Packet) {
+        self.offset = packet.offset;
+    }
+
+    fn on_pmt(&mut self, _pid: u16, pmt: &Pmt) {
+        let program_number = pmt.get_program_number();
+        for es in &pmt.elementary_streams {
+            if CueAnalyzer::is_cue_stream(pmt, es) {
+                self.cue_pids.insert(es.get_elementary_pid(), program_number);
+            }
+        }
+        if let Some(es) = pmt.elementary_streams.iter().find(|es| es.get_codec().is_video()) {
+            self.video_pids.insert(program_number, es.get_elementary_pid());
+        }
+    }
+
+    fn on_pes(&mut self, pid: u16, pes: &Pes) {
+        if let Some(pts) = pes.pts() {
+            self.video_pts.insert(pid, pts);
+        }
+    }
+
+    fn on_section(&mut self, pid: u16, table_id: u8, section: &[u8]) {
+        if table_id != SPLICE_INFO_TABLE_ID {
+            return;
+        }
+        let program_number = self.cue_pids.get(&pid).copied();
+        match SpliceInfoSection::new(section) {
+            Ok(section) => {
+                let video_pts = program_number
+                    .and_then(|p| self.video_pids.get(&p))
+                    .and_then(|video_pid| self.video_pts.get(video_pid))
+                    .copied();
+                self.cues.push(Cue { pid, program_number, offset: self.offset, section, video_pts });
+            },
+            Err(e) => self.errors.push((self.offset, e.with_pid(pid))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    // The time_signal and splice_insert samples of SCTE 35 section 14
+    const TIME_SIGNAL: &[u8] = b"\xFC\x30\x34\x00\x00\x00\x00\x00\x00\xFF\xFF\xF0\x05\x06\xFE\x72\xBD\x00\x50\x00\x1E\x02\x1C\
+        \x43\x55\x45\x49\x48\x00\x00\x8E\x7F\xCF\x00\x01\xA5\x99\xB0\x08\x08\x00\x00\x00\x00\x2C\xA0\xA1\x8A\x34\x02\x00\
+        \x9A\xC9\xD1\x7E";
+    const SPLICE_INSERT: &[u8] = b"\xFC\x30\x2F\x00\x00\x00\x00\x00\x00\xFF\xFF\xF0\x14\x05\x48\x00\x00\x8F\x7F\xEF\xFE\x73\
+        \x69\xC0\x2E\xFE\x00\x52\xCC\xF5\x00\x00\x00\x00\x00\x0A\x00\x08\x43\x55\x45\x49\x00\x00\x01\x35\x62\xDB\xA3\x0A";
+
+    /// A splice_info_section with `command` (and no descriptors)
+    fn command_section(pts_adjustment: u64, splice_command_type: u8, command: &[u8]) -> Vec<u8> {
+        let section_length = 11 + command.len() + 2 + 4;
+        let mut buf = vec![SPLICE_INFO_TABLE_ID, 0x30 | (section_length >> 8) as u8, section_length as u8, 0x00,
+            (pts_adjustment >> 32) as u8 & 0x01];
+        buf.extend_from_slice(&(pts_adjustment as u32).to_be_bytes());
+        buf.extend_from_slice(&[0x00, 0xFF, 0xF0 | (command.len() >> 8) as u8, command.len() as u8, splice_command_type]);
+        buf.extend_from_slice(command);
+        buf.extend_from_slice(&[0x00, 0x00]);
+        test_util::with_crc(buf)
+    }
+
+    #[test]
+    fn samples() {
+        for (buf, expected) in [
+            (TIME_SIGNAL, "[SCTE-35] Command: 0x6 (time_signal), PTS adjustment: 0, Tier: 0xFFF\
+                \n\t=> Time: 1924989008\
+                \n\t=> Segmentation: Event ID: 0x4800008E, Type: 0x34 (Provider Placement Opportunity Start), \
+                Segment: 2/0, Duration: 307.000s, UPID type 0x8: 000000002CA0A18A"),
+            (SPLICE_INSERT, "[SCTE-35] Command: 0x5 (splice_insert), PTS adjustment: 0, Tier: 0xFFF\
+                \n\t=> Event ID: 0x4800008F, Out of network, Time: 1936310318, Duration: 60.294s (auto return), \
+                Program ID: 0, Avail: 0/0\
+                \n\t=> Avail: Provider Avail ID: 0x135"),
+        ] {
+            let section = SpliceInfoSection::new(buf).unwrap();
+            assert!(!section.crc_error);
+            assert_eq!(section.to_string(), expected);
+        }
+
+        let section = SpliceInfoSection::new(TIME_SIGNAL).unwrap();
+        assert_eq!(section.splice_pts(), Some(1_924_989_008));
+        match &section.descriptors[0] {
+            SpliceDescriptor::Segmentation { segment: Some(s), .. } => {
+                assert!(s.program_segmentation);
+                assert_eq!(s.delivery_restrictions, Some(DeliveryRestrictions {
+                    web_delivery_allowed: false, no_regional_blackout: true, archive_allowed: true, device_restrictions: 3,
+                }));
+                assert_eq!(s.sub_segment, None);
+            },
+            d => panic!("{}", d),
+        }
+    }
+
+    #[test]
+    fn commands() {
+        // The pts_adjustment wraps the splice time around
+        let section = SpliceInfoSection::new(&command_section(PTS_MODULUS - 10, 0x06, b"\xFE\x00\x00\x00\x64")).unwrap();
+        assert_eq!(section.splice_pts(), Some(90));
+
+        for (splice_command_type, command, name) in [
+            (0x00, &b""[..], "splice_null"),
+            (0x06, b"\x7F", "time_signal"),
+            (0x07, b"", "bandwidth_reservation"),
+            (0xFF, b"CUEI\x01\x02", "private_command"),
+            (0x10, b"\x01\x02\x03", "reserved"),
+        ] {
+            let section = SpliceInfoSection::new(&command_section(0, splice_command_type, command)).unwrap();
+            assert_eq!(section.splice_pts(), None);
+            assert_eq!(section.splice_command.unwrap().name(), name);
+        }
+
+        // A cancelled insert, and a schedule with one component at a UTC time
+        let section = SpliceInfoSection::new(&command_section(0, 0x05, b"\x00\x00\x00\x01\xFF")).unwrap();
+        assert_eq!(section.splice_command, Some(SpliceCommand::Insert(SpliceEvent { splice_event_id: 1, splice: None })));
+        let section = SpliceInfoSection::new(&command_section(0, 0x04,
+            b"\x01\x00\x00\x00\x02\x7F\x1F\x01\x05\x52\xCF\x4A\x00\x00\x01\x02\x03")).unwrap();
+        assert_eq!(section.to_string(), "[SCTE-35] Command: 0x4 (splice_schedule), PTS adjustment: 0, Tier: 0xFFF\
+            \n\t=> Event ID: 0x2, Back to network, Component 0x5 at 1389316608, Program ID: 1, Avail: 2/3");
+    }
+
+
+    #[test]
+    fn encrypted_and_unknown_commands() {
+        let mut buf = command_section(0, 0x06, b"\x7F");
+        buf[4] |= 0x80;
+        let section = SpliceInfoSection::new(&buf).unwrap();
+        assert!(section.encrypted_packet);
+        assert_eq!(section.splice_command, None);
+        assert!(section.to_string().starts_with("[SCTE-35] Command: 0x6 (encrypted)"), "{}", section);
+
+        // An unknown command can only be skipped with its splice_command_length
+        let mut buf = command_section(0, 0x10, b"\x01\x02\x03");
+        buf[11] |= 0x0F;
+        buf[12] = 0xFF;
+        assert_eq!(SpliceInfoSection::new(&buf),
+            Err(ParseError::InvalidValue { pid: None, offset: 13, field: "splice_command_type", value: 0x10 }));
+    }
+
+    #[test]
+    fn invalid_sections() {
+        for buf in [TIME_SIGNAL, SPLICE_INSERT] {
+            for len in 0..buf.len() {
+                assert!(SpliceInfoSection::new(&buf[..len]).is_err(), "{} bytes", len);
+            }
+        }
+        // The descriptor loop running into the CRC
+        let mut buf = SPLICE_INSERT.to_vec();
+        buf[35] = 0x0B;
+        assert_eq!(SpliceInfoSection::new(&buf), Err(ParseError::Truncated { pid: None, offset: 0, field: "splice_descriptors" }));
+        // An avail_descriptor with nothing after its identifier
+        let mut buf = SPLICE_INSERT.to_vec();
+        buf[35] = 0x06;
+        buf[37] = 0x04;
+        assert_eq!(SpliceInfoSection::new(&buf), Err(ParseError::Truncated { pid: None, offset: 42, field: "provider_avail_id" }));
+        // A segmentation_upid running past its descriptor
+        let mut buf = TIME_SIGNAL.to_vec();
+        buf[39] = 0x10;
+        assert_eq!(SpliceInfoSection::new(&buf), Err(ParseError::Truncated { pid: None, offset: 40, field: "segmentation_upid" }));
+    }
+}
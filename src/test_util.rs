@@ -0,0 +1,147 @@
+//! Builders for the buffers the unit tests feed to the parsers
+use crate::mpeg32_crc;
+use crate::packet::{PACKET_SIZE, SYNC_BYTE_VAL};
+
+/// A long form section (version 0, current, section 0 of 0) around `body`, with its CRC
+pub fn section(table_id: u8, table_id_extension: u16, body: &[u8]) -> Vec<u8> {
+    let section_length = 5 + body.len() + 4;
+    let mut buf = vec![table_id, 0xB0 | (section_length >> 8) as u8, section_length as u8];
+    buf.extend_from_slice(&table_id_extension.to_be_bytes());
+    buf.extend_from_slice(&[0xC1, 0x00, 0x00]);
+    buf.extend_from_slice(body);
+    with_crc(buf)
+}
+
+/// Append the CRC_32 of `buf`
+pub fn with_crc(mut buf: Vec<u8>) -> Vec<u8> {
+    let crc = mpeg32_crc::crc32_mpeg(&buf);
+    buf.extend_from_slice(&crc.to_be_bytes());
+    buf
+}
+
+/// A descriptor with tag `tag` around `body`
+pub fn descriptor(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut buf = vec![tag, body.len() as u8];
+    buf.extend_from_slice(body);
+    buf
+}
+
+/// A transport packet with an optional adaptation field (its bytes after
+/// adaptation_field_length) and `payload`, padded with stuffing bytes
+pub fn packet(pid: u16, unit_start: bool, cc: u8, adaptation_field: Option<&[u8]>, payload: &[u8]) -> Vec<u8> {
+    let control = match (adaptation_field.is_some(), payload.is_empty()) {
+        (true, true) => 0x20,
+        (true, false) => 0x30,
+        (false, _) => 0x10,
+    };
+    let mut buf = vec![SYNC_BYTE_VAL, (pid >> 8) as u8 | if unit_start { 0x40 } else { 0 }, pid as u8, control | cc];
+    if let Some(af) = adaptation_field {
+        // Stuff the adaptation field so the payload ends the packet
+        let length = PACKET_SIZE - 4 - 1 - payload.len();
+        buf.push(length as u8);
+        buf.extend_from_slice(af);
+        buf.resize(5 + length, 0xFF);
+    }
+    buf.extend_from_slice(payload);
+    buf.resize(PACKET_SIZE, 0xFF);
+    buf
+}
+
+/// Adaptation field bytes (after adaptation_field_length) with just a PCR of `ticks`
+pub fn pcr_field(ticks: u64) -> Vec<u8> {
+    let value = (ticks / 300) << 15 | 0x3F << 9 | (ticks % 300);
+    [&[0x10], &value.to_be_bytes()[2..]].concat()
+}
+
+/// `len` pseudo random bytes (the same ones for the same `seed`)
+pub fn garbage(seed: u32, len: usize) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9E37_79B9) | 1;
+    (0..len).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state >> 24) as u8
+    }).collect()
+}
+
+/// Feed `parse` every truncation of `valid`, `valid` with each byte replaced by 0x00 and
+/// 0xFF, and a few hundred garbage buffers: it may fail, but must not panic
+pub fn hostile_inputs<F: FnMut(&[u8])>(valid: &[u8], mut parse: F) {
+    for len in 0..valid.len() {
+        parse(&valid[..len]);
+    }
+    for i in 0..valid.len() {
+        for b in [0x00, 0xFF] {
+            let mut buf = valid.to_vec();
+            buf[i] = b;
+            parse(&buf);
+        }
+    }
+    for seed in 0..256 {
+        let mut buf = valid.to_vec();
+        // Keep the leading bytes sometimes, so the garbage gets past the first checks
+        let keep = (seed as usize % 4).min(buf.len());
+        buf.truncate(keep);
+        buf.extend(garbage(seed, 8 + seed as usize % 64));
+        parse(&buf);
+    }
+}
+
+/// Builds the bitstreams of the video and audio tests, most significant bit first
+#[derive(Default)]
+pub struct BitWriter {
+    buf: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    /// Append the `n` low bits of `value`
+    pub fn bits(&mut self, n: u32, value: u64) -> &mut BitWriter {
+        for i in (0..n).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.buf.push(0);
+            }
+            *self.buf.last_mut().unwrap() |= (((value >> i) & 1) as u8) << (7 - self.bits % 8);
+            self.bits += 1;
+        }
+        self
+    }
+
+    pub fn flag(&mut self, flag: bool) -> &mut BitWriter {
+        self.bits(1, u64::from(flag))
+    }
+
+    /// Append an Exp-Golomb coded ue(v)
+    pub fn ue(&mut self, value: u32) -> &mut BitWriter {
+        let value = u64::from(value) + 1;
+        let length = 64 - value.leading_zeros();
+        self.bits(length - 1, 0).bits(length, value)
+    }
+
+    /// The bits so far, padded with zeros to a whole byte
+    pub fn finish(&self) -> Vec<u8> {
+        self.buf.clone()
+    }
+
+    /// The bits so far followed by the rbsp_trailing_bits
+    pub fn rbsp(&mut self) -> Vec<u8> {
+        self.bits(1, 1);
+        self.finish()
+    }
+}
+
+/// A NAL unit with its start code, header and `rbsp` (with emulation_prevention_three_bytes)
+pub fn nal_unit(header: &[u8], rbsp: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0x00, 0x00, 0x00, 0x01];
+    buf.extend_from_slice(header);
+    let mut zeros = 0;
+    for &b in rbsp {
+        if zeros >= 2 && b <= 0x03 {
+            buf.push(0x03);
+            zeros = 0;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        buf.push(b);
+    }
+    buf
+}
@@ -0,0 +1,737 @@
+use std::fmt;
+use std::collections::{BTreeMap, HashMap};
+use crate::demux::DemuxHandler;
+use crate::packet::{Packet, NULL_PACKET_PID, SYSTEM_CLOCK_FREQUENCY};
+use crate::psi::{pmt::Pmt, psip::Psip, tdt::{LocalTimeOffset, Tdt, Tot}, time::UtcTime};
+
+// Constants
+/// PCRs wrap around after 2^33 * 300 ticks of the 27 MHz clock
+const PCR_MODULUS: u64 = (1 << 33) * 300;
+/// M2TS arrival time stamps wrap around after 2^30 ticks of the 27 MHz clock
+const ATS_MODULUS: u64 = 1 << 30;
+
+/// Where the arrival times of the PCR packets come from
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ArrivalClock {
+    /// The arrival_time_stamp of the M2TS TP_extra_header
+    Ats,
+    /// The byte position of the packet at the mean transport rate. The recovered
+    /// clock is then relative to the stream itself, so the mean frequency offset is 0
+    BytePosition,
+}
+
+impl fmt::Display for ArrivalClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArrivalClock::Ats => write!(f, "arrival time stamps"),
+            ArrivalClock::BytePosition => write!(f, "byte position"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Largest jump (in seconds) between consecutive PCRs that isn't a discontinuity
+    pub max_pcr_interval: f64,
+    /// Length (in seconds) of the window the frequency offset is measured over
+    pub frequency_window: f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_pcr_interval: 0.1,
+            frequency_window: 10.0,
+        }
+    }
+}
+
+/// Measurements at a single PCR. All times are in seconds
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PcrSample {
+    /// Stream offset of the packet carrying the PCR
+    pub offset: u64,
+    /// The PCR in units of the 27 MHz clock
+    pub pcr: u64,
+    /// Arrival time of the packet since the first PCR packet of the PID
+    pub arrival: f64,
+    /// Time since the previous PCR (None after a discontinuity)
+    pub interval: Option<f64>,
+    /// Transport rate in bits/s since the previous PCR
+    pub rate: Option<f64>,
+    /// Deviation of the PCR from the constant rate between its neighbours (PCR_AC)
+    pub accuracy: Option<f64>,
+    /// Deviation of the PCR from the recovered clock (PCR_OJ)
+    pub jitter: f64,
+    /// Frequency offset of the PCRs over the last frequency window in ppm (PCR_FO)
+    pub frequency_offset: Option<f64>,
+}
+
+/// A point where the PCRs stop following on from each other
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Discontinuity {
+    /// Stream offset of the first packet after the discontinuity
+    pub offset: u64,
+    /// Whether the discontinuity_indicator was set
+    pub signalled: bool,
+    /// Jump of the PCR in seconds (negative if it went backwards)
+    pub jump: f64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stats {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+impl Stats {
+    fn new<I: Iterator<Item = f64>>(values: I) -> Option<Stats> {
+        let mut count = 0;
+        let mut stats = Stats { min: f64::MAX, mean: 0.0, max: f64::MIN };
+        for v in values {
+            count += 1;
+            stats.min = stats.min.min(v);
+            stats.max = stats.max.max(v);
+            stats.mean += v;
+        }
+        if count == 0 {
+            return None;
+        }
+        stats.mean /= count as f64;
+        Some(stats)
+    }
+}
+
+/// Summary of the PCR measurements of a PID. All times are in seconds
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimingSummary {
+    pub pcr_count: usize,
+    pub interval: Option<Stats>,
+    /// Transport rate in bits/s
+    pub rate: Option<Stats>,
+    /// Largest absolute PCR_AC
+    pub max_accuracy: Option<f64>,
+    pub jitter_peak_to_peak: f64,
+    pub jitter_rms: f64,
+    /// Mean frequency offset over the stream in ppm
+    pub frequency_offset: Option<f64>,
+    /// Change of the frequency offset in ppm per second (PCR_DR)
+    pub drift: Option<f64>,
+}
+
+/// The recovered system clock of a program
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgramTiming {
+    /// None if the PCR PID isn't referenced by any PMT
+    pub program_number: Option<u16>,
+    pub pcr_pid: u16,
+    pub arrival_clock: ArrivalClock,
+    pub samples: Vec<PcrSample>,
+    pub discontinuities: Vec<Discontinuity>,
+    pub summary: TimingSummary,
+}
+
+impl fmt::Display for ProgramTiming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = &self.summary;
+        match self.program_number {
+            Some(n) => write!(f, "[Timing] Program Number: {}", n)?,
+            None => write!(f, "[Timing] No program")?,
+        }
+        write!(f, ", PCR PID: {0:#X} ({0}), Arrival from {1}", self.pcr_pid, self.arrival_clock)?;
+        write!(f, "\n\t=> PCRs: {}, Discontinuities: {}", s.pcr_count, self.discontinuities.len())?;
+        if let Some(i) = s.interval {
+            write!(f, "\n\t=> Interval: min {:.3}ms, mean {:.3}ms, max {:.3}ms",
+                i.min * 1e3, i.mean * 1e3, i.max * 1e3)?;
+        }
+        if let Some(r) = s.rate {
+            write!(f, "\n\t=> Transport rate: min {:.0}, mean {:.0}, max {:.0} bit/s", r.min, r.mean, r.max)?;
+        }
+        if let Some(a) = s.max_accuracy {
+            write!(f, "\n\t=> Accuracy: max {:.0}ns", a * 1e9)?;
+        }
+        write!(f, "\n\t=> Overall jitter: {:.0}ns peak to peak, {:.0}ns RMS",
+            s.jitter_peak_to_peak * 1e9, s.jitter_rms * 1e9)?;
+        if let Some(fo) = s.frequency_offset {
+            write!(f, "\n\t=> Frequency offset: {:+.3}ppm ({:+.1}Hz)", fo, ppm_to_hz(fo))?;
+        }
+        if let Some(dr) = s.drift {
+            write!(f, "\n\t=> Drift: {:+.6}ppm/s ({:+.4}Hz/s)", dr, ppm_to_hz(dr))?;
+        }
+        for d in &self.discontinuities {
+            write!(f, "\n\t=> Discontinuity at offset {}: PCR jumped by {:+.6}s ({})", d.offset, d.jump,
+                if d.signalled { "signalled" } else { "not signalled" })?;
+        }
+        Ok(())
+    }
+}
+
+/// A PCR as it was read from the stream
+#[derive(Copy, Clone, Debug)]
+struct RawPcr {
+    offset: u64,
+    pcr: u64,
+    ats: Option<u32>,
+    discontinuity: bool,
+}
+
+/// Recovers the 27 MHz system clock of each program from its PCRs as a DemuxHandler.
+///
+/// The PCRs of every PID are collected as the stream is read and measured
+/// (following TR 101 290 Annex I) once `programs` is called.
+#[derive(Clone, Debug, Default)]
+pub struct ClockAnalyzer {
+    config: Config,
+    pcrs: HashMap<u16, Vec<RawPcr>>,
+    /// PCR PID of each program
+    programs: BTreeMap<u16, u16>,
+}
+
+impl ClockAnalyzer {
+    pub fn new(config: Config) -> ClockAnalyzer {
+        ClockAnalyzer {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Measure the clock of every program with PCRs, followed by any PCR PIDs
+    /// that no program refers to
+    pub fn programs(&self) -> Vec<ProgramTiming> {
+        let mut timings = vec![];
+        for (program_number, pcr_pid) in &self.programs {
+            if let Some(pcrs) = self.pcrs.get(pcr_pid) {
+                timings.push(self.measure(Some(*program_number), *pcr_pid, pcrs));
+            }
+        }
+        let mut orphans: Vec<_> = self.pcrs.keys()
+            .filter(|pid| !self.programs.values().any(|p| p == *pid))
+            .collect();
+        orphans.sort();
+        for pid in orphans {
+            timings.push(self.measure(None, *pid, &self.pcrs[pid]));
+        }
+        timings
+    }
+
+    fn measure(&self, program_number: Option<u16>, pcr_pid: u16, pcrs: &[RawPcr]) -> ProgramTiming {
+        let freq = SYSTEM_CLOCK_FREQUENCY as f64;
+
+        // Split the PCRs into runs without discontinuities, unwrapping them within each run
+        let mut runs: Vec<std::ops::Range<usize>> = vec![];
+        let mut discontinuities = vec![];
+        let mut unwrapped = vec![0u64; pcrs.len()];
+        let mut start = 0;
+        for i in 1..pcrs.len() {
+            let delta = (pcrs[i].pcr + PCR_MODULUS - pcrs[i - 1].pcr) % PCR_MODULUS;
+            if pcrs[i].discontinuity || delta as f64 / freq > self.config.max_pcr_interval {
+                let jump = if delta > PCR_MODULUS / 2 {
+                    -((PCR_MODULUS - delta) as f64)
+                } else {
+                    delta as f64
+                };
+                discontinuities.push(Discontinuity {
+                    offset: pcrs[i].offset,
+                    signalled: pcrs[i].discontinuity,
+                    jump: jump / freq,
+                });
+                runs.push(start..i);
+                start = i;
+            } else {
+                unwrapped[i] = unwrapped[i - 1] + delta;
+            }
+        }
+        runs.push(start..pcrs.len());
+
+        // Arrival times in seconds
+        let arrival_clock = if pcrs.iter().all(|p| p.ats.is_some()) {
+            ArrivalClock::Ats
+        } else {
+            ArrivalClock::BytePosition
+        };
+        let arrivals: Vec<f64> = match arrival_clock {
+            ArrivalClock::Ats => {
+                let mut ticks = 0;
+                let mut arrivals = vec![0.0; pcrs.len()];
+                for i in 1..pcrs.len() {
+                    let (prev, ats) = (pcrs[i - 1].ats.unwrap_or(0), pcrs[i].ats.unwrap_or(0));
+                    ticks += (u64::from(ats) + ATS_MODULUS - u64::from(prev)) % ATS_MODULUS;
+                    arrivals[i] = ticks as f64 / freq;
+                }
+                arrivals
+            },
+            ArrivalClock::BytePosition => {
+                // The mean transport rate over the runs gives the time of each byte
+                let bytes: u64 = runs.iter().map(|r| pcrs[r.end - 1].offset - pcrs[r.start].offset).sum();
+                let ticks: u64 = runs.iter().map(|r| unwrapped[r.end - 1]).sum();
+                let first = pcrs.first().map_or(0, |p| p.offset);
+                pcrs.iter().map(|p| match bytes {
+                    0 => 0.0,
+                    _ => (p.offset - first) as f64 * ticks as f64 / bytes as f64 / freq,
+                }).collect()
+            },
+        };
+
+        let mut samples = Vec::with_capacity(pcrs.len());
+        let window = self.config.frequency_window;
+        for run in &runs {
+            // The recovered clock is the straight line through the PCRs of the run
+            let points: Vec<(f64, f64)> = run.clone()
+                .map(|i| (arrivals[i], unwrapped[i] as f64 / freq))
+                .collect();
+            let (intercept, slope) = linear_fit(&points).unwrap_or((points[0].1 - points[0].0, 1.0));
+
+            let mut window_start = run.start;
+            for i in run.clone() {
+                let pcr_time = unwrapped[i] as f64 / freq;
+                let mut sample = PcrSample {
+                    offset: pcrs[i].offset,
+                    pcr: pcrs[i].pcr,
+                    arrival: arrivals[i],
+                    interval: None,
+                    rate: None,
+                    accuracy: None,
+                    jitter: pcr_time - (intercept + slope * arrivals[i]),
+                    frequency_offset: None,
+                };
+                if i > run.start {
+                    let interval = (unwrapped[i] - unwrapped[i - 1]) as f64 / freq;
+                    sample.interval = Some(interval);
+                    if interval > 0.0 {
+                        sample.rate = Some((pcrs[i].offset - pcrs[i - 1].offset) as f64 * 8.0 / interval);
+                    }
+                }
+                if i > run.start && i + 1 < run.end {
+                    let bytes = (pcrs[i + 1].offset - pcrs[i - 1].offset) as f64;
+                    if bytes > 0.0 {
+                        let span = (unwrapped[i + 1] - unwrapped[i - 1]) as f64;
+                        let expected = span * (pcrs[i].offset - pcrs[i - 1].offset) as f64 / bytes;
+                        sample.accuracy = Some(((unwrapped[i] - unwrapped[i - 1]) as f64 - expected) / freq);
+                    }
+                }
+                // Only measure the frequency once a whole window has been seen
+                if arrivals[i] - arrivals[run.start] >= window {
+                    while window_start + 1 < i && arrivals[i] - arrivals[window_start + 1] >= window {
+                        window_start += 1;
+                    }
+                    let elapsed = arrivals[i] - arrivals[window_start];
+                    let pcr_elapsed = (unwrapped[i] - unwrapped[window_start]) as f64 / freq;
+                    if elapsed > 0.0 {
+                        sample.frequency_offset = Some((pcr_elapsed / elapsed - 1.0) * 1e6);
+                    }
+                }
+                samples.push(sample);
+            }
+        }
+
+        let summary = summarize(&samples, &runs, &arrivals, &unwrapped);
+        ProgramTiming {
+            program_number,
+            pcr_pid,
+            arrival_clock,
+            samples,
+            discontinuities,
+            summary,
+        }
+    }
+}
+
+fn summarize(samples: &[PcrSample], runs: &[std::ops::Range<usize>], arrivals: &[f64], unwrapped: &[u64])
+    -> TimingSummary {
+    let jitter = Stats::new(samples.iter().map(|s| s.jitter));
+    let jitter_rms = match samples.len() {
+        0 => 0.0,
+        n => (samples.iter().map(|s| s.jitter * s.jitter).sum::<f64>() / n as f64).sqrt(),
+    };
+
+    // Mean frequency offset: the PCR time over the arrival time of all the runs
+    let elapsed: f64 = runs.iter().map(|r| arrivals[r.end - 1] - arrivals[r.start]).sum();
+    let pcr_elapsed: f64 = runs.iter()
+        .map(|r| unwrapped[r.end - 1] as f64 / SYSTEM_CLOCK_FREQUENCY as f64)
+        .sum();
+    let frequency_offset = if elapsed > 0.0 {
+        Some((pcr_elapsed / elapsed - 1.0) * 1e6)
+    } else {
+        None
+    };
+
+    let frequency_points: Vec<(f64, f64)> = samples.iter()
+        .filter_map(|s| s.frequency_offset.map(|fo| (s.arrival, fo)))
+        .collect();
+
+    TimingSummary {
+        pcr_count: samples.len(),
+        interval: Stats::new(samples.iter().filter_map(|s| s.interval)),
+        rate: Stats::new(samples.iter().filter_map(|s| s.rate)),
+        max_accuracy: samples.iter().filter_map(|s| s.accuracy).map(f64::abs).reduce(f64::max),
+        jitter_peak_to_peak: jitter.map_or(0.0, |j| j.max - j.min),
+        jitter_rms,
+        frequency_offset,
+        drift: linear_fit(&frequency_points).map(|(_, slope)| slope),
+    }
+}
+
+/// Least squares fit of a straight line through `points`: Some((intercept, slope))
+/// or None if there are too few points or they all have the same x
+fn linear_fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let mut sxx = 0.0;
+    let mut sxy = 0.0;
+    for (x, y) in points {
+        sxx += (x - mean_x) * (x - mean_x);
+        sxy += (x - mean_x) * (y - mean_y);
+    }
+    if sxx == 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some((mean_y - slope * mean_x, slope))
+}
+
+/// Convert parts per million of the 27 MHz clock to Hz
+fn ppm_to_hz(ppm: f64) -> f64 {
+    ppm * SYSTEM_CLOCK_FREQUENCY as f64 / 1e6
+}
+
+impl DemuxHandler for ClockAnalyzer {
+    fn on_packet(&mut self, packet: &Packet) {
+        if let Some(pcr) = packet.pcr() {
+            self.pcrs.entry(packet.pid).or_default().push(RawPcr {
+                offset: packet.offset,
+                pcr: pcr.value(),
+                ats: packet.tp_extra_header.map(|h| h.arrival_time_stamp),
+                discontinuity: packet.is_discontinuity(),
+            });
+        }
+    }
+
+    fn on_pmt(&mut self, _pid: u16, pmt: &Pmt) {
+        if pmt.get_pcr_pid() != NULL_PACKET_PID {
+            self.programs.insert(pmt.get_program_number(), pmt.get_pcr_pid());
+        }
+    }
+}
+
+/// Estimates the stream time of each packet from the PCRs of a single PID,
+/// interpolating between them from the byte offset of the packet
+#[derive(Clone, Debug, Default)]
+pub struct StreamClock {
+    pcr_pid: Option<u16>,
+    /// Offset and value of the last PCR
+    last: Option<(u64, u64)>,
+    /// Time (27 MHz ticks since the first PCR) of the last PCR
+    elapsed: u64,
+    /// 27 MHz ticks per byte, from the last two PCRs
+    ticks_per_byte: Option<f64>,
+}
+
+impl StreamClock {
+    pub fn update(&mut self, packet: &Packet) {
+        let pcr = match packet.pcr() {
+            Some(pcr) => pcr.value(),
+            None => return,
+        };
+        // Follow the first PID that carries a PCR
+        if *self.pcr_pid.get_or_insert(packet.pid) != packet.pid {
+            return;
+        }
+        if let Some((offset, last_pcr)) = self.last {
+            let bytes = packet.offset.saturating_sub(offset);
+            let delta = (pcr + PCR_MODULUS - last_pcr) % PCR_MODULUS;
+            if packet.is_discontinuity() || delta > SYSTEM_CLOCK_FREQUENCY {
+                // The PCR jumped: carry on at the last known rate
+                self.elapsed += self.ticks_per_byte.map_or(0, |t| (bytes as f64 * t) as u64);
+            } else {
+                self.elapsed += delta;
+                if bytes > 0 {
+                    self.ticks_per_byte = Some(delta as f64 / bytes as f64);
+                }
+            }
+        }
+        self.last = Some((packet.offset, pcr));
+    }
+
+    /// Stream time in seconds of the packet at `offset`
+    pub fn time(&self, offset: u64) -> Option<f64> {
+        let (last_offset, _) = self.last?;
+        let since = self.ticks_per_byte.map_or(0.0, |t| offset.saturating_sub(last_offset) as f64 * t);
+        Some((self.elapsed as f64 + since) / SYSTEM_CLOCK_FREQUENCY as f64)
+    }
+
+    /// The PCR the packet at `offset` would carry, extrapolated from the last one
+    pub fn pcr(&self, offset: u64) -> Option<u64> {
+        let (last_offset, last_pcr) = self.last?;
+        let since = self.ticks_per_byte.map_or(0.0, |t| offset.saturating_sub(last_offset) as f64 * t);
+        Some((last_pcr + since as u64) % PCR_MODULUS)
+    }
+
+    /// Seconds per byte at the current transport rate
+    pub fn seconds_per_byte(&self) -> Option<f64> {
+        self.ticks_per_byte.map(|t| t / SYSTEM_CLOCK_FREQUENCY as f64)
+    }
+}
+
+/// A UTC time from a TDT, TOT or STT along with where it was found in the stream
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TimeReference {
+    /// Stream offset of the packet completing the table
+    pub offset: u64,
+    /// Stream time (in seconds since the first PCR) at `offset`, if a PCR has been seen yet
+    pub stream_time: Option<f64>,
+    /// The PCR at `offset`, extrapolated from the last one
+    pub pcr: Option<u64>,
+    pub utc: UtcTime,
+}
+
+/// Maps positions in the stream (stream times, offsets and PCRs) to UTC
+/// wall-clock time from the TDTs and TOTs (or the ATSC STTs) as a DemuxHandler.
+///
+/// The tables only carry whole seconds, so the mapping is accurate to about a second.
+#[derive(Clone, Debug, Default)]
+pub struct WallClock {
+    clock: StreamClock,
+    /// Offset of the packet being handled
+    offset: u64,
+    references: Vec<TimeReference>,
+    local_time_offsets: Vec<LocalTimeOffset>,
+}
+
+impl WallClock {
+    pub fn new() -> WallClock {
+        Default::default()
+    }
+
+    /// The clock the stream times are measured with
+    pub fn stream_clock(&self) -> &StreamClock {
+        &self.clock
+    }
+
+    /// Every TDT/TOT/STT time in order of appearance
+    pub fn references(&self) -> &[TimeReference] {
+        &self.references
+    }
+
+    /// The local time offsets of the last TOT
+    pub fn local_time_offsets(&self) -> &[LocalTimeOffset] {
+        &self.local_time_offsets
+    }
+
+    /// UTC at `stream_time` (in seconds since the first PCR, e.g. `StreamClock::time`)
+    pub fn utc_at_time(&self, stream_time: f64) -> Option<UtcTime> {
+        let timed = self.references.iter().filter_map(|r| r.stream_time.map(|t| (t, r.utc)));
+        let (time, utc) = timed.clone().rfind(|(t, _)| *t <= stream_time)
+            .or_else(|| timed.clone().next())?;
+        Some(utc.add_seconds((stream_time - time).floor() as i64))
+    }
+
+    /// UTC at the packet at stream `offset`, assuming a constant transport rate
+    pub fn utc_at_offset(&self, offset: u64) -> Option<UtcTime> {
+        let reference = self.references.iter().rfind(|r| r.offset <= offset)
+            .or_else(|| self.references.first())?;
+        let bytes = offset as f64 - reference.offset as f64;
+        let seconds = self.clock.seconds_per_byte().map_or(0.0, |s| bytes * s);
+        Some(reference.utc.add_seconds(seconds.floor() as i64))
+    }
+
+    /// UTC at `pcr` (in units of the 27 MHz clock), from the time table closest to it
+    pub fn utc_at_pcr(&self, pcr: u64) -> Option<UtcTime> {
+        // Difference to the PCR of a reference, taking the wrap around into account
+        let ticks = |reference: u64| {
+            let delta = ((pcr + PCR_MODULUS - reference) % PCR_MODULUS) as i64;
+            if delta > (PCR_MODULUS / 2) as i64 { delta - PCR_MODULUS as i64 } else { delta }
+        };
+        let (delta, utc) = self.references.iter()
+            .filter_map(|r| r.pcr.map(|p| (ticks(p), r.utc)))
+            .min_by_key(|(delta, _)| delta.abs())?;
+        Some(utc.add_seconds((delta as f64 / SYSTEM_CLOCK_FREQUENCY as f64).floor() as i64))
+    }
+
+    fn add_reference(&mut self, utc: UtcTime) {
+        self.references.push(TimeReference {
+            offset: self.offset,
+            stream_time: self.clock.time(self.offset),
+            pcr: self.clock.pcr(self.offset),
+            utc,
+        });
+    }
+}
+
+impl DemuxHandler for WallClock {
+    fn on_packet(&mut self, packet: &Packet) {
+        self.clock.update(packet);
+        self.offset = packet.offset;
+    }
+
+    fn on_tdt(&mut self, _pid: u16, tdt: &Tdt) {
+        self.add_reference(tdt.utc_time);
+    }
+
+    fn on_tot(&mut self, _pid: u16, tot: &Tot) {
+        self.add_reference(tot.utc_time);
+        self.local_time_offsets = tot.local_time_offsets.clone();
+    }
+
+    fn on_psip(&mut self, _pid: u16, psip: &Psip) {
+        if let Psip::Stt(stt) = psip {
+            self.add_reference(stt.utc_time());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{TpExtraHeader, PACKET_SIZE};
+    use crate::test_util;
+
+    /// 27 MHz ticks between PCRs: 30ms
+    const PCR_TICKS: u64 = 810_000;
+    /// Packets between PCRs
+    const PCR_PACKETS: u64 = 10;
+
+    /// Feed a ClockAnalyzer PCRs on PID 0x100, every PCR_PACKETS packets, given as
+    /// (pcr, arrival time stamp, discontinuity_indicator)
+    fn measure(pcrs: &[(u64, Option<u64>, bool)]) -> ProgramTiming {
+        let mut analyzer = ClockAnalyzer::new(Config::default());
+        for (i, (pcr, ats, discontinuity)) in pcrs.iter().enumerate() {
+            let mut adaptation_field = test_util::pcr_field(*pcr);
+            if *discontinuity {
+                adaptation_field[0] |= 0x80;
+            }
+            let mut packet = Packet::new(&test_util::packet(0x100, false, 0, Some(&adaptation_field), &[])).unwrap();
+            packet.offset = i as u64 * PCR_PACKETS * PACKET_SIZE as u64;
+            packet.tp_extra_header = ats.map(|ats| TpExtraHeader {
+                copy_permission_indicator: 0,
+                arrival_time_stamp: (ats % ATS_MODULUS) as u32,
+            });
+            analyzer.on_packet(&packet);
+        }
+        let mut programs = analyzer.programs();
+        assert_eq!(programs.len(), 1);
+        programs.remove(0)
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{} isn't {}", actual, expected);
+    }
+
+    #[test]
+    fn constant_rate() {
+        let timing = measure(&(0..400).map(|i| (i * PCR_TICKS, None, false)).collect::<Vec<_>>());
+        assert_eq!(timing.program_number, None);
+        assert_eq!(timing.arrival_clock, ArrivalClock::BytePosition);
+        assert_eq!(timing.discontinuities, vec![]);
+        let s = timing.summary;
+        assert_eq!(s.pcr_count, 400);
+        let interval = s.interval.unwrap();
+        assert_close(interval.min, 0.03, 1e-12);
+        assert_close(interval.max, 0.03, 1e-12);
+        // 1880 bytes every 30ms
+        assert_close(s.rate.unwrap().mean, 1880.0 * 8.0 / 0.03, 1e-6);
+        assert_close(s.max_accuracy.unwrap(), 0.0, 1e-12);
+        assert_close(s.jitter_peak_to_peak, 0.0, 1e-12);
+        assert_close(s.frequency_offset.unwrap(), 0.0, 1e-9);
+        assert_close(s.drift.unwrap(), 0.0, 1e-9);
+        // Only the PCRs a whole frequency window after the first have a frequency offset
+        let first_fo = timing.samples.iter().position(|s| s.frequency_offset.is_some());
+        assert_eq!(first_fo, Some(334));
+    }
+
+    #[test]
+    fn jitter() {
+        // PCRs arriving on time but 10us late, early, early, late and so on, which
+        // leaves the recovered clock where it would be without the jitter
+        let late = |i: u64| (i + 1) % 4 < 2;
+        let pcrs: Vec<_> = (0..400).map(|i| {
+            let ticks = i * PCR_TICKS;
+            (if late(i) { ticks + 270 } else { ticks - 270 }, Some(ticks), false)
+        }).collect();
+        let timing = measure(&pcrs);
+        assert_eq!(timing.arrival_clock, ArrivalClock::Ats);
+        for (i, sample) in timing.samples.iter().enumerate() {
+            assert_close(sample.jitter, if late(i as u64) { 10e-6 } else { -10e-6 }, 1e-12);
+        }
+        let s = timing.summary;
+        assert_close(s.jitter_peak_to_peak, 20e-6, 2e-9);
+        assert_close(s.jitter_rms, 10e-6, 1e-9);
+        // Each PCR is 10us off the line between its neighbours
+        assert_eq!(timing.samples[0].accuracy, None);
+        assert_close(timing.samples[1].accuracy.unwrap(), -10e-6, 1e-12);
+        assert_close(timing.samples[3].accuracy.unwrap(), 10e-6, 1e-12);
+        assert_close(s.max_accuracy.unwrap(), 10e-6, 1e-12);
+        assert_eq!(timing.samples[399].accuracy, None);
+    }
+
+    #[test]
+    fn frequency_offset_and_drift() {
+        // A clock 10ppm fast that speeds up by 0.1ppm/s, over 30s
+        let pcrs: Vec<_> = (0..1000).map(|i| {
+            let ticks = i * PCR_TICKS;
+            let t = ticks as f64 / SYSTEM_CLOCK_FREQUENCY as f64;
+            let pcr = t + (10.0 * t + 0.1 * t * t / 2.0) * 1e-6;
+            ((pcr * SYSTEM_CLOCK_FREQUENCY as f64).round() as u64, Some(ticks), false)
+        }).collect();
+        let timing = measure(&pcrs);
+        // Over each window (10.02s: a whole number of PCRs) the offset is the one at its
+        // middle, give or take the rounding of the PCRs to whole ticks
+        let last = timing.samples.last().unwrap();
+        assert_close(last.frequency_offset.unwrap(), 10.0 + 0.1 * (last.arrival - 10.02 / 2.0), 4e-3);
+        let s = timing.summary;
+        assert_close(s.frequency_offset.unwrap(), 10.0 + 0.1 * 29.97 / 2.0, 1e-3);
+        assert_close(s.drift.unwrap(), 0.1, 1e-4);
+        assert!(timing.to_string().contains("Drift: +0.1"), "{}", timing);
+    }
+
+    #[test]
+    fn wrap_around() {
+        // The PCRs and arrival time stamps both wrap around half way
+        let start = PCR_MODULUS - 50 * PCR_TICKS;
+        let pcrs: Vec<_> = (0..100)
+            .map(|i| ((start + i * PCR_TICKS) % PCR_MODULUS, Some(ATS_MODULUS - 50 * PCR_TICKS + i * PCR_TICKS), false))
+            .collect();
+        let timing = measure(&pcrs);
+        assert_eq!(timing.discontinuities, vec![]);
+        let s = timing.summary;
+        assert_close(s.interval.unwrap().max, 0.03, 1e-12);
+        assert_close(s.jitter_peak_to_peak, 0.0, 1e-12);
+        assert_close(s.frequency_offset.unwrap(), 0.0, 1e-9);
+    }
+
+    #[test]
+    fn discontinuities() {
+        // A second's jump forward, then back with the discontinuity_indicator
+        let pcrs: Vec<_> = (0..30).map(|i| match i {
+            0..=9 => (i * PCR_TICKS, None, false),
+            10..=19 => (i * PCR_TICKS + SYSTEM_CLOCK_FREQUENCY, None, false),
+            _ => (i * PCR_TICKS, None, i == 20),
+        }).collect();
+        let timing = measure(&pcrs);
+        assert_eq!(timing.discontinuities, vec![
+            Discontinuity { offset: 10 * 1880, signalled: false, jump: 1.03 },
+            Discontinuity { offset: 20 * 1880, signalled: true, jump: -0.97 },
+        ]);
+        // The runs are measured separately
+        assert_eq!(timing.samples.iter().filter(|s| s.interval.is_none()).count(), 3);
+        let s = timing.summary;
+        assert_eq!(s.pcr_count, 30);
+        assert_close(s.interval.unwrap().max, 0.03, 1e-12);
+        assert_close(s.jitter_peak_to_peak, 0.0, 1e-12);
+        assert_close(s.frequency_offset.unwrap(), 0.0, 1e-9);
+    }
+
+    #[test]
+    fn fit() {
+        assert_eq!(linear_fit(&[]), None);
+        assert_eq!(linear_fit(&[(1.0, 2.0)]), None);
+        assert_eq!(linear_fit(&[(1.0, 2.0), (1.0, 3.0)]), None);
+        let (intercept, slope) = linear_fit(&[(0.0, 1.0), (1.0, 3.5), (2.0, 5.0), (3.0, 7.5)]).unwrap();
+        assert_close(slope, 2.1, 1e-12);
+        assert_close(intercept, 1.1, 1e-12);
+    }
+}
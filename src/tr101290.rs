@@ -0,0 +1,797 @@
+use std::fmt;
+use std::collections::{HashMap, HashSet};
+use byteorder::{ByteOrder, BigEndian};
+use crate::demux::DemuxHandler;
+use crate::error::ParseError;
+use crate::mpeg32_crc;
+use crate::packet::{Packet, CRC_SIZE, SYSTEM_CLOCK_FREQUENCY};
+use crate::pes::Pes;
+use crate::psi::{pat::Pat, pmt::Pmt, psip::Psip, tdt::{Tdt, Tot}, time::UtcTime};
+use crate::timing::WallClock;
+
+// Constants
+const PAT_PID: u16 = 0x0000;
+const CAT_PID: u16 = 0x0001;
+const PAT_TABLE_ID: u8 = 0x00;
+const CAT_TABLE_ID: u8 = 0x01;
+const PMT_TABLE_ID: u8 = 0x02;
+/// The TOT is a short form section that still ends in a CRC
+const TOT_TABLE_ID: u8 = 0x73;
+/// PCRs wrap around after 2^33 * 300 ticks of the 27 MHz clock
+const PCR_MODULUS: u64 = (1 << 33) * 300;
+
+/// The ETSI TR 101 290 indicators that are checked
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Indicator {
+    TsSyncLoss,
+    SyncByteError,
+    PatError2,
+    ContinuityCountError,
+    PmtError2,
+    PidError,
+    TransportError,
+    CrcError,
+    PcrRepetitionError,
+    PcrDiscontinuityIndicatorError,
+    PcrAccuracyError,
+    PtsError,
+    CatError,
+}
+
+impl fmt::Display for Indicator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Indicator::TsSyncLoss => "1.1 TS_sync_loss",
+            Indicator::SyncByteError => "1.2 Sync_byte_error",
+            Indicator::PatError2 => "1.3.a PAT_error_2",
+            Indicator::ContinuityCountError => "1.4 Continuity_count_error",
+            Indicator::PmtError2 => "1.5.a PMT_error_2",
+            Indicator::PidError => "1.6 PID_error",
+            Indicator::TransportError => "2.1 Transport_error",
+            Indicator::CrcError => "2.2 CRC_error",
+            Indicator::PcrRepetitionError => "2.3.a PCR_repetition_error",
+            Indicator::PcrDiscontinuityIndicatorError => "2.3.b PCR_discontinuity_indicator_error",
+            Indicator::PcrAccuracyError => "2.4 PCR_accuracy_error",
+            Indicator::PtsError => "2.5 PTS_error",
+            Indicator::CatError => "2.6 CAT_error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A single failed check
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub indicator: Indicator,
+    pub pid: Option<u16>,
+    /// Stream offset of the packet the error was detected on
+    pub offset: u64,
+    /// Stream time in seconds (from the first PCR) the error was detected at,
+    /// if a PCR has been seen yet
+    pub time: Option<f64>,
+    /// Wall-clock time the error was detected at, if the stream has a TDT, TOT or STT
+    pub utc: Option<UtcTime>,
+    pub description: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.time {
+            Some(t) => write!(f, "[{:10.3}s]", t)?,
+            None => write!(f, "[{:>11}]", "-")?,
+        }
+        if let Some(utc) = self.utc {
+            write!(f, " [{}]", utc)?;
+        }
+        write!(f, " offset {}: {}", self.offset, self.indicator)?;
+        if let Some(pid) = self.pid {
+            write!(f, " PID {:#X}", pid)?;
+        }
+        write!(f, ": {}", self.description)
+    }
+}
+
+/// Thresholds of the checks (in seconds)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Longest allowed interval between PAT sections
+    pub pat_interval: f64,
+    /// Longest allowed interval between sections of each PMT
+    pub pmt_interval: f64,
+    /// Longest a PID referenced by a PMT may be missing
+    pub pid_interval: f64,
+    /// Longest allowed interval between PCRs of a PID
+    pub pcr_interval: f64,
+    /// Largest allowed jump between consecutive PCRs not flagged as a discontinuity
+    pub pcr_discontinuity: f64,
+    /// Largest allowed deviation of a PCR from the constant rate of its neighbours
+    pub pcr_accuracy: f64,
+    /// Longest allowed interval between PTSs of an elementary stream
+    pub pts_interval: f64,
+    /// Longest scrambled packets may be sent before a CAT
+    pub cat_interval: f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            pat_interval: 0.5,
+            pmt_interval: 0.5,
+            pid_interval: 5.0,
+            pcr_interval: 0.04,
+            pcr_discontinuity: 0.1,
+            pcr_accuracy: 500e-9,
+            pts_interval: 0.7,
+            cat_interval: 0.5,
+        }
+    }
+}
+
+/// Something that has to appear regularly in the stream
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+enum Timer {
+    Pat,
+    Pmt(u16),
+    Pid(u16),
+    Pts(u16),
+    /// A CAT, once packets are scrambled
+    Cat,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct TimerState {
+    /// None until the stream time is known
+    last_seen: Option<f64>,
+    /// Whether the current gap was already reported
+    reported: bool,
+}
+
+/// Offset and value of the last two PCRs of a PID since its last discontinuity
+#[derive(Copy, Clone, Debug, Default)]
+struct PcrHistory {
+    before: Option<(u64, u64)>,
+    last: Option<(u64, u64)>,
+}
+
+/// Runs the ETSI TR 101 290 priority 1 and 2 checks on a stream as a DemuxHandler.
+///
+/// CRCs are checked on every section the Demuxer assembles, so PIDs of other
+/// SI tables have to be registered with `Demuxer::add_section_pid` to be covered.
+#[derive(Clone, Debug, Default)]
+pub struct Analyzer {
+    config: Config,
+    findings: Vec<Finding>,
+    clock: WallClock,
+    timers: HashMap<Timer, TimerState>,
+    pcrs: HashMap<u16, PcrHistory>,
+    /// Video and audio PIDs of each PMT, the ones whose PTSs are checked
+    pts_pids: HashMap<u16, HashSet<u16>>,
+    cat_seen: bool,
+    /// Offset and time of the packet being handled
+    offset: u64,
+    time: Option<f64>,
+}
+
+impl Analyzer {
+    pub fn new(config: Config) -> Analyzer {
+        Analyzer {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    /// Number of findings of each indicator
+    pub fn counts(&self) -> HashMap<Indicator, usize> {
+        let mut counts = HashMap::new();
+        for finding in &self.findings {
+            *counts.entry(finding.indicator).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    fn report(&mut self, indicator: Indicator, pid: Option<u16>, description: String) {
+        self.findings.push(Finding {
+            indicator,
+            pid,
+            offset: self.offset,
+            time: self.time,
+            utc: self.utc(self.offset, self.time),
+            description,
+        });
+    }
+
+    /// Wall-clock time of the packet at `offset` and stream `time`
+    fn utc(&self, offset: u64, time: Option<f64>) -> Option<UtcTime> {
+        time.and_then(|t| self.clock.utc_at_time(t))
+            .or_else(|| self.clock.utc_at_offset(offset))
+    }
+
+    /// Give the findings from before the first time table their wall-clock time
+    fn backfill_utc(&mut self) {
+        for i in 0..self.findings.len() {
+            if self.findings[i].utc.is_none() {
+                self.findings[i].utc = self.utc(self.findings[i].offset, self.findings[i].time);
+            }
+        }
+    }
+
+    /// Start expecting `timer` (if it isn't already expected)
+    fn expect(&mut self, timer: Timer) {
+        let now = self.time;
+        self.timers.entry(timer).or_insert(TimerState { last_seen: now, reported: false });
+    }
+
+    /// Record that `timer` was seen, reporting it if the gap since the last time was too long
+    fn seen(&mut self, timer: Timer) {
+        let now = self.time;
+        if let (Some(state), Some(now)) = (self.timers.get(&timer).copied(), now) {
+            let gap = now - state.last_seen.unwrap_or(now);
+            if !state.reported && gap > limit(&self.config, timer) {
+                self.report_gap(timer, gap);
+            }
+        }
+        self.timers.insert(timer, TimerState { last_seen: now, reported: false });
+    }
+
+    /// Report everything that has now been missing for too long
+    fn check_timers(&mut self) {
+        let now = match self.time {
+            Some(now) => now,
+            None => return,
+        };
+        let mut late: Vec<(Timer, f64)> = vec![];
+        for (timer, state) in self.timers.iter_mut() {
+            let gap = now - *state.last_seen.get_or_insert(now);
+            if !state.reported && gap > limit(&self.config, *timer) {
+                state.reported = true;
+                late.push((*timer, gap));
+            }
+        }
+        for (timer, gap) in late {
+            self.report_gap(timer, gap);
+        }
+    }
+
+    fn report_gap(&mut self, timer: Timer, gap: f64) {
+        match timer {
+            Timer::Pat => self.report(Indicator::PatError2, Some(PAT_PID),
+                format!("no PAT section for {:.3}s", gap)),
+            Timer::Pmt(pid) => self.report(Indicator::PmtError2, Some(pid),
+                format!("no PMT section for {:.3}s", gap)),
+            Timer::Pid(pid) => self.report(Indicator::PidError, Some(pid),
+                format!("referenced PID missing for {:.3}s", gap)),
+            Timer::Pts(pid) => self.report(Indicator::PtsError, Some(pid),
+                format!("no PTS for {:.3}s", gap)),
+            Timer::Cat => self.report(Indicator::CatError, Some(CAT_PID),
+                format!("scrambled packets but no CAT for {:.3}s", gap)),
+        }
+    }
+
+    /// Check the interval, continuity and accuracy of the packet's PCR
+    fn check_pcr(&mut self, packet: &Packet) {
+        let pcr = match packet.pcr() {
+            Some(pcr) => pcr.value(),
+            None => return,
+        };
+        let pid = packet.pid;
+        let current = Some((packet.offset, pcr));
+        let history = self.pcrs.get(&pid).copied().unwrap_or_default();
+        let (last_offset, last_pcr) = match history.last {
+            Some(last) if !packet.is_discontinuity() => last,
+            _ => {
+                self.pcrs.insert(pid, PcrHistory { before: None, last: current });
+                return;
+            },
+        };
+
+        let interval = to_seconds((pcr + PCR_MODULUS - last_pcr) % PCR_MODULUS);
+        if interval > self.config.pcr_discontinuity {
+            // Also catches PCRs going backwards, which wrap around to a huge interval
+            self.report(Indicator::PcrDiscontinuityIndicatorError, Some(pid),
+                format!("PCR jumped by {:.3}s without the discontinuity_indicator", interval));
+            self.pcrs.insert(pid, PcrHistory { before: None, last: current });
+            return;
+        }
+        if interval > self.config.pcr_interval {
+            self.report(Indicator::PcrRepetitionError, Some(pid),
+                format!("PCRs {:.1}ms apart", interval * 1000.0));
+        }
+
+        // The last PCR should sit where the constant rate between its neighbours puts it
+        if let Some((first_offset, first_pcr)) = history.before {
+            let bytes = packet.offset.saturating_sub(first_offset);
+            if bytes > 0 {
+                let total = (pcr + PCR_MODULUS - first_pcr) % PCR_MODULUS;
+                let expected = total as f64 * last_offset.saturating_sub(first_offset) as f64 / bytes as f64;
+                let actual = (last_pcr + PCR_MODULUS - first_pcr) % PCR_MODULUS;
+                let error = (actual as f64 - expected) / SYSTEM_CLOCK_FREQUENCY as f64;
+                if error.abs() > self.config.pcr_accuracy {
+                    self.report(Indicator::PcrAccuracyError, Some(pid),
+                        format!("PCR at offset {} is {:+.0}ns off", last_offset, error * 1e9));
+                }
+            }
+        }
+        self.pcrs.insert(pid, PcrHistory { before: history.last, last: current });
+    }
+}
+
+/// Convert 27 MHz ticks to seconds
+fn to_seconds(ticks: u64) -> f64 {
+    ticks as f64 / SYSTEM_CLOCK_FREQUENCY as f64
+}
+
+/// Whether the CRC_32 at the end of a section doesn't match its contents
+/// (sections without a CRC never have an error)
+fn has_crc_error(section: &[u8]) -> bool {
+    let has_crc = section.len() > 1 && (section[1] & 0x80 != 0 || section[0] == TOT_TABLE_ID);
+    if !has_crc || section.len() < 3 + CRC_SIZE {
+        return false;
+    }
+    let end = section.len() - CRC_SIZE;
+    BigEndian::read_u32(&section[end..]) != mpeg32_crc::crc32_mpeg(&section[..end])
+}
+
+/// Longest allowed gap of `timer`
+fn limit(config: &Config, timer: Timer) -> f64 {
+    match timer {
+        Timer::Pat => config.pat_interval,
+        Timer::Pmt(_) => config.pmt_interval,
+        Timer::Pid(_) => config.pid_interval,
+        Timer::Pts(_) => config.pts_interval,
+        Timer::Cat => config.cat_interval,
+    }
+}
+
+impl DemuxHandler for Analyzer {
+    fn on_packet(&mut self, packet: &Packet) {
+        self.clock.on_packet(packet);
+        self.offset = packet.offset;
+        self.time = self.clock.stream_clock().time(packet.offset);
+        self.expect(Timer::Pat);
+
+        let pid = packet.pid;
+        if packet.transport_error_indicator {
+            self.report(Indicator::TransportError, Some(pid),
+                "transport_error_indicator is set".to_string());
+        }
+        if packet.transport_scrambling_control != 0 {
+            if pid == PAT_PID {
+                self.report(Indicator::PatError2, Some(pid), "PAT packet is scrambled".to_string());
+            } else if self.timers.contains_key(&Timer::Pmt(pid)) {
+                self.report(Indicator::PmtError2, Some(pid), "PMT packet is scrambled".to_string());
+            }
+            // The CAT may still be on its way, so only report it once it is overdue
+            if !self.cat_seen {
+                self.expect(Timer::Cat);
+            }
+        }
+        if self.timers.contains_key(&Timer::Pid(pid)) {
+            self.seen(Timer::Pid(pid));
+        }
+        self.check_pcr(packet);
+        self.check_timers();
+    }
+
+    fn on_continuity_error(&mut self, packet: &Packet, expected: u8) {
+        self.report(Indicator::ContinuityCountError, Some(packet.pid),
+            format!("continuity_counter is {}, expected {}", packet.continuity_counter, expected));
+    }
+
+    fn on_section(&mut self, pid: u16, table_id: u8, section: &[u8]) {
+        if has_crc_error(section) {
+            self.report(Indicator::CrcError, Some(pid),
+                format!("CRC error in section with table_id {:#X}", table_id));
+        }
+        if pid == CAT_PID {
+            if table_id == CAT_TABLE_ID {
+                self.cat_seen = true;
+                self.timers.remove(&Timer::Cat);
+            } else {
+                self.report(Indicator::CatError, Some(pid),
+                    format!("section with table_id {:#X} on the CAT PID", table_id));
+            }
+        } else if pid == PAT_PID {
+            if table_id == PAT_TABLE_ID {
+                self.seen(Timer::Pat);
+            } else {
+                self.report(Indicator::PatError2, Some(pid),
+                    format!("section with table_id {:#X} on the PAT PID", table_id));
+            }
+        } else if table_id == PMT_TABLE_ID && self.timers.contains_key(&Timer::Pmt(pid)) {
+            self.seen(Timer::Pmt(pid));
+        }
+    }
+
+    fn on_pat(&mut self, pat: &Pat) {
+        for pid in pat.get_pmt_pids() {
+            self.expect(Timer::Pmt(pid));
+        }
+    }
+
+    fn on_pmt(&mut self, pid: u16, pmt: &Pmt) {
+        for es_pid in pmt.get_es_pids() {
+            self.expect(Timer::Pid(es_pid));
+        }
+        // Other streams (subtitles, metadata...) only carry a PTS when they have something to show
+        let pts_pids = pmt.elementary_streams.iter()
+            .filter(|es| es.get_codec().is_video() || es.get_codec().is_audio())
+            .map(|es| es.get_elementary_pid())
+            .collect();
+        self.pts_pids.insert(pid, pts_pids);
+        let pts_pids = &self.pts_pids;
+        self.timers.retain(|timer, _| match timer {
+            Timer::Pts(pid) => pts_pids.values().any(|pids| pids.contains(pid)),
+            _ => true,
+        });
+    }
+
+    fn on_pes(&mut self, pid: u16, pes: &Pes) {
+        if pes.pts().is_some() && self.pts_pids.values().any(|pids| pids.contains(&pid)) {
+            self.seen(Timer::Pts(pid));
+        }
+    }
+
+    fn on_tdt(&mut self, pid: u16, tdt: &Tdt) {
+        let first = self.clock.references().is_empty();
+        self.clock.on_tdt(pid, tdt);
+        if first {
+            self.backfill_utc();
+        }
+    }
+
+    fn on_tot(&mut self, pid: u16, tot: &Tot) {
+        let first = self.clock.references().is_empty();
+        self.clock.on_tot(pid, tot);
+        if first {
+            self.backfill_utc();
+        }
+    }
+
+    fn on_psip(&mut self, pid: u16, psip: &Psip) {
+        let first = self.clock.references().is_empty();
+        self.clock.on_psip(pid, psip);
+        if first {
+            self.backfill_utc();
+        }
+    }
+
+    fn on_error(&mut self, offset: u64, error: &ParseError) {
+        if let ParseError::SyncByte { found } = error {
+            self.offset = offset;
+            self.time = self.clock.stream_clock().time(offset);
+            self.report(Indicator::SyncByteError, None,
+                format!("sync byte is {:#X}", found));
+        }
+    }
+
+    fn on_sync_lost(&mut self, offset: u64) {
+        self.offset = offset;
+        self.time = self.clock.stream_clock().time(offset);
+        self.report(Indicator::TsSyncLoss, None, "sync lost".to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demux::Demuxer;
+    use crate::packet::PACKET_SIZE;
+    use crate::test_util;
+
+    const PMT_PID: u16 = 0x100;
+    const VIDEO_PID: u16 = 0x101;
+    const AUDIO_PID: u16 = 0x102;
+    const NULL_PID: u16 = 0x1FFF;
+    /// 27 MHz ticks per packet: a packet every 0.75ms, so no gap is ever exactly a limit
+    const PACKET_TICKS: u64 = 20_250;
+
+    /// Builds a single program stream: a PCR on the video PID every 20 packets, a PAT,
+    /// PMT and audio PES packet every 100 and null packets in between
+    #[derive(Default)]
+    struct Stream {
+        data: Vec<u8>,
+        ccs: HashMap<u16, u8>,
+        /// PIDs `fill` sends null packets instead of
+        muted: Vec<u16>,
+        /// (stream_type, elementary_PID) the PMT lists after the video and audio
+        extra_streams: Vec<(u8, u16)>,
+        /// Added to the PCRs
+        pcr_offset: u64,
+    }
+
+    impl Stream {
+        /// Number of packets so far
+        fn len(&self) -> u64 {
+            (self.data.len() / PACKET_SIZE) as u64
+        }
+
+        fn packet(&mut self, pid: u16, unit_start: bool, adaptation_field: Option<&[u8]>, payload: &[u8]) {
+            // The counter only advances on packets with a payload
+            let cc = self.ccs.entry(pid).or_insert(0);
+            if !payload.is_empty() {
+                *cc = (*cc + 1) & 0x0F;
+            }
+            self.data.extend(test_util::packet(pid, unit_start, *cc, adaptation_field, payload));
+        }
+
+        fn section(&mut self, pid: u16, section: &[u8]) {
+            self.packet(pid, true, None, &[&[0x00], section].concat());
+        }
+
+        /// Append the regular packets up to packet `n`
+        fn fill(&mut self, n: u64) {
+            while self.len() < n {
+                let i = self.len();
+                let pid = match i {
+                    _ if i.is_multiple_of(20) => VIDEO_PID,
+                    _ if i % 100 == 1 => PAT_PID,
+                    _ if i % 100 == 2 => PMT_PID,
+                    _ if i % 100 == 3 => AUDIO_PID,
+                    _ => NULL_PID,
+                };
+                match pid {
+                    _ if self.muted.contains(&pid) => self.packet(NULL_PID, false, None, &[0xFF]),
+                    VIDEO_PID => self.pcr_packet(false),
+                    PAT_PID => self.section(pid, &test_util::section(PAT_TABLE_ID, 1, &[0x00, 0x01, 0xE1, 0x00])),
+                    PMT_PID => {
+                        let mut body = vec![0xE1, 0x01, 0xF0, 0x00];
+                        for (stream_type, pid) in [(0x1B, VIDEO_PID), (0x0F, AUDIO_PID)].iter().chain(&self.extra_streams) {
+                            body.extend_from_slice(&[*stream_type, 0xE0 | (pid >> 8) as u8, *pid as u8, 0xF0, 0x00]);
+                        }
+                        self.section(pid, &test_util::section(PMT_TABLE_ID, 1, &body));
+                    },
+                    // A PES packet with a PTS
+                    AUDIO_PID => self.packet(pid, true, None, &[
+                        0x00, 0x00, 0x01, 0xC0, 0x00, 0x09, 0x80, 0x80, 0x05,
+                        0x21, 0x00, 0x01, 0x00, 0x01, 0xAA,
+                    ]),
+                    _ => self.packet(pid, false, None, &[0xFF]),
+                }
+            }
+        }
+
+        /// A video packet with just a PCR for the current packet number
+        fn pcr_packet(&mut self, discontinuity: bool) {
+            let mut adaptation_field = test_util::pcr_field(self.len() * PACKET_TICKS + self.pcr_offset);
+            if discontinuity {
+                adaptation_field[0] |= 0x80;
+            }
+            self.packet(VIDEO_PID, false, Some(&adaptation_field), &[]);
+        }
+
+        /// Set the transport_scrambling_control of the packets of `pid` from packet `from` on
+        fn scramble(&mut self, pid: u16, from: u64) {
+            for packet in self.data.chunks_mut(PACKET_SIZE).skip(from as usize) {
+                if BigEndian::read_u16(&packet[1..]) & 0x1FFF == pid {
+                    packet[3] |= 0x80;
+                }
+            }
+        }
+    }
+
+    fn findings(stream: &Stream) -> Vec<Finding> {
+        let mut analyzer = Analyzer::new(Config::default());
+        let mut demux = Demuxer::new();
+        demux.add_handler(&mut analyzer);
+        demux.push(&stream.data);
+        demux.finish();
+        analyzer.findings().to_vec()
+    }
+
+    /// The (indicator, PID, packet number) of the findings on `stream`, checking
+    /// their offset and stream time agree with the packet number
+    fn analyze(stream: &Stream) -> Vec<(Indicator, Option<u16>, u64)> {
+        findings(stream).iter().map(|finding| {
+            assert_eq!(finding.offset % PACKET_SIZE as u64, 0, "{}", finding);
+            let n = finding.offset / PACKET_SIZE as u64;
+            let time = finding.time.unwrap();
+            // The stream clock carries on in whole ticks over PCR jumps
+            assert!((time - to_seconds(n * PACKET_TICKS)).abs() < 1e-6, "{}", finding);
+            (finding.indicator, finding.pid, n)
+        }).collect()
+    }
+
+    #[test]
+    fn clean_stream() {
+        let mut stream = Stream::default();
+        stream.fill(8000);
+        assert_eq!(analyze(&stream), vec![]);
+    }
+
+    #[test]
+    fn sync_errors() {
+        let mut stream = Stream::default();
+        stream.fill(200);
+        // A single corrupted sync byte, then two in a row
+        for n in [104, 150, 151] {
+            stream.data[n * PACKET_SIZE] = 0x07;
+        }
+        assert_eq!(analyze(&stream), vec![
+            (Indicator::SyncByteError, None, 104),
+            (Indicator::SyncByteError, None, 150),
+            (Indicator::TsSyncLoss, None, 151),
+        ]);
+    }
+
+    #[test]
+    fn pat_error() {
+        let mut stream = Stream::default();
+        // The PAT of packet 901 is the last one for 1.1s
+        stream.fill(1000);
+        stream.muted.push(PAT_PID);
+        stream.fill(2000);
+        stream.muted.clear();
+        stream.fill(2200);
+        // A section that isn't a PAT on the PAT PID
+        stream.section(PAT_PID, &test_util::section(PMT_TABLE_ID, 1, &[0xE1, 0x01, 0xF0, 0x00]));
+        stream.fill(2400);
+        stream.scramble(PAT_PID, 2301);
+        assert_eq!(analyze(&stream), vec![
+            (Indicator::PatError2, Some(PAT_PID), 901 + 667),
+            (Indicator::PatError2, Some(PAT_PID), 2200),
+            (Indicator::PatError2, Some(PAT_PID), 2301),
+        ]);
+    }
+
+    #[test]
+    fn continuity_count_error() {
+        let mut stream = Stream::default();
+        stream.fill(500);
+        // The PMT of packet 502 skips a continuity_counter value
+        *stream.ccs.get_mut(&PMT_PID).unwrap() += 1;
+        stream.fill(700);
+        // Sending the audio packet of packet 603 again is fine, but not a third time
+        let audio = stream.data[603 * PACKET_SIZE..604 * PACKET_SIZE].to_vec();
+        stream.data.extend_from_slice(&audio);
+        stream.data.extend_from_slice(&audio);
+        stream.fill(800);
+        assert_eq!(analyze(&stream), vec![
+            (Indicator::ContinuityCountError, Some(PMT_PID), 502),
+            (Indicator::ContinuityCountError, Some(AUDIO_PID), 701),
+        ]);
+    }
+
+    #[test]
+    fn pmt_error() {
+        let mut stream = Stream::default();
+        // The PMT of packet 902 is the last one for 1.1s
+        stream.fill(1000);
+        stream.muted.push(PMT_PID);
+        stream.fill(2000);
+        stream.muted.clear();
+        stream.fill(2200);
+        stream.scramble(PMT_PID, 2102);
+        assert_eq!(analyze(&stream), vec![
+            (Indicator::PmtError2, Some(PMT_PID), 902 + 667),
+            (Indicator::PmtError2, Some(PMT_PID), 2102),
+        ]);
+    }
+
+    #[test]
+    fn pid_error() {
+        let mut stream = Stream::default();
+        // The audio PES packet of packet 903 is the last one
+        stream.fill(1000);
+        stream.muted.push(AUDIO_PID);
+        stream.fill(8000);
+        // Its PTSs go missing too, and sooner
+        assert_eq!(analyze(&stream), vec![
+            (Indicator::PtsError, Some(AUDIO_PID), 903 + 934),
+            (Indicator::PidError, Some(AUDIO_PID), 903 + 6667),
+        ]);
+    }
+
+    #[test]
+    fn cat_error() {
+        // Scrambling from packet 1003 on with no CAT is reported once it is 0.5s overdue
+        let mut stream = Stream::default();
+        stream.fill(3000);
+        stream.scramble(AUDIO_PID, 1000);
+        assert_eq!(analyze(&stream), vec![(Indicator::CatError, Some(CAT_PID), 1003 + 667)]);
+
+        // A CAT in time clears it
+        let mut stream = Stream::default();
+        stream.fill(1500);
+        stream.section(CAT_PID, &test_util::section(CAT_TABLE_ID, 0xFFFF, &[]));
+        stream.fill(3000);
+        stream.scramble(AUDIO_PID, 1000);
+        assert_eq!(analyze(&stream), vec![]);
+
+        // Any other table on the CAT PID is an error straight away
+        let mut stream = Stream::default();
+        stream.fill(100);
+        stream.section(CAT_PID, &test_util::section(PMT_TABLE_ID, 1, &[0xE1, 0x01, 0xF0, 0x00]));
+        stream.fill(200);
+        assert_eq!(analyze(&stream), vec![(Indicator::CatError, Some(CAT_PID), 100)]);
+    }
+
+    #[test]
+    fn transport_and_crc_errors() {
+        let mut stream = Stream::default();
+        stream.fill(300);
+        let mut pat = test_util::section(PAT_TABLE_ID, 1, &[0x00, 0x01, 0xE1, 0x00]);
+        *pat.last_mut().unwrap() ^= 0x01;
+        stream.section(PAT_PID, &pat);
+        stream.fill(400);
+        stream.data[350 * PACKET_SIZE + 1] |= 0x80;
+        assert_eq!(analyze(&stream), vec![
+            (Indicator::CrcError, Some(PAT_PID), 300),
+            (Indicator::TransportError, Some(NULL_PID), 350),
+        ]);
+    }
+
+    #[test]
+    fn pcr_repetition_error() {
+        // The PCRs of packets 1000 to 1080 are missing: 90ms between the ones of 980 and 1100
+        let mut stream = Stream::default();
+        stream.fill(1000);
+        stream.muted.push(VIDEO_PID);
+        stream.fill(1100);
+        stream.muted.clear();
+        stream.fill(1200);
+        assert_eq!(analyze(&stream), vec![(Indicator::PcrRepetitionError, Some(VIDEO_PID), 1100)]);
+    }
+
+    #[test]
+    fn pcr_discontinuity_indicator_error() {
+        // The PCRs jump ahead by a second, then back where the discontinuity_indicator
+        // says they do
+        let mut stream = Stream::default();
+        stream.fill(1500);
+        stream.pcr_offset = SYSTEM_CLOCK_FREQUENCY;
+        stream.fill(1600);
+        stream.pcr_offset = 0;
+        stream.pcr_packet(true);
+        stream.fill(1700);
+        assert_eq!(analyze(&stream), vec![(Indicator::PcrDiscontinuityIndicatorError, Some(VIDEO_PID), 1500)]);
+
+        // Going backwards without it is a discontinuity too
+        let mut stream = Stream::default();
+        stream.fill(1500);
+        stream.pcr_offset = SYSTEM_CLOCK_FREQUENCY;
+        stream.fill(1600);
+        stream.pcr_offset = 0;
+        stream.fill(1700);
+        assert_eq!(analyze(&stream), vec![
+            (Indicator::PcrDiscontinuityIndicatorError, Some(VIDEO_PID), 1500),
+            (Indicator::PcrDiscontinuityIndicatorError, Some(VIDEO_PID), 1600),
+        ]);
+    }
+
+    #[test]
+    fn pcr_accuracy_error() {
+        // The PCR of packet 1000 is 20 ticks (741ns) late, which shows once the one
+        // of packet 1020 arrives
+        let mut stream = Stream::default();
+        stream.fill(1000);
+        stream.pcr_offset = 20;
+        stream.fill(1001);
+        stream.pcr_offset = 0;
+        stream.fill(1100);
+        assert_eq!(analyze(&stream), vec![(Indicator::PcrAccuracyError, Some(VIDEO_PID), 1020)]);
+        assert_eq!(findings(&stream)[0].description, "PCR at offset 188000 is +741ns off");
+    }
+
+    #[test]
+    fn pts_error() {
+        // Subtitles only carry a PTS now and then, but the audio's stop after packet 903
+        let mut stream = Stream::default();
+        stream.extra_streams.push((0x06, 0x103));
+        stream.fill(100);
+        for _ in 0..2 {
+            stream.packet(0x103, true, None, &[
+                0x00, 0x00, 0x01, 0xBD, 0x00, 0x09, 0x80, 0x80, 0x05,
+                0x21, 0x00, 0x01, 0x00, 0x01, 0x20,
+            ]);
+            stream.fill(stream.len() + 100);
+        }
+        stream.fill(1000);
+        stream.muted.push(AUDIO_PID);
+        stream.fill(2000);
+        assert_eq!(analyze(&stream), vec![(Indicator::PtsError, Some(AUDIO_PID), 903 + 934)]);
+    }
+}